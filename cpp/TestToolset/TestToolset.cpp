@@ -65,31 +65,42 @@ void onError(void* context, const char* error) {
 	std::cerr << error << std::endl;
 }
 
+void onDiagnostics(void* context, const KaijuDiagnostic* diagnostics, uintptr_t count) {
+	for (uintptr_t i = 0; i < count; ++i) {
+		std::cerr << diagnostics[i].pretty << std::endl;
+	}
+}
+
 template<typename T>
-const T* getValue(uintptr_t address) {
-	if (address + sizeof(T) <= kaiju_state_size()) {
-		return (const T*)kaiju_state_ptr(address);
+const T* getValue(KaijuContext* kaiju, uintptr_t address) {
+	if (address + sizeof(T) <= kaiju_state_size(kaiju)) {
+		return (const T*)kaiju_state_ptr(kaiju, address);
 	}
 	return nullptr;
 }
 
 template<typename T>
-void setValue(uintptr_t address, const T& value) {
-	if (address + sizeof(T) <= kaiju_state_size()) {
-		*(T*)kaiju_state_ptr_mut(address) = value;
+void setValue(KaijuContext* kaiju, uintptr_t address, const T& value) {
+	if (address + sizeof(T) <= kaiju_state_size(kaiju)) {
+		*(T*)kaiju_state_ptr_mut(kaiju, address) = value;
 	}
 }
 
-void onProcessOp(void* context, const char* op, const uintptr_t* params, uintptr_t params_count, const uintptr_t* targets, uintptr_t targets_count) {
+bool onBindOp(void* context, const char* op) {
+	auto id = std::string(op);
+	return id == "add" || id == "out";
+}
+
+void onProcessOp(KaijuContext* kaiju, void* context, const char* op, const uintptr_t* params, uintptr_t params_count, const uintptr_t* targets, uintptr_t targets_count) {
 	auto id = std::string(op);
 	if (id == "add") {
-		auto a = *getValue<int32_t>(params[0]);
-		auto b = *getValue<int32_t>(params[1]);
+		auto a = *getValue<int32_t>(kaiju, params[0]);
+		auto b = *getValue<int32_t>(kaiju, params[1]);
 		auto c = a + b;
-		setValue<int32_t>(targets[0], c);
+		setValue<int32_t>(kaiju, targets[0], c);
 	}
 	else if (id == "out") {
-		auto v = *getValue<int32_t>(params[0]);
+		auto v = *getValue<int32_t>(kaiju, params[0]);
 		std::cout << "out: " << v << std::endl;
 	}
 }
@@ -110,7 +121,7 @@ int main()
 		&context,
 		onResultPstFile,
 		&resultPst,
-		onError,
+		onDiagnostics,
 		nullptr
 	);
 	std::cout << resultPst << std::endl;
@@ -118,11 +129,12 @@ int main()
 	kaiju_compile_program_bin(
 		"program.kj",
 		"descriptor.kjo",
+		true,
 		onServeFile,
 		&context,
 		onResultBinFile,
 		&resultBin,
-		onError,
+		onDiagnostics,
 		nullptr
 	);
 
@@ -132,6 +144,9 @@ int main()
 		"main",
 		1024,
 		1024,
+		nullptr,
+		0,
+		onBindOp,
 		onProcessOp,
 		nullptr,
 		onError,
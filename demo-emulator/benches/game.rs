@@ -0,0 +1,146 @@
+//! Runs demo game headlessly (no window and rendering) with scripted input and measures how fast
+//! VM executes its frames.
+//! Run with: `cargo bench -p demo-emulator`.
+
+extern crate byteorder;
+extern crate kaiju_compiler_core as compiler_core;
+extern crate kaiju_core as core;
+extern crate kaiju_vm_core as vm_core;
+
+// Shared with console binary, which uses parts of these modules that headless run does not.
+#[allow(dead_code)]
+#[path = "../src/console/cartridge.rs"]
+mod cartridge;
+#[allow(dead_code)]
+#[path = "../src/console/processor.rs"]
+mod processor;
+#[allow(dead_code)]
+#[path = "../src/console/render.rs"]
+mod render;
+
+use crate::processor::Processor;
+use compiler_core::module_reader::ModuleReader;
+use core::program::compile_ops_descriptor;
+use core::validator::EmptyDeepValidator;
+use std::time::{Duration, Instant};
+use vm_core::vm::Vm;
+
+const FRAMES: usize = 600;
+const SAMPLES: usize = 10;
+const WIDTH: usize = 160;
+const HEIGHT: usize = 128;
+
+struct GameModuleReader {}
+
+impl ModuleReader for GameModuleReader {
+    fn load_module_source(&self, path: &str) -> Option<String> {
+        if path == "game.kj" {
+            Some(include_str!("../res/game.kj").to_owned())
+        } else {
+            None
+        }
+    }
+
+    fn push_module_path(&mut self, _: &str) {}
+
+    fn pop_module_path(&mut self) {}
+
+    fn compose_path(&self, path: &str) -> String {
+        path.to_owned()
+    }
+}
+
+fn make_processor() -> Processor {
+    let tcols = 2 * WIDTH / 16;
+    let trows = 2 * HEIGHT / 16;
+    let mut processor = Processor::new();
+    processor.tcols = tcols;
+    processor.trows = trows;
+    processor.tiles = vec![0; tcols * trows];
+    processor.text_cols = tcols;
+    processor.text_rows = trows;
+    processor.text_buffer = vec![0; tcols * trows];
+    processor
+}
+
+/// Input of given frame: moves player around while shooting, never presses BACK/B (exit).
+fn input(frame: usize) -> u8 {
+    let direction = match (frame / 30) % 4 {
+        0 => 1 << 2,
+        1 => 1,
+        2 => 1 << 3,
+        _ => 1 << 1,
+    };
+    let shoot = if frame % 8 < 4 { 1 << 6 } else { 0 };
+    direction | shoot
+}
+
+/// Runs `FRAMES` frames, starting new game from `vm` whenever current one is over.
+/// Returns number of played games and VM budget units spent.
+fn run(vm: &Vm) -> (usize, usize) {
+    let mut games = 0;
+    let mut spent = 0;
+    let mut frame = 0;
+    while frame < FRAMES {
+        let mut vm = vm.fork().unwrap_or_else(|e| panic!("{}", e.message));
+        let mut processor = make_processor();
+        vm.bind(&mut processor)
+            .and_then(|_| vm.start("main"))
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        while frame < FRAMES && vm.can_resume() {
+            processor.halt = false;
+            processor.input = input(frame);
+            while !processor.halt && vm.can_resume() {
+                vm.resume(&mut processor)
+                    .unwrap_or_else(|e| panic!("{}", e.message));
+            }
+            frame += 1;
+        }
+        games += 1;
+        spent += vm.spent_budget();
+    }
+    (games, spent)
+}
+
+fn main() {
+    let ops = compile_ops_descriptor(include_str!("../res/descriptor.kjo"))
+        .unwrap_or_else(|e| panic!("{}", e.pretty));
+    let vm = Vm::from_source::<EmptyDeepValidator, _>(
+        "game.kj",
+        GameModuleReader {},
+        &ops,
+        1024 * 4,
+        1024 * 60,
+    )
+    .unwrap_or_else(|e| panic!("{}", e.message));
+
+    let mut times = Vec::with_capacity(SAMPLES);
+    let mut games = 0;
+    let mut spent = 0;
+    for _ in 0..SAMPLES {
+        let timer = Instant::now();
+        let result = run(&vm);
+        times.push(timer.elapsed());
+        games = result.0;
+        spent = result.1;
+    }
+    times.sort();
+    let mean = times.iter().sum::<Duration>() / SAMPLES as u32;
+    let median = times[SAMPLES / 2];
+    println!(
+        "game: {} frames ({} games), {} ops per sample, {} samples",
+        FRAMES, games, spent, SAMPLES
+    );
+    println!(
+        "  min: {:?} | median: {:?} | mean: {:?} | max: {:?}",
+        times[0],
+        median,
+        mean,
+        times[SAMPLES - 1]
+    );
+    println!(
+        "  {:.1} frames/s | {:.0} ops/s (median)",
+        FRAMES as f64 / median.as_secs_f64(),
+        spent as f64 / median.as_secs_f64()
+    );
+}
@@ -13,14 +13,18 @@ pub struct Color {
     pub b: u8,
 }
 
-impl Into<u32> for Color {
-    fn into(self) -> u32 {
-        u32::from(self.b) | (u32::from(self.g) << 8) | (u32::from(self.r) << 16)
+impl From<Color> for u32 {
+    fn from(color: Color) -> Self {
+        u32::from(color.b) | (u32::from(color.g) << 8) | (u32::from(color.r) << 16)
     }
 }
 
 type ReadCartridge = (Vec<Sprite>, Vec<u8>, Vm, (usize, usize));
 
+/// First assembly version guaranteed to pass `@label` op params as bytecode addresses, older
+/// ones may pass label names as strings.
+const MIN_ASSEMBLY_VERSION: u8 = 2;
+
 pub fn read_cartridge(bytes: &[u8]) -> SimpleResult<ReadCartridge> {
     let mut stream = Cursor::new(bytes);
     let mut header = vec![0; 4];
@@ -64,6 +68,12 @@ pub fn read_cartridge(bytes: &[u8]) -> SimpleResult<ReadCartridge> {
     let mut buffer = vec![0; size];
     stream.read_exact(&mut buffer)?;
     let vm = Vm::from_bytes(buffer, 1024 * 4, 1024 * 60)?;
+    if vm.assembly().version() < MIN_ASSEMBLY_VERSION {
+        return Err(SimpleError::new(format!(
+            "Cartridge assembly version {} is too old, rebuild it from sources",
+            vm.assembly().version()
+        )));
+    }
     Ok((sprites, data, vm, wh))
 }
 
@@ -5,8 +5,6 @@ extern crate clap;
 extern crate kaiju_core as core;
 extern crate kaiju_vm_core as vm_core;
 extern crate minifb;
-#[macro_use]
-extern crate lazy_static;
 extern crate rayon;
 
 mod cartridge;
@@ -19,8 +17,10 @@ use crate::render::*;
 use clap::{App, Arg};
 use minifb::{InputCallback, Key, Scale, Window, WindowOptions};
 use rayon::prelude::*;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs::read;
+use std::rc::Rc;
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 
@@ -30,11 +30,13 @@ enum MapKey {
     Range(String, String),
 }
 
-struct CharsPressed {}
+struct CharsPressed {
+    chars: Rc<RefCell<Vec<u8>>>,
+}
 
 impl InputCallback for CharsPressed {
     fn add_char(&mut self, uni_char: u32) {
-        Processor::with_mut(|p| p.chars_pressed.push(uni_char as u8));
+        self.chars.borrow_mut().push(uni_char as u8);
     }
 }
 
@@ -139,7 +141,10 @@ fn main() {
         let buffer = read(cartridge).unwrap_or_else(|e| panic!("{}", e));
         read_cartridge(&buffer).unwrap_or_else(|e| panic!(e.message))
     };
-    vm.start("main").unwrap_or_else(|e| panic!(e.message));
+    let mut processor = Processor::new();
+    vm.bind(&mut processor)
+        .and_then(|_| vm.start("main"))
+        .unwrap_or_else(|e| panic!(e.message));
     if !data.is_empty() {
         let v = vm
             .state_mut()
@@ -148,27 +153,22 @@ fn main() {
         vm.state_mut()
             .store_bytes(v.address, &data)
             .unwrap_or_else(|e| panic!(e.message));
-        Processor::with_mut(|p| p.data_block = v);
+        processor.data_block = v;
     }
     let tcols = 2 * width / 16;
     let trows = 2 * height / 16;
-    Processor::with_mut(|p| {
-        p.tcols = tcols;
-        p.trows = trows;
-        p.sprites = sprites.clone();
-        p.tiles = vec![0; tcols * trows];
-        if let Some(path) = matches.value_of("save") {
-            p.save_file = path.to_owned();
-        }
-        p.font = font.clone();
-        p.chars = chars.clone();
-        p.text_cols = tcols;
-        p.text_rows = trows;
-        p.text_buffer = vec![0; tcols * trows];
-    });
-    drop(sprites);
-    drop(font);
-    drop(chars);
+    processor.tcols = tcols;
+    processor.trows = trows;
+    processor.sprites = sprites;
+    processor.tiles = vec![0; tcols * trows];
+    if let Some(path) = matches.value_of("save") {
+        processor.save_file = path.to_owned();
+    }
+    processor.font = font;
+    processor.chars = chars;
+    processor.text_cols = tcols;
+    processor.text_rows = trows;
+    processor.text_buffer = vec![0; tcols * trows];
     let mut buffer: Vec<u32> = vec![0; width * height];
     let mut window = Window::new(
         &format!("Console Emulator ({}x{})", width, height),
@@ -180,13 +180,17 @@ fn main() {
         },
     )
     .unwrap_or_else(|e| panic!("{}", e));
-    window.set_input_callback(Box::new(CharsPressed {}));
+    let chars_pressed = Rc::new(RefCell::new(Vec::with_capacity(1024)));
+    window.set_input_callback(Box::new(CharsPressed {
+        chars: chars_pressed.clone(),
+    }));
 
     let tile_indices = (0..(tcols * trows)).collect::<Vec<usize>>();
     while window.is_open() && vm.can_resume() {
         let timer = Instant::now();
 
-        Processor::with_mut(|p| {
+        {
+            let p = &mut processor;
             let mut input = 0;
             // UP
             if window.is_key_down(Key::Up) {
@@ -222,19 +226,18 @@ fn main() {
             }
             p.halt = false;
             p.input = input;
-        });
+            p.chars_pressed.extend(chars_pressed.borrow_mut().drain(..));
+        }
 
-        while !Processor::with(|p| p.halt)
-            && vm.can_resume()
-            && timer.elapsed() < Duration::from_millis(30)
-        {
-            vm.resume::<Processor>()
+        while !processor.halt && vm.can_resume() && timer.elapsed() < Duration::from_millis(30) {
+            vm.resume(&mut processor)
                 .unwrap_or_else(|e| panic!(e.message));
         }
 
-        Processor::with_mut(|p| p.chars_pressed.clear());
+        processor.chars_pressed.clear();
 
-        Processor::with(|p| {
+        {
+            let p = &processor;
             if p.clear_screen {
                 for c in &mut buffer {
                     *c = p.bg_color;
@@ -312,7 +315,7 @@ fn main() {
                     });
                 }
             }
-        });
+        }
 
         window.update_with_buffer(&buffer).unwrap();
         if let Some(rest) = Duration::from_millis(33).checked_sub(timer.elapsed()) {
@@ -4,16 +4,11 @@ use core::error::*;
 use rand::prelude::*;
 use std::fs::{read, write};
 use std::path::Path;
-use std::sync::Mutex;
 use vm_core::load_cstring;
-use vm_core::processor::{OpAction, Processor as VmProcessor};
+use vm_core::processor::{HandlerId, OpAction, OpsRegistry, Processor as VmProcessor};
 use vm_core::state::Value;
 use vm_core::vm::Vm;
 
-lazy_static! {
-    static ref PROC: Mutex<Processor> = Mutex::new(Processor::new());
-}
-
 #[derive(Debug, Copy, Clone)]
 pub struct Object {
     pub index: usize,
@@ -45,6 +40,7 @@ fn overlap(a: (i16, i16, i16, i16), b: (i16, i16, i16, i16)) -> bool {
 }
 
 pub struct Processor {
+    ops: OpsRegistry<Processor>,
     pub modes: u8,
     pub tcols: usize,
     pub trows: usize,
@@ -73,16 +69,72 @@ pub struct Processor {
 
 impl Processor {
     pub fn new() -> Self {
+        let ops = OpsRegistry::new()
+            .with("halt", Self::op_halt)
+            .with("goto", Self::op_goto)
+            .with("if", Self::op_if)
+            .with("ret", Self::op_ret)
+            .with("pass", Self::op_pass)
+            .with("dbgi", Self::op_dbgi)
+            .with("dbgs", Self::op_dbgs)
+            .with("dbgp", Self::op_dbgp)
+            .with("dbgm", Self::op_dbgm)
+            .with("test", Self::op_test)
+            .with("cstp", Self::op_cstp)
+            .with("poff", Self::op_poff)
+            .with("i2b", Self::op_i2b)
+            .with("b2i", Self::op_b2i)
+            .with("allc", Self::op_allc)
+            .with("free", Self::op_free)
+            .with("mode", Self::op_mode)
+            .with("inp", Self::op_inp)
+            .with("data", Self::op_data)
+            .with("tls", Self::op_tls)
+            .with("tln", Self::op_tln)
+            .with("tlb", Self::op_tlb)
+            .with("tlv", Self::op_tlv)
+            .with("objs", Self::op_objs)
+            .with("objp", Self::op_objp)
+            .with("objn", Self::op_objn)
+            .with("objb", Self::op_objb)
+            .with("objv", Self::op_objv)
+            .with("std", Self::op_std)
+            .with("ldd", Self::op_ldd)
+            .with("hd", Self::op_hd)
+            .with("bgc", Self::op_bgc)
+            .with("fgc", Self::op_fgc)
+            .with("chr", Self::op_chr)
+            .with("kcc", Self::op_kcc)
+            .with("gkc", Self::op_gkc)
+            .with("ovlp", Self::op_ovlp)
+            .with("add", Self::op_add)
+            .with("sub", Self::op_sub)
+            .with("mul", Self::op_mul)
+            .with("div", Self::op_div)
+            .with("mod", Self::op_mod)
+            .with("mov", Self::op_mov)
+            .with("eq", Self::op_eq)
+            .with("nq", Self::op_nq)
+            .with("gt", Self::op_gt)
+            .with("lt", Self::op_lt)
+            .with("ge", Self::op_ge)
+            .with("le", Self::op_le)
+            .with("lsh", Self::op_lsh)
+            .with("rsh", Self::op_rsh)
+            .with("and", Self::op_and)
+            .with("or", Self::op_or)
+            .with("xor", Self::op_xor)
+            .with("neg", Self::op_neg)
+            .with("rnd", Self::op_rnd);
         Self {
+            ops,
             modes: 1,
             tcols: 0,
             trows: 0,
             sprites: vec![],
             tiles: vec![],
             tiles_viewport: (0, 0),
-            objects: ::std::iter::repeat(Object::new(0, 0, 0, Blending::None))
-                .take(600)
-                .collect::<Vec<Object>>(),
+            objects: vec![Object::new(0, 0, 0, Blending::None); 600],
             objects_viewport: (0, 0),
             blending: Blending::None,
             clear_screen: true,
@@ -103,414 +155,712 @@ impl Processor {
         }
     }
 
-    pub fn with<T, F>(mut cb: F) -> T
-    where
-        F: FnMut(&Self) -> T,
-    {
-        cb(&PROC.lock().unwrap())
+    fn op_halt(
+        &mut self,
+        _params: &[usize],
+        _targets: &[usize],
+        _vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        self.halt = true;
+        Ok(OpAction::None)
+    }
+
+    fn op_goto(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        _vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        Ok(OpAction::GoTo(params[0]))
+    }
+
+    fn op_if(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let v = vm.state().load_data::<i16>(params[0])?;
+        Ok(OpAction::GoTo(if v != 0 { params[1] } else { params[2] }))
+    }
+
+    fn op_ret(
+        &mut self,
+        _params: &[usize],
+        _targets: &[usize],
+        _vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        Ok(OpAction::Return)
+    }
+
+    fn op_pass(
+        &mut self,
+        _params: &[usize],
+        _targets: &[usize],
+        _vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        Ok(OpAction::None)
+    }
+
+    fn op_dbgi(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let v = vm.state().load_data::<i16>(params[0])?;
+        println!("{}", v);
+        Ok(OpAction::None)
+    }
+
+    fn op_dbgs(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let v = load_cstring(params[0], vm)?;
+        println!("{}", v);
+        Ok(OpAction::None)
+    }
+
+    fn op_dbgp(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let p = vm.state().load_usize(params[0])?;
+        println!("{:#X} ({})", p, p);
+        Ok(OpAction::None)
+    }
+
+    fn op_dbgm(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_usize(params[0])?;
+        let s = vm.state().load_data::<i16>(params[1])? as usize;
+        println!("{:?}", vm.state().load_bytes(a, s)?);
+        Ok(OpAction::None)
+    }
+
+    fn op_test(
+        &mut self,
+        _params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        println!("<stack: {}>", vm.state().stack_pos());
+        Ok(OpAction::None)
+    }
+
+    fn op_cstp(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_usize(params[0])?;
+        vm.state_mut().store_usize(targets[0], a)?;
+        Ok(OpAction::None)
+    }
+
+    fn op_poff(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_usize(params[0])?;
+        let o = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut()
+            .store_usize(targets[0], (a as isize + o as isize) as usize)?;
+        Ok(OpAction::None)
+    }
+
+    fn op_i2b(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let v = vm.state().load_data::<i16>(params[0])?;
+        vm.state_mut().store_data(targets[0], &(v as u8))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_b2i(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let v = vm.state().load_data::<u8>(params[0])?;
+        vm.state_mut().store_data(targets[0], &i16::from(v))?;
+        Ok(OpAction::None)
     }
 
-    pub fn with_mut<T, F>(mut cb: F) -> T
-    where
-        F: FnMut(&mut Self) -> T,
-    {
-        cb(&mut PROC.lock().unwrap())
+    fn op_allc(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let size = vm.state().load_data::<i16>(params[0])? as usize;
+        let v = vm.state_mut().alloc_memory_value(size)?;
+        vm.state_mut().store_usize(targets[0], v.address)?;
+        Ok(OpAction::None)
+    }
+
+    fn op_free(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let address = vm.state().load_usize(params[0])?;
+        vm.state_mut().dealloc_memory(address)?;
+        Ok(OpAction::None)
+    }
+
+    fn op_mode(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let f = vm.state().load_usize(params[0])?;
+        self.modes = f as u8;
+        Ok(OpAction::None)
+    }
+
+    fn op_inp(
+        &mut self,
+        _params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let input = self.input;
+        vm.state_mut().store_data(targets[0], &input)?;
+        Ok(OpAction::None)
+    }
+
+    fn op_data(
+        &mut self,
+        _params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let address = self.data_block.address;
+        vm.state_mut().store_usize(targets[0], address)?;
+        Ok(OpAction::None)
+    }
+
+    fn op_tls(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let tile = vm.state().load_data::<i16>(params[0])? as usize;
+        if tile >= self.tiles.len() {
+            return Err(SimpleError::new(format!(
+                "Trying to access tile out of bounds: {}",
+                tile,
+            )));
+        }
+        let col = vm.state().load_data::<i16>(params[1])? as usize;
+        let row = vm.state().load_data::<i16>(params[2])? as usize;
+        self.tiles[row * self.tcols + col] = tile;
+        Ok(OpAction::None)
+    }
+
+    fn op_tln(
+        &mut self,
+        _params: &[usize],
+        _targets: &[usize],
+        _vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        self.blending = Blending::None;
+        Ok(OpAction::None)
+    }
+
+    fn op_tlb(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let xr = vm.state().load_data::<i16>(params[0])?;
+        let gb = vm.state().load_data::<i16>(params[1])?;
+        let r = (xr & 0xFF) as u8;
+        let g = (gb >> 8) as u8;
+        let b = (gb & 0xFF) as u8;
+        self.blending = Blending::Key(Color { r, g, b });
+        Ok(OpAction::None)
+    }
+
+    fn op_tlv(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let x = vm.state().load_data::<i16>(params[0])? as isize;
+        let y = vm.state().load_data::<i16>(params[1])? as isize;
+        self.tiles_viewport = (x, y);
+        Ok(OpAction::None)
+    }
+
+    fn op_objs(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let index = vm.state().load_data::<i16>(params[0])? as usize;
+        if index >= self.objects.len() {
+            return Err(SimpleError::new(format!(
+                "Trying to access object out of bounds: {}",
+                index,
+            )));
+        }
+        let sprite = vm.state().load_data::<i16>(params[1])? as usize;
+        self.objects[index].index = sprite;
+        Ok(OpAction::None)
+    }
+
+    fn op_objp(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let index = vm.state().load_data::<i16>(params[0])? as usize;
+        if index >= self.objects.len() {
+            return Err(SimpleError::new(format!(
+                "Trying to access object out of bounds: {}",
+                index,
+            )));
+        }
+        let x = vm.state().load_data::<i16>(params[1])?;
+        let y = vm.state().load_data::<i16>(params[2])?;
+        self.objects[index].x = x as isize;
+        self.objects[index].y = y as isize;
+        Ok(OpAction::None)
+    }
+
+    fn op_objn(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let index = vm.state().load_data::<i16>(params[0])? as usize;
+        if index >= self.objects.len() {
+            return Err(SimpleError::new(format!(
+                "Trying to access object out of bounds: {}",
+                index,
+            )));
+        }
+        self.objects[index].blending = Blending::None;
+        Ok(OpAction::None)
+    }
+
+    fn op_objb(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let index = vm.state().load_data::<i16>(params[0])? as usize;
+        if index >= self.objects.len() {
+            return Err(SimpleError::new(format!(
+                "Trying to access object out of bounds: {}",
+                index,
+            )));
+        }
+        let xr = vm.state().load_data::<i16>(params[1])?;
+        let gb = vm.state().load_data::<i16>(params[2])?;
+        let r = (xr & 0xFF) as u8;
+        let g = (gb >> 8) as u8;
+        let b = (gb & 0xFF) as u8;
+        self.objects[index].blending = Blending::Key(Color { r, g, b });
+        Ok(OpAction::None)
+    }
+
+    fn op_objv(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let x = vm.state().load_data::<i16>(params[0])? as isize;
+        let y = vm.state().load_data::<i16>(params[1])? as isize;
+        self.objects_viewport = (x, y);
+        Ok(OpAction::None)
+    }
+
+    fn op_std(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let address = vm.state().load_usize(params[0])?;
+        let size = vm.state().load_data::<i16>(params[1])? as usize;
+        let bytes = vm.state().load_bytes(address, size)?;
+        write(&self.save_file, &bytes)?;
+        Ok(OpAction::None)
+    }
+
+    fn op_ldd(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let address = vm.state().load_usize(params[0])?;
+        let size = vm.state().load_data::<i16>(params[1])? as usize;
+        let bytes = read(&self.save_file)?;
+        vm.state_mut().store_bytes(address, &bytes[0..size])?;
+        Ok(OpAction::None)
+    }
+
+    fn op_hd(
+        &mut self,
+        _params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let exists = Path::new(&self.save_file).is_file();
+        vm.state_mut()
+            .store_data(targets[0], if exists { &1i16 } else { &0i16 })?;
+        Ok(OpAction::None)
+    }
+
+    fn op_bgc(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let xr = vm.state().load_data::<i16>(params[0])?;
+        let gb = vm.state().load_data::<i16>(params[1])?;
+        let color = gb as u32 | ((xr as u32) << 16);
+        self.bg_color = color;
+        Ok(OpAction::None)
+    }
+
+    fn op_fgc(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let xr = vm.state().load_data::<i16>(params[0])? as u32;
+        let gb = vm.state().load_data::<i16>(params[1])? as u32;
+        let color = gb | (xr << 16);
+        self.fnt_color = color;
+        Ok(OpAction::None)
+    }
+
+    fn op_chr(
+        &mut self,
+        params: &[usize],
+        _targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let c = vm.state().load_data::<u8>(params[0])?;
+        let x = vm.state().load_data::<i16>(params[1])? as usize;
+        let y = vm.state().load_data::<i16>(params[2])? as usize;
+        self.text_buffer[y * self.text_cols + x] = c;
+        Ok(OpAction::None)
+    }
+
+    fn op_kcc(
+        &mut self,
+        _params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let c = self.chars_pressed.len() as i16;
+        vm.state_mut().store_data(targets[0], &c)?;
+        Ok(OpAction::None)
+    }
+
+    fn op_gkc(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let index = vm.state().load_data::<i16>(params[0])?;
+        let c = self.chars_pressed[index as usize];
+        vm.state_mut().store_data(targets[0], &c)?;
+        Ok(OpAction::None)
+    }
+
+    fn op_ovlp(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<(i16, i16, i16, i16)>(params[0])?;
+        let b = vm.state().load_data::<(i16, i16, i16, i16)>(params[1])?;
+        vm.state_mut()
+            .store_data(targets[0], &if overlap(a, b) { 1i16 } else { 0i16 })?;
+        Ok(OpAction::None)
+    }
+
+    fn op_add(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut().store_data(targets[0], &(a + b))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_sub(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut().store_data(targets[0], &(a - b))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_mul(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut().store_data(targets[0], &(a * b))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_div(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut().store_data(targets[0], &(a / b))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_mod(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut().store_data(targets[0], &(a % b))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_mov(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let v = vm.state().load_data::<i16>(params[0])?;
+        vm.state_mut().store_data(targets[0], &v)?;
+        Ok(OpAction::None)
+    }
+
+    fn op_eq(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut()
+            .store_data(targets[0], if a == b { &1i16 } else { &0i16 })?;
+        Ok(OpAction::None)
+    }
+
+    fn op_nq(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut()
+            .store_data(targets[0], if a != b { &1i16 } else { &0i16 })?;
+        Ok(OpAction::None)
+    }
+
+    fn op_gt(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut()
+            .store_data(targets[0], if a > b { &1i16 } else { &0i16 })?;
+        Ok(OpAction::None)
+    }
+
+    fn op_lt(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut()
+            .store_data(targets[0], if a < b { &1i16 } else { &0i16 })?;
+        Ok(OpAction::None)
+    }
+
+    fn op_ge(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut()
+            .store_data(targets[0], if a >= b { &1i16 } else { &0i16 })?;
+        Ok(OpAction::None)
+    }
+
+    fn op_le(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut()
+            .store_data(targets[0], if a <= b { &1i16 } else { &0i16 })?;
+        Ok(OpAction::None)
+    }
+
+    fn op_lsh(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut().store_data(targets[0], &(a << b))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_rsh(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut().store_data(targets[0], &(a >> b))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_and(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut().store_data(targets[0], &(a & b))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_or(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut().store_data(targets[0], &(a | b))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_xor(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let a = vm.state().load_data::<i16>(params[0])?;
+        let b = vm.state().load_data::<i16>(params[1])?;
+        vm.state_mut().store_data(targets[0], &(a ^ b))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_neg(
+        &mut self,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let v = vm.state().load_data::<i16>(params[0])?;
+        vm.state_mut().store_data(targets[0], &(!v))?;
+        Ok(OpAction::None)
+    }
+
+    fn op_rnd(
+        &mut self,
+        _params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        vm.state_mut().store_data::<i16>(targets[0], &random())?;
+        Ok(OpAction::None)
     }
 }
 
 impl VmProcessor for Processor {
-    #[allow(clippy::ptr_arg)]
+    fn bind_op(&mut self, op: &str) -> Option<HandlerId> {
+        self.ops.find(op)
+    }
+
     fn process_op(
-        op: &String,
+        &mut self,
+        handler: HandlerId,
         params: &[usize],
         targets: &[usize],
         vm: &mut Vm,
     ) -> SimpleResult<OpAction> {
-        match op.as_str() {
-            "halt" => {
-                Self::with_mut(|p| p.halt = true);
-                Ok(OpAction::None)
-            }
-            "goto" => {
-                let v = load_cstring(params[0], vm)?;
-                if let Some(pos) = vm.find_label(&v) {
-                    Ok(OpAction::GoTo(pos))
-                } else {
-                    Err(SimpleError::new(format!(
-                        "Function does not have `{}` label",
-                        v
-                    )))
-                }
-            }
-            "if" => {
-                let v = vm.state().load_data::<i16>(params[0])?;
-                let th = load_cstring(params[1], vm)?;
-                let th = if let Some(pos) = vm.find_label(&th) {
-                    pos
-                } else {
-                    return Err(SimpleError::new(format!(
-                        "Function does not have `{}` label",
-                        v
-                    )));
-                };
-                let el = load_cstring(params[2], vm)?;
-                let el = if let Some(pos) = vm.find_label(&el) {
-                    pos
-                } else {
-                    return Err(SimpleError::new(format!(
-                        "Function does not have `{}` label",
-                        v
-                    )));
-                };
-                Ok(OpAction::GoTo(if v != 0 { th } else { el }))
-            }
-            "ret" => Ok(OpAction::Return),
-            "pass" => Ok(OpAction::None),
-            "dbgi" => {
-                let v = vm.state().load_data::<i16>(params[0])?;
-                println!("{}", v);
-                Ok(OpAction::None)
-            }
-            "dbgs" => {
-                let v = load_cstring(params[0], vm)?;
-                println!("{}", v);
-                Ok(OpAction::None)
-            }
-            "dbgp" => {
-                let p = vm.state().load_data::<usize>(params[0])?;
-                println!("{:#X} ({})", p, p);
-                Ok(OpAction::None)
-            }
-            "dbgm" => {
-                let a = vm.state().load_data::<usize>(params[0])?;
-                let s = vm.state().load_data::<i16>(params[1])? as usize;
-                println!("{:?}", vm.state().load_bytes(a, s)?);
-                Ok(OpAction::None)
-            }
-            "test" => {
-                println!("<stack: {}>", vm.state().stack_pos());
-                Ok(OpAction::None)
-            }
-            "cstp" => {
-                let a = vm.state().load_data::<usize>(params[0])?;
-                vm.state_mut().store_data(targets[0], &a)?;
-                Ok(OpAction::None)
-            }
-            "poff" => {
-                let a = vm.state().load_data::<usize>(params[0])?;
-                let o = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut()
-                    .store_data(targets[0], &((a as isize + o as isize) as usize))?;
-                Ok(OpAction::None)
-            }
-            "i2b" => {
-                let v = vm.state().load_data::<i16>(params[0])?;
-                vm.state_mut().store_data(targets[0], &(v as u8))?;
-                Ok(OpAction::None)
-            }
-            "b2i" => {
-                let v = vm.state().load_data::<u8>(params[0])?;
-                vm.state_mut().store_data(targets[0], &i16::from(v))?;
-                Ok(OpAction::None)
-            }
-            "allc" => {
-                let size = vm.state().load_data::<i16>(params[0])? as usize;
-                let v = vm.state_mut().alloc_memory_value(size + 2)?;
-                vm.state_mut().store_data(v.address, &(v.size as u16))?;
-                vm.state_mut().store_data(targets[0], &(v.address + 2))?;
-                Ok(OpAction::None)
-            }
-            "free" => {
-                let address = vm.state().load_data::<usize>(params[0])?;
-                let size = vm.state().load_data::<u16>(address - 2)? + 2;
-                vm.state_mut()
-                    .dealloc_memory_value(&Value::new(address - 2, size as usize))?;
-                Ok(OpAction::None)
-            }
-            "mode" => {
-                let f = vm.state().load_data::<usize>(params[0])?;
-                Self::with_mut(|p| p.modes = f as u8);
-                Ok(OpAction::None)
-            }
-            "inp" => {
-                let input = Self::with_mut(|p| p.input);
-                vm.state_mut().store_data(targets[0], &input)?;
-                Ok(OpAction::None)
-            }
-            "data" => {
-                let address = Self::with_mut(|p| p.data_block.address);
-                vm.state_mut().store_data(targets[0], &address)?;
-                Ok(OpAction::None)
-            }
-            "tls" => {
-                let tile = vm.state().load_data::<i16>(params[0])? as usize;
-                if tile >= Self::with(|p| p.tiles.len()) {
-                    return Err(SimpleError::new(format!(
-                        "Trying to access tile out of bounds: {}",
-                        tile,
-                    )));
-                }
-                let col = vm.state().load_data::<i16>(params[1])? as usize;
-                let row = vm.state().load_data::<i16>(params[2])? as usize;
-                Self::with_mut(|p| p.tiles[row * p.tcols + col] = tile as usize);
-                Ok(OpAction::None)
-            }
-            "tln" => {
-                Self::with_mut(|p| p.blending = Blending::None);
-                Ok(OpAction::None)
-            }
-            "tlb" => {
-                let xr = vm.state().load_data::<i16>(params[0])?;
-                let gb = vm.state().load_data::<i16>(params[1])?;
-                let r = (xr & 0xFF) as u8;
-                let g = (gb >> 8) as u8;
-                let b = (gb & 0xFF) as u8;
-                Self::with_mut(|p| p.blending = Blending::Key(Color { r, g, b }));
-                Ok(OpAction::None)
-            }
-            "tlv" => {
-                let x = vm.state().load_data::<i16>(params[0])? as isize;
-                let y = vm.state().load_data::<i16>(params[1])? as isize;
-                Self::with_mut(|p| p.tiles_viewport = (x, y));
-                Ok(OpAction::None)
-            }
-            "objs" => {
-                let index = vm.state().load_data::<i16>(params[0])? as usize;
-                if index >= Self::with(|p| p.objects.len()) {
-                    return Err(SimpleError::new(format!(
-                        "Trying to access object out of bounds: {}",
-                        index,
-                    )));
-                }
-                let sprite = vm.state().load_data::<i16>(params[1])? as usize;
-                Self::with_mut(|p| p.objects[index].index = sprite as usize);
-                Ok(OpAction::None)
-            }
-            "objp" => {
-                let index = vm.state().load_data::<i16>(params[0])? as usize;
-                if index >= Self::with(|p| p.objects.len()) {
-                    return Err(SimpleError::new(format!(
-                        "Trying to access object out of bounds: {}",
-                        index,
-                    )));
-                }
-                let x = vm.state().load_data::<i16>(params[1])?;
-                let y = vm.state().load_data::<i16>(params[2])?;
-                Self::with_mut(|p| {
-                    p.objects[index].x = x as isize;
-                    p.objects[index].y = y as isize;
-                });
-                Ok(OpAction::None)
-            }
-            "objn" => {
-                let index = vm.state().load_data::<i16>(params[0])? as usize;
-                if index >= Self::with(|p| p.objects.len()) {
-                    return Err(SimpleError::new(format!(
-                        "Trying to access object out of bounds: {}",
-                        index,
-                    )));
-                }
-                Self::with_mut(|p| p.objects[index].blending = Blending::None);
-                Ok(OpAction::None)
-            }
-            "objb" => {
-                let index = vm.state().load_data::<i16>(params[0])? as usize;
-                if index >= Self::with(|p| p.objects.len()) {
-                    return Err(SimpleError::new(format!(
-                        "Trying to access object out of bounds: {}",
-                        index,
-                    )));
-                }
-                let xr = vm.state().load_data::<i16>(params[1])?;
-                let gb = vm.state().load_data::<i16>(params[2])?;
-                let r = (xr & 0xFF) as u8;
-                let g = (gb >> 8) as u8;
-                let b = (gb & 0xFF) as u8;
-                Self::with_mut(|p| p.objects[index].blending = Blending::Key(Color { r, g, b }));
-                Ok(OpAction::None)
-            }
-            "objv" => {
-                let x = vm.state().load_data::<i16>(params[0])? as isize;
-                let y = vm.state().load_data::<i16>(params[1])? as isize;
-                Self::with_mut(|p| p.objects_viewport = (x, y));
-                Ok(OpAction::None)
-            }
-            "std" => {
-                let address = vm.state().load_data::<usize>(params[0])?;
-                let size = vm.state().load_data::<i16>(params[1])? as usize;
-                let bytes = vm.state().load_bytes(address, size)?;
-                Self::with_mut(|p| write(&p.save_file, &bytes))?;
-                Ok(OpAction::None)
-            }
-            "ldd" => {
-                let address = vm.state().load_data::<usize>(params[0])?;
-                let size = vm.state().load_data::<i16>(params[1])? as usize;
-                let bytes = Self::with_mut(|p| read(&p.save_file))?;
-                vm.state_mut().store_bytes(address, &bytes[0..size])?;
-                Ok(OpAction::None)
-            }
-            "hd" => {
-                let exists = Self::with_mut(|p| Path::new(&p.save_file).is_file());
-                vm.state_mut()
-                    .store_data(targets[0], if exists { &1i16 } else { &0i16 })?;
-                Ok(OpAction::None)
-            }
-            "bgc" => {
-                let xr = vm.state().load_data::<i16>(params[0])?;
-                let gb = vm.state().load_data::<i16>(params[1])?;
-                let color = gb as u32 | ((xr as u32) << 16);
-                Self::with_mut(|p| p.bg_color = color);
-                Ok(OpAction::None)
-            }
-            "fgc" => {
-                let xr = vm.state().load_data::<i16>(params[0])? as u32;
-                let gb = vm.state().load_data::<i16>(params[1])? as u32;
-                let color = gb as u32 | ((xr as u32) << 16);
-                Self::with_mut(|p| p.fnt_color = color);
-                Ok(OpAction::None)
-            }
-            "chr" => {
-                let c = vm.state().load_data::<u8>(params[0])?;
-                let x = vm.state().load_data::<i16>(params[1])? as usize;
-                let y = vm.state().load_data::<i16>(params[2])? as usize;
-                Self::with_mut(|p| p.text_buffer[y * p.text_cols + x] = c);
-                Ok(OpAction::None)
-            }
-            "kcc" => {
-                let c = Self::with(|p| p.chars_pressed.len() as i16);
-                vm.state_mut().store_data(targets[0], &c)?;
-                Ok(OpAction::None)
-            }
-            "gkc" => {
-                let index = vm.state().load_data::<i16>(params[0])?;
-                let c = Self::with(|p| p.chars_pressed[index as usize]);
-                vm.state_mut().store_data(targets[0], &c)?;
-                Ok(OpAction::None)
-            }
-            "ovlp" => {
-                let a = vm.state().load_data::<(i16, i16, i16, i16)>(params[0])?;
-                let b = vm.state().load_data::<(i16, i16, i16, i16)>(params[1])?;
-                vm.state_mut()
-                    .store_data(targets[0], &if overlap(a, b) { 1i16 } else { 0i16 })?;
-                Ok(OpAction::None)
-            }
-            "add" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut().store_data(targets[0], &(a + b))?;
-                Ok(OpAction::None)
-            }
-            "sub" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut().store_data(targets[0], &(a - b))?;
-                Ok(OpAction::None)
-            }
-            "mul" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut().store_data(targets[0], &(a * b))?;
-                Ok(OpAction::None)
-            }
-            "div" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut().store_data(targets[0], &(a / b))?;
-                Ok(OpAction::None)
-            }
-            "mod" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut().store_data(targets[0], &(a % b))?;
-                Ok(OpAction::None)
-            }
-            "mov" => {
-                let v = vm.state().load_data::<i16>(params[0])?;
-                vm.state_mut().store_data(targets[0], &v)?;
-                Ok(OpAction::None)
-            }
-            "eq" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut()
-                    .store_data(targets[0], if a == b { &1i16 } else { &0i16 })?;
-                Ok(OpAction::None)
-            }
-            "nq" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut()
-                    .store_data(targets[0], if a != b { &1i16 } else { &0i16 })?;
-                Ok(OpAction::None)
-            }
-            "gt" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut()
-                    .store_data(targets[0], if a > b { &1i16 } else { &0i16 })?;
-                Ok(OpAction::None)
-            }
-            "lt" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut()
-                    .store_data(targets[0], if a < b { &1i16 } else { &0i16 })?;
-                Ok(OpAction::None)
-            }
-            "ge" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut()
-                    .store_data(targets[0], if a >= b { &1i16 } else { &0i16 })?;
-                Ok(OpAction::None)
-            }
-            "le" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut()
-                    .store_data(targets[0], if a <= b { &1i16 } else { &0i16 })?;
-                Ok(OpAction::None)
-            }
-            "lsh" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut().store_data(targets[0], &(a << b))?;
-                Ok(OpAction::None)
-            }
-            "rsh" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut().store_data(targets[0], &(a >> b))?;
-                Ok(OpAction::None)
-            }
-            "and" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut().store_data(targets[0], &(a & b))?;
-                Ok(OpAction::None)
-            }
-            "or" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut().store_data(targets[0], &(a | b))?;
-                Ok(OpAction::None)
-            }
-            "xor" => {
-                let a = vm.state().load_data::<i16>(params[0])?;
-                let b = vm.state().load_data::<i16>(params[1])?;
-                vm.state_mut().store_data(targets[0], &(a ^ b))?;
-                Ok(OpAction::None)
-            }
-            "neg" => {
-                let v = vm.state().load_data::<i16>(params[0])?;
-                vm.state_mut().store_data(targets[0], &(!v))?;
-                Ok(OpAction::None)
-            }
-            "rnd" => {
-                vm.state_mut().store_data::<i16>(targets[0], &random())?;
-                Ok(OpAction::None)
-            }
-            _ => Err(SimpleError::new(format!("Unsupported op: {}", op))),
-        }
+        let handler = self.ops.handler(handler).unwrap();
+        handler(self, params, targets, vm)
     }
 }
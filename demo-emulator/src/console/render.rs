@@ -21,7 +21,7 @@ pub fn draw_character(
             let y = row * 8 + sy;
             for sx in 0..8 {
                 let x = col * 8 + sx;
-                let pos = (y * w + x) as usize;
+                let pos = y * w + x;
                 if character[sy * 8 + sx] > 0 {
                     target[pos] = color;
                 }
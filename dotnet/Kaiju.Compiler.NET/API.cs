@@ -4,11 +4,35 @@ using System.Runtime.InteropServices;
 
 namespace Kaiju.Compiler
 {
+    public enum Severity
+    {
+        Error,
+        Warning,
+        Note
+    }
+
+    public class Diagnostic
+    {
+        public string Code;
+        public Severity Severity;
+        public string Message;
+        public string Help;
+        public string Path;
+        public uint Line;
+        public uint Column;
+        public string Pretty;
+
+        public override string ToString()
+        {
+            return Pretty;
+        }
+    }
+
     public static class API
     {
-        public delegate void OnError(string error);
+        public delegate void OnDiagnostic(Diagnostic diagnostic);
 
-        public static byte[] CompilePst(string inputPath, string opsdescPath, bool pretty, Dictionary<string, byte[]> files, OnError onError = null)
+        public static byte[] CompilePst(string inputPath, string opsdescPath, bool pretty, Dictionary<string, byte[]> files, OnDiagnostic onDiagnostic = null)
         {
             var ptrs = new Dictionary<string, IntPtr>();
             byte[] result = null;
@@ -37,13 +61,19 @@ namespace Kaiju.Compiler
                         Marshal.Copy(bytes, result, 0, (int)size);
                     },
                     IntPtr.Zero,
-                    (context, error) => onError?.Invoke(error),
+                    (context, diagnostics, count) => ReportDiagnostics(diagnostics, count, onDiagnostic),
                     IntPtr.Zero
                 );
             }
             catch (Exception error)
             {
-                onError?.Invoke(error.Message);
+                onDiagnostic?.Invoke(new Diagnostic
+                {
+                    Code = "E0000",
+                    Severity = Severity.Error,
+                    Message = error.Message,
+                    Pretty = error.Message
+                });
             }
             finally
             {
@@ -55,7 +85,7 @@ namespace Kaiju.Compiler
             return result;
         }
 
-        public static byte[] CompileBin(string inputPath, string opsdescPath, Dictionary<string, byte[]> files, OnError onError = null)
+        public static byte[] CompileBin(string inputPath, string opsdescPath, Dictionary<string, byte[]> files, OnDiagnostic onDiagnostic = null, bool debugInfo = false)
         {
             var ptrs = new Dictionary<string, IntPtr>(files.Count);
             byte[] result = null;
@@ -64,6 +94,7 @@ namespace Kaiju.Compiler
                 NAPI.CompileBin(
                     inputPath,
                     opsdescPath,
+                    debugInfo,
                     (IntPtr context, string path, ref UIntPtr outSize) =>
                     {
                         IntPtr ptr = IntPtr.Zero;
@@ -83,13 +114,19 @@ namespace Kaiju.Compiler
                         Marshal.Copy(bytes, result, 0, (int)size);
                     },
                     IntPtr.Zero,
-                    (context, error) => onError?.Invoke(error),
+                    (context, diagnostics, count) => ReportDiagnostics(diagnostics, count, onDiagnostic),
                     IntPtr.Zero
                 );
             }
             catch (Exception error)
             {
-                onError?.Invoke(error.Message);
+                onDiagnostic?.Invoke(new Diagnostic
+                {
+                    Code = "E0000",
+                    Severity = Severity.Error,
+                    Message = error.Message,
+                    Pretty = error.Message
+                });
             }
             finally
             {
@@ -100,5 +137,29 @@ namespace Kaiju.Compiler
             }
             return result;
         }
+
+        private static void ReportDiagnostics(IntPtr diagnostics, UIntPtr count, OnDiagnostic onDiagnostic)
+        {
+            if (onDiagnostic == null)
+            {
+                return;
+            }
+            var size = Marshal.SizeOf<NAPI.Diagnostic>();
+            for (var i = 0; i < (int)count; ++i)
+            {
+                var item = Marshal.PtrToStructure<NAPI.Diagnostic>(diagnostics + i * size);
+                onDiagnostic(new Diagnostic
+                {
+                    Code = Marshal.PtrToStringAnsi(item.code),
+                    Severity = (Severity)item.severity,
+                    Message = Marshal.PtrToStringAnsi(item.message),
+                    Help = item.help == IntPtr.Zero ? null : Marshal.PtrToStringAnsi(item.help),
+                    Path = item.path == IntPtr.Zero ? null : Marshal.PtrToStringAnsi(item.path),
+                    Line = (uint)item.line,
+                    Column = (uint)item.column,
+                    Pretty = Marshal.PtrToStringAnsi(item.pretty)
+                });
+            }
+        }
     }
 }
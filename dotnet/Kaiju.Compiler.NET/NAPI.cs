@@ -23,13 +23,30 @@ namespace Kaiju.Compiler
             UIntPtr size
         );
 
+        [StructLayout(LayoutKind.Sequential)]
+        public struct Diagnostic
+        {
+            public IntPtr code;
+            public byte severity;
+            public IntPtr message;
+            public IntPtr help;
+            public IntPtr path;
+            public UIntPtr line;
+            public UIntPtr column;
+            public UIntPtr start;
+            public UIntPtr end;
+            public IntPtr pretty;
+        }
+
         [UnmanagedFunctionPointer(LibCall)]
-        public delegate void OnError(
+        public delegate void OnDiagnostics(
             IntPtr context,
-            [MarshalAs(UnmanagedType.LPStr)]
-            string error
+            IntPtr diagnostics,
+            UIntPtr count
         );
 
+        // Callbacks may be null: compilation then fails, reporting it to onDiagnostics and
+        // empty result to onResultFile if they are given.
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_compile_program_pst", CharSet = CharSet.Ansi)]
         [return: MarshalAs(UnmanagedType.I1)]
         public extern static bool CompilePst(
@@ -46,8 +63,8 @@ namespace Kaiju.Compiler
             OnResultFile onResultFile,
             IntPtr resultContext,
             [MarshalAs(UnmanagedType.FunctionPtr)]
-            OnError onError,
-            IntPtr errorContext
+            OnDiagnostics onDiagnostics,
+            IntPtr diagnosticsContext
         );
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_compile_program_bin", CharSet = CharSet.Ansi)]
@@ -57,6 +74,8 @@ namespace Kaiju.Compiler
             string inputPath,
             [MarshalAs(UnmanagedType.LPStr)]
             string opsdescPath,
+            [MarshalAs(UnmanagedType.I1)]
+            bool debugInfo,
             [MarshalAs(UnmanagedType.FunctionPtr)]
             OnServeFile onServeFile,
             IntPtr serveContext,
@@ -64,8 +83,8 @@ namespace Kaiju.Compiler
             OnResultFile onResultFile,
             IntPtr resultContext,
             [MarshalAs(UnmanagedType.FunctionPtr)]
-            OnError onError,
-            IntPtr errorContext
+            OnDiagnostics onDiagnostics,
+            IntPtr diagnosticsContext
         );
     }
 }
@@ -1,4 +1,6 @@
 ﻿using System;
+using System.Collections.Generic;
+using System.Linq;
 using System.Runtime.InteropServices;
 using System.Text;
 
@@ -7,14 +9,27 @@ namespace Kaiju.VM
     public static class API
     {
         public delegate void OnError(string error);
-        public delegate void OnProcessOp(string op, UIntPtr[] paramsPtrs, UIntPtr[] targetsPtrs);
-        public delegate void OnPerform();
+        public delegate bool OnBindOp(string op);
+        public delegate void OnProcessOp(IntPtr kaiju, string op, UIntPtr[] paramsPtrs, UIntPtr[] targetsPtrs);
+        public delegate void OnPerform(IntPtr kaiju);
+        public delegate bool OnCallNative(IntPtr kaiju, UIntPtr[] paramsPtrs, UIntPtr? resultPtr);
 
-        public static bool Run(byte[] bytes, string entry, uint memSize, uint stackSize, OnProcessOp onProcessOp, OnError onError = null)
+        public class Native
+        {
+            public string Module;
+            public string Function;
+            public OnCallNative OnCall;
+        }
+
+        // Natives of running programs must stay alive for as long as VM can call them.
+        private static readonly Dictionary<UIntPtr, NAPI.KaijuNative[]> ProgramNatives = new Dictionary<UIntPtr, NAPI.KaijuNative[]>();
+
+        public static bool Run(byte[] bytes, string entry, uint memSize, uint stackSize, OnBindOp onBindOp, OnProcessOp onProcessOp, OnError onError = null, Native[] natives = null)
         {
             IntPtr buffer = Marshal.AllocHGlobal(bytes.Length);
             Marshal.Copy(bytes, 0, buffer, bytes.Length);
             var result = false;
+            var nativesTable = MakeNatives(natives);
             try
             {
                 result = NAPI.Run(
@@ -23,9 +38,12 @@ namespace Kaiju.VM
                     entry,
                     (UIntPtr)memSize,
                     (UIntPtr)stackSize,
-                    (context, op, paramsPtrs, paramsCount, targetsPtrs, targetsCount) =>
+                    nativesTable,
+                    (UIntPtr)nativesTable.Length,
+                    (context, op) => onBindOp != null && onBindOp(op),
+                    (kaiju, context, op, paramsPtrs, paramsCount, targetsPtrs, targetsCount) =>
                     {
-                        onProcessOp?.Invoke(op, MakePointers(paramsPtrs, paramsCount), MakePointers(targetsPtrs, targetsCount));
+                        onProcessOp?.Invoke(kaiju, op, MakePointers(paramsPtrs, paramsCount), MakePointers(targetsPtrs, targetsCount));
                     },
                     IntPtr.Zero,
                     (context, error) => onError?.Invoke(error),
@@ -39,15 +57,17 @@ namespace Kaiju.VM
             finally
             {
                 Marshal.FreeHGlobal(buffer);
+                GC.KeepAlive(nativesTable);
             }
             return result;
         }
 
-        public static UIntPtr Start(byte[] bytes, string entry, uint memSize, uint stackSize, OnError onError = null)
+        public static UIntPtr Start(byte[] bytes, string entry, uint memSize, uint stackSize, OnBindOp onBindOp, OnError onError = null, Native[] natives = null)
         {
             IntPtr buffer = Marshal.AllocHGlobal(bytes.Length);
             Marshal.Copy(bytes, 0, buffer, bytes.Length);
             var result = UIntPtr.Zero;
+            var nativesTable = MakeNatives(natives);
             try
             {
                 result = NAPI.Start(
@@ -56,9 +76,14 @@ namespace Kaiju.VM
                     entry,
                     (UIntPtr)memSize,
                     (UIntPtr)stackSize,
+                    nativesTable,
+                    (UIntPtr)nativesTable.Length,
+                    (context, op) => onBindOp != null && onBindOp(op),
+                    IntPtr.Zero,
                     (context, error) => onError?.Invoke(error),
                     IntPtr.Zero
                 );
+                KeepNatives(result, nativesTable);
             }
             catch (Exception error)
             {
@@ -78,9 +103,33 @@ namespace Kaiju.VM
             {
                 result = NAPI.Resume(
                     handle,
-                    (context, op, paramsPtrs, paramsCount, targetsPtrs, targetsCount) =>
+                    (kaiju, context, op, paramsPtrs, paramsCount, targetsPtrs, targetsCount) =>
                     {
-                        onProcessOp?.Invoke(op, MakePointers(paramsPtrs, paramsCount), MakePointers(targetsPtrs, targetsCount));
+                        onProcessOp?.Invoke(kaiju, op, MakePointers(paramsPtrs, paramsCount), MakePointers(targetsPtrs, targetsCount));
+                    },
+                    IntPtr.Zero,
+                    (context, error) => onError?.Invoke(error),
+                    IntPtr.Zero
+                );
+            }
+            catch (Exception error)
+            {
+                onError?.Invoke(error.Message);
+            }
+            return result;
+        }
+
+        public static NAPI.ResumeStatus ResumeBudget(UIntPtr handle, uint budget, OnProcessOp onProcessOp, OnError onError = null)
+        {
+            var result = NAPI.ResumeStatus.Error;
+            try
+            {
+                result = NAPI.ResumeBudget(
+                    handle,
+                    (UIntPtr)budget,
+                    (kaiju, context, op, paramsPtrs, paramsCount, targetsPtrs, targetsCount) =>
+                    {
+                        onProcessOp?.Invoke(kaiju, op, MakePointers(paramsPtrs, paramsCount), MakePointers(targetsPtrs, targetsCount));
                     },
                     IntPtr.Zero,
                     (context, error) => onError?.Invoke(error),
@@ -101,25 +150,103 @@ namespace Kaiju.VM
             {
                 result = NAPI.Consume(
                     handle,
-                    (context, op, paramsPtrs, paramsCount, targetsPtrs, targetsCount) =>
+                    (kaiju, context, op, paramsPtrs, paramsCount, targetsPtrs, targetsCount) =>
+                    {
+                        onProcessOp?.Invoke(kaiju, op, MakePointers(paramsPtrs, paramsCount), MakePointers(targetsPtrs, targetsCount));
+                    },
+                    IntPtr.Zero,
+                    (context, error) => onError?.Invoke(error),
+                    IntPtr.Zero
+                );
+            }
+            catch (Exception error)
+            {
+                onError?.Invoke(error.Message);
+            }
+            return result;
+        }
+
+        public static UIntPtr Load(byte[] bytes, uint memSize, uint stackSize, OnBindOp onBindOp, OnError onError = null, Native[] natives = null)
+        {
+            IntPtr buffer = Marshal.AllocHGlobal(bytes.Length);
+            Marshal.Copy(bytes, 0, buffer, bytes.Length);
+            var result = UIntPtr.Zero;
+            var nativesTable = MakeNatives(natives);
+            try
+            {
+                result = NAPI.Load(
+                    buffer,
+                    (UIntPtr)bytes.Length,
+                    (UIntPtr)memSize,
+                    (UIntPtr)stackSize,
+                    nativesTable,
+                    (UIntPtr)nativesTable.Length,
+                    (context, op) => onBindOp != null && onBindOp(op),
+                    IntPtr.Zero,
+                    (context, error) => onError?.Invoke(error),
+                    IntPtr.Zero
+                );
+                KeepNatives(result, nativesTable);
+            }
+            catch (Exception error)
+            {
+                onError?.Invoke(error.Message);
+            }
+            finally
+            {
+                Marshal.FreeHGlobal(buffer);
+            }
+            return result;
+        }
+
+        public static byte[] CallFunction(UIntPtr handle, string function, byte[] args, uint resultSize, OnProcessOp onProcessOp, OnError onError = null)
+        {
+            IntPtr argsBuffer = Marshal.AllocHGlobal(Math.Max(args.Length, 1));
+            IntPtr resultBuffer = Marshal.AllocHGlobal(Math.Max((int)resultSize, 1));
+            Marshal.Copy(args, 0, argsBuffer, args.Length);
+            byte[] result = null;
+            try
+            {
+                var status = NAPI.CallFunction(
+                    handle,
+                    function,
+                    argsBuffer,
+                    (UIntPtr)args.Length,
+                    resultBuffer,
+                    (UIntPtr)resultSize,
+                    (kaiju, context, op, paramsPtrs, paramsCount, targetsPtrs, targetsCount) =>
                     {
-                        onProcessOp?.Invoke(op, MakePointers(paramsPtrs, paramsCount), MakePointers(targetsPtrs, targetsCount));
+                        onProcessOp?.Invoke(kaiju, op, MakePointers(paramsPtrs, paramsCount), MakePointers(targetsPtrs, targetsCount));
                     },
                     IntPtr.Zero,
                     (context, error) => onError?.Invoke(error),
                     IntPtr.Zero
                 );
+                if (status)
+                {
+                    result = new byte[resultSize];
+                    Marshal.Copy(resultBuffer, result, 0, (int)resultSize);
+                }
             }
             catch (Exception error)
             {
                 onError?.Invoke(error.Message);
             }
+            finally
+            {
+                Marshal.FreeHGlobal(argsBuffer);
+                Marshal.FreeHGlobal(resultBuffer);
+            }
             return result;
         }
 
         public static void Cancel(UIntPtr handle)
         {
             NAPI.Cancel(handle);
+            lock (ProgramNatives)
+            {
+                ProgramNatives.Remove(handle);
+            }
         }
 
         public static UIntPtr Fork(UIntPtr handle, string entry, uint memSize, uint stackSize, OnError onError = null)
@@ -135,6 +262,13 @@ namespace Kaiju.VM
                     (context, error) => onError?.Invoke(error),
                     IntPtr.Zero
                 );
+                lock (ProgramNatives)
+                {
+                    if (ProgramNatives.TryGetValue(handle, out var nativesTable))
+                    {
+                        KeepNatives(result, nativesTable);
+                    }
+                }
             }
             catch (Exception error)
             {
@@ -150,7 +284,7 @@ namespace Kaiju.VM
             {
                 result = NAPI.With(
                     handle,
-                    (context) => onPerform?.Invoke(),
+                    (kaiju, context) => onPerform?.Invoke(kaiju),
                     IntPtr.Zero,
                     (context, error) => onError?.Invoke(error),
                     IntPtr.Zero
@@ -163,70 +297,80 @@ namespace Kaiju.VM
             return result;
         }
 
-        public static uint StateSize()
+        public static uint StateSize(IntPtr kaiju)
         {
-            return (uint)NAPI.StateSize();
+            return (uint)NAPI.StateSize(kaiju);
         }
 
-        public static NAPI.KaijuInfoState StateInfo()
+        public static NAPI.KaijuInfoState StateInfo(IntPtr kaiju)
         {
             var info = new NAPI.KaijuInfoState();
-            NAPI.StateInfo(ref info);
+            NAPI.StateInfo(kaiju, ref info);
             return info;
         }
 
-        public static UIntPtr? StateAllocStack(uint size)
+        public static UIntPtr? StateAllocStack(IntPtr kaiju, uint size)
         {
             UIntPtr address = UIntPtr.Zero;
-            if (NAPI.StateAllocStack((UIntPtr)size, ref address))
+            if (NAPI.StateAllocStack(kaiju, (UIntPtr)size, ref address))
             {
                 return address;
             }
             return null;
         }
 
-        public static bool StatePopStack(uint size)
+        public static bool StatePopStack(IntPtr kaiju, uint size)
         {
-            return NAPI.StatePopStack((UIntPtr)size);
+            return NAPI.StatePopStack(kaiju, (UIntPtr)size);
         }
 
-        public static UIntPtr? StateStackAddress()
+        public static UIntPtr? StateStackAddress(IntPtr kaiju)
         {
             UIntPtr address = UIntPtr.Zero;
-            if (NAPI.StateStackAddress(ref address))
+            if (NAPI.StateStackAddress(kaiju, ref address))
             {
                 return address;
             }
             return null;
         }
 
-        public static UIntPtr? StateAllocMemory(uint size)
+        public static UIntPtr? StateAllocMemory(IntPtr kaiju, uint size)
         {
             UIntPtr address = UIntPtr.Zero;
-            if (NAPI.StateAllocMemory((UIntPtr)size, ref address))
+            if (NAPI.StateAllocMemory(kaiju, (UIntPtr)size, ref address))
             {
                 return address;
             }
             return null;
         }
 
-        public static bool StateDeallocMemory(UIntPtr address)
+        public static UIntPtr? StateReallocMemory(IntPtr kaiju, UIntPtr address, uint size)
+        {
+            UIntPtr result = UIntPtr.Zero;
+            if (NAPI.StateReallocMemory(kaiju, address, (UIntPtr)size, ref result))
+            {
+                return result;
+            }
+            return null;
+        }
+
+        public static bool StateDeallocMemory(IntPtr kaiju, UIntPtr address)
         {
-            return NAPI.StateDeallocMemory(address);
+            return NAPI.StateDeallocMemory(kaiju, address);
         }
 
-        public static T? StateLoad<T>(UIntPtr address) where T : struct
+        public static T? StateLoad<T>(IntPtr kaiju, UIntPtr address) where T : struct
         {
-            if ((ulong)address + (ulong)Marshal.SizeOf<T>() <= (ulong)NAPI.StateSize())
+            if ((ulong)address + (ulong)Marshal.SizeOf<T>() <= (ulong)NAPI.StateSize(kaiju))
             {
-                return Marshal.PtrToStructure<T>(NAPI.StatePtr(address));
+                return Marshal.PtrToStructure<T>(NAPI.StatePtr(kaiju, address));
             }
             return null;
         }
 
-        public static string StateLoad(UIntPtr address)
+        public static string StateLoad(IntPtr kaiju, UIntPtr address)
         {
-            var p = StateLoad<IntPtr>(address);
+            var p = StateLoad<IntPtr>(kaiju, address);
             if (p.HasValue)
             {
                 return Marshal.PtrToStringAuto(p.Value);
@@ -234,26 +378,26 @@ namespace Kaiju.VM
             return null;
         }
 
-        public static byte[] StateLoadBytes(UIntPtr address, int size)
+        public static byte[] StateLoadBytes(IntPtr kaiju, UIntPtr address, int size)
         {
-            if ((ulong)address + (ulong)size <= (ulong)NAPI.StateSize())
+            if ((ulong)address + (ulong)size <= (ulong)NAPI.StateSize(kaiju))
             {
                 var result = new byte[size];
-                Marshal.Copy(NAPI.StatePtrMut(address), result, 0, size);
+                Marshal.Copy(NAPI.StatePtrMut(kaiju, address), result, 0, size);
                 return result;
             }
             return null;
         }
 
-        public static void StateStore<T>(UIntPtr address, T value) where T : struct
+        public static void StateStore<T>(IntPtr kaiju, UIntPtr address, T value) where T : struct
         {
-            if ((ulong)address + (ulong)Marshal.SizeOf<T>() <= (ulong)NAPI.StateSize())
+            if ((ulong)address + (ulong)Marshal.SizeOf<T>() <= (ulong)NAPI.StateSize(kaiju))
             {
-                Marshal.StructureToPtr(value, NAPI.StatePtr(address), false);
+                Marshal.StructureToPtr(value, NAPI.StatePtr(kaiju, address), false);
             }
         }
 
-        public static void StateStore(UIntPtr address, string value, bool wrap = true)
+        public static void StateStore(IntPtr kaiju, UIntPtr address, string value, bool wrap = true)
         {
             if (value == null)
             {
@@ -265,35 +409,64 @@ namespace Kaiju.VM
             bytes[bytes.Length - 1] = 0;
             if (wrap)
             {
-                var addr = StateAllocStack((uint)bytes.Length);
+                var addr = StateAllocStack(kaiju, (uint)bytes.Length);
                 if (addr.HasValue)
                 {
-                    StateStoreBytes(addr.Value, bytes);
-                    StateStore(address, addr.Value);
+                    StateStoreBytes(kaiju, addr.Value, bytes);
+                    StateStore(kaiju, address, addr.Value);
                 }
             }
             else
             {
-                StateStoreBytes(address, bytes);
+                StateStoreBytes(kaiju, address, bytes);
             }
         }
 
-        public static void StateStoreBytes(UIntPtr address, byte[] bytes)
+        public static void StateStoreBytes(IntPtr kaiju, UIntPtr address, byte[] bytes)
         {
-            if ((ulong)address + (ulong)bytes.Length <= (ulong)NAPI.StateSize())
+            if ((ulong)address + (ulong)bytes.Length <= (ulong)NAPI.StateSize(kaiju))
             {
-                Marshal.Copy(bytes, 0, NAPI.StatePtrMut(address), bytes.Length);
+                Marshal.Copy(bytes, 0, NAPI.StatePtrMut(kaiju, address), bytes.Length);
             }
         }
 
-        public static bool ContextGoTo(string label)
+        public static bool ContextGoTo(IntPtr kaiju, string label)
+        {
+            return NAPI.ContextGoTo(kaiju, label);
+        }
+
+        public static void ContextReturn(IntPtr kaiju)
+        {
+            NAPI.ContextReturn(kaiju);
+        }
+
+        private static NAPI.KaijuNative[] MakeNatives(Native[] natives)
         {
-            return NAPI.ContextGoTo(label);
+            if (natives == null)
+            {
+                return new NAPI.KaijuNative[0];
+            }
+            return natives.Select(native => new NAPI.KaijuNative
+            {
+                module = native.Module,
+                function = native.Function,
+                onCallNative = (kaiju, context, paramsPtrs, paramsCount, resultPtr, hasResult) =>
+                {
+                    return native.OnCall != null && native.OnCall(kaiju, MakePointers(paramsPtrs, paramsCount), hasResult ? resultPtr : (UIntPtr?)null);
+                },
+                context = IntPtr.Zero,
+            }).ToArray();
         }
 
-        public static void ContextReturn()
+        private static void KeepNatives(UIntPtr handle, NAPI.KaijuNative[] natives)
         {
-            NAPI.ContextReturn();
+            if (handle != UIntPtr.Zero && natives.Length > 0)
+            {
+                lock (ProgramNatives)
+                {
+                    ProgramNatives[handle] = natives;
+                }
+            }
         }
 
         private static UIntPtr[] MakePointers(IntPtr table, UIntPtr count)
@@ -19,8 +19,24 @@ namespace Kaiju.VM
             UIntPtr allFree;
         }
 
+        public enum ResumeStatus
+        {
+            Error = 0,
+            Yielded = 1,
+            Completed = 2,
+        }
+
+        [UnmanagedFunctionPointer(LibCall)]
+        [return: MarshalAs(UnmanagedType.I1)]
+        public delegate bool OnBindOp(
+            IntPtr context,
+            [MarshalAs(UnmanagedType.LPStr)]
+            string op
+        );
+
         [UnmanagedFunctionPointer(LibCall)]
         public delegate void OnProcessOp(
+            IntPtr kaiju,
             IntPtr context,
             [MarshalAs(UnmanagedType.LPStr)]
             string op,
@@ -30,6 +46,30 @@ namespace Kaiju.VM
             UIntPtr targetsCount
         );
 
+        [UnmanagedFunctionPointer(LibCall)]
+        [return: MarshalAs(UnmanagedType.I1)]
+        public delegate bool OnCallNative(
+            IntPtr kaiju,
+            IntPtr context,
+            IntPtr paramsPtr,
+            UIntPtr paramsCount,
+            UIntPtr result,
+            [MarshalAs(UnmanagedType.I1)]
+            bool hasResult
+        );
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct KaijuNative
+        {
+            [MarshalAs(UnmanagedType.LPStr)]
+            public string module;
+            [MarshalAs(UnmanagedType.LPStr)]
+            public string function;
+            [MarshalAs(UnmanagedType.FunctionPtr)]
+            public OnCallNative onCallNative;
+            public IntPtr context;
+        }
+
         [UnmanagedFunctionPointer(LibCall)]
         public delegate void OnError(
             IntPtr context,
@@ -38,7 +78,7 @@ namespace Kaiju.VM
         );
 
         [UnmanagedFunctionPointer(LibCall)]
-        public delegate void OnPerform(IntPtr context);
+        public delegate void OnPerform(IntPtr kaiju, IntPtr context);
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_run_program", CharSet = CharSet.Ansi)]
         [return: MarshalAs(UnmanagedType.I1)]
@@ -49,6 +89,10 @@ namespace Kaiju.VM
             string entry,
             UIntPtr memSize,
             UIntPtr stackSize,
+            KaijuNative[] natives,
+            UIntPtr nativesCount,
+            [MarshalAs(UnmanagedType.FunctionPtr)]
+            OnBindOp onBindOp,
             [MarshalAs(UnmanagedType.FunctionPtr)]
             OnProcessOp onProcessOp,
             IntPtr processorContext,
@@ -65,6 +109,11 @@ namespace Kaiju.VM
             string entry,
             UIntPtr memSize,
             UIntPtr stackSize,
+            KaijuNative[] natives,
+            UIntPtr nativesCount,
+            [MarshalAs(UnmanagedType.FunctionPtr)]
+            OnBindOp onBindOp,
+            IntPtr processorContext,
             [MarshalAs(UnmanagedType.FunctionPtr)]
             OnError onError,
             IntPtr errorContext
@@ -82,6 +131,18 @@ namespace Kaiju.VM
             IntPtr errorContext
         );
 
+        [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_resume_program_budget", CharSet = CharSet.Ansi)]
+        public extern static ResumeStatus ResumeBudget(
+            UIntPtr handle,
+            UIntPtr budget,
+            [MarshalAs(UnmanagedType.FunctionPtr)]
+            OnProcessOp onProcessOp,
+            IntPtr processorContext,
+            [MarshalAs(UnmanagedType.FunctionPtr)]
+            OnError onError,
+            IntPtr errorContext
+        );
+
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_consume_program", CharSet = CharSet.Ansi)]
         [return: MarshalAs(UnmanagedType.I1)]
         public extern static bool Consume(
@@ -94,6 +155,40 @@ namespace Kaiju.VM
             IntPtr errorContext
         );
 
+        [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_load_program", CharSet = CharSet.Ansi)]
+        public extern static UIntPtr Load(
+            IntPtr bytes,
+            UIntPtr size,
+            UIntPtr memSize,
+            UIntPtr stackSize,
+            KaijuNative[] natives,
+            UIntPtr nativesCount,
+            [MarshalAs(UnmanagedType.FunctionPtr)]
+            OnBindOp onBindOp,
+            IntPtr processorContext,
+            [MarshalAs(UnmanagedType.FunctionPtr)]
+            OnError onError,
+            IntPtr errorContext
+        );
+
+        [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_call_function", CharSet = CharSet.Ansi)]
+        [return: MarshalAs(UnmanagedType.I1)]
+        public extern static bool CallFunction(
+            UIntPtr handle,
+            [MarshalAs(UnmanagedType.LPStr)]
+            string function,
+            IntPtr args,
+            UIntPtr argsSize,
+            IntPtr result,
+            UIntPtr resultSize,
+            [MarshalAs(UnmanagedType.FunctionPtr)]
+            OnProcessOp onProcessOp,
+            IntPtr processorContext,
+            [MarshalAs(UnmanagedType.FunctionPtr)]
+            OnError onError,
+            IntPtr errorContext
+        );
+
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_cancel_program", CharSet = CharSet.Ansi)]
         public extern static void Cancel(UIntPtr handle);
 
@@ -122,52 +217,64 @@ namespace Kaiju.VM
         );
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_state_size", CharSet = CharSet.Ansi)]
-        public extern static UIntPtr StateSize();
+        public extern static UIntPtr StateSize(IntPtr kaiju);
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_state_ptr", CharSet = CharSet.Ansi)]
-        public extern static IntPtr StatePtr(UIntPtr address);
+        public extern static IntPtr StatePtr(IntPtr kaiju, UIntPtr address);
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_state_ptr_mut", CharSet = CharSet.Ansi)]
-        public extern static IntPtr StatePtrMut(UIntPtr address);
+        public extern static IntPtr StatePtrMut(IntPtr kaiju, UIntPtr address);
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_state_info", CharSet = CharSet.Ansi)]
         [return: MarshalAs(UnmanagedType.I1)]
-        public extern static bool StateInfo(ref KaijuInfoState outState);
+        public extern static bool StateInfo(IntPtr kaiju, ref KaijuInfoState outState);
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_state_alloc_stack", CharSet = CharSet.Ansi)]
         [return: MarshalAs(UnmanagedType.I1)]
         public extern static bool StateAllocStack(
+            IntPtr kaiju,
             UIntPtr size,
             ref UIntPtr outAddress
         );
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_state_pop_stack", CharSet = CharSet.Ansi)]
         [return: MarshalAs(UnmanagedType.I1)]
-        public extern static bool StatePopStack(UIntPtr size);
+        public extern static bool StatePopStack(IntPtr kaiju, UIntPtr size);
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_state_stack_address", CharSet = CharSet.Ansi)]
         [return: MarshalAs(UnmanagedType.I1)]
-        public extern static bool StateStackAddress(ref UIntPtr outAddress);
+        public extern static bool StateStackAddress(IntPtr kaiju, ref UIntPtr outAddress);
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_state_alloc_memory", CharSet = CharSet.Ansi)]
         [return: MarshalAs(UnmanagedType.I1)]
         public extern static bool StateAllocMemory(
+            IntPtr kaiju,
+            UIntPtr size,
+            ref UIntPtr outAddress
+        );
+
+        [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_state_realloc_memory", CharSet = CharSet.Ansi)]
+        [return: MarshalAs(UnmanagedType.I1)]
+        public extern static bool StateReallocMemory(
+            IntPtr kaiju,
+            UIntPtr address,
             UIntPtr size,
             ref UIntPtr outAddress
         );
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_state_dealloc_memory", CharSet = CharSet.Ansi)]
         [return: MarshalAs(UnmanagedType.I1)]
-        public extern static bool StateDeallocMemory(UIntPtr address);
+        public extern static bool StateDeallocMemory(IntPtr kaiju, UIntPtr address);
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_context_go_to", CharSet = CharSet.Ansi)]
         [return: MarshalAs(UnmanagedType.I1)]
         public extern static bool ContextGoTo(
+            IntPtr kaiju,
             [MarshalAs(UnmanagedType.LPStr)]
             string label
         );
 
         [DllImport(LibName, CallingConvention = LibCall, EntryPoint = "kaiju_context_return", CharSet = CharSet.Ansi)]
-        public extern static void ContextReturn();
+        public extern static void ContextReturn(IntPtr kaiju);
     }
 }
@@ -19,24 +19,25 @@ namespace Tests
                 "program.kj",
                 "descriptor.kjo",
                 files,
-                error => Console.Error.WriteLine(error)
+                diagnostic => Console.Error.WriteLine(diagnostic)
             );
             Kaiju.VM.API.Run(
                 result,
                 "main",
                 1024,
                 1024,
-                (op, paramsPtrs, targetsPtrs) =>
+                op => op == "add" || op == "out",
+                (kaiju, op, paramsPtrs, targetsPtrs) =>
                 {
                     if (op == "add")
                     {
-                        var a = Kaiju.VM.API.StateLoad<int>(paramsPtrs[0]).Value;
-                        var b = Kaiju.VM.API.StateLoad<int>(paramsPtrs[1]).Value;
-                        Kaiju.VM.API.StateStore(targetsPtrs[0], a + b);
+                        var a = Kaiju.VM.API.StateLoad<int>(kaiju, paramsPtrs[0]).Value;
+                        var b = Kaiju.VM.API.StateLoad<int>(kaiju, paramsPtrs[1]).Value;
+                        Kaiju.VM.API.StateStore(kaiju, targetsPtrs[0], a + b);
                     }
                     else if (op == "out")
                     {
-                        var v = Kaiju.VM.API.StateLoad<int>(paramsPtrs[0]).Value;
+                        var v = Kaiju.VM.API.StateLoad<int>(kaiju, paramsPtrs[0]).Value;
                         Console.WriteLine("OUT: {0}", v);
                     }
                 },
@@ -1,4 +1,4 @@
-use crate::string_from_raw;
+use crate::{string_from_raw, KaijuFuncServeFile};
 use compiler_core::module_reader::ModuleReader;
 use relative_path::{RelativePath, RelativePathBuf};
 use std::ffi::CString;
@@ -11,15 +11,12 @@ fn dir_path(path: &RelativePath) -> RelativePathBuf {
 
 pub struct FmModuleReader {
     context: *mut libc::c_void,
-    serve: fn(*mut libc::c_void, *const libc::c_char, *mut usize) -> *const libc::c_uchar,
+    serve: KaijuFuncServeFile,
     path_stack: Vec<RelativePathBuf>,
 }
 
 impl FmModuleReader {
-    pub fn new(
-        context: *mut libc::c_void,
-        serve: fn(*mut libc::c_void, *const libc::c_char, *mut usize) -> *const libc::c_uchar,
-    ) -> Self {
+    pub fn new(context: *mut libc::c_void, serve: KaijuFuncServeFile) -> Self {
         Self {
             context,
             serve,
@@ -10,79 +10,82 @@ pub mod fm_module_reader;
 use crate::fm_module_reader::*;
 use compiler_core::*;
 use core::assembly::*;
+use core::diagnostic::*;
+use core::error::*;
 use core::program::*;
 use core::validator::*;
 use std::ffi::CString;
 use std::ptr::{copy_nonoverlapping, null};
 
+#[repr(C)]
+pub struct KaijuDiagnostic {
+    pub code: *const libc::c_char,
+    /// 0 - error, 1 - warning, 2 - note.
+    pub severity: u8,
+    pub message: *const libc::c_char,
+    /// Null if there is no help.
+    pub help: *const libc::c_char,
+    /// Null if diagnostic is not located in any module.
+    pub path: *const libc::c_char,
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+    pub pretty: *const libc::c_char,
+}
+
+pub type KaijuFuncServeFile = extern "C" fn(
+    context: *mut libc::c_void,
+    path: *const libc::c_char,
+    size: *mut usize,
+) -> *const libc::c_uchar;
+
+pub type KaijuFuncResultFile =
+    extern "C" fn(context: *mut libc::c_void, bytes: *const libc::c_uchar, size: usize);
+
+pub type KaijuFuncDiagnostics =
+    extern "C" fn(context: *mut libc::c_void, diagnostics: *const KaijuDiagnostic, count: usize);
+
 #[no_mangle]
 pub extern "C" fn kaiju_compile_program_pst(
     input: *const libc::c_char,
     opsdesc: *const libc::c_char,
     pretty: bool,
-    serve_file: fn(*mut libc::c_void, *const libc::c_char, *mut usize) -> *const libc::c_uchar,
+    serve_file: Option<KaijuFuncServeFile>,
     serve_context: *mut libc::c_void,
-    result_file: fn(*mut libc::c_void, *const libc::c_uchar, usize),
+    result_file: Option<KaijuFuncResultFile>,
     result_context: *mut libc::c_void,
-    error: fn(*mut libc::c_void, *const libc::c_char),
-    error_context: *mut libc::c_void,
+    diagnostics: Option<KaijuFuncDiagnostics>,
+    diagnostics_context: *mut libc::c_void,
 ) -> bool {
-    if (serve_file as *const libc::c_void).is_null()
-        || (result_file as *const libc::c_void).is_null()
-        || (error as *const libc::c_void).is_null()
-    {
-        if !(error as *const libc::c_void).is_null() {
-            let err = CString::new("Some callbacks are null pointers!").unwrap();
-            error(error_context, err.as_ptr());
+    let (serve_file, result_file, diagnostics) = match unwrap_callbacks(
+        serve_file,
+        result_file,
+        result_context,
+        diagnostics,
+        diagnostics_context,
+    ) {
+        Some(callbacks) => callbacks,
+        None => return false,
+    };
+    let reader = FmModuleReader::new(serve_context, serve_file);
+    let result = compile_program_from_raw(input, opsdesc, serve_file, serve_context).and_then(
+        |(program, _, warnings)| match program.to_json(pretty) {
+            Ok(json) => Ok((json.into_bytes(), warnings)),
+            Err(err) => Err(SimpleError::new(format!("{}", err)).into()),
+        },
+    );
+    match result {
+        Ok((bytes, warnings)) => {
+            report_diagnostics(&warnings, Some(&reader), diagnostics, diagnostics_context);
+            result_file(result_context, bytes.as_ptr(), bytes.len());
+            true
         }
-        result_file(result_context, null(), 0);
-        return false;
-    }
-    let mut size = 0;
-    let buffer = serve_file(serve_context, opsdesc, &mut size);
-    if !buffer.is_null() {
-        match compile_ops_descriptor(&string_from_raw(buffer, size as usize)) {
-            Ok(desc) => {
-                match compile_program::<EmptyDeepValidator, _>(
-                    &string_from_raw_unsized(input as *const u8),
-                    FmModuleReader::new(serve_context, serve_file),
-                    &desc,
-                ) {
-                    Ok(program) => match program.to_json(pretty) {
-                        Ok(json) => {
-                            let bytes = json.as_bytes();
-                            result_file(result_context, bytes.as_ptr(), bytes.len());
-                            true
-                        }
-                        Err(err) => {
-                            let err = CString::new(format!("{}", err)).unwrap();
-                            error(error_context, err.as_ptr());
-                            result_file(result_context, null(), 0);
-                            false
-                        }
-                    },
-                    Err(err) => {
-                        let err = CString::new(err.message).unwrap();
-                        error(error_context, err.as_ptr());
-                        result_file(result_context, null(), 0);
-                        false
-                    }
-                }
-            }
-            Err(err) => {
-                let opsdesc = string_from_raw_unsized(opsdesc as *const u8);
-                let err = CString::new(format!("{}: {}", opsdesc, err.pretty)).unwrap();
-                error(error_context, err.as_ptr());
-                result_file(result_context, null(), 0);
-                false
-            }
+        Err(errors) => {
+            report_diagnostics(&errors, Some(&reader), diagnostics, diagnostics_context);
+            result_file(result_context, null(), 0);
+            false
         }
-    } else {
-        let opsdesc = string_from_raw_unsized(opsdesc as *const u8);
-        let err = CString::new(format!("Could not read file: {}", opsdesc)).unwrap();
-        error(error_context, err.as_ptr());
-        result_file(result_context, null(), 0);
-        false
     }
 }
 
@@ -90,71 +93,162 @@ pub extern "C" fn kaiju_compile_program_pst(
 pub extern "C" fn kaiju_compile_program_bin(
     input: *const libc::c_char,
     opsdesc: *const libc::c_char,
-    serve_file: fn(*mut libc::c_void, *const libc::c_char, *mut usize) -> *const libc::c_uchar,
+    debug_info: bool,
+    serve_file: Option<KaijuFuncServeFile>,
     serve_context: *mut libc::c_void,
-    result_file: fn(*mut libc::c_void, *const libc::c_uchar, usize),
+    result_file: Option<KaijuFuncResultFile>,
     result_context: *mut libc::c_void,
-    error: fn(*mut libc::c_void, *const libc::c_char),
-    error_context: *mut libc::c_void,
+    diagnostics: Option<KaijuFuncDiagnostics>,
+    diagnostics_context: *mut libc::c_void,
 ) -> bool {
-    if (serve_file as *const libc::c_void).is_null()
-        || (result_file as *const libc::c_void).is_null()
-        || (error as *const libc::c_void).is_null()
-    {
-        if !(error as *const libc::c_void).is_null() {
-            let err = CString::new("Some callbacks are null pointers!").unwrap();
-            error(error_context, err.as_ptr());
+    let (serve_file, result_file, diagnostics) = match unwrap_callbacks(
+        serve_file,
+        result_file,
+        result_context,
+        diagnostics,
+        diagnostics_context,
+    ) {
+        Some(callbacks) => callbacks,
+        None => return false,
+    };
+    let reader = FmModuleReader::new(serve_context, serve_file);
+    let result = compile_program_from_raw(input, opsdesc, serve_file, serve_context).and_then(
+        |(program, desc, warnings)| {
+            encode_assembly(&program, &desc, debug_info).map(|bytes| (bytes, warnings))
+        },
+    );
+    match result {
+        Ok((bytes, warnings)) => {
+            report_diagnostics(&warnings, Some(&reader), diagnostics, diagnostics_context);
+            result_file(result_context, bytes.as_ptr(), bytes.len());
+            true
+        }
+        Err(errors) => {
+            report_diagnostics(&errors, Some(&reader), diagnostics, diagnostics_context);
+            result_file(result_context, null(), 0);
+            false
         }
-        result_file(result_context, null(), 0);
-        return false;
     }
-    let mut size = 0;
-    let buffer = serve_file(serve_context, opsdesc, &mut size);
-    if !buffer.is_null() {
-        match compile_ops_descriptor(&string_from_raw(buffer, size as usize)) {
-            Ok(desc) => {
-                match compile_program::<EmptyDeepValidator, _>(
-                    &string_from_raw_unsized(input as *const u8),
-                    FmModuleReader::new(serve_context, serve_file),
-                    &desc,
-                ) {
-                    Ok(program) => match encode_assembly(&program, &desc) {
-                        Ok(bytes) => {
-                            result_file(result_context, bytes.as_ptr(), bytes.len());
-                            true
-                        }
-                        Err(err) => {
-                            let err = CString::new(err.message).unwrap();
-                            error(error_context, err.as_ptr());
-                            result_file(result_context, null(), 0);
-                            false
-                        }
-                    },
-                    Err(err) => {
-                        let err = CString::new(err.message).unwrap();
-                        error(error_context, err.as_ptr());
-                        result_file(result_context, null(), 0);
-                        false
-                    }
-                }
+}
+
+/// Returns all callbacks, or reports null ones (if diagnostics callback is there) and empty result.
+fn unwrap_callbacks(
+    serve_file: Option<KaijuFuncServeFile>,
+    result_file: Option<KaijuFuncResultFile>,
+    result_context: *mut libc::c_void,
+    diagnostics: Option<KaijuFuncDiagnostics>,
+    diagnostics_context: *mut libc::c_void,
+) -> Option<(
+    KaijuFuncServeFile,
+    KaijuFuncResultFile,
+    KaijuFuncDiagnostics,
+)> {
+    match (serve_file, result_file, diagnostics) {
+        (Some(serve_file), Some(result_file), Some(diagnostics)) => {
+            Some((serve_file, result_file, diagnostics))
+        }
+        (_, result_file, diagnostics) => {
+            if let Some(diagnostics) = diagnostics {
+                let err = Diagnostic::error(
+                    codes::GENERIC,
+                    "Some callbacks are null pointers!".to_owned(),
+                );
+                report_diagnostics(&[err], None, diagnostics, diagnostics_context);
             }
-            Err(err) => {
-                let opsdesc = string_from_raw_unsized(opsdesc as *const u8);
-                let err = CString::new(format!("{}: {}", opsdesc, err.pretty)).unwrap();
-                error(error_context, err.as_ptr());
+            if let Some(result_file) = result_file {
                 result_file(result_context, null(), 0);
-                false
             }
+            None
         }
-    } else {
+    }
+}
+
+fn compile_program_from_raw(
+    input: *const libc::c_char,
+    opsdesc: *const libc::c_char,
+    serve_file: KaijuFuncServeFile,
+    serve_context: *mut libc::c_void,
+) -> DiagnosticResult<(Program, OpsDescriptor, Vec<Diagnostic>)> {
+    let mut size = 0;
+    let buffer = serve_file(serve_context, opsdesc, &mut size);
+    if buffer.is_null() {
         let opsdesc = string_from_raw_unsized(opsdesc as *const u8);
-        let err = CString::new(format!("Could not read file: {}", opsdesc)).unwrap();
-        error(error_context, err.as_ptr());
-        result_file(result_context, null(), 0);
-        false
+        return Err(
+            SimpleError::new(format!("Could not read file: {}", opsdesc))
+                .with_code(codes::MODULE)
+                .into(),
+        );
+    }
+    match compile_ops_descriptor(&string_from_raw(buffer, size as usize)) {
+        Ok(desc) => {
+            let (program, warnings) = compile_program::<EmptyDeepValidator, _>(
+                &string_from_raw_unsized(input as *const u8),
+                FmModuleReader::new(serve_context, serve_file),
+                &desc,
+            )?;
+            Ok((program, desc, warnings))
+        }
+        Err(err) => {
+            let opsdesc = string_from_raw_unsized(opsdesc as *const u8);
+            Err(SimpleError::new(format!("{}: {}", opsdesc, err.pretty))
+                .with_code(codes::SYNTAX)
+                .into())
+        }
     }
 }
 
+fn report_diagnostics(
+    diagnostics: &[Diagnostic],
+    reader: Option<&FmModuleReader>,
+    callback: KaijuFuncDiagnostics,
+    context: *mut libc::c_void,
+) {
+    if diagnostics.is_empty() {
+        return;
+    }
+    let strings = diagnostics
+        .iter()
+        .map(|d| {
+            let pretty = if let Some(reader) = reader {
+                pretty_diagnostic(d, reader)
+            } else {
+                d.pretty(|_| None)
+            };
+            (
+                CString::new(d.code.as_str()).unwrap(),
+                CString::new(d.message.as_str()).unwrap(),
+                d.help.as_ref().map(|h| CString::new(h.as_str()).unwrap()),
+                d.path.as_ref().map(|p| CString::new(p.as_str()).unwrap()),
+                CString::new(pretty).unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let items = diagnostics
+        .iter()
+        .zip(strings.iter())
+        .map(|(d, (code, message, help, path, pretty))| {
+            let span = d.span.unwrap_or_default();
+            KaijuDiagnostic {
+                code: code.as_ptr(),
+                severity: match d.severity {
+                    Severity::Error => 0,
+                    Severity::Warning => 1,
+                    Severity::Note => 2,
+                },
+                message: message.as_ptr(),
+                help: help.as_ref().map_or(null(), |h| h.as_ptr()),
+                path: path.as_ref().map_or(null(), |p| p.as_ptr()),
+                line: span.line,
+                column: span.column,
+                start: span.start,
+                end: span.end,
+                pretty: pretty.as_ptr(),
+            }
+        })
+        .collect::<Vec<_>>();
+    callback(context, items.as_ptr(), items.len());
+}
+
 pub fn bytes_from_raw(source: *const libc::c_uchar, size: usize) -> Vec<u8> {
     if source.is_null() || size == 0 {
         return vec![];
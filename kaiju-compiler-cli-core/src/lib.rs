@@ -9,11 +9,13 @@ pub mod external_deep_validator;
 pub mod fs_module_reader;
 
 use crate::core::assembly::*;
+use crate::core::diagnostic::*;
+use crate::core::disasm::*;
 use crate::core::error::*;
 use crate::core::program::*;
 use crate::core::validator::*;
 use crate::fs_module_reader::*;
-use std::fs::{read_to_string, write};
+use std::fs::{read, read_to_string, write};
 
 pub fn load_opdescs(paths: &[String]) -> SimpleResult<OpsDescriptor> {
     if paths.is_empty() {
@@ -35,7 +37,14 @@ pub fn load_opdescs(paths: &[String]) -> SimpleResult<OpsDescriptor> {
     }
 }
 
-pub fn compile_program<V>(input: &str, opsdesc: &OpsDescriptor) -> SimpleResult<Program>
+pub fn pretty_diagnostic(diagnostic: &Diagnostic) -> String {
+    compiler_core::pretty_diagnostic(diagnostic, &FsModuleReader::default())
+}
+
+pub fn compile_program<V>(
+    input: &str,
+    opsdesc: &OpsDescriptor,
+) -> DiagnosticResult<(Program, Vec<Diagnostic>)>
 where
     V: DeepValidator,
 {
@@ -47,20 +56,20 @@ pub fn compile_program_and_write_pst<V>(
     output: &str,
     opsdesc: &OpsDescriptor,
     pretty: bool,
-) -> SimpleResult<()>
+) -> DiagnosticResult<Vec<Diagnostic>>
 where
     V: DeepValidator,
 {
-    let program = compile_program::<V>(input, opsdesc)?;
+    let (program, warnings) = compile_program::<V>(input, opsdesc)?;
     match program.to_json(pretty) {
         Ok(json) => {
             if let Err(err) = write(output, &json) {
-                Err(SimpleError::new(format!("{:?}: {}", output, err)))
+                Err(SimpleError::new(format!("{:?}: {}", output, err)).into())
             } else {
-                Ok(())
+                Ok(warnings)
             }
         }
-        Err(err) => Err(SimpleError::new(format!("{:?}: {}", output, err))),
+        Err(err) => Err(SimpleError::new(format!("{:?}: {}", output, err)).into()),
     }
 }
 
@@ -68,19 +77,34 @@ pub fn compile_program_and_write_bin<V>(
     input: &str,
     output: &str,
     opsdesc: &OpsDescriptor,
-) -> SimpleResult<()>
+    options: &AssemblyOptions,
+) -> DiagnosticResult<Vec<Diagnostic>>
 where
     V: DeepValidator,
 {
-    let program = compile_program::<V>(input, opsdesc)?;
-    match encode_assembly(&program, opsdesc) {
+    let (program, warnings) = compile_program::<V>(input, opsdesc)?;
+    match encode_assembly_with_options(&program, opsdesc, options) {
         Ok(bytes) => {
             if let Err(err) = write(output, &bytes) {
-                Err(SimpleError::new(format!("{:?}: {}", output, err)))
+                Err(SimpleError::new(format!("{:?}: {}", output, err)).into())
             } else {
-                Ok(())
+                Ok(warnings)
             }
         }
-        Err(err) => Err(SimpleError::new(format!("{:?}: {}", output, err.message))),
+        Err(errors) => Err(errors
+            .into_iter()
+            .map(|err| Diagnostic {
+                message: format!("{:?}: {}", output, err.message),
+                ..err
+            })
+            .collect()),
+    }
+}
+
+pub fn disassemble_bin(input: &str) -> SimpleResult<String> {
+    match read(input) {
+        Ok(bytes) => disassemble_bytes(bytes)
+            .map_err(|err| SimpleError::new(format!("{:?}: {}", input, err.message))),
+        Err(err) => Err(SimpleError::new(format!("{:?}: {}", input, err))),
     }
 }
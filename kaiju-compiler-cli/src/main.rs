@@ -5,7 +5,10 @@ extern crate kaiju_core as core;
 
 use crate::compiler_cli_core::external_deep_validator::*;
 use crate::compiler_cli_core::*;
+use crate::core::assembly::AssemblyOptions;
+use crate::core::diagnostic::*;
 use crate::core::program::OpsDescriptor;
+use crate::core::target::Target;
 use clap::{App, Arg, SubCommand};
 
 fn main() {
@@ -55,6 +58,39 @@ fn main() {
                         .required(false)
                         .takes_value(true)
                         .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("debug-info")
+                        .short("g")
+                        .long("debug-info")
+                        .help("Include debug info section in binary")
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("operand-types")
+                        .short("t")
+                        .long("operand-types")
+                        .help("Include types of ops params and targets in binary")
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("target")
+                        .long("target")
+                        .value_name("NAME")
+                        .help("Target machine layout: host, le16, le32, le64, be16, be32 or be64, optionally with -packed suffix")
+                        .required(false)
+                        .takes_value(true)
+                        .default_value("host"),
+                )
+                .arg(
+                    Arg::with_name("message-format")
+                        .long("message-format")
+                        .value_name("FORMAT")
+                        .help("Format of reported diagnostics")
+                        .required(false)
+                        .takes_value(true)
+                        .possible_values(&["human", "json"])
+                        .default_value("human"),
                 ),
         )
         .subcommand(
@@ -106,6 +142,40 @@ fn main() {
                         .long("pretty")
                         .help("Make output pretty")
                         .required(false),
+                )
+                .arg(
+                    Arg::with_name("message-format")
+                        .long("message-format")
+                        .value_name("FORMAT")
+                        .help("Format of reported diagnostics")
+                        .required(false)
+                        .takes_value(true)
+                        .possible_values(&["human", "json"])
+                        .default_value("human"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("disasm")
+                .version(env!("CARGO_PKG_VERSION"))
+                .author(env!("CARGO_PKG_AUTHORS"))
+                .about("Disassemble binary into readable listing")
+                .arg(
+                    Arg::with_name("input")
+                        .short("i")
+                        .long("input")
+                        .value_name("FILE")
+                        .help("Kaiju input binary file (*.kjb)")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Listing output file (prints to stdout if not set)")
+                        .required(false)
+                        .takes_value(true),
                 ),
         )
         .get_matches();
@@ -128,11 +198,27 @@ fn main() {
                     }
                 }
             }
-            if let Err(err) =
-                compile_program_and_write_bin::<ExternalDeepValidator>(&input, &output, &opsdesc)
-            {
-                eprintln!("{}", err.message);
-                ::std::process::exit(1);
+            let target = match Target::from_name(matches.value_of("target").unwrap()) {
+                Ok(target) => target,
+                Err(err) => {
+                    eprintln!("{}", err.message);
+                    ::std::process::exit(1);
+                }
+            };
+            let options = AssemblyOptions {
+                debug_info: matches.is_present("debug-info"),
+                operand_types: matches.is_present("operand-types"),
+                target,
+            };
+            let json = matches.value_of("message-format") == Some("json");
+            match compile_program_and_write_bin::<ExternalDeepValidator>(
+                &input, &output, &opsdesc, &options,
+            ) {
+                Ok(warnings) => report_diagnostics(&warnings, json),
+                Err(errors) => {
+                    report_diagnostics(&errors, json);
+                    ::std::process::exit(1);
+                }
             }
         }
         ("pst", Some(matches)) => {
@@ -153,11 +239,34 @@ fn main() {
                     }
                 }
             }
-            if let Err(err) = compile_program_and_write_pst::<ExternalDeepValidator>(
+            let json = matches.value_of("message-format") == Some("json");
+            match compile_program_and_write_pst::<ExternalDeepValidator>(
                 &input, &output, &opsdesc, pretty,
             ) {
-                eprintln!("{}", err.message);
-                ::std::process::exit(1);
+                Ok(warnings) => report_diagnostics(&warnings, json),
+                Err(errors) => {
+                    report_diagnostics(&errors, json);
+                    ::std::process::exit(1);
+                }
+            }
+        }
+        ("disasm", Some(matches)) => {
+            let input = matches.value_of("input").unwrap();
+            match disassemble_bin(input) {
+                Ok(listing) => {
+                    if let Some(output) = matches.value_of("output") {
+                        if let Err(err) = ::std::fs::write(output, listing) {
+                            eprintln!("{:?}: {}", output, err);
+                            ::std::process::exit(1);
+                        }
+                    } else {
+                        print!("{}", listing);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("{}", err.message);
+                    ::std::process::exit(1);
+                }
             }
         }
         _ => {
@@ -166,3 +275,13 @@ fn main() {
         }
     }
 }
+
+fn report_diagnostics(diagnostics: &[Diagnostic], json: bool) {
+    for diagnostic in diagnostics {
+        if json {
+            println!("{}", diagnostic.to_json(false).unwrap());
+        } else {
+            eprintln!("{}\n", pretty_diagnostic(diagnostic));
+        }
+    }
+}
@@ -2,6 +2,7 @@ extern crate kaiju_core as core;
 
 pub mod module_reader;
 
+use crate::core::diagnostic::*;
 use crate::core::error::*;
 use crate::core::program::*;
 use crate::core::validator::*;
@@ -12,14 +13,21 @@ pub fn compile_program<V, R>(
     entry_path: &str,
     mut module_reader: R,
     ops_descriptor: &OpsDescriptor,
-) -> SimpleResult<Program>
+) -> DiagnosticResult<(Program, Vec<Diagnostic>)>
 where
     V: DeepValidator,
     R: ModuleReader,
 {
     let mut modules = HashMap::new();
     let validator = Validator::new(ops_descriptor);
-    load_module::<V, R>(&entry_path, &mut module_reader, &mut modules, &validator)?;
+    let mut loading = vec![];
+    load_module::<V, R>(
+        &entry_path,
+        &mut module_reader,
+        &mut modules,
+        &mut loading,
+        &validator,
+    )?;
     let mut program =
         Program::from_modules(None, modules.iter().map(|(_, v)| v.clone()).collect())?;
     validator.filter_program::<V>(&mut program);
@@ -32,25 +40,23 @@ where
             program.entry = Some(Entry::new(module, function));
         }
     }
-    if let Err(err) = validator.transform_program::<V>(&mut program) {
-        return Err(SimpleError::new(format!(
-            "Program {}: {}",
-            entry_path, err.message
-        )));
-    }
-    if let Err(err) = validator.validate_program::<V>(&program) {
-        return Err(SimpleError::new(format!(
-            "Program {}: {}",
-            entry_path, err.message
-        )));
-    }
-    Ok(program)
+    validator.transform_program::<V>(&mut program)?;
+    let warnings = validator.validate_program::<V>(&program)?;
+    Ok((program, warnings))
+}
+
+pub fn pretty_diagnostic<R>(diagnostic: &Diagnostic, module_reader: &R) -> String
+where
+    R: ModuleReader,
+{
+    diagnostic.pretty(|path| module_reader.load_module_source(path))
 }
 
 fn load_module<V, R>(
     relative_path: &str,
     module_reader: &mut R,
     modules: &mut HashMap<String, Module>,
+    loading: &mut Vec<String>,
     validator: &Validator,
 ) -> SimpleResult<String>
 where
@@ -60,25 +66,112 @@ where
     let path = module_reader.compose_path(relative_path);
     if modules.contains_key(&path) {
         Ok(path)
+    } else if loading.contains(&path) {
+        let chain = loading
+            .iter()
+            .chain(std::iter::once(&path))
+            .map(|p| p.as_str())
+            .collect::<Vec<_>>()
+            .join(" => ");
+        Err(SimpleError::new(format!(
+            "Cyclic module import: {} (cycle starts at {})",
+            chain, path
+        ))
+        .with_code(codes::MODULE))
     } else if let Some(source) = module_reader.load_module_source(&path) {
         match compile_module(&source) {
             Ok(mut module) => {
                 module.path = path.clone();
                 module_reader.push_module_path(&path);
+                loading.push(path.clone());
                 for import in &mut module.imports {
-                    import.module =
-                        load_module::<V, R>(&import.module, module_reader, modules, validator)?;
+                    import.module = load_module::<V, R>(
+                        &import.module,
+                        module_reader,
+                        modules,
+                        loading,
+                        validator,
+                    )?;
                 }
+                loading.pop();
                 module_reader.pop_module_path();
                 modules.insert(path.clone(), module);
                 Ok(path)
             }
-            Err(err) => Err(SimpleError::new(err.pretty)),
+            Err(err) => Err(SimpleError::new(err.message)
+                .with_code(codes::SYNTAX)
+                .with_path(&path)
+                .with_span(Span::new(
+                    err.location.0,
+                    err.location.1,
+                    err.line.0,
+                    err.column.0,
+                ))),
         }
     } else {
-        Err(SimpleError::new(format!(
-            "Could not load module: {:?}",
-            path
-        )))
+        Err(SimpleError::new(format!("Could not load module: {:?}", path)).with_code(codes::MODULE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestModuleReader(HashMap<String, String>);
+
+    impl ModuleReader for TestModuleReader {
+        fn load_module_source(&self, path: &str) -> Option<String> {
+            self.0.get(path).cloned()
+        }
+
+        fn push_module_path(&mut self, _: &str) {}
+
+        fn pop_module_path(&mut self) {}
+
+        fn compose_path(&self, relative_path: &str) -> String {
+            relative_path.to_owned()
+        }
+    }
+
+    #[test]
+    fn test_cyclic_imports() {
+        let mut sources = HashMap::new();
+        sources.insert(
+            "a.kj".to_owned(),
+            "import { b } from './b.kj';\nexport fn a() {}".to_owned(),
+        );
+        sources.insert(
+            "./b.kj".to_owned(),
+            "import { a } from 'a.kj';\nexport fn b() {}".to_owned(),
+        );
+        let ops = compile_ops_descriptor("").unwrap();
+        let err = compile_program::<EmptyDeepValidator, _>("a.kj", TestModuleReader(sources), &ops)
+            .unwrap_err();
+        assert_eq!(err[0].code, codes::MODULE);
+        assert_eq!(
+            err[0].message,
+            "Cyclic module import: a.kj => ./b.kj => a.kj (cycle starts at a.kj)"
+        );
+
+        let mut sources = HashMap::new();
+        sources.insert(
+            "main.kj".to_owned(),
+            "import { a } from 'a.kj';\nexport fn main() {}".to_owned(),
+        );
+        sources.insert(
+            "a.kj".to_owned(),
+            "import { b } from './b.kj';\nexport fn a() {}".to_owned(),
+        );
+        sources.insert(
+            "./b.kj".to_owned(),
+            "import { a } from 'a.kj';\nexport fn b() {}".to_owned(),
+        );
+        let err =
+            compile_program::<EmptyDeepValidator, _>("main.kj", TestModuleReader(sources), &ops)
+                .unwrap_err();
+        assert_eq!(
+            err[0].message,
+            "Cyclic module import: main.kj => a.kj => ./b.kj => a.kj (cycle starts at a.kj)"
+        );
     }
 }
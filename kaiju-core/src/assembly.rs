@@ -1,22 +1,30 @@
 #![allow(clippy::unused_io_amount)]
 #![allow(clippy::map_entry)]
+#![allow(clippy::too_many_arguments)]
 
+use crate::diagnostic::*;
 use crate::error::*;
 use crate::program::{
     Access as CoreAccess, BlockOp as CoreBlockOp, Extern as CoreExtern, Function as CoreFunction,
     Number as CoreNumber, OpsDescriptor, Program as CoreProgram, Struct as CoreStruct,
     Type as CoreType, Value as CoreValue, Variable as CoreVariable,
 };
+use crate::target::{align_offset, Target};
 use crate::utils::*;
+use crate::vm::ATOMS;
 use byteorder::{BigEndian, WriteBytesExt};
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::ffi::CString;
 use std::io::{Cursor, Write};
-use std::mem::size_of;
+
+/// Version of assembly format, stored in last byte of assembly magic.
+pub const ASSEMBLY_VERSION: u8 = 3;
 
 pub type OpsMap = HashMap<String, (u64, Option<CoreType>)>;
+pub type OpsLocations = Vec<(u64, Span)>;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum OpIndex {
     NoOp = 0,
     DataPointer = 1,
@@ -35,29 +43,48 @@ pub enum OpIndex {
     ExecuteOpInlineStop = 14,
     ProduceTuple = 15,
     CallFunction = 16,
+    LabelAddress = 17,
+    StoreTypedTargetAddress = 18,
+    StoreTypedParamAddress = 19,
+    ExecuteOpInlineTypedStop = 20,
 }
 
-impl From<u8> for OpIndex {
-    fn from(v: u8) -> Self {
+impl OpIndex {
+    pub fn from_byte(v: u8) -> Option<Self> {
         match v {
-            0 => OpIndex::NoOp,
-            1 => OpIndex::DataPointer,
-            2 => OpIndex::ParamsPointer,
-            3 => OpIndex::ResultPointer,
-            4 => OpIndex::LocalsPointer,
-            5 => OpIndex::GlobalsPointer,
-            6 => OpIndex::OffsetPointer,
-            7 => OpIndex::ReferencePointer,
-            8 => OpIndex::DereferencePointer,
-            9 => OpIndex::StoreTargetAddress,
-            10 => OpIndex::StoreParamAddress,
-            11 => OpIndex::ExecuteOpStart,
-            12 => OpIndex::ExecuteOpStop,
-            13 => OpIndex::ExecuteOpInlineStart,
-            14 => OpIndex::ExecuteOpInlineStop,
-            15 => OpIndex::ProduceTuple,
-            16 => OpIndex::CallFunction,
-            _ => panic!("Unsupported op index: {}", v),
+            0 => Some(OpIndex::NoOp),
+            1 => Some(OpIndex::DataPointer),
+            2 => Some(OpIndex::ParamsPointer),
+            3 => Some(OpIndex::ResultPointer),
+            4 => Some(OpIndex::LocalsPointer),
+            5 => Some(OpIndex::GlobalsPointer),
+            6 => Some(OpIndex::OffsetPointer),
+            7 => Some(OpIndex::ReferencePointer),
+            8 => Some(OpIndex::DereferencePointer),
+            9 => Some(OpIndex::StoreTargetAddress),
+            10 => Some(OpIndex::StoreParamAddress),
+            11 => Some(OpIndex::ExecuteOpStart),
+            12 => Some(OpIndex::ExecuteOpStop),
+            13 => Some(OpIndex::ExecuteOpInlineStart),
+            14 => Some(OpIndex::ExecuteOpInlineStop),
+            15 => Some(OpIndex::ProduceTuple),
+            16 => Some(OpIndex::CallFunction),
+            17 => Some(OpIndex::LabelAddress),
+            18 => Some(OpIndex::StoreTypedTargetAddress),
+            19 => Some(OpIndex::StoreTypedParamAddress),
+            20 => Some(OpIndex::ExecuteOpInlineTypedStop),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for OpIndex {
+    type Error = SimpleError;
+
+    fn try_from(v: u8) -> SimpleResult<Self> {
+        match Self::from_byte(v) {
+            Some(v) => Ok(v),
+            None => Err(SimpleError::new(format!("Unsupported op index: {}", v))),
         }
     }
 }
@@ -80,31 +107,71 @@ pub enum DataType {
     StringU8 = 13,
 }
 
-impl From<u8> for DataType {
-    fn from(v: u8) -> Self {
+impl DataType {
+    pub fn from_byte(v: u8) -> Option<Self> {
         match v {
-            0 => DataType::Unknown,
-            1 => DataType::I8,
-            2 => DataType::U8,
-            3 => DataType::I16,
-            4 => DataType::U16,
-            5 => DataType::I32,
-            6 => DataType::U32,
-            7 => DataType::I64,
-            8 => DataType::U64,
-            9 => DataType::F32,
-            10 => DataType::F64,
-            11 => DataType::Isize,
-            12 => DataType::Usize,
-            13 => DataType::StringU8,
-            _ => panic!("Unsupported data type: {}", v),
+            0 => Some(DataType::Unknown),
+            1 => Some(DataType::I8),
+            2 => Some(DataType::U8),
+            3 => Some(DataType::I16),
+            4 => Some(DataType::U16),
+            5 => Some(DataType::I32),
+            6 => Some(DataType::U32),
+            7 => Some(DataType::I64),
+            8 => Some(DataType::U64),
+            9 => Some(DataType::F32),
+            10 => Some(DataType::F64),
+            11 => Some(DataType::Isize),
+            12 => Some(DataType::Usize),
+            13 => Some(DataType::StringU8),
+            _ => None,
         }
     }
 }
 
-pub fn encode_assembly(program: &CoreProgram, ops: &OpsDescriptor) -> SimpleResult<Vec<u8>> {
-    let assembly = Assembly::from_core(program)?;
-    assembly.to_bytes(ops)
+impl TryFrom<u8> for DataType {
+    type Error = SimpleError;
+
+    fn try_from(v: u8) -> SimpleResult<Self> {
+        match Self::from_byte(v) {
+            Some(v) => Ok(v),
+            None => Err(SimpleError::new(format!("Unsupported data type: {}", v))),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AssemblyOptions {
+    pub debug_info: bool,
+    /// Store resolved type of each op param and target in function bodies.
+    pub operand_types: bool,
+    /// Target machine that assembly is laid out for, recorded in assembly header.
+    pub target: Target,
+}
+
+pub fn encode_assembly(
+    program: &CoreProgram,
+    ops: &OpsDescriptor,
+    debug_info: bool,
+) -> DiagnosticResult<Vec<u8>> {
+    encode_assembly_with_options(
+        program,
+        ops,
+        &AssemblyOptions {
+            debug_info,
+            ..Default::default()
+        },
+    )
+}
+
+pub fn encode_assembly_with_options(
+    program: &CoreProgram,
+    ops: &OpsDescriptor,
+    options: &AssemblyOptions,
+) -> DiagnosticResult<Vec<u8>> {
+    Assembly::from_core_with_target(program, &options.target)
+        .and_then(|assembly| assembly.to_bytes_with_options(ops, options))
+        .map_err(|err| err.with_code(codes::ASSEMBLY).into())
 }
 
 fn write_core_type(
@@ -137,6 +204,22 @@ fn write_core_type(
     Ok(())
 }
 
+fn find_op_label_params(id: &str, opsdesc: &OpsDescriptor) -> Vec<usize> {
+    opsdesc
+        .rules
+        .iter()
+        .find(|r| r.id == id)
+        .map(|r| {
+            r.params
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| p.is_label())
+                .map(|(i, _)| i)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 fn write_string(value: &str, stream: &mut dyn Write) -> SimpleResult<()> {
     stream.write_u64::<BigEndian>(value.as_bytes().len() as u64)?;
     stream.write(value.as_bytes())?;
@@ -146,6 +229,7 @@ fn write_string(value: &str, stream: &mut dyn Write) -> SimpleResult<()> {
 #[derive(Debug, Clone)]
 pub struct Assembly {
     magic: [u8; 4],
+    target: Target,
     structs: Vec<Struct>,
     globals: Vec<Variable>,
     functions: Vec<Function>,
@@ -155,21 +239,18 @@ pub struct Assembly {
 }
 
 impl Assembly {
+    #[inline]
     pub fn from_core(program: &CoreProgram) -> SimpleResult<Self> {
-        let mut structs = vec![
-            Struct::new_atom(0, "i8", 1),
-            Struct::new_atom(1, "u8", 1),
-            Struct::new_atom(2, "i16", 2),
-            Struct::new_atom(3, "u16", 2),
-            Struct::new_atom(4, "i32", 4),
-            Struct::new_atom(5, "u32", 4),
-            Struct::new_atom(6, "i64", 8),
-            Struct::new_atom(7, "u64", 8),
-            Struct::new_atom(8, "f32", 4),
-            Struct::new_atom(9, "f64", 8),
-            Struct::new_atom(10, "isize", size_of::<isize>()),
-            Struct::new_atom(11, "usize", size_of::<usize>()),
-        ];
+        Self::from_core_with_target(program, &Target::default())
+    }
+
+    pub fn from_core_with_target(program: &CoreProgram, target: &Target) -> SimpleResult<Self> {
+        target.validate()?;
+        let mut structs = ATOMS
+            .iter()
+            .enumerate()
+            .map(|(index, id)| Struct::new_atom(index, id, target.atom_size(index)))
+            .collect::<Vec<_>>();
         let mut globals = vec![];
         let mut functions = vec![];
         let mut modules = vec![];
@@ -180,7 +261,7 @@ impl Assembly {
                 .iter()
                 .map(|struct_| {
                     let index = structs.len();
-                    let s = Struct::from_core(index, struct_, program)?;
+                    let s = Struct::from_core(index, struct_, program, target)?;
                     structs.push(s);
                     Ok(index)
                 })
@@ -190,7 +271,7 @@ impl Assembly {
                 .iter()
                 .map(|global| {
                     let index = globals.len();
-                    let g = Variable::from_core(index, global, program, None)?;
+                    let g = Variable::from_core(index, global, program, target, None)?;
                     globals.push(g);
                     Ok(index)
                 })
@@ -200,7 +281,7 @@ impl Assembly {
                 .iter()
                 .map(|function| {
                     let index = functions.len();
-                    let f = Function::from_core(index, function, program)?;
+                    let f = Function::from_core(index, function, &module.path, program, target)?;
                     functions.push(f);
                     Ok(index)
                 })
@@ -258,14 +339,15 @@ impl Assembly {
                 modules[index].functions.extend(ff);
             }
         }
-        let mut extern_functions = HashMap::<String, Function>::new();
+        let mut extern_functions = Vec::<(String, Function)>::new();
         for module in &program.modules {
             for extern_ in &module.externs {
-                let id = extern_.item.to_string();
-                if !extern_functions.contains_key(&id) {
+                let id = extern_.item.id.clone();
+                if !extern_functions.iter().any(|(i, _)| i == &id) {
                     let index = functions.len() + extern_functions.len();
-                    let f = Function::from_core_extern(index, extern_, program)?;
-                    extern_functions.insert(id, f);
+                    let f =
+                        Function::from_core_extern(index, extern_, &module.path, program, target)?;
+                    extern_functions.push((id, f));
                 }
             }
         }
@@ -275,7 +357,7 @@ impl Assembly {
                 let id = extern_.item.id.clone();
                 let index = extern_functions
                     .iter()
-                    .find(|(i, _)| i == &&id)
+                    .find(|(i, _)| i == &id)
                     .unwrap()
                     .1
                     .index;
@@ -294,7 +376,13 @@ impl Assembly {
             .filter_map(|f| if f.export { Some(f.index) } else { None })
             .collect();
         Ok(Self {
-            magic: program.magic,
+            magic: [
+                program.magic[0],
+                program.magic[1],
+                program.magic[2],
+                ASSEMBLY_VERSION,
+            ],
+            target: *target,
             structs,
             globals,
             functions,
@@ -304,7 +392,22 @@ impl Assembly {
         })
     }
 
-    pub fn to_bytes(&self, ops: &OpsDescriptor) -> SimpleResult<Vec<u8>> {
+    #[inline]
+    pub fn to_bytes(&self, ops: &OpsDescriptor, debug_info: bool) -> SimpleResult<Vec<u8>> {
+        self.to_bytes_with_options(
+            ops,
+            &AssemblyOptions {
+                debug_info,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn to_bytes_with_options(
+        &self,
+        ops: &OpsDescriptor,
+        options: &AssemblyOptions,
+    ) -> SimpleResult<Vec<u8>> {
         let mut stream = Cursor::new(vec![]);
         let export_structs = {
             let mut stream = Cursor::new(vec![]);
@@ -354,7 +457,7 @@ impl Assembly {
             }
             stream.into_inner()
         };
-        let (data_offsets, data) = self.collect_data()?;
+        let (data_offsets, data) = self.collect_data(ops)?;
         let (globals, globals_size) = {
             let mut result = HashMap::new();
             let mut offset = 0;
@@ -364,16 +467,28 @@ impl Assembly {
             }
             (result, offset)
         };
-        let (ops_map, ops) = self.collect_ops(ops)?;
+        let (ops_map, ops_bytes) = self.collect_ops(ops)?;
         let bodies = self
             .functions
             .iter()
-            .map(|i| Ok((i, i.to_body_bytes(&ops_map, &data_offsets, &globals, self)?)))
-            .collect::<SimpleResult<Vec<(&Function, Vec<u8>)>>>()?;
+            .map(|i| {
+                let (bytes, locations) = i
+                    .to_body_bytes(
+                        ops,
+                        &ops_map,
+                        &data_offsets,
+                        &globals,
+                        self,
+                        options.operand_types,
+                    )
+                    .map_err(|err| err.with_path(&i.path))?;
+                Ok((i, bytes, locations))
+            })
+            .collect::<SimpleResult<Vec<_>>>()?;
         let bodies_offsets = {
             let mut stream = Cursor::new(vec![]);
             let mut offset = 0;
-            for (i, b) in &bodies {
+            for (i, b, _) in &bodies {
                 stream.write_u64::<BigEndian>(i.index() as u64)?;
                 stream.write_u64::<BigEndian>(offset)?;
                 offset += b.len() as u64;
@@ -382,6 +497,7 @@ impl Assembly {
         };
 
         stream.write(&self.magic)?;
+        self.target.write(&mut stream)?;
 
         stream.write_u64::<BigEndian>(export_structs.len() as u64)?;
         stream.write_u64::<BigEndian>(self.export_structs.len() as u64)?;
@@ -411,18 +527,67 @@ impl Assembly {
 
         stream.write_u64::<BigEndian>(globals_size)?;
 
-        stream.write_u64::<BigEndian>(ops.len() as u64)?;
+        stream.write_u64::<BigEndian>(ops_bytes.len() as u64)?;
         stream.write_u64::<BigEndian>(ops_map.len() as u64)?;
-        stream.write(&ops)?;
+        stream.write(&ops_bytes)?;
 
         stream.write_u64::<BigEndian>(bodies_offsets.len() as u64)?;
         stream.write_u64::<BigEndian>(bodies.len() as u64)?;
         stream.write(&bodies_offsets)?;
-        for (_, b) in &bodies {
+        for (_, b, _) in &bodies {
             stream.write_u64::<BigEndian>(b.len() as u64)?;
             stream.write(&b)?;
         }
 
+        if options.debug_info {
+            let locations = bodies.into_iter().map(|(_, _, l)| l).collect::<Vec<_>>();
+            let debug = self.to_debug_bytes(&globals, &locations)?;
+            stream.write_u64::<BigEndian>(debug.len() as u64)?;
+            stream.write(&debug)?;
+        }
+
+        Ok(stream.into_inner())
+    }
+
+    fn to_debug_bytes(
+        &self,
+        globals: &HashMap<String, u64>,
+        locations: &[OpsLocations],
+    ) -> SimpleResult<Vec<u8>> {
+        let mut stream = Cursor::new(vec![]);
+        stream.write_u64::<BigEndian>(self.structs.len() as u64)?;
+        for s in &self.structs {
+            write_string(s.id(), &mut stream)?;
+            stream.write_u64::<BigEndian>(s.fields().len() as u64)?;
+            for f in s.fields() {
+                write_string(f.id(), &mut stream)?;
+            }
+        }
+        stream.write_u64::<BigEndian>(self.globals.len() as u64)?;
+        for g in &self.globals {
+            write_string(g.id(), &mut stream)?;
+            stream.write_u64::<BigEndian>(globals[g.id()])?;
+            write_core_type(g.typeid(), &mut stream, self)?;
+        }
+        stream.write_u64::<BigEndian>(self.functions.len() as u64)?;
+        for (f, locations) in self.functions.iter().zip(locations.iter()) {
+            write_string(f.id(), &mut stream)?;
+            write_string(f.path(), &mut stream)?;
+            stream.write_u64::<BigEndian>(f.params().len() as u64)?;
+            for p in f.params() {
+                write_string(p.id(), &mut stream)?;
+            }
+            stream.write_u64::<BigEndian>(f.locals().len() as u64)?;
+            for l in f.locals() {
+                write_string(l.id(), &mut stream)?;
+            }
+            stream.write_u64::<BigEndian>(locations.len() as u64)?;
+            for (address, span) in locations {
+                stream.write_u64::<BigEndian>(*address)?;
+                stream.write_u64::<BigEndian>(span.line as u64)?;
+                stream.write_u64::<BigEndian>(span.column as u64)?;
+            }
+        }
         Ok(stream.into_inner())
     }
 
@@ -431,6 +596,11 @@ impl Assembly {
         &self.magic
     }
 
+    #[inline]
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
     #[inline]
     pub fn structs(&self) -> &[Struct] {
         &self.structs
@@ -484,15 +654,24 @@ impl Assembly {
         None
     }
 
-    fn collect_data(&self) -> SimpleResult<(HashMap<String, u64>, Vec<u8>)> {
+    fn collect_data(
+        &self,
+        opsdesc: &OpsDescriptor,
+    ) -> SimpleResult<(HashMap<String, u64>, Vec<u8>)> {
         let mut stream = Cursor::new(vec![]);
         let mut offsets = HashMap::new();
         let mut offset = 0;
         for f in &self.functions {
             for o in &f.body {
                 if let CoreBlockOp::Operation(o) = o {
-                    for p in &o.params {
-                        offset = self.collect_op_data(p, &mut stream, &mut offsets, offset)?;
+                    let labels = find_op_label_params(&o.id, opsdesc);
+                    for (i, p) in o.params.iter().enumerate() {
+                        if labels.contains(&i) {
+                            continue;
+                        }
+                        offset = self
+                            .collect_op_data(p, &mut stream, &mut offsets, offset)
+                            .map_err(|err| err.with_span(p.span()).with_path(&f.path))?;
                     }
                 }
             }
@@ -508,21 +687,21 @@ impl Assembly {
         mut offset: usize,
     ) -> SimpleResult<usize> {
         match value {
-            CoreValue::Ref(ref v, _) => self.collect_op_data(v, stream, offsets, offset),
-            CoreValue::Deref(ref v, _) => self.collect_op_data(v, stream, offsets, offset),
-            CoreValue::FunctionCall(_, ref v, _) => {
+            CoreValue::Ref(ref v, _, _) => self.collect_op_data(v, stream, offsets, offset),
+            CoreValue::Deref(ref v, _, _) => self.collect_op_data(v, stream, offsets, offset),
+            CoreValue::FunctionCall(_, ref v, _, _) => {
                 for v in v {
                     offset = self.collect_op_data(v, stream, offsets, offset)?;
                 }
                 Ok(offset)
             }
-            CoreValue::Tuple(ref v, _) => {
+            CoreValue::Tuple(ref v, _, _) => {
                 for v in v {
                     offset = self.collect_op_data(v, stream, offsets, offset)?;
                 }
                 Ok(offset)
             }
-            CoreValue::String(ref s, ref t) => {
+            CoreValue::String(ref s, ref t, _) => {
                 let id = format!("___CONST_STRING_{}", hash(s));
                 if !offsets.contains_key(&id) {
                     if let Ok(ref cs) = CString::new(s.as_str()) {
@@ -532,7 +711,7 @@ impl Assembly {
                                 write_string(s, stream)?;
                                 offset += cs.as_bytes_with_nul().len();
                                 offsets.insert(id, offset as u64);
-                                Ok(offset + size_of::<usize>())
+                                Ok(offset + self.target.pointer_size())
                             }
                             _ => Err(SimpleError::new(format!(
                                 "Trying to store constant as non-string bytes type `{}`",
@@ -549,7 +728,7 @@ impl Assembly {
                     Ok(offset)
                 }
             }
-            CoreValue::Number(ref n) => match n {
+            CoreValue::Number(ref n, _) => match n {
                 CoreNumber::Integer(i, ref t) => {
                     let id = format!("___CONST_INTEGER_{}", i);
                     if !offsets.contains_key(&id) {
@@ -604,15 +783,17 @@ impl Assembly {
                             }
                             "isize" => {
                                 stream.write_u8(DataType::Isize as u8)?;
-                                stream.write_int::<BigEndian>(*i as i64, size_of::<isize>())?;
+                                let size = self.target.pointer_size();
+                                stream.write_int::<BigEndian>(*i as i64, size)?;
                                 offsets.insert(id, offset as u64);
-                                Ok(offset + size_of::<isize>())
+                                Ok(offset + size)
                             }
                             "usize" => {
                                 stream.write_u8(DataType::Usize as u8)?;
-                                stream.write_uint::<BigEndian>(*i as u64, size_of::<usize>())?;
+                                let size = self.target.pointer_size();
+                                stream.write_uint::<BigEndian>(*i as u64, size)?;
                                 offsets.insert(id, offset as u64);
-                                Ok(offset + size_of::<usize>())
+                                Ok(offset + size)
                             }
                             _ => Err(SimpleError::new(format!(
                                 "Trying to store constant as non-integer type `{}`",
@@ -649,13 +830,13 @@ impl Assembly {
                     }
                 }
             },
-            CoreValue::OperationInline(_, ref v, _) => {
+            CoreValue::OperationInline(_, ref v, _, _) => {
                 for v in v {
                     offset = self.collect_op_data(v, stream, offsets, offset)?;
                 }
                 Ok(offset)
             }
-            CoreValue::Variable(_, _) => Ok(offset),
+            CoreValue::Variable(_, _, _) => Ok(offset),
         }
     }
 
@@ -692,21 +873,21 @@ impl Assembly {
         index: &mut u64,
     ) -> SimpleResult<()> {
         match value {
-            CoreValue::Ref(v, _) => self.collect_value_ops(v, opsdesc, stream, ops, index),
-            CoreValue::Deref(v, _) => self.collect_value_ops(v, opsdesc, stream, ops, index),
-            CoreValue::FunctionCall(_, v, _) => {
+            CoreValue::Ref(v, _, _) => self.collect_value_ops(v, opsdesc, stream, ops, index),
+            CoreValue::Deref(v, _, _) => self.collect_value_ops(v, opsdesc, stream, ops, index),
+            CoreValue::FunctionCall(_, v, _, _) => {
                 for v in v {
                     self.collect_value_ops(v, opsdesc, stream, ops, index)?;
                 }
                 Ok(())
             }
-            CoreValue::Tuple(v, _) => {
+            CoreValue::Tuple(v, _, _) => {
                 for v in v {
                     self.collect_value_ops(v, opsdesc, stream, ops, index)?;
                 }
                 Ok(())
             }
-            CoreValue::OperationInline(id, v, _) => {
+            CoreValue::OperationInline(id, v, _, _) => {
                 if !ops.contains_key(id) {
                     write_string(id, stream)?;
                     ops.insert(id.to_owned(), (*index, self.find_op_type(id, opsdesc)));
@@ -729,6 +910,39 @@ impl Assembly {
             .map(|r| r.targets[0].clone())
     }
 
+    /// Stores op operand address, with its type if types are requested and can be resolved.
+    fn write_operand_store(
+        &self,
+        value: &CoreValue,
+        stream: &mut Cursor<Vec<u8>>,
+        function: &Function,
+        data: &HashMap<String, u64>,
+        ops: &HashMap<String, (u64, Option<CoreType>)>,
+        typed: bool,
+        target: bool,
+    ) -> SimpleResult<()> {
+        let typeid = if typed {
+            self.find_value_type(value, function, data, ops).ok()
+        } else {
+            None
+        };
+        if let Some(typeid) = typeid {
+            if target {
+                stream.write_u8(OpIndex::StoreTypedTargetAddress as u8)?;
+            } else {
+                stream.write_u8(OpIndex::StoreTypedParamAddress as u8)?;
+            }
+            write_core_type(&typeid, stream, self)
+        } else {
+            if target {
+                stream.write_u8(OpIndex::StoreTargetAddress as u8)?;
+            } else {
+                stream.write_u8(OpIndex::StoreParamAddress as u8)?;
+            }
+            Ok(())
+        }
+    }
+
     fn write_core_value(
         &self,
         value: &CoreValue,
@@ -737,10 +951,11 @@ impl Assembly {
         data: &HashMap<String, u64>,
         globals: &HashMap<String, u64>,
         ops: &HashMap<String, (u64, Option<CoreType>)>,
+        typed: bool,
     ) -> SimpleResult<()> {
         match value {
-            CoreValue::Ref(ref v, ref a) => {
-                self.write_core_value(v, stream, function, data, globals, ops)?;
+            CoreValue::Ref(ref v, ref a, _) => {
+                self.write_core_value(v, stream, function, data, globals, ops, typed)?;
                 stream.write_u8(OpIndex::ReferencePointer as u8)?;
                 if a.is_some() {
                     Err(SimpleError::new(
@@ -751,8 +966,8 @@ impl Assembly {
                     Ok(())
                 }
             }
-            CoreValue::Deref(ref v, ref a) => {
-                self.write_core_value(v, stream, function, data, globals, ops)?;
+            CoreValue::Deref(ref v, ref a, _) => {
+                self.write_core_value(v, stream, function, data, globals, ops, typed)?;
                 stream.write_u8(OpIndex::DereferencePointer as u8)?;
                 if let Some(ref a) = a {
                     stream.write_u8(OpIndex::OffsetPointer as u8)?;
@@ -761,10 +976,10 @@ impl Assembly {
                 }
                 Ok(())
             }
-            CoreValue::FunctionCall(ref id, ref v, ref a) => {
+            CoreValue::FunctionCall(ref id, ref v, ref a, _) => {
                 let f = self.find_function(id).unwrap();
                 for v in v.iter().rev() {
-                    self.write_core_value(v, stream, function, data, globals, ops)?;
+                    self.write_core_value(v, stream, function, data, globals, ops, typed)?;
                 }
                 stream.write_u8(OpIndex::CallFunction as u8)?;
                 stream.write_u64::<BigEndian>(f.index() as u64)?;
@@ -783,9 +998,9 @@ impl Assembly {
                     Ok(())
                 }
             }
-            CoreValue::Tuple(ref v, ref a) => {
+            CoreValue::Tuple(ref v, ref a, _) => {
                 for v in v.iter().rev() {
-                    self.write_core_value(v, stream, function, data, globals, ops)?;
+                    self.write_core_value(v, stream, function, data, globals, ops, typed)?;
                 }
                 stream.write_u8(OpIndex::ProduceTuple as u8)?;
                 stream.write_u64::<BigEndian>(v.len() as u64)?;
@@ -803,12 +1018,12 @@ impl Assembly {
                 }
                 Ok(())
             }
-            CoreValue::String(ref s, _) => {
+            CoreValue::String(ref s, _, _) => {
                 stream.write_u8(OpIndex::DataPointer as u8)?;
                 stream.write_u64::<BigEndian>(data[&format!("___CONST_STRING_{}", hash(s))])?;
                 Ok(())
             }
-            CoreValue::Number(ref n) => {
+            CoreValue::Number(ref n, _) => {
                 stream.write_u8(OpIndex::DataPointer as u8)?;
                 match n {
                     CoreNumber::Integer(ref v, _) => {
@@ -821,23 +1036,30 @@ impl Assembly {
                     }
                 }
             }
-            CoreValue::OperationInline(ref id, ref v, ref a) => {
+            CoreValue::OperationInline(ref id, ref v, ref a, _) => {
                 stream.write_u8(OpIndex::ExecuteOpInlineStart as u8)?;
                 for v in v {
-                    self.write_core_value(v, stream, function, data, globals, ops)?;
-                    stream.write_u8(OpIndex::StoreParamAddress as u8)?;
+                    self.write_core_value(v, stream, function, data, globals, ops, typed)?;
+                    self.write_operand_store(v, stream, function, data, ops, typed, false)?;
                 }
-                stream.write_u8(OpIndex::ExecuteOpInlineStop as u8)?;
-                stream.write_u64::<BigEndian>(ops[id].0)?;
                 let t = ops[id].1.clone().unwrap();
+                if typed {
+                    stream.write_u8(OpIndex::ExecuteOpInlineTypedStop as u8)?;
+                } else {
+                    stream.write_u8(OpIndex::ExecuteOpInlineStop as u8)?;
+                }
+                stream.write_u64::<BigEndian>(ops[id].0)?;
                 stream.write_u64::<BigEndian>(self.type_size(&t) as u64)?;
+                if typed {
+                    write_core_type(&t, stream, self)?;
+                }
                 if let Some(ref a) = a {
                     stream.write_u8(OpIndex::OffsetPointer as u8)?;
                     self.write_core_value_access(stream, &t, a)?;
                 }
                 Ok(())
             }
-            CoreValue::Variable(ref id, ref a) => {
+            CoreValue::Variable(ref id, ref a, _) => {
                 let t = if let Some(v) = function.params().iter().find(|v| v.id() == id) {
                     stream.write_u8(OpIndex::ParamsPointer as u8)?;
                     stream.write_u64::<BigEndian>(v.offset().unwrap() as u64)?;
@@ -960,7 +1182,7 @@ impl Assembly {
     pub fn type_size(&self, typeid: &CoreType) -> usize {
         match typeid {
             CoreType::Identifier(id) => self.structs.iter().find(|s| s.id() == id).unwrap().size(),
-            CoreType::Pointer(_) => size_of::<usize>(),
+            CoreType::Pointer(_) => self.target.pointer_size(),
             CoreType::Tuple(t) => t.iter().map(|t| self.type_size(t)).sum(),
         }
     }
@@ -973,7 +1195,7 @@ impl Assembly {
         ops: &HashMap<String, (u64, Option<CoreType>)>,
     ) -> SimpleResult<CoreType> {
         match value {
-            CoreValue::Ref(ref v, ref a) => {
+            CoreValue::Ref(ref v, ref a, _) => {
                 let t = CoreType::Pointer(Box::new(self.find_value_type(v, function, data, ops)?));
                 if a.is_some() {
                     Err(SimpleError::new(
@@ -984,7 +1206,7 @@ impl Assembly {
                     Ok(t)
                 }
             }
-            CoreValue::Deref(ref v, ref a) => {
+            CoreValue::Deref(ref v, ref a, _) => {
                 if let CoreType::Pointer(t) = self.find_value_type(v, function, data, ops)? {
                     if let Some(ref a) = a {
                         self.find_access_type(&t, a, function, data)
@@ -998,7 +1220,7 @@ impl Assembly {
                     )))
                 }
             }
-            CoreValue::FunctionCall(ref id, _, ref a) => {
+            CoreValue::FunctionCall(ref id, _, ref a, _) => {
                 if let Some(f) = self.find_function(id) {
                     if let Some(ref t) = f.typeid() {
                         if let Some(ref a) = a {
@@ -1016,7 +1238,7 @@ impl Assembly {
                     )))
                 }
             }
-            CoreValue::Tuple(ref v, ref a) => {
+            CoreValue::Tuple(ref v, ref a, _) => {
                 let mut t = vec![];
                 for v in v {
                     t.push(self.find_value_type(v, function, data, ops)?);
@@ -1028,12 +1250,12 @@ impl Assembly {
                     Ok(t)
                 }
             }
-            CoreValue::String(_, ref t) => Ok(t.clone()),
-            CoreValue::Number(ref n) => Ok(match n {
+            CoreValue::String(_, ref t, _) => Ok(t.clone()),
+            CoreValue::Number(ref n, _) => Ok(match n {
                 CoreNumber::Integer(_, ref t) => t.clone(),
                 CoreNumber::Float(_, ref t) => t.clone(),
             }),
-            CoreValue::OperationInline(ref id, _, ref a) => {
+            CoreValue::OperationInline(ref id, _, ref a, _) => {
                 let t = if let Some((_, Some(t))) = ops.get(id) {
                     Ok(t.clone())
                 } else {
@@ -1048,13 +1270,15 @@ impl Assembly {
                     Ok(t)
                 }
             }
-            CoreValue::Variable(ref id, ref a) => {
+            CoreValue::Variable(ref id, ref a, _) => {
                 let t = if let Some(v) = function.params().iter().find(|v| v.id() == id) {
                     v.typeid()
                 } else if let Some(v) = function.locals().iter().find(|v| v.id() == id) {
                     v.typeid()
                 } else if let Some(v) = self.globals.iter().find(|v| v.id() == id) {
                     v.typeid()
+                } else if let (true, Some(t)) = (id == "_", function.typeid()) {
+                    t
                 } else {
                     return Err(SimpleError::new(format!(
                         "Trying to get type of non-existing symbol: {}",
@@ -1136,6 +1360,7 @@ pub struct Struct {
     id: String,
     fields: Vec<StructField>,
     size: usize,
+    alignment: usize,
     export: bool,
 }
 
@@ -1146,27 +1371,34 @@ impl Struct {
             id: id.to_owned(),
             fields: vec![],
             size,
+            alignment: 1,
             export: false,
         }
     }
 
+    /// Lays out fields aligned for given target; size is padded to struct alignment.
     pub fn from_core(
         index: usize,
         struct_: &CoreStruct,
         program: &CoreProgram,
+        target: &Target,
     ) -> SimpleResult<Self> {
         let mut fields = vec![];
         let mut offset = 0;
+        let mut alignment = 1;
         for f in &struct_.fields {
-            let field = StructField::from_core(f, offset, program)?;
-            offset += field.size;
+            let a = calculate_type_alignment(&f.typeid, program, target);
+            let field = StructField::from_core(f, align_offset(offset, a), program, target)?;
+            offset = field.offset + field.size;
+            alignment = alignment.max(a);
             fields.push(field);
         }
         Ok(Self {
             index,
             id: struct_.id.clone(),
             fields,
-            size: offset,
+            size: align_offset(offset, alignment),
+            alignment,
             export: struct_.export,
         })
     }
@@ -1203,6 +1435,11 @@ impl Struct {
         self.size
     }
 
+    #[inline]
+    pub fn alignment(&self) -> usize {
+        self.alignment
+    }
+
     #[inline]
     pub fn export(&self) -> bool {
         self.export
@@ -1227,12 +1464,13 @@ impl StructField {
         field: &CoreVariable,
         offset: usize,
         program: &CoreProgram,
+        target: &Target,
     ) -> SimpleResult<Self> {
         Ok(Self {
             id: field.id.clone(),
             typeid: field.typeid.clone(),
             offset,
-            size: calculate_type_size(&field.typeid, program),
+            size: calculate_type_size(&field.typeid, program, target),
         })
     }
 
@@ -1278,13 +1516,14 @@ impl Variable {
         index: usize,
         variable: &CoreVariable,
         program: &CoreProgram,
+        target: &Target,
         offset: Option<usize>,
     ) -> SimpleResult<Self> {
         Ok(Self {
             index,
             id: variable.id.clone(),
             typeid: variable.typeid.clone(),
-            size: calculate_type_size(&variable.typeid, program),
+            size: calculate_type_size(&variable.typeid, program, target),
             offset,
         })
     }
@@ -1344,13 +1583,16 @@ pub struct Function {
     body: Vec<CoreBlockOp>,
     external: Option<(String, String)>,
     export: bool,
+    path: String,
 }
 
 impl Function {
     pub fn from_core(
         index: usize,
         function: &CoreFunction,
+        path: &str,
         program: &CoreProgram,
+        target: &Target,
     ) -> SimpleResult<Function> {
         let mut po = 0;
         let mut lo = 0;
@@ -1363,7 +1605,7 @@ impl Function {
                 .iter()
                 .enumerate()
                 .map(|(i, p)| {
-                    let v = Variable::from_core(i, p, program, Some(po))?;
+                    let v = Variable::from_core(i, p, program, target, Some(po))?;
                     po += v.size();
                     Ok(v)
                 })
@@ -1374,7 +1616,7 @@ impl Function {
                 .iter()
                 .enumerate()
                 .map(|(i, p)| {
-                    let v = Variable::from_core(i, p, program, Some(lo))?;
+                    let v = Variable::from_core(i, p, program, target, Some(lo))?;
                     lo += v.size();
                     Ok(v)
                 })
@@ -1382,13 +1624,16 @@ impl Function {
             body: function.body.clone(),
             external: None,
             export: function.export,
+            path: path.to_owned(),
         })
     }
 
     pub fn from_core_extern(
         index: usize,
         extern_: &CoreExtern,
+        path: &str,
         program: &CoreProgram,
+        target: &Target,
     ) -> SimpleResult<Function> {
         let mut po = 0;
         Ok(Self {
@@ -1400,7 +1645,7 @@ impl Function {
                 .iter()
                 .enumerate()
                 .map(|(i, p)| {
-                    let v = Variable::from_core(i, p, program, Some(po))?;
+                    let v = Variable::from_core(i, p, program, target, Some(po))?;
                     po += v.size();
                     Ok(v)
                 })
@@ -1413,6 +1658,7 @@ impl Function {
                 extern_.location_function.clone(),
             )),
             export: false,
+            path: path.to_owned(),
         })
     }
 
@@ -1446,38 +1692,102 @@ impl Function {
 
     pub fn to_body_bytes(
         &self,
+        opsdesc: &OpsDescriptor,
         ops: &HashMap<String, (u64, Option<CoreType>)>,
         data: &HashMap<String, u64>,
         globals: &HashMap<String, u64>,
         assembly: &Assembly,
-    ) -> SimpleResult<Vec<u8>> {
+        typed: bool,
+    ) -> SimpleResult<(Vec<u8>, OpsLocations)> {
         let mut stream_labels = Cursor::new(vec![]);
         let mut stream_ops = Cursor::new(vec![]);
+        let mut locations = vec![];
         let mut labels_count = 0;
         let mut ops_count = 0;
+        let mut labels = HashMap::new();
+        let mut labels_fixups = vec![];
         for op in &self.body {
             match op {
                 CoreBlockOp::Operation(op) => {
+                    let label_params = find_op_label_params(&op.id, opsdesc);
+                    locations.push((stream_ops.position(), op.span));
                     stream_ops.write_u8(OpIndex::ExecuteOpStart as u8)?;
                     for v in op.targets.iter() {
-                        assembly.write_core_value(v, &mut stream_ops, self, data, globals, ops)?;
-                        stream_ops.write_u8(OpIndex::StoreTargetAddress as u8)?;
+                        assembly
+                            .write_core_value(v, &mut stream_ops, self, data, globals, ops, typed)
+                            .map_err(|err| err.with_span(v.span()))?;
+                        assembly.write_operand_store(
+                            v,
+                            &mut stream_ops,
+                            self,
+                            data,
+                            ops,
+                            typed,
+                            true,
+                        )?;
                     }
-                    for v in op.params.iter() {
-                        assembly.write_core_value(v, &mut stream_ops, self, data, globals, ops)?;
-                        stream_ops.write_u8(OpIndex::StoreParamAddress as u8)?;
+                    for (i, v) in op.params.iter().enumerate() {
+                        if label_params.contains(&i) {
+                            if let CoreValue::String(ref name, _, span) = v {
+                                stream_ops.write_u8(OpIndex::LabelAddress as u8)?;
+                                labels_fixups.push((name, *span, stream_ops.position()));
+                                stream_ops.write_u64::<BigEndian>(0)?;
+                            } else {
+                                return Err(SimpleError::new(format!(
+                                    "Operation `{}` expects label name as parameter #{}",
+                                    op.id, i
+                                ))
+                                .with_span(v.span()));
+                            }
+                            stream_ops.write_u8(OpIndex::StoreParamAddress as u8)?;
+                        } else {
+                            assembly
+                                .write_core_value(
+                                    v,
+                                    &mut stream_ops,
+                                    self,
+                                    data,
+                                    globals,
+                                    ops,
+                                    typed,
+                                )
+                                .map_err(|err| err.with_span(v.span()))?;
+                            assembly.write_operand_store(
+                                v,
+                                &mut stream_ops,
+                                self,
+                                data,
+                                ops,
+                                typed,
+                                false,
+                            )?;
+                        }
                     }
                     stream_ops.write_u8(OpIndex::ExecuteOpStop as u8)?;
                     stream_ops.write_u64::<BigEndian>(ops[&op.id].0)?;
                     ops_count += 1;
                 }
-                CoreBlockOp::Label(name) => {
+                CoreBlockOp::Label(name, _) => {
                     write_string(name, &mut stream_labels)?;
                     stream_labels.write_u64::<BigEndian>(stream_ops.position())?;
+                    labels.insert(name, stream_ops.position());
                     labels_count += 1;
                 }
             }
         }
+        for (name, span, position) in labels_fixups {
+            if let Some(address) = labels.get(name) {
+                stream_ops.set_position(position);
+                stream_ops.write_u64::<BigEndian>(*address)?;
+            } else {
+                return Err(SimpleError::new(format!(
+                    "Function `{}` does not have `{}` label",
+                    self.id, name
+                ))
+                .with_span(span));
+            }
+        }
+        stream_ops.set_position(stream_ops.get_ref().len() as u64);
         let mut stream = Cursor::new(vec![]);
         stream.write_u64::<BigEndian>(stream_labels.position())?;
         stream.write_u64::<BigEndian>(labels_count)?;
@@ -1485,7 +1795,7 @@ impl Function {
         stream.write_u64::<BigEndian>(stream_ops.position())?;
         stream.write_u64::<BigEndian>(ops_count)?;
         stream.write(&stream_ops.into_inner())?;
-        Ok(stream.into_inner())
+        Ok((stream.into_inner(), locations))
     }
 
     #[inline]
@@ -1527,6 +1837,11 @@ impl Function {
     pub fn export(&self) -> bool {
         self.export
     }
+
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -1570,26 +1885,52 @@ impl Module {
     }
 }
 
-pub fn calculate_type_size(typeid: &CoreType, program: &CoreProgram) -> usize {
+pub fn calculate_type_size(typeid: &CoreType, program: &CoreProgram, target: &Target) -> usize {
     match typeid {
-        CoreType::Identifier(ref id) => match id.as_str() {
-            "i8" | "u8" => 1,
-            "i16" | "u16" => 2,
-            "i32" | "u32" | "f32" => 4,
-            "i64" | "u64" | "f64" => 8,
-            "isize" | "usize" => size_of::<usize>(),
-            _ => {
-                if let Some(s) = program.find_struct(id) {
-                    s.fields
-                        .iter()
-                        .map(|f| calculate_type_size(&f.typeid, program))
-                        .sum()
-                } else {
-                    0
+        CoreType::Identifier(ref id) => {
+            if let Some(index) = ATOMS.iter().position(|a| *a == id.as_str()) {
+                target.atom_size(index)
+            } else if let Some(s) = program.find_struct(id) {
+                let mut offset = 0;
+                for f in &s.fields {
+                    offset =
+                        align_offset(offset, calculate_type_alignment(&f.typeid, program, target))
+                            + calculate_type_size(&f.typeid, program, target);
                 }
+                align_offset(offset, calculate_type_alignment(typeid, program, target))
+            } else {
+                0
             }
-        },
-        CoreType::Pointer(_) => size_of::<usize>(),
-        CoreType::Tuple(ref v) => v.iter().map(|t| calculate_type_size(t, program)).sum(),
+        }
+        CoreType::Pointer(_) => target.pointer_size(),
+        CoreType::Tuple(ref v) => v
+            .iter()
+            .map(|t| calculate_type_size(t, program, target))
+            .sum(),
+    }
+}
+
+/// Alignment of type within struct; tuples are always packed.
+pub fn calculate_type_alignment(
+    typeid: &CoreType,
+    program: &CoreProgram,
+    target: &Target,
+) -> usize {
+    match typeid {
+        CoreType::Identifier(ref id) => {
+            if let Some(alignment) = target.alignment(id) {
+                alignment
+            } else if let Some(s) = program.find_struct(id) {
+                s.fields
+                    .iter()
+                    .map(|f| calculate_type_alignment(&f.typeid, program, target))
+                    .max()
+                    .unwrap_or(1)
+            } else {
+                1
+            }
+        }
+        CoreType::Pointer(_) => target.alignment("usize").unwrap_or(1),
+        CoreType::Tuple(_) => 1,
     }
 }
@@ -1,3 +1,4 @@
+use crate::error::Span;
 use serde_json;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -52,14 +53,29 @@ pub enum AstNumber {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum AstValue {
-    Ref(Box<AstValue>, Option<Box<AstAccess>>),
-    Deref(Box<AstValue>, Option<Box<AstAccess>>),
-    FunctionCall(AstIdentifier, Vec<AstValue>, Option<Box<AstAccess>>),
-    Tuple(Vec<AstValue>, Option<Box<AstAccess>>),
-    String(AstString),
-    Number(AstNumber),
-    OperationInline(AstIdentifier, Vec<AstValue>, Option<Box<AstAccess>>),
-    Variable(AstIdentifier, Option<Box<AstAccess>>),
+    Ref(Box<AstValue>, Option<Box<AstAccess>>, Span),
+    Deref(Box<AstValue>, Option<Box<AstAccess>>, Span),
+    FunctionCall(AstIdentifier, Vec<AstValue>, Option<Box<AstAccess>>, Span),
+    Tuple(Vec<AstValue>, Option<Box<AstAccess>>, Span),
+    String(AstString, Span),
+    Number(AstNumber, Span),
+    OperationInline(AstIdentifier, Vec<AstValue>, Option<Box<AstAccess>>, Span),
+    Variable(AstIdentifier, Option<Box<AstAccess>>, Span),
+}
+
+impl AstValue {
+    pub fn span(&self) -> Span {
+        match self {
+            AstValue::Ref(_, _, s) => *s,
+            AstValue::Deref(_, _, s) => *s,
+            AstValue::FunctionCall(_, _, _, s) => *s,
+            AstValue::Tuple(_, _, s) => *s,
+            AstValue::String(_, s) => *s,
+            AstValue::Number(_, s) => *s,
+            AstValue::OperationInline(_, _, _, s) => *s,
+            AstValue::Variable(_, _, s) => *s,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -72,6 +88,8 @@ pub enum AstAccess {
 pub struct AstVariable {
     pub id: AstIdentifier,
     pub typeid: AstType,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -119,6 +137,8 @@ pub struct AstImport {
     pub meta: Vec<AstMeta>,
     pub names: Vec<AstIdentifier>,
     pub module: AstString,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -127,6 +147,8 @@ pub struct AstExtern {
     pub item: AstFunctionHeader,
     pub location_module: AstIdentifier,
     pub location_function: AstIdentifier,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -135,6 +157,8 @@ pub struct AstStruct {
     pub export: bool,
     pub id: AstIdentifier,
     pub fields: Vec<AstVariable>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -144,6 +168,8 @@ pub struct AstFunction {
     pub header: AstFunctionHeader,
     pub locals: Vec<AstVariable>,
     pub ops: Vec<AstBlockOp>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -155,7 +181,7 @@ pub struct AstFunctionHeader {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum AstBlockOp {
-    Label(AstIdentifier),
+    Label(AstIdentifier, Span),
     Operation(AstOperation),
 }
 
@@ -165,6 +191,8 @@ pub struct AstOperation {
     pub id: AstIdentifier,
     pub params: Vec<AstValue>,
     pub targets: Vec<AstValue>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -185,7 +213,13 @@ pub struct AstOpRule {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AstOpParam {
     pub id: AstIdentifier,
-    pub typeid: AstType,
+    pub typeid: AstOpValue,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum AstOpValue {
+    Type(AstType),
+    Label,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
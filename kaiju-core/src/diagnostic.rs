@@ -0,0 +1,223 @@
+use crate::error::*;
+use serde_json;
+use std::fmt;
+
+pub mod codes {
+    pub const GENERIC: &str = "E0000";
+    pub const SYNTAX: &str = "E0001";
+    pub const MODULE: &str = "E0002";
+    pub const DUPLICATE: &str = "E0100";
+    pub const UNKNOWN_TYPE: &str = "E0101";
+    pub const UNKNOWN_VARIABLE: &str = "E0102";
+    pub const UNKNOWN_FUNCTION: &str = "E0103";
+    pub const UNKNOWN_OPERATION: &str = "E0104";
+    pub const TYPE_MISMATCH: &str = "E0105";
+    pub const ARITY: &str = "E0106";
+    pub const ACCESS: &str = "E0107";
+    pub const RECURSIVE_STRUCT: &str = "E0108";
+    pub const UNKNOWN_LABEL: &str = "E0109";
+    pub const ASSEMBLY: &str = "E0200";
+    pub const UNUSED_LOCAL: &str = "W0001";
+    pub const UNUSED_PARAM: &str = "W0002";
+    pub const UNUSED_LABEL: &str = "W0003";
+    pub const UNUSED_IMPORT: &str = "W0004";
+    pub const UNUSED_FUNCTION: &str = "W0005";
+    pub const UNREACHABLE: &str = "W0006";
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DiagnosticLabel {
+    pub path: Option<String>,
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Diagnostic {
+    pub code: String,
+    pub severity: Severity,
+    pub message: String,
+    pub path: Option<String>,
+    pub span: Option<Span>,
+    pub labels: Vec<DiagnosticLabel>,
+    pub help: Option<String>,
+}
+
+pub type DiagnosticResult<T> = Result<T, Vec<Diagnostic>>;
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &str, message: String) -> Self {
+        Self {
+            code: code.to_owned(),
+            severity,
+            message,
+            path: None,
+            span: None,
+            labels: vec![],
+            help: None,
+        }
+    }
+
+    #[inline]
+    pub fn error(code: &str, message: String) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    #[inline]
+    pub fn warning(code: &str, message: String) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    #[inline]
+    pub fn note(code: &str, message: String) -> Self {
+        Self::new(Severity::Note, code, message)
+    }
+
+    pub fn with_path(mut self, path: &str) -> Self {
+        if self.path.is_none() && !path.is_empty() {
+            self.path = Some(path.to_owned());
+        }
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        if self.span.is_none() && !span.is_empty() {
+            self.span = Some(span);
+        }
+        self
+    }
+
+    pub fn with_label(mut self, path: Option<&str>, span: Span, message: &str) -> Self {
+        if !span.is_empty() {
+            self.labels.push(DiagnosticLabel {
+                path: path.or(self.path.as_deref()).map(String::from),
+                span,
+                message: message.to_owned(),
+            });
+        }
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_owned());
+        self
+    }
+
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn location(&self) -> Option<String> {
+        let span = self.span?;
+        Some(location(&self.path, span))
+    }
+
+    pub fn pretty<F>(&self, load_source: F) -> String
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut result = format!("{}[{}]: {}", self.severity, self.code, self.message);
+        let width = self
+            .labels
+            .iter()
+            .map(|l| l.span.line)
+            .chain(self.span.iter().map(|s| s.line))
+            .max()
+            .unwrap_or(0)
+            .to_string()
+            .len();
+        let pad = " ".repeat(width);
+        if let Some(span) = self.span {
+            result.push_str(&format!("\n{}--> {}", pad, location(&self.path, span)));
+            if let Some(source) = self.path.as_ref().and_then(|p| load_source(p)) {
+                if let Some(snippet) = snippet(&source, span, &pad, "") {
+                    result.push_str(&format!("\n{} |\n{}", pad, snippet));
+                    result.push_str(&format!("{} |", pad));
+                }
+            }
+        }
+        for label in &self.labels {
+            result.push_str(&format!(
+                "\n{}::: {}",
+                pad,
+                location(&label.path, label.span)
+            ));
+            if let Some(source) = label.path.as_ref().and_then(|p| load_source(p)) {
+                if let Some(snippet) = snippet(&source, label.span, &pad, &label.message) {
+                    result.push_str(&format!("\n{} |\n{}", pad, snippet));
+                    result.push_str(&format!("{} |", pad));
+                    continue;
+                }
+            }
+            result.push_str(&format!(": {}", label.message));
+        }
+        if let Some(ref help) = self.help {
+            result.push_str(&format!("\n{} = help: {}", pad, help));
+        }
+        result
+    }
+
+    #[inline]
+    pub fn to_json(&self, pretty: bool) -> serde_json::Result<String> {
+        if pretty {
+            serde_json::to_string_pretty(self)
+        } else {
+            serde_json::to_string(self)
+        }
+    }
+}
+
+impl From<SimpleError> for Diagnostic {
+    fn from(error: SimpleError) -> Self {
+        let mut result = Self::error(error.code.unwrap_or(codes::GENERIC), error.message);
+        result.path = error.path;
+        result.span = error.span;
+        result
+    }
+}
+
+impl From<SimpleError> for Vec<Diagnostic> {
+    fn from(error: SimpleError) -> Self {
+        vec![error.into()]
+    }
+}
+
+impl From<Vec<Diagnostic>> for SimpleError {
+    fn from(diagnostics: Vec<Diagnostic>) -> Self {
+        let message = diagnostics
+            .iter()
+            .map(|d| {
+                if let Some(location) = d.location() {
+                    format!("{}: {}", location, d.message)
+                } else {
+                    d.message.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        Self::new(message)
+    }
+}
+
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.is_error())
+}
@@ -0,0 +1,458 @@
+use crate::assembly::OpIndex;
+use crate::error::*;
+use crate::vm::*;
+use byteorder::{BigEndian, ReadBytesExt};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::Cursor;
+
+pub fn disassemble_bytes(bytes: Vec<u8>) -> SimpleResult<String> {
+    disassemble(&VmAssembly::new(bytes)?)
+}
+
+pub fn disassemble(assembly: &VmAssembly) -> SimpleResult<String> {
+    Disassembler::new(assembly).run()
+}
+
+struct Disassembler<'a> {
+    assembly: &'a VmAssembly,
+    structs_names: HashMap<usize, &'a str>,
+    functions_names: HashMap<usize, &'a str>,
+    data_offsets: HashMap<usize, &'a Data>,
+    debug_info: Option<&'a DebugInfo>,
+    output: String,
+}
+
+impl<'a> Disassembler<'a> {
+    fn new(assembly: &'a VmAssembly) -> Self {
+        let data_offsets = assembly
+            .data_offsets()
+            .into_iter()
+            .zip(assembly.data().iter())
+            .collect();
+        let debug_info = assembly.debug_info().as_ref();
+        let mut structs_names = HashMap::new();
+        let mut functions_names = HashMap::new();
+        if let Some(debug_info) = debug_info {
+            for (i, s) in debug_info.structs().iter().enumerate() {
+                structs_names.insert(i, s.id());
+            }
+            for (i, f) in debug_info.functions().iter().enumerate() {
+                functions_names.insert(i, f.id());
+            }
+        }
+        for (k, v) in assembly.export_structs() {
+            structs_names.insert(*v, k.as_str());
+        }
+        for (k, v) in assembly.export_functions() {
+            functions_names.insert(*v, k.as_str());
+        }
+        Self {
+            assembly,
+            structs_names,
+            functions_names,
+            data_offsets,
+            debug_info,
+            output: String::new(),
+        }
+    }
+
+    fn run(mut self) -> SimpleResult<String> {
+        writeln!(self.output, "target: {}", self.assembly.target())?;
+        self.write_exports()?;
+        self.write_structs()?;
+        self.write_data()?;
+        writeln!(
+            self.output,
+            "globals size: {}",
+            self.assembly.globals_size()
+        )?;
+        if let Some(debug_info) = self.debug_info {
+            for global in debug_info.globals() {
+                writeln!(
+                    self.output,
+                    "  +{} {}: {}",
+                    global.offset(),
+                    global.id(),
+                    self.type_name(global.typeid())
+                )?;
+            }
+        }
+        self.write_ops()?;
+        self.write_functions()?;
+        Ok(self.output)
+    }
+
+    fn struct_name(&self, index: usize) -> String {
+        if let Some(name) = ATOMS.get(index) {
+            (*name).to_owned()
+        } else if let Some(name) = self.structs_names.get(&index) {
+            (*name).to_owned()
+        } else {
+            format!("#{}", index)
+        }
+    }
+
+    fn function_name(&self, index: usize) -> Option<String> {
+        if let Some(name) = self.functions_names.get(&index) {
+            Some((*name).to_owned())
+        } else if let Some((m, f)) = self
+            .assembly
+            .function_by_index(index)
+            .and_then(|f| f.external().as_ref())
+        {
+            Some(format!("{}:{}", m, f))
+        } else {
+            None
+        }
+    }
+
+    fn type_name(&self, typeid: &Type) -> String {
+        match typeid {
+            Type::Identifier(i) => self.struct_name(*i),
+            Type::Pointer(t) => format!("*{}", self.type_name(t)),
+            Type::Tuple(t) => format!(
+                "({})",
+                t.iter()
+                    .map(|t| self.type_name(t))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    fn named(name: Option<&String>, value: String) -> String {
+        if let Some(name) = name {
+            format!("{}: {}", name, value)
+        } else {
+            value
+        }
+    }
+
+    fn data_value(data: &Data) -> String {
+        match data {
+            Data::None => "".to_owned(),
+            Data::I8(v) => v.to_string(),
+            Data::U8(v) => v.to_string(),
+            Data::I16(v) => v.to_string(),
+            Data::U16(v) => v.to_string(),
+            Data::I32(v) => v.to_string(),
+            Data::U32(v) => v.to_string(),
+            Data::I64(v) => v.to_string(),
+            Data::U64(v) => v.to_string(),
+            Data::F32(v) => v.to_string(),
+            Data::F64(v) => v.to_string(),
+            Data::Isize(v) => v.to_string(),
+            Data::Usize(v) => v.to_string(),
+            Data::String(v) => format!("{:?}", v),
+        }
+    }
+
+    fn write_exports(&mut self) -> SimpleResult<()> {
+        writeln!(self.output, "exports:")?;
+        let mut structs = self.assembly.export_structs().iter().collect::<Vec<_>>();
+        structs.sort_by_key(|(_, i)| **i);
+        for (id, index) in structs {
+            writeln!(self.output, "  struct {} = #{}", id, index)?;
+        }
+        let mut functions = self.assembly.export_functions().iter().collect::<Vec<_>>();
+        functions.sort_by_key(|(_, i)| **i);
+        for (id, index) in functions {
+            writeln!(self.output, "  fn {} = #{}", id, index)?;
+        }
+        Ok(())
+    }
+
+    fn write_structs(&mut self) -> SimpleResult<()> {
+        writeln!(self.output, "structs:")?;
+        for s in self.assembly.structs().iter().skip(ATOMS.len()) {
+            writeln!(
+                self.output,
+                "  #{}{} (size: {})",
+                s.index(),
+                self.structs_names
+                    .get(&s.index())
+                    .map_or("".to_owned(), |n| format!(" {}", n)),
+                s.size()
+            )?;
+            let names = self
+                .debug_info
+                .and_then(|d| d.struct_by_index(s.index()))
+                .map(|d| d.fields());
+            for (i, f) in s.fields().iter().enumerate() {
+                writeln!(
+                    self.output,
+                    "    +{} {} (size: {})",
+                    f.offset(),
+                    Self::named(names.and_then(|n| n.get(i)), self.type_name(f.typeid())),
+                    f.size()
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_data(&mut self) -> SimpleResult<()> {
+        writeln!(self.output, "data:")?;
+        let mut data = self.data_offsets.iter().collect::<Vec<_>>();
+        data.sort_by_key(|(o, _)| **o);
+        for (offset, d) in data {
+            writeln!(
+                self.output,
+                "  +{} {:?} {}",
+                offset,
+                d.data_type(),
+                Self::data_value(d)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_ops(&mut self) -> SimpleResult<()> {
+        writeln!(self.output, "ops:")?;
+        for (i, op) in self.assembly.ops_map().iter().enumerate() {
+            writeln!(self.output, "  #{} {}", i, op)?;
+        }
+        Ok(())
+    }
+
+    fn write_functions(&mut self) -> SimpleResult<()> {
+        writeln!(self.output, "functions:")?;
+        for f in self.assembly.functions() {
+            let debug_info = self.debug_info.and_then(|d| d.function_by_index(f.index()));
+            let params = f
+                .params()
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    Self::named(
+                        debug_info.and_then(|d| d.params().get(i)),
+                        self.type_name(p.typeid()),
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let typeid = if let Some(t) = f.typeid() {
+                format!(": {}", self.type_name(t))
+            } else {
+                "".to_owned()
+            };
+            writeln!(
+                self.output,
+                "  #{} {}{}({}){}",
+                f.index(),
+                if f.export() { "export " } else { "" },
+                self.function_name(f.index()).unwrap_or_default(),
+                params,
+                typeid
+            )?;
+            if let Some(d) = debug_info {
+                if !d.path().is_empty() {
+                    writeln!(self.output, "    path {}", d.path())?;
+                }
+            }
+            if let Some((m, n)) = f.external() {
+                writeln!(self.output, "    extern {}:{}", m, n)?;
+                continue;
+            }
+            for (i, l) in f.locals().iter().enumerate() {
+                writeln!(
+                    self.output,
+                    "    local +{} {}",
+                    l.offset().unwrap_or(0),
+                    Self::named(
+                        debug_info.and_then(|d| d.locals().get(i)),
+                        self.type_name(l.typeid())
+                    )
+                )?;
+            }
+            if let Some(body) = self.assembly.function_body_by_index(f.index()) {
+                self.write_body(f, body).map_err(|err| {
+                    SimpleError::new(format!("Function #{}: {}", f.index(), err.message))
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_body(&mut self, function: &Function, body: &FunctionBody) -> SimpleResult<()> {
+        let labels = body
+            .labels()
+            .iter()
+            .map(|(k, v)| (*v, k.as_str()))
+            .collect::<HashMap<_, _>>();
+        let debug_info = self
+            .debug_info
+            .and_then(|d| d.function_by_index(function.index()));
+        let variables_names = |variables: &[Variable], names: Option<&'a [String]>| {
+            variables
+                .iter()
+                .zip(names.unwrap_or_default().iter())
+                .filter_map(|(v, n)| v.offset().map(|o| (o, n.as_str())))
+                .collect::<HashMap<_, _>>()
+        };
+        let params = variables_names(function.params(), debug_info.map(|d| d.params()));
+        let locals = variables_names(function.locals(), debug_info.map(|d| d.locals()));
+        let locations = debug_info
+            .map(|d| {
+                d.locations()
+                    .iter()
+                    .map(|l| (l.address(), d.describe_location(l.address())))
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+        let with_name = |offset: usize, name: Option<&str>| {
+            if let Some(name) = name {
+                format!(" +{} ; {}", offset, name)
+            } else {
+                format!(" +{}", offset)
+            }
+        };
+        let mut stream = Cursor::new(body.code());
+        while (stream.position() as usize) < body.code().len() {
+            let address = stream.position() as usize;
+            if let Some(label) = labels.get(&address) {
+                writeln!(self.output, "  {}:", label)?;
+            }
+            let byte = stream.read_u8()?;
+            let op = if let Some(op) = OpIndex::from_byte(byte) {
+                op
+            } else {
+                return Err(SimpleError::new(format!(
+                    "Unsupported op index at {:04}: {}",
+                    address, byte
+                )));
+            };
+            let args = match op {
+                OpIndex::DataPointer => {
+                    let offset = stream.read_u64::<BigEndian>()? as usize;
+                    if let Some(d) = self.data_offsets.get(&offset) {
+                        format!(" +{} ; {:?} {}", offset, d.data_type(), Self::data_value(d))
+                    } else {
+                        format!(" +{}", offset)
+                    }
+                }
+                OpIndex::ParamsPointer => {
+                    let offset = stream.read_u64::<BigEndian>()? as usize;
+                    with_name(offset, params.get(&offset).cloned())
+                }
+                OpIndex::LocalsPointer => {
+                    let offset = stream.read_u64::<BigEndian>()? as usize;
+                    with_name(offset, locals.get(&offset).cloned())
+                }
+                OpIndex::GlobalsPointer => {
+                    let offset = stream.read_u64::<BigEndian>()? as usize;
+                    with_name(
+                        offset,
+                        self.debug_info.and_then(|d| d.global_by_offset(offset)),
+                    )
+                }
+                OpIndex::OffsetPointer => format!(" +{}", stream.read_u64::<BigEndian>()?),
+                OpIndex::ExecuteOpStart => {
+                    if let Some(location) = locations.get(&address) {
+                        format!(" ; {}", location)
+                    } else {
+                        "".to_owned()
+                    }
+                }
+                OpIndex::ExecuteOpStop => {
+                    let index = stream.read_u64::<BigEndian>()? as usize;
+                    format!(" {}", self.op_name(index))
+                }
+                OpIndex::ExecuteOpInlineStop => {
+                    let index = stream.read_u64::<BigEndian>()? as usize;
+                    let size = stream.read_u64::<BigEndian>()?;
+                    format!(" {} (size: {})", self.op_name(index), size)
+                }
+                OpIndex::ExecuteOpInlineTypedStop => {
+                    let index = stream.read_u64::<BigEndian>()? as usize;
+                    let size = stream.read_u64::<BigEndian>()?;
+                    let typeid = read_type(&mut stream)?;
+                    format!(
+                        " {} (size: {}): {}",
+                        self.op_name(index),
+                        size,
+                        self.type_name(&typeid)
+                    )
+                }
+                OpIndex::StoreTypedTargetAddress | OpIndex::StoreTypedParamAddress => {
+                    format!(" : {}", self.type_name(&read_type(&mut stream)?))
+                }
+                OpIndex::ProduceTuple => {
+                    let count = stream.read_u64::<BigEndian>()?;
+                    let mut sizes = vec![];
+                    for _ in 0..count {
+                        sizes.push(stream.read_u64::<BigEndian>()?.to_string());
+                    }
+                    format!(" ({})", sizes.join(", "))
+                }
+                OpIndex::CallFunction => {
+                    let index = stream.read_u64::<BigEndian>()? as usize;
+                    if let Some(name) = self.function_name(index) {
+                        format!(" #{} ; {}", index, name)
+                    } else {
+                        format!(" #{}", index)
+                    }
+                }
+                OpIndex::LabelAddress => {
+                    let address = stream.read_u64::<BigEndian>()? as usize;
+                    if let Some(label) = labels.get(&address) {
+                        format!(" {:04} ; {}", address, label)
+                    } else {
+                        format!(" {:04}", address)
+                    }
+                }
+                _ => "".to_owned(),
+            };
+            writeln!(self.output, "    {:04} {:?}{}", address, op, args)?;
+        }
+        Ok(())
+    }
+
+    fn op_name(&self, index: usize) -> String {
+        if let Some(op) = self.assembly.ops_map().get(index) {
+            op.clone()
+        } else {
+            format!("#{}", index)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembly::{encode_assembly, encode_assembly_with_options, AssemblyOptions};
+    use crate::program::*;
+    use crate::target::Target;
+    use crate::validator::*;
+
+    #[test]
+    fn test_disassemble() {
+        let ops = compile_ops_descriptor("goto to: @label {}\npass v: $? {}").unwrap();
+        let source = "export fn main(a: i32) {\nloop:\n  pass a;\n  pass 'hi';\n  goto 'loop';\n}";
+        let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+        let program = Program::from_modules(None, vec![module]).unwrap();
+        let bytes = encode_assembly(&program, &ops, false).unwrap();
+        let listing = disassemble_bytes(bytes).unwrap();
+        assert!(listing.starts_with(&format!("target: {}\n", Target::default())));
+        assert!(listing.contains("  fn main = #0"));
+        assert!(listing.contains("  #0 export main(i32)"));
+        assert!(listing.contains("  loop:\n    0000 ExecuteOpStart\n    0001 ParamsPointer +0\n"));
+        assert!(listing.contains("StringU8 \"hi\""));
+        assert!(listing.contains("ExecuteOpStop pass"));
+        assert!(listing.contains("LabelAddress 0000 ; loop"));
+        let bytes = encode_assembly(&program, &ops, true).unwrap();
+        let listing = disassemble_bytes(bytes).unwrap();
+        assert!(listing.contains("  #0 export main(a: i32)"));
+        assert!(listing.contains("    0000 ExecuteOpStart ; 3:3\n    0001 ParamsPointer +0 ; a\n"));
+        let options = AssemblyOptions {
+            operand_types: true,
+            ..Default::default()
+        };
+        let bytes = encode_assembly_with_options(&program, &ops, &options).unwrap();
+        let listing = disassemble_bytes(bytes).unwrap();
+        assert!(
+            listing.contains("    0001 ParamsPointer +0\n    0010 StoreTypedParamAddress : i32\n")
+        );
+    }
+}
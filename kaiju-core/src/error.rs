@@ -1,3 +1,4 @@
+use std::fmt::Error as FmtError;
 use std::io::Error as IoError;
 use std::result::Result as StdResult;
 
@@ -12,18 +13,129 @@ pub struct CompilationError {
 
 pub type CompilationResult<T> = StdResult<T, CompilationError>;
 
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    #[inline]
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.line == 0
+    }
+}
+
 #[derive(Debug)]
 pub struct SimpleError {
     pub message: String,
+    pub code: Option<&'static str>,
+    pub path: Option<String>,
+    pub span: Option<Span>,
 }
 
 impl SimpleError {
     #[inline]
     pub fn new(message: String) -> Self {
-        Self { message }
+        Self {
+            message,
+            code: None,
+            path: None,
+            span: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        if self.code.is_none() {
+            self.code = Some(code);
+        }
+        self
+    }
+
+    pub fn with_path(mut self, path: &str) -> Self {
+        if self.path.is_none() && !path.is_empty() {
+            self.path = Some(path.to_owned());
+        }
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        if self.span.is_none() && !span.is_empty() {
+            self.span = Some(span);
+        }
+        self
+    }
+
+    pub fn location(&self) -> Option<String> {
+        Some(location(&self.path, self.span?))
+    }
+
+    pub fn pretty(&self, source: &str) -> String {
+        match self.span {
+            Some(span) => {
+                let pad = " ".repeat(span.line.to_string().len());
+                let location = self.location().unwrap();
+                match snippet(source, span, &pad, "") {
+                    Some(snippet) => format!(
+                        "{}--> {}\n{} |\n{}{} |\n{} = {}",
+                        pad, location, pad, snippet, pad, pad, self.message
+                    ),
+                    None => format!("{}: {}", location, self.message),
+                }
+            }
+            None => self.message.clone(),
+        }
+    }
+}
+
+pub(crate) fn location(path: &Option<String>, span: Span) -> String {
+    if let Some(ref path) = path {
+        format!("{}:{}:{}", path, span.line, span.column)
+    } else {
+        format!("{}:{}", span.line, span.column)
     }
 }
 
+pub(crate) fn snippet(source: &str, span: Span, pad: &str, label: &str) -> Option<String> {
+    let text = source.lines().nth(span.line.checked_sub(1)?)?;
+    let column = span.column - 1;
+    let size = (span.end - span.start)
+        .min(text.len().saturating_sub(column))
+        .max(1);
+    let marker = if size == 1 {
+        "^".to_owned()
+    } else {
+        format!("^{}^", "-".repeat(size - 2))
+    };
+    let label = if label.is_empty() {
+        "".to_owned()
+    } else {
+        format!(" {}", label)
+    };
+    Some(format!(
+        "{:>width$} | {}\n{} | {}{}{}\n",
+        span.line,
+        text,
+        pad,
+        " ".repeat(column),
+        marker,
+        label,
+        width = pad.len()
+    ))
+}
+
 pub type SimpleResult<T> = StdResult<T, SimpleError>;
 
 impl From<IoError> for SimpleError {
@@ -31,3 +143,9 @@ impl From<IoError> for SimpleError {
         Self::new(format!("{}", error))
     }
 }
+
+impl From<FmtError> for SimpleError {
+    fn from(error: FmtError) -> Self {
+        Self::new(format!("{}", error))
+    }
+}
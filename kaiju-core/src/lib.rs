@@ -11,9 +11,14 @@ extern crate serde_json;
 
 pub mod assembly;
 pub mod ast;
+pub mod diagnostic;
+pub mod disasm;
 pub mod error;
+pub mod lint;
 pub mod parser;
 pub mod program;
+pub mod target;
 pub mod utils;
 pub mod validator;
+pub mod verifier;
 pub mod vm;
@@ -0,0 +1,324 @@
+use crate::diagnostic::*;
+use crate::error::*;
+use crate::program::*;
+use crate::validator::*;
+use std::collections::HashSet;
+
+pub const UNUSED_LOCALS: &str = "unused_locals";
+pub const UNUSED_PARAMS: &str = "unused_params";
+pub const UNUSED_LABELS: &str = "unused_labels";
+pub const UNUSED_IMPORTS: &str = "unused_imports";
+pub const DEAD_CODE: &str = "dead_code";
+pub const UNREACHABLE_CODE: &str = "unreachable_code";
+pub const WARNINGS: &str = "warnings";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    variables: HashSet<String>,
+    functions: HashSet<String>,
+    strings: HashSet<String>,
+    types: HashSet<String>,
+}
+
+impl Usage {
+    fn read_value(&mut self, value: &Value) {
+        match value {
+            Value::Ref(v, _, _) | Value::Deref(v, _, _) => self.read_value(v),
+            Value::FunctionCall(id, args, _, _) => {
+                self.functions.insert(id.clone());
+                for a in args {
+                    self.read_value(a);
+                }
+            }
+            Value::Tuple(values, _, _) | Value::OperationInline(_, values, _, _) => {
+                for v in values {
+                    self.read_value(v);
+                }
+            }
+            Value::String(v, t, _) => {
+                self.strings.insert(v.clone());
+                self.read_type(t);
+            }
+            Value::Number(Number::Integer(_, t), _) | Value::Number(Number::Float(_, t), _) => {
+                self.read_type(t)
+            }
+            Value::Variable(id, _, _) => {
+                self.variables.insert(id.clone());
+            }
+        }
+    }
+
+    fn write_value(&mut self, value: &Value) {
+        if let Value::Variable(_, _, _) = value {
+            return;
+        }
+        self.read_value(value);
+    }
+
+    fn read_type(&mut self, typeid: &Type) {
+        match typeid {
+            Type::Identifier(id) => {
+                self.types.insert(id.clone());
+            }
+            Type::Pointer(t) => self.read_type(t),
+            Type::Tuple(t) => {
+                for t in t {
+                    self.read_type(t);
+                }
+            }
+        }
+    }
+
+    fn read_header(&mut self, header: &FunctionHeader) {
+        for p in &header.params {
+            self.read_type(&p.typeid);
+        }
+        if let Some(ref t) = header.typeid {
+            self.read_type(t);
+        }
+    }
+
+    fn read_function(&mut self, function: &Function) {
+        self.read_header(&function.header);
+        for l in &function.locals {
+            self.read_type(&l.typeid);
+        }
+        for o in &function.body {
+            if let BlockOp::Operation(ref o) = o {
+                for v in &o.params {
+                    self.read_value(v);
+                }
+                for v in &o.targets {
+                    self.write_value(v);
+                }
+            }
+        }
+    }
+}
+
+struct Lints<'a> {
+    module: &'a Module,
+    levels: Vec<(String, LintLevel)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Lints<'a> {
+    fn new(module: &'a Module) -> Self {
+        let mut levels = vec![];
+        for meta in &module.meta {
+            let level = match meta.id.as_str() {
+                "allow" => LintLevel::Allow,
+                "warn" => LintLevel::Warn,
+                "deny" => LintLevel::Deny,
+                _ => continue,
+            };
+            for arg in &meta.args {
+                match arg {
+                    MetaValue::Field(m) => levels.push((m.id.clone(), level)),
+                    MetaValue::String(s) => levels.push((s.clone(), level)),
+                    _ => {}
+                }
+            }
+        }
+        Self {
+            module,
+            levels,
+            diagnostics: vec![],
+        }
+    }
+
+    fn level(&self, lint: &str) -> LintLevel {
+        self.levels
+            .iter()
+            .rev()
+            .find(|(id, _)| id == lint || id == WARNINGS)
+            .map_or(LintLevel::Warn, |(_, level)| *level)
+    }
+
+    fn report(&mut self, lint: &str, code: &str, message: String, span: Span) {
+        let severity = match self.level(lint) {
+            LintLevel::Allow => return,
+            LintLevel::Warn => Severity::Warning,
+            LintLevel::Deny => Severity::Error,
+        };
+        let diagnostic = Diagnostic::new(severity, code, message)
+            .with_path(&self.module.path)
+            .with_span(span);
+        self.diagnostics.push(if severity == Severity::Warning {
+            diagnostic.with_help(&format!(
+                "add `#![allow({})];` to the module to silence this",
+                lint
+            ))
+        } else {
+            diagnostic
+        });
+    }
+}
+
+pub fn lint_program(program: &Program, validator: &Validator) -> Vec<Diagnostic> {
+    program
+        .modules
+        .iter()
+        .flat_map(|m| lint_module(m, validator))
+        .collect()
+}
+
+pub fn lint_module(module: &Module, validator: &Validator) -> Vec<Diagnostic> {
+    let mut lints = Lints::new(module);
+    let mut module_usage = Usage::default();
+    for g in &module.globals {
+        module_usage.read_type(&g.typeid);
+    }
+    for s in &module.structs {
+        for f in &s.fields {
+            module_usage.read_type(&f.typeid);
+        }
+    }
+    for e in &module.externs {
+        module_usage.read_header(&e.item);
+    }
+    for f in &module.functions {
+        let mut usage = Usage::default();
+        usage.read_function(f);
+        lint_function(f, &usage, validator, &mut lints);
+        module_usage.functions.extend(usage.functions);
+        module_usage.types.extend(usage.types);
+    }
+    for f in &module.functions {
+        if !f.export && f.header.id != "main" && !module_usage.functions.contains(&f.header.id) {
+            lints.report(
+                DEAD_CODE,
+                codes::UNUSED_FUNCTION,
+                format!("Function `{}` is never called", f.header.id),
+                f.span,
+            );
+        }
+    }
+    for i in &module.imports {
+        for n in &i.names {
+            if !module_usage.functions.contains(n) && !module_usage.types.contains(n) {
+                lints.report(
+                    UNUSED_IMPORTS,
+                    codes::UNUSED_IMPORT,
+                    format!("Imported name `{}` is never used", n),
+                    i.span,
+                );
+            }
+        }
+    }
+    lints.diagnostics
+}
+
+fn lint_function(function: &Function, usage: &Usage, validator: &Validator, lints: &mut Lints) {
+    for p in &function.header.params {
+        if !p.id.starts_with('_') && !usage.variables.contains(&p.id) {
+            lints.report(
+                UNUSED_PARAMS,
+                codes::UNUSED_PARAM,
+                format!(
+                    "Function `{}`: Parameter `{}` is never read",
+                    function.header.id, p.id
+                ),
+                p.span,
+            );
+        }
+    }
+    for l in &function.locals {
+        if !l.id.starts_with('_') && !usage.variables.contains(&l.id) {
+            lints.report(
+                UNUSED_LOCALS,
+                codes::UNUSED_LOCAL,
+                format!(
+                    "Function `{}`: Local variable `{}` is never read",
+                    function.header.id, l.id
+                ),
+                l.span,
+            );
+        }
+    }
+    let mut terminated = false;
+    let mut reported = false;
+    for o in &function.body {
+        match o {
+            BlockOp::Label(id, span) => {
+                terminated = false;
+                reported = false;
+                if !usage.strings.contains(id) {
+                    lints.report(
+                        UNUSED_LABELS,
+                        codes::UNUSED_LABEL,
+                        format!(
+                            "Function `{}`: Label `{}` is never used",
+                            function.header.id, id
+                        ),
+                        *span,
+                    );
+                }
+            }
+            BlockOp::Operation(o) => {
+                if terminated {
+                    if reported {
+                        continue;
+                    }
+                    reported = true;
+                    lints.report(
+                        UNREACHABLE_CODE,
+                        codes::UNREACHABLE,
+                        format!(
+                            "Function `{}`: Operation `{}` is unreachable",
+                            function.header.id, o.id
+                        ),
+                        o.span,
+                    );
+                    continue;
+                }
+                terminated = validator
+                    .rules()
+                    .iter()
+                    .any(|r| r.id == o.id && r.terminating);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(source: &str) -> DiagnosticResult<Vec<Diagnostic>> {
+        let ops = compile_ops_descriptor("#[terminating] ret {}\npass v: $? {}").unwrap();
+        let module = transform_module_auto_types(compile_module(source).unwrap())?;
+        let program = Program::from_modules(None, vec![module])?;
+        Validator::new(&ops).validate_program::<EmptyDeepValidator>(&program)
+    }
+
+    #[test]
+    fn test_lints() {
+        let source =
+            "fn foo(a: i32) <b: i32, _c: i32> {\n  ret;\n  pass a;\nl:\n  pass 1;\n}\nfn main() {}";
+        let warnings = lint(source).unwrap();
+        let codes = warnings.iter().map(|w| w.code.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            vec![
+                codes::UNUSED_LOCAL,
+                codes::UNREACHABLE,
+                codes::UNUSED_LABEL,
+                codes::UNUSED_FUNCTION
+            ]
+        );
+        assert_eq!(warnings[1].location().unwrap(), "3:3");
+        let source = format!("#![allow(warnings)];\n#![deny(dead_code)];\n{}", source);
+        let errors = lint(&source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, codes::UNUSED_FUNCTION);
+        assert!(errors[0].is_error());
+    }
+}
@@ -95,7 +95,7 @@ pub fn parse_module(source: &str) -> CompilationResult<AstModule> {
             };
             let err = translate_error(err);
             Err(CompilationError {
-                message: "".to_owned(),
+                message: err.variant.message().into_owned(),
                 location,
                 line,
                 column,
@@ -125,7 +125,7 @@ pub fn parse_ops_descriptor(source: &str) -> CompilationResult<AstOpsDescriptor>
             };
             let err = translate_error(err);
             Err(CompilationError {
-                message: "".to_owned(),
+                message: err.variant.message().into_owned(),
                 location,
                 line,
                 column,
@@ -135,6 +135,12 @@ pub fn parse_ops_descriptor(source: &str) -> CompilationResult<AstOpsDescriptor>
     }
 }
 
+fn parse_span(pair: &Pair<Rule>) -> Span {
+    let span = pair.as_span();
+    let (line, column) = span.start_pos().line_col();
+    Span::new(span.start(), span.end(), line, column)
+}
+
 fn parse_module_inner(pair: Pair<Rule>) -> AstModule {
     let mut shebang = None;
     let mut instructions = vec![];
@@ -276,6 +282,7 @@ fn parse_float(pair: Pair<Rule>) -> AstFloat {
 }
 
 fn parse_import(pair: Pair<Rule>) -> AstImport {
+    let span = parse_span(&pair);
     let mut meta = vec![];
     let mut names = vec![];
     let mut module = AstString::default();
@@ -296,14 +303,16 @@ fn parse_import(pair: Pair<Rule>) -> AstImport {
         meta,
         names,
         module,
+        span,
     }
 }
 
 fn parse_variable(pair: Pair<Rule>) -> AstVariable {
+    let span = parse_span(&pair);
     let mut inner = pair.into_inner();
     let id = parse_identifier(inner.next().unwrap());
     let typeid = parse_type(inner.next().unwrap().into_inner().next().unwrap());
-    AstVariable { id, typeid }
+    AstVariable { id, typeid, span }
 }
 
 fn parse_type(pair: Pair<Rule>) -> AstType {
@@ -319,6 +328,7 @@ fn parse_type(pair: Pair<Rule>) -> AstType {
 }
 
 fn parse_extern(pair: Pair<Rule>) -> AstExtern {
+    let span = parse_span(&pair);
     let mut meta = vec![];
     let mut item = AstFunctionHeader {
         id: AstIdentifier::default(),
@@ -330,7 +340,7 @@ fn parse_extern(pair: Pair<Rule>) -> AstExtern {
     for p in pair.into_inner() {
         match p.as_rule() {
             Rule::meta_local => meta.push(parse_meta(p)),
-            Rule::extern_item => item = parse_function_header(p),
+            Rule::extern_item => item = parse_function_header(p.into_inner().next().unwrap()),
             Rule::extern_location => {
                 let mut inner = p.into_inner();
                 location_module = parse_identifier(inner.next().unwrap());
@@ -344,10 +354,12 @@ fn parse_extern(pair: Pair<Rule>) -> AstExtern {
         item,
         location_module,
         location_function,
+        span,
     }
 }
 
 fn parse_struct(pair: Pair<Rule>) -> AstStruct {
+    let span = parse_span(&pair);
     let mut meta = vec![];
     let mut export = false;
     let mut id = AstIdentifier::default();
@@ -366,10 +378,12 @@ fn parse_struct(pair: Pair<Rule>) -> AstStruct {
         export,
         id,
         fields,
+        span,
     }
 }
 
 fn parse_function(pair: Pair<Rule>) -> AstFunction {
+    let span = parse_span(&pair);
     let mut meta = vec![];
     let mut export = false;
     let mut header = None;
@@ -391,6 +405,7 @@ fn parse_function(pair: Pair<Rule>) -> AstFunction {
         header: header.unwrap(),
         locals,
         ops,
+        span,
     }
 }
 
@@ -412,7 +427,10 @@ fn parse_function_header(pair: Pair<Rule>) -> AstFunctionHeader {
 fn parse_block(pair: Pair<Rule>) -> Vec<AstBlockOp> {
     pair.into_inner()
         .map(|p| match p.as_rule() {
-            Rule::label => AstBlockOp::Label(parse_identifier(p.into_inner().next().unwrap())),
+            Rule::label => {
+                let span = parse_span(&p);
+                AstBlockOp::Label(parse_identifier(p.into_inner().next().unwrap()), span)
+            }
             Rule::operation => AstBlockOp::Operation(parse_operation(p)),
             _ => unreachable!(),
         })
@@ -420,6 +438,7 @@ fn parse_block(pair: Pair<Rule>) -> Vec<AstBlockOp> {
 }
 
 fn parse_operation(pair: Pair<Rule>) -> AstOperation {
+    let span = parse_span(&pair);
     let mut meta = vec![];
     let mut id = AstIdentifier::default();
     let mut params = vec![];
@@ -438,43 +457,47 @@ fn parse_operation(pair: Pair<Rule>) -> AstOperation {
         id,
         params,
         targets,
+        span,
     }
 }
 
 fn parse_value(pair: Pair<Rule>) -> AstValue {
+    let s = parse_span(&pair);
     let mut inner = pair.into_inner();
     let p = inner.next().unwrap().into_inner().next().unwrap();
     let a = inner.next().map(|p| Box::new(parse_access(p)));
     match p.as_rule() {
-        Rule::ref_value => parse_ref(p, a),
-        Rule::deref_value => parse_deref(p, a),
-        Rule::function_call => parse_function_call(p, a),
-        Rule::tuple_value => AstValue::Tuple(p.into_inner().map(parse_value).collect(), a),
-        Rule::string => AstValue::String(parse_string(p)),
-        Rule::number => AstValue::Number(parse_number(p)),
-        Rule::operation_inline => parse_operation_inline(p, a),
+        Rule::ref_value => parse_ref(p, a, s),
+        Rule::deref_value => parse_deref(p, a, s),
+        Rule::function_call => parse_function_call(p, a, s),
+        Rule::tuple_value => AstValue::Tuple(p.into_inner().map(parse_value).collect(), a, s),
+        Rule::string => AstValue::String(parse_string(p), s),
+        Rule::number => AstValue::Number(parse_number(p), s),
+        Rule::operation_inline => parse_operation_inline(p, a, s),
         Rule::variable_value => {
-            AstValue::Variable(parse_identifier(p.into_inner().next().unwrap()), a)
+            AstValue::Variable(parse_identifier(p.into_inner().next().unwrap()), a, s)
         }
         _ => unreachable!(),
     }
 }
 
-fn parse_ref(pair: Pair<Rule>, access: Option<Box<AstAccess>>) -> AstValue {
+fn parse_ref(pair: Pair<Rule>, access: Option<Box<AstAccess>>, span: Span) -> AstValue {
     AstValue::Ref(
         Box::new(parse_value(pair.into_inner().next().unwrap())),
         access,
+        span,
     )
 }
 
-fn parse_deref(pair: Pair<Rule>, access: Option<Box<AstAccess>>) -> AstValue {
+fn parse_deref(pair: Pair<Rule>, access: Option<Box<AstAccess>>, span: Span) -> AstValue {
     AstValue::Deref(
         Box::new(parse_value(pair.into_inner().next().unwrap())),
         access,
+        span,
     )
 }
 
-fn parse_function_call(pair: Pair<Rule>, access: Option<Box<AstAccess>>) -> AstValue {
+fn parse_function_call(pair: Pair<Rule>, access: Option<Box<AstAccess>>, span: Span) -> AstValue {
     let mut inner = pair.into_inner();
     let id = parse_identifier(inner.next().unwrap());
     let params = inner
@@ -483,7 +506,7 @@ fn parse_function_call(pair: Pair<Rule>, access: Option<Box<AstAccess>>) -> AstV
         .into_inner()
         .map(parse_value)
         .collect();
-    AstValue::FunctionCall(id, params, access)
+    AstValue::FunctionCall(id, params, access, span)
 }
 
 fn parse_access(pair: Pair<Rule>) -> AstAccess {
@@ -517,7 +540,11 @@ fn parse_tuple_access(pair: Pair<Rule>) -> AstAccess {
     AstAccess::Tuple(id, next)
 }
 
-fn parse_operation_inline(pair: Pair<Rule>, access: Option<Box<AstAccess>>) -> AstValue {
+fn parse_operation_inline(
+    pair: Pair<Rule>,
+    access: Option<Box<AstAccess>>,
+    span: Span,
+) -> AstValue {
     let mut inner = pair.into_inner();
     let id = parse_identifier(inner.next().unwrap());
     let params = inner
@@ -526,7 +553,7 @@ fn parse_operation_inline(pair: Pair<Rule>, access: Option<Box<AstAccess>>) -> A
         .into_inner()
         .map(parse_value)
         .collect();
-    AstValue::OperationInline(id, params, access)
+    AstValue::OperationInline(id, params, access, span)
 }
 
 fn parse_ops_descriptor_inner(pair: Pair<Rule>) -> AstOpsDescriptor {
@@ -576,9 +603,11 @@ fn parse_op_param(pair: Pair<Rule>) -> AstOpParam {
     }
 }
 
-fn parse_op_value(pair: Pair<Rule>) -> AstType {
-    match pair.as_rule() {
-        Rule::op_value => parse_type(pair.into_inner().next().unwrap()),
+fn parse_op_value(pair: Pair<Rule>) -> AstOpValue {
+    let p = pair.into_inner().next().unwrap();
+    match p.as_rule() {
+        Rule::op_label => AstOpValue::Label,
+        Rule::type_ => AstOpValue::Type(parse_type(p)),
         _ => unreachable!(),
     }
 }
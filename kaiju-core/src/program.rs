@@ -3,6 +3,7 @@ use crate::error::*;
 use crate::parser::*;
 use serde_json;
 use std::collections::HashMap;
+use std::fmt;
 
 const VERSION: u8 = 1;
 const MAGIC_PROGRAM: [u8; 4] = [0x4b, 0x4a, 0x50, VERSION];
@@ -236,6 +237,11 @@ impl Module {
     pub fn find_function(&self, id: &str) -> Option<&Function> {
         self.functions.iter().find(|f| f.header.id == id)
     }
+
+    #[inline]
+    pub fn find_extern(&self, id: &str) -> Option<&Extern> {
+        self.externs.iter().find(|e| e.item.id == id)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -283,6 +289,8 @@ pub struct Import {
     pub meta: Vec<Meta>,
     pub names: Vec<String>,
     pub module: String,
+    #[serde(default)]
+    pub span: Span,
 }
 
 impl Import {
@@ -291,6 +299,7 @@ impl Import {
             meta: convert_ast_meta(&ast.meta)?,
             names: ast.names.iter().map(|n| n.0.clone()).collect(),
             module: ast.module.0.clone(),
+            span: ast.span,
         })
     }
 }
@@ -301,6 +310,8 @@ pub struct Extern {
     pub item: FunctionHeader,
     pub location_module: String,
     pub location_function: String,
+    #[serde(default)]
+    pub span: Span,
 }
 
 impl Extern {
@@ -310,6 +321,7 @@ impl Extern {
             item: FunctionHeader::from_ast(&ast.item)?,
             location_module: ast.location_module.0.clone(),
             location_function: ast.location_function.0.clone(),
+            span: ast.span,
         })
     }
 }
@@ -318,6 +330,8 @@ impl Extern {
 pub struct Variable {
     pub id: String,
     pub typeid: Type,
+    #[serde(default)]
+    pub span: Span,
 }
 
 impl Variable {
@@ -325,6 +339,7 @@ impl Variable {
         Ok(Self {
             id: ast.id.0.clone(),
             typeid: Type::from_ast(&ast.typeid)?,
+            span: ast.span,
         })
     }
 }
@@ -429,6 +444,8 @@ pub struct Struct {
     pub export: bool,
     pub id: String,
     pub fields: Vec<Variable>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 impl Struct {
@@ -442,6 +459,7 @@ impl Struct {
                 .iter()
                 .map(|v| Variable::from_ast(v))
                 .collect::<CompilationResult<Vec<Variable>>>()?,
+            span: ast.span,
         })
     }
 }
@@ -453,6 +471,8 @@ pub struct Function {
     pub header: FunctionHeader,
     pub locals: Vec<Variable>,
     pub body: Vec<BlockOp>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 impl Function {
@@ -471,6 +491,7 @@ impl Function {
                 .iter()
                 .map(|o| BlockOp::from_ast(o))
                 .collect::<CompilationResult<Vec<BlockOp>>>()?,
+            span: ast.span,
         })
     }
 }
@@ -520,14 +541,14 @@ impl ToString for FunctionHeader {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum BlockOp {
-    Label(String),
+    Label(String, Span),
     Operation(Operation),
 }
 
 impl BlockOp {
     pub fn from_ast(ast: &AstBlockOp) -> CompilationResult<Self> {
         Ok(match ast {
-            AstBlockOp::Label(l) => BlockOp::Label(l.0.clone()),
+            AstBlockOp::Label(l, s) => BlockOp::Label(l.0.clone(), *s),
             AstBlockOp::Operation(o) => BlockOp::Operation(Operation::from_ast(o)?),
         })
     }
@@ -541,7 +562,7 @@ impl BlockOp {
 
     pub fn as_label(&self) -> Option<&String> {
         match self {
-            BlockOp::Label(ref v) => Some(v),
+            BlockOp::Label(ref v, _) => Some(v),
             _ => None,
         }
     }
@@ -560,6 +581,8 @@ pub struct Operation {
     pub id: String,
     pub params: Vec<Value>,
     pub targets: Vec<Value>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 impl Operation {
@@ -577,42 +600,45 @@ impl Operation {
                 .iter()
                 .map(|t| Value::from_ast(t))
                 .collect::<CompilationResult<Vec<Value>>>()?,
+            span: ast.span,
         })
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Value {
-    Ref(Box<Value>, Option<Box<Access>>),
-    Deref(Box<Value>, Option<Box<Access>>),
-    FunctionCall(String, Vec<Value>, Option<Box<Access>>),
-    Tuple(Vec<Value>, Option<Box<Access>>),
-    String(String, Type),
-    Number(Number),
-    OperationInline(String, Vec<Value>, Option<Box<Access>>),
-    Variable(String, Option<Box<Access>>),
+    Ref(Box<Value>, Option<Box<Access>>, Span),
+    Deref(Box<Value>, Option<Box<Access>>, Span),
+    FunctionCall(String, Vec<Value>, Option<Box<Access>>, Span),
+    Tuple(Vec<Value>, Option<Box<Access>>, Span),
+    String(String, Type, Span),
+    Number(Number, Span),
+    OperationInline(String, Vec<Value>, Option<Box<Access>>, Span),
+    Variable(String, Option<Box<Access>>, Span),
 }
 
 impl Value {
     pub fn from_ast(ast: &AstValue) -> CompilationResult<Self> {
         Ok(match ast {
-            AstValue::Ref(v, a) => Value::Ref(
+            AstValue::Ref(v, a, s) => Value::Ref(
                 Box::new(Value::from_ast(v)?),
                 if let Some(a) = a {
                     Some(Box::new(Access::from_ast(a)?))
                 } else {
                     None
                 },
+                *s,
             ),
-            AstValue::Deref(v, a) => Value::Deref(
+            AstValue::Deref(v, a, s) => Value::Deref(
                 Box::new(Value::from_ast(v)?),
                 if let Some(a) = a {
                     Some(Box::new(Access::from_ast(a)?))
                 } else {
                     None
                 },
+                *s,
             ),
-            AstValue::FunctionCall(i, v, a) => Value::FunctionCall(
+            AstValue::FunctionCall(i, v, a, s) => Value::FunctionCall(
                 i.0.clone(),
                 v.iter()
                     .map(|v| Value::from_ast(v))
@@ -622,8 +648,9 @@ impl Value {
                 } else {
                     None
                 },
+                *s,
             ),
-            AstValue::Tuple(v, a) => Value::Tuple(
+            AstValue::Tuple(v, a, s) => Value::Tuple(
                 v.iter()
                     .map(|v| Value::from_ast(v))
                     .collect::<CompilationResult<Vec<Value>>>()?,
@@ -632,10 +659,11 @@ impl Value {
                 } else {
                     None
                 },
+                *s,
             ),
-            AstValue::String(v) => Value::String(v.0.clone(), Type::from_ast(&v.1)?),
-            AstValue::Number(v) => Value::Number(Number::from_ast(v)?),
-            AstValue::OperationInline(i, v, a) => Value::OperationInline(
+            AstValue::String(v, s) => Value::String(v.0.clone(), Type::from_ast(&v.1)?, *s),
+            AstValue::Number(v, s) => Value::Number(Number::from_ast(v)?, *s),
+            AstValue::OperationInline(i, v, a, s) => Value::OperationInline(
                 i.0.clone(),
                 v.iter()
                     .map(|v| Value::from_ast(v))
@@ -645,17 +673,32 @@ impl Value {
                 } else {
                     None
                 },
+                *s,
             ),
-            AstValue::Variable(v, a) => Value::Variable(
+            AstValue::Variable(v, a, s) => Value::Variable(
                 v.0.clone(),
                 if let Some(a) = a {
                     Some(Box::new(Access::from_ast(a)?))
                 } else {
                     None
                 },
+                *s,
             ),
         })
     }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Value::Ref(_, _, s) => *s,
+            Value::Deref(_, _, s) => *s,
+            Value::FunctionCall(_, _, _, s) => *s,
+            Value::Tuple(_, _, s) => *s,
+            Value::String(_, _, s) => *s,
+            Value::Number(_, s) => *s,
+            Value::OperationInline(_, _, _, s) => *s,
+            Value::Variable(_, _, s) => *s,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -760,16 +803,52 @@ impl OpRule {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OpParam {
     pub id: String,
-    pub typeid: Type,
+    pub typeid: OpValue,
 }
 
 impl OpParam {
     pub fn from_ast(ast: &AstOpParam) -> CompilationResult<Self> {
         Ok(OpParam {
             id: ast.id.0.clone(),
-            typeid: Type::from_ast(&ast.typeid)?,
+            typeid: OpValue::from_ast(&ast.typeid)?,
         })
     }
+
+    #[inline]
+    pub fn is_label(&self) -> bool {
+        self.typeid == OpValue::Label
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum OpValue {
+    Type(Type),
+    Label,
+}
+
+impl OpValue {
+    pub fn from_ast(ast: &AstOpValue) -> CompilationResult<Self> {
+        Ok(match ast {
+            AstOpValue::Type(t) => OpValue::Type(Type::from_ast(t)?),
+            AstOpValue::Label => OpValue::Label,
+        })
+    }
+
+    pub fn as_type(&self) -> Option<&Type> {
+        match self {
+            OpValue::Type(ref t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OpValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpValue::Type(t) => write!(f, "{}", t.to_string()),
+            OpValue::Label => write!(f, "@label"),
+        }
+    }
 }
 
 pub type OpDefinition = HashMap<String, HashMap<String, String>>;
@@ -0,0 +1,296 @@
+use crate::error::*;
+use crate::vm::ATOMS;
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use std::fmt;
+use std::io::{Read, Write};
+use std::mem::size_of;
+
+/// Sizes of built-in atom structs in `ATOMS` order; `isize` and `usize` take target pointer size.
+const ATOM_SIZES: [usize; 10] = [1, 1, 2, 2, 4, 4, 8, 8, 4, 8];
+
+/// Byte order of multi-byte values stored in VM memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    #[default]
+    Little = 0,
+    Big = 1,
+}
+
+impl Endianness {
+    pub fn from_byte(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Endianness::Little),
+            1 => Some(Endianness::Big),
+            _ => None,
+        }
+    }
+
+    /// Byte order of the host machine.
+    #[inline]
+    pub fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        }
+    }
+}
+
+impl fmt::Display for Endianness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Endianness::Little => write!(f, "little"),
+            Endianness::Big => write!(f, "big"),
+        }
+    }
+}
+
+/// Description of machine that assembly is laid out for: size of pointers (and `isize`/`usize`),
+/// byte order of VM memory and alignment of atom types used to lay out struct fields.
+/// Default target uses host pointer size, little-endian memory and packed structs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pointer_size: usize,
+    endianness: Endianness,
+    alignments: [usize; 12],
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self::new(size_of::<usize>(), Endianness::default())
+    }
+}
+
+impl Target {
+    /// Creates target with packed structs (all alignments set to 1).
+    pub fn new(pointer_size: usize, endianness: Endianness) -> Self {
+        Self {
+            pointer_size,
+            endianness,
+            alignments: [1; 12],
+        }
+    }
+
+    /// Parses target name: `host` or `le`/`be` followed by pointer bits (`16`, `32` or `64`),
+    /// optionally with `-packed` suffix, e.g. `le16` or `be32-packed`. Structs of non-packed
+    /// targets align atoms to their size, but not more than pointer size.
+    pub fn from_name(name: &str) -> SimpleResult<Self> {
+        if name == "host" {
+            return Ok(Self::default());
+        }
+        let (name, packed) = if let Some(name) = name.strip_suffix("-packed") {
+            (name, true)
+        } else {
+            (name, false)
+        };
+        let endianness = if name.starts_with("le") {
+            Endianness::Little
+        } else if name.starts_with("be") {
+            Endianness::Big
+        } else {
+            return Err(SimpleError::new(format!("Unknown target: {}", name)));
+        };
+        let pointer_size = match &name[2..] {
+            "16" => 2,
+            "32" => 4,
+            "64" => 8,
+            bits => {
+                return Err(SimpleError::new(format!(
+                    "Unsupported target pointer bits: {}",
+                    bits
+                )))
+            }
+        };
+        let target = Self::new(pointer_size, endianness);
+        if packed {
+            Ok(target)
+        } else {
+            Ok(target.with_natural_alignment())
+        }
+    }
+
+    /// Aligns every atom to its size, but not more than pointer size.
+    pub fn with_natural_alignment(mut self) -> Self {
+        for (index, alignment) in self.alignments.iter_mut().enumerate() {
+            *alignment = atom_size(index, self.pointer_size).min(self.pointer_size);
+        }
+        self
+    }
+
+    pub fn with_alignment(mut self, atom: &str, alignment: usize) -> SimpleResult<Self> {
+        if let Some(index) = ATOMS.iter().position(|a| *a == atom) {
+            self.alignments[index] = alignment;
+            Ok(self)
+        } else {
+            Err(SimpleError::new(format!("There is no atom type: {}", atom)))
+        }
+    }
+
+    #[inline]
+    pub fn pointer_size(&self) -> usize {
+        self.pointer_size
+    }
+
+    #[inline]
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Alignments of atoms in `ATOMS` order.
+    #[inline]
+    pub fn alignments(&self) -> &[usize] {
+        &self.alignments
+    }
+
+    /// Size of atom at given index in `ATOMS` order.
+    #[inline]
+    pub fn atom_size(&self, index: usize) -> usize {
+        atom_size(index, self.pointer_size)
+    }
+
+    /// Alignment of atom with given name; pointers are aligned the same as `usize`.
+    pub fn alignment(&self, atom: &str) -> Option<usize> {
+        ATOMS
+            .iter()
+            .position(|a| *a == atom)
+            .map(|index| self.alignments[index])
+    }
+
+    pub fn validate(&self) -> SimpleResult<()> {
+        match self.pointer_size {
+            2 | 4 | 8 => {}
+            size => {
+                return Err(SimpleError::new(format!(
+                    "Unsupported target pointer size: {}",
+                    size
+                )))
+            }
+        }
+        for (atom, alignment) in ATOMS.iter().zip(self.alignments.iter()) {
+            if !alignment.is_power_of_two() {
+                return Err(SimpleError::new(format!(
+                    "Alignment of `{}` is not a power of two: {}",
+                    atom, alignment
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, stream: &mut dyn Write) -> SimpleResult<()> {
+        stream.write_u8(self.endianness as u8)?;
+        stream.write_u8(self.pointer_size as u8)?;
+        for alignment in &self.alignments {
+            stream.write_u8(*alignment as u8)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(stream: &mut dyn Read) -> SimpleResult<Self> {
+        let endianness = stream.read_u8()?;
+        let endianness = if let Some(endianness) = Endianness::from_byte(endianness) {
+            endianness
+        } else {
+            return Err(SimpleError::new(format!(
+                "Unsupported target endianness: {}",
+                endianness
+            )));
+        };
+        let mut result = Self::new(stream.read_u8()? as usize, endianness);
+        for alignment in result.alignments.iter_mut() {
+            *alignment = stream.read_u8()? as usize;
+        }
+        result.validate()?;
+        Ok(result)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let prefix = match self.endianness {
+            Endianness::Little => "le",
+            Endianness::Big => "be",
+        };
+        write!(f, "{}{}", prefix, self.pointer_size * 8)?;
+        if self.alignments.iter().all(|a| *a == 1) {
+            write!(f, "-packed")
+        } else if *self != self.with_natural_alignment() {
+            write!(f, "-custom")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn atom_size(index: usize, pointer_size: usize) -> usize {
+    ATOM_SIZES.get(index).cloned().unwrap_or(pointer_size)
+}
+
+#[inline]
+pub fn align_offset(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembly::Assembly;
+    use crate::program::{compile_module, Program};
+    use crate::validator::transform_module_auto_types;
+
+    #[test]
+    fn test_target() {
+        let target = Target::from_name("le16").unwrap();
+        assert_eq!(target.pointer_size(), 2);
+        assert_eq!(target.endianness(), Endianness::Little);
+        assert_eq!(target.atom_size(11), 2);
+        assert_eq!(target.alignment("i8"), Some(1));
+        assert_eq!(target.alignment("i64"), Some(2));
+        assert_eq!(target.to_string(), "le16");
+        let target = Target::from_name("be32-packed").unwrap();
+        assert_eq!(target.alignment("f64"), Some(1));
+        assert_eq!(target.to_string(), "be32-packed");
+        assert_eq!(
+            target.with_alignment("f64", 4).unwrap().to_string(),
+            "be32-custom"
+        );
+        assert_eq!(Target::from_name("host").unwrap(), Target::default());
+        assert!(Target::from_name("le8").is_err());
+        assert!(Target::from_name("x64").is_err());
+        let mut bytes = vec![];
+        target.write(&mut bytes).unwrap();
+        assert_eq!(Target::read(&mut bytes.as_slice()).unwrap(), target);
+        bytes[2] = 3;
+        assert_eq!(
+            Target::read(&mut bytes.as_slice()).unwrap_err().message,
+            "Alignment of `i8` is not a power of two: 3"
+        );
+        assert_eq!(align_offset(5, 4), 8);
+        assert_eq!(align_offset(8, 4), 8);
+    }
+
+    #[test]
+    fn test_layout() {
+        let source = "export struct A {\n  a: u8,\n  b: i32,\n  c: *u8,\n}\nexport struct B {\n  a: A,\n  b: u8,\n}";
+        let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+        let program = Program::from_modules(None, vec![module]).unwrap();
+        let layout = |name: &str| {
+            let target = Target::from_name(name).unwrap();
+            let assembly = Assembly::from_core_with_target(&program, &target).unwrap();
+            ["A", "B"]
+                .iter()
+                .map(|id| {
+                    let s = assembly.find_struct(id).unwrap();
+                    let offsets = s.fields().iter().map(|f| f.offset()).collect::<Vec<_>>();
+                    (offsets, s.size())
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            layout("le16-packed"),
+            vec![(vec![0, 1, 5], 7), (vec![0, 7], 8)]
+        );
+        assert_eq!(layout("le16"), vec![(vec![0, 2, 6], 8), (vec![0, 8], 10)]);
+        assert_eq!(layout("be64"), vec![(vec![0, 4, 8], 16), (vec![0, 16], 24)]);
+    }
+}
@@ -1,8 +1,10 @@
 #![allow(clippy::too_many_arguments)]
 
+use crate::diagnostic::*;
 use crate::error::*;
+use crate::lint::*;
 use crate::program::*;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 pub trait DeepValidator {
     fn filter_module(_module: &Module, _program: &Program, _validator: &Validator) -> bool {
@@ -77,6 +79,7 @@ pub struct Rule {
     pub id: String,
     pub params: Vec<OpParam>,
     pub targets: Vec<Type>,
+    pub terminating: bool,
 }
 
 impl Rule {
@@ -85,12 +88,13 @@ impl Rule {
             id: rule.id.clone(),
             params: rule.params.clone(),
             targets: rule.targets.clone(),
+            terminating: rule.meta.iter().any(|m| m.id == "terminating"),
         }
     }
 }
 
 type ModuleTypeIDs = HashMap<String, Option<String>>;
-type ModuleFunctionIDs = HashMap<String, Option<Type>>;
+type ModuleFunctionIDs = HashMap<String, FunctionHeader>;
 type FunctionVariablesIDs = HashMap<String, Type>;
 
 pub fn resolve_module_types(module: &Module, program: &Program) -> SimpleResult<ModuleTypeIDs> {
@@ -109,10 +113,11 @@ pub fn resolve_module_types(module: &Module, program: &Program) -> SimpleResult<
     types.insert("usize".to_owned(), None);
     for s in &module.structs {
         if types.contains_key(&s.id) {
-            return Err(SimpleError::new(format!(
-                "Struct name already taken: {}",
-                s.id
-            )));
+            return Err(
+                SimpleError::new(format!("Struct name already taken: {}", s.id))
+                    .with_code(codes::DUPLICATE)
+                    .with_span(s.span),
+            );
         }
         types.insert(s.id.clone(), Some(module.path.clone()));
     }
@@ -124,16 +129,19 @@ pub fn resolve_module_types(module: &Module, program: &Program) -> SimpleResult<
                         return Err(SimpleError::new(format!(
                             "Struct name already taken: {}",
                             s.id
-                        )));
+                        ))
+                        .with_code(codes::DUPLICATE)
+                        .with_span(i.span));
                     }
                     types.insert(s.id.clone(), Some(m.path.clone()));
                 }
             }
         } else {
-            return Err(SimpleError::new(format!(
-                "There is no module: {}",
-                i.module
-            )));
+            return Err(
+                SimpleError::new(format!("There is no module: {}", i.module))
+                    .with_code(codes::MODULE)
+                    .with_span(i.span),
+            );
         }
     }
     Ok(types)
@@ -146,31 +154,50 @@ pub fn resolve_module_functions(
     let mut functions = HashMap::new();
     for f in &module.functions {
         if functions.contains_key(&f.header.id) {
-            return Err(SimpleError::new(format!(
-                "Function name already taken: {}",
-                f.header.id
-            )));
+            return Err(
+                SimpleError::new(format!("Function name already taken: {}", f.header.id))
+                    .with_code(codes::DUPLICATE)
+                    .with_span(f.span),
+            );
+        }
+        functions.insert(f.header.id.clone(), f.header.clone());
+    }
+    for e in &module.externs {
+        if functions.contains_key(&e.item.id) {
+            return Err(
+                SimpleError::new(format!("Function name already taken: {}", e.item.id))
+                    .with_code(codes::DUPLICATE)
+                    .with_span(e.span),
+            );
         }
-        functions.insert(f.header.id.clone(), f.header.typeid.clone());
+        functions.insert(e.item.id.clone(), e.item.clone());
     }
     for i in &module.imports {
         if let Some(m) = program.find_module(&i.module) {
             for n in &i.names {
-                if let Some(f) = m.find_function(n) {
-                    if functions.contains_key(&f.header.id) {
-                        return Err(SimpleError::new(format!(
-                            "Function name already taken: {}",
-                            f.header.id
-                        )));
-                    }
-                    functions.insert(f.header.id.clone(), f.header.typeid.clone());
+                let header = if let Some(f) = m.find_function(n) {
+                    &f.header
+                } else if let Some(e) = m.find_extern(n) {
+                    &e.item
+                } else {
+                    continue;
+                };
+                if functions.contains_key(&header.id) {
+                    return Err(SimpleError::new(format!(
+                        "Function name already taken: {}",
+                        header.id
+                    ))
+                    .with_code(codes::DUPLICATE)
+                    .with_span(i.span));
                 }
+                functions.insert(header.id.clone(), header.clone());
             }
         } else {
-            return Err(SimpleError::new(format!(
-                "There is no module: {}",
-                i.module
-            )));
+            return Err(
+                SimpleError::new(format!("There is no module: {}", i.module))
+                    .with_code(codes::MODULE)
+                    .with_span(i.span),
+            );
         }
     }
     Ok(functions)
@@ -183,10 +210,11 @@ pub fn resolve_function_variables(
     let mut variables = HashMap::new();
     for g in &module.globals {
         if variables.contains_key(&g.id) {
-            return Err(SimpleError::new(format!(
-                "Variable name already taken: {}",
-                g.id
-            )));
+            return Err(
+                SimpleError::new(format!("Variable name already taken: {}", g.id))
+                    .with_code(codes::DUPLICATE)
+                    .with_span(g.span),
+            );
         }
         variables.insert(g.id.clone(), g.typeid.clone());
     }
@@ -198,7 +226,9 @@ pub fn resolve_function_variables(
             return Err(SimpleError::new(format!(
                 "Function `{}`: Variable name already taken: {}",
                 function.header.id, p.id,
-            )));
+            ))
+            .with_code(codes::DUPLICATE)
+            .with_span(p.span));
         }
         variables.insert(p.id.clone(), p.typeid.clone());
     }
@@ -207,7 +237,9 @@ pub fn resolve_function_variables(
             return Err(SimpleError::new(format!(
                 "Function `{}`: Local variable name already taken: {}",
                 function.header.id, l.id,
-            )));
+            ))
+            .with_code(codes::DUPLICATE)
+            .with_span(l.span));
         }
         variables.insert(l.id.clone(), l.typeid.clone());
     }
@@ -277,7 +309,7 @@ pub fn transform_function_auto_types(
                     .collect::<SimpleResult<Vec<_>>>()?;
                 Ok(BlockOp::Operation(op))
             }
-            BlockOp::Label(n) => Ok(BlockOp::Label(n)),
+            BlockOp::Label(n, s) => Ok(BlockOp::Label(n, s)),
         })
         .collect::<SimpleResult<Vec<_>>>()?;
     Ok(function)
@@ -290,7 +322,7 @@ pub fn transform_value_auto_types(
     string_type: &str,
 ) -> SimpleResult<Value> {
     match value {
-        Value::Ref(v, a) => Ok(Value::Ref(
+        Value::Ref(v, a, s) => Ok(Value::Ref(
             Box::new(transform_value_auto_types(
                 *v,
                 integer_type,
@@ -298,8 +330,9 @@ pub fn transform_value_auto_types(
                 string_type,
             )?),
             a,
+            s,
         )),
-        Value::Deref(v, a) => Ok(Value::Deref(
+        Value::Deref(v, a, s) => Ok(Value::Deref(
             Box::new(transform_value_auto_types(
                 *v,
                 integer_type,
@@ -307,21 +340,24 @@ pub fn transform_value_auto_types(
                 string_type,
             )?),
             a,
+            s,
         )),
-        Value::FunctionCall(i, v, a) => Ok(Value::FunctionCall(
+        Value::FunctionCall(i, v, a, s) => Ok(Value::FunctionCall(
             i,
             v.into_iter()
                 .map(|v| transform_value_auto_types(v, integer_type, float_type, string_type))
                 .collect::<SimpleResult<Vec<_>>>()?,
             a,
+            s,
         )),
-        Value::Tuple(v, a) => Ok(Value::Tuple(
+        Value::Tuple(v, a, s) => Ok(Value::Tuple(
             v.into_iter()
                 .map(|v| transform_value_auto_types(v, integer_type, float_type, string_type))
                 .collect::<SimpleResult<Vec<_>>>()?,
             a,
+            s,
         )),
-        Value::String(v, t) => {
+        Value::String(v, t, s) => {
             if let Type::Pointer(t) = t {
                 if let Type::Identifier(i) = *t {
                     Ok(Value::String(
@@ -331,64 +367,76 @@ pub fn transform_value_auto_types(
                         } else {
                             i
                         }))),
+                        s,
                     ))
                 } else {
-                    Err(SimpleError::new(format!(
-                        "Type is not identifier: {}",
-                        t.to_string()
-                    )))
+                    Err(
+                        SimpleError::new(format!("Type is not identifier: {}", t.to_string()))
+                            .with_code(codes::TYPE_MISMATCH)
+                            .with_span(s),
+                    )
                 }
             } else {
-                Err(SimpleError::new(format!(
-                    "Type is not pointer: {}",
-                    t.to_string()
-                )))
+                Err(
+                    SimpleError::new(format!("Type is not pointer: {}", t.to_string()))
+                        .with_code(codes::TYPE_MISMATCH)
+                        .with_span(s),
+                )
             }
         }
-        Value::Number(v) => match v {
+        Value::Number(v, s) => match v {
             Number::Integer(v, t) => {
                 if let Type::Identifier(i) = t {
-                    Ok(Value::Number(Number::Integer(
-                        v,
-                        Type::Identifier(if i == "{integer}" {
-                            integer_type.to_owned()
-                        } else {
-                            i
-                        }),
-                    )))
+                    Ok(Value::Number(
+                        Number::Integer(
+                            v,
+                            Type::Identifier(if i == "{integer}" {
+                                integer_type.to_owned()
+                            } else {
+                                i
+                            }),
+                        ),
+                        s,
+                    ))
                 } else {
-                    Err(SimpleError::new(format!(
-                        "Type is not identifier: {}",
-                        t.to_string()
-                    )))
+                    Err(
+                        SimpleError::new(format!("Type is not identifier: {}", t.to_string()))
+                            .with_code(codes::TYPE_MISMATCH)
+                            .with_span(s),
+                    )
                 }
             }
             Number::Float(v, t) => {
                 if let Type::Identifier(i) = t {
-                    Ok(Value::Number(Number::Float(
-                        v,
-                        Type::Identifier(if i == "{float}" {
-                            float_type.to_owned()
-                        } else {
-                            i
-                        }),
-                    )))
+                    Ok(Value::Number(
+                        Number::Float(
+                            v,
+                            Type::Identifier(if i == "{float}" {
+                                float_type.to_owned()
+                            } else {
+                                i
+                            }),
+                        ),
+                        s,
+                    ))
                 } else {
-                    Err(SimpleError::new(format!(
-                        "Type is not identifier: {}",
-                        t.to_string()
-                    )))
+                    Err(
+                        SimpleError::new(format!("Type is not identifier: {}", t.to_string()))
+                            .with_code(codes::TYPE_MISMATCH)
+                            .with_span(s),
+                    )
                 }
             }
         },
-        Value::OperationInline(i, v, a) => Ok(Value::OperationInline(
+        Value::OperationInline(i, v, a, s) => Ok(Value::OperationInline(
             i,
             v.into_iter()
                 .map(|v| transform_value_auto_types(v, integer_type, float_type, string_type))
                 .collect::<SimpleResult<Vec<_>>>()?,
             a,
+            s,
         )),
-        Value::Variable(i, a) => Ok(Value::Variable(i, a)),
+        Value::Variable(i, a, s) => Ok(Value::Variable(i, a, s)),
     }
 }
 
@@ -489,15 +537,29 @@ impl Validator {
         function.body = body;
     }
 
-    pub fn validate_program<V>(&self, program: &Program) -> SimpleResult<()>
+    pub fn validate_program<V>(&self, program: &Program) -> DiagnosticResult<Vec<Diagnostic>>
     where
         V: DeepValidator,
     {
+        let mut diagnostics = vec![];
         for module in &program.modules {
-            self.ensure_no_duplicates_module(module)?;
-            self.validate_module::<V>(module, program)?;
+            let count = diagnostics.len();
+            self.ensure_no_duplicates_module(module, &mut diagnostics);
+            if diagnostics.len() == count {
+                self.validate_module::<V>(module, program, &mut diagnostics);
+            }
+        }
+        if let Err(err) = V::validate_program(program, self) {
+            diagnostics.push(err.into());
+        }
+        if !has_errors(&diagnostics) {
+            diagnostics.extend(lint_program(program, self));
+        }
+        if has_errors(&diagnostics) {
+            Err(diagnostics)
+        } else {
+            Ok(diagnostics)
         }
-        V::validate_program(program, self)
     }
 
     pub fn transform_program<V>(&self, program: &mut Program) -> SimpleResult<()>
@@ -507,83 +569,113 @@ impl Validator {
         program.modules = program
             .modules
             .iter()
-            .map(|m| V::transform_module(m.clone(), program, self))
+            .map(|m| {
+                V::transform_module(m.clone(), program, self).map_err(|err| err.with_path(&m.path))
+            })
             .collect::<SimpleResult<Vec<Module>>>()?;
         Ok(())
     }
 
-    fn ensure_no_duplicates_module(&self, module: &Module) -> SimpleResult<()> {
-        let mut ids = HashSet::new();
-        for i in &module.imports {
-            for name in &i.names {
-                if ids.contains(name) {
-                    return Err(SimpleError::new(format!(
-                        "Import name `{}` is already taken",
-                        name
-                    )));
-                }
-                ids.insert(name.clone());
-            }
-        }
-        for g in &module.globals {
-            if ids.contains(&g.id) {
-                return Err(SimpleError::new(format!(
-                    "Global name `{}` is already taken",
-                    g.id
-                )));
-            }
-            ids.insert(g.id.clone());
-        }
-        for e in &module.externs {
-            if ids.contains(&e.item.id) {
-                return Err(SimpleError::new(format!(
-                    "Export name `{}` is already taken",
-                    e.item.id
-                )));
-            }
-            ids.insert(e.item.id.clone());
-        }
-        for s in &module.structs {
-            if ids.contains(&s.id) {
-                return Err(SimpleError::new(format!(
-                    "Struct name `{}` is already taken",
-                    s.id
-                )));
-            }
-            ids.insert(s.id.clone());
-        }
-        for f in &module.functions {
-            if ids.contains(&f.header.id) {
-                return Err(SimpleError::new(format!(
-                    "Function name `{}` is already taken",
-                    f.header.id
-                )));
+    fn ensure_no_duplicates_module(&self, module: &Module, diagnostics: &mut Vec<Diagnostic>) {
+        let mut ids = HashMap::new();
+        let items = module
+            .imports
+            .iter()
+            .flat_map(|i| i.names.iter().map(move |n| ("Import", n, i.span)))
+            .chain(module.globals.iter().map(|g| ("Global", &g.id, g.span)))
+            .chain(
+                module
+                    .externs
+                    .iter()
+                    .map(|e| ("Export", &e.item.id, e.span)),
+            )
+            .chain(module.structs.iter().map(|s| ("Struct", &s.id, s.span)))
+            .chain(
+                module
+                    .functions
+                    .iter()
+                    .map(|f| ("Function", &f.header.id, f.span)),
+            );
+        for (kind, id, span) in items {
+            if let Some(first) = ids.get(id) {
+                diagnostics.push(
+                    Diagnostic::error(
+                        codes::DUPLICATE,
+                        format!("{} name `{}` is already taken", kind, id),
+                    )
+                    .with_path(&module.path)
+                    .with_span(span)
+                    .with_label(None, *first, "first defined here"),
+                );
+            } else {
+                ids.insert(id.clone(), span);
             }
-            ids.insert(f.header.id.clone());
         }
-        Ok(())
     }
 
-    fn validate_module<V>(&self, module: &Module, program: &Program) -> SimpleResult<()>
-    where
+    fn validate_module<V>(
+        &self,
+        module: &Module,
+        program: &Program,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) where
         V: DeepValidator,
     {
-        let types = resolve_module_types(module, program)?;
-        let functions = resolve_module_functions(module, program)?;
+        let mut report = |err: SimpleError| {
+            let diagnostic = Diagnostic::from(err.with_path(&module.path));
+            diagnostics.push(match diagnostic.code.as_str() {
+                codes::RECURSIVE_STRUCT => {
+                    diagnostic.with_help("consider using pointer to that type instead")
+                }
+                codes::UNKNOWN_VARIABLE => diagnostic.with_help(
+                    "declare it as function parameter, local (`<name: type>`) or global",
+                ),
+                codes::UNKNOWN_OPERATION => {
+                    diagnostic.with_help("make sure that ops descriptor declares this operation")
+                }
+                _ => diagnostic,
+            });
+        };
+        let types = match resolve_module_types(module, program) {
+            Ok(types) => types,
+            Err(err) => return report(err),
+        };
+        let functions = match resolve_module_functions(module, program) {
+            Ok(functions) => functions,
+            Err(err) => return report(err),
+        };
         for s in &module.structs {
-            Self::validate_struct(s, program)?;
+            if let Err(err) = Self::validate_struct(s, program) {
+                report(err.with_span(s.span));
+            }
         }
         for g in &module.globals {
-            Self::validate_type(&g.typeid, &types)?;
+            if let Err(err) = Self::validate_type(&g.typeid, &types) {
+                report(err.with_span(g.span));
+            }
         }
         for e in &module.externs {
-            Self::validate_function_header(&e.item, &types)?;
+            if let Err(err) = Self::validate_function_header(&e.item, &types) {
+                report(err.with_span(e.span));
+            }
         }
         for f in &module.functions {
-            let variables = resolve_function_variables(f, module)?;
-            self.validate_function::<V>(f, module, program, &types, &functions, &variables)?;
+            match resolve_function_variables(f, module) {
+                Ok(variables) => self.validate_function::<V>(
+                    f,
+                    module,
+                    program,
+                    &types,
+                    &functions,
+                    &variables,
+                    &mut report,
+                ),
+                Err(err) => report(err),
+            }
+        }
+        if let Err(err) = V::validate_module(module, program, self) {
+            report(err);
         }
-        V::validate_module(module, program, self)
     }
 
     fn validate_struct(struct_: &Struct, program: &Program) -> SimpleResult<()> {
@@ -614,10 +706,10 @@ impl Validator {
                 if let Some(s) = program.find_struct(t) {
                     if stack.iter().any(|t| t == &s.id) {
                         return Err(SimpleError::new(format!(
-                            "Type `{}` is found to be in infinite loop of fields types with chain: {}\nConsider using pointer to that type instead",
+                            "Type `{}` is found to be in infinite loop of fields types with chain: {}",
                             s.id,
                             stack.join(" => "),
-                        )));
+                        )).with_code(codes::RECURSIVE_STRUCT));
                     }
                     Self::validate_struct_inner(s, program, stack)?;
                 }
@@ -641,20 +733,27 @@ impl Validator {
         types: &ModuleTypeIDs,
         functions: &ModuleFunctionIDs,
         variables: &FunctionVariablesIDs,
-    ) -> SimpleResult<()>
-    where
+        report: &mut dyn FnMut(SimpleError),
+    ) where
         V: DeepValidator,
     {
-        Self::validate_function_header(&function.header, types)?;
+        if let Err(err) = Self::validate_function_header(&function.header, types) {
+            report(err.with_span(function.span));
+        }
         for v in &function.locals {
-            Self::validate_type(&v.typeid, types)?;
+            if let Err(err) = Self::validate_type(&v.typeid, types) {
+                report(err.with_span(v.span));
+            }
         }
         for o in &function.body {
             if let BlockOp::Operation(ref o) = o {
-                self.validate_op::<V>(o, function, module, program, types, functions, variables)?;
+                if let Err(err) =
+                    self.validate_op::<V>(o, function, module, program, types, functions, variables)
+                {
+                    report(err.with_span(o.span));
+                }
             }
         }
-        Ok(())
     }
 
     fn validate_function_header(
@@ -662,7 +761,7 @@ impl Validator {
         types: &ModuleTypeIDs,
     ) -> SimpleResult<()> {
         for p in &header.params {
-            Self::validate_type(&p.typeid, types)?;
+            Self::validate_type(&p.typeid, types).map_err(|err| err.with_span(p.span))?;
         }
         if let Some(ref t) = header.typeid {
             Self::validate_type(t, types)?;
@@ -674,7 +773,8 @@ impl Validator {
         match typeid {
             Type::Identifier(ref t) => {
                 if !t.is_empty() && !types.contains_key(t) {
-                    Err(SimpleError::new(format!("Found unknown type: `{}`", t)))
+                    Err(SimpleError::new(format!("Found unknown type: `{}`", t))
+                        .with_code(codes::UNKNOWN_TYPE))
                 } else {
                     Ok(())
                 }
@@ -708,26 +808,36 @@ impl Validator {
                     "Operation `{}` expects {} parameter(s)",
                     op.id,
                     rule.params.len()
-                )));
+                ))
+                .with_code(codes::ARITY));
             }
             if rule.targets.len() != op.targets.len() {
                 return Err(SimpleError::new(format!(
                     "Operation `{}` expects {} target(s)",
                     op.id,
                     rule.targets.len()
-                )));
+                ))
+                .with_code(codes::ARITY));
             }
             for i in 0..rule.params.len() {
                 let pr = &rule.params[i];
                 let po = &op.params[i];
+                let typeid = match pr.typeid {
+                    OpValue::Type(ref t) => t,
+                    OpValue::Label => {
+                        Self::validate_label_param(op, pr, po, function)?;
+                        continue;
+                    }
+                };
                 match self.find_value_type(po, types, functions, variables, program) {
                     Ok(t) => {
                         if let Err(err) = Self::validate_type(&t, types) {
-                            Err(SimpleError::new(format!(
-                                "Operation `{}`: {}",
-                                op.id, err.message
-                            )))
-                        } else if t == pr.typeid {
+                            Err(
+                                SimpleError::new(format!("Operation `{}`: {}", op.id, err.message))
+                                    .with_code(codes::UNKNOWN_TYPE)
+                                    .with_span(po.span()),
+                            )
+                        } else if &t == typeid {
                             Ok(())
                         } else {
                             Err(SimpleError::new(format!(
@@ -735,14 +845,16 @@ impl Validator {
                                 op.id,
                                 pr.id,
                                 t.to_string(),
-                                pr.typeid.to_string(),
-                            )))
+                                typeid.to_string(),
+                            ))
+                            .with_code(codes::TYPE_MISMATCH)
+                            .with_span(po.span()))
                         }
                     }
-                    Err(err) => Err(SimpleError::new(format!(
-                        "Operation `{}`: {}",
-                        op.id, err.message
-                    ))),
+                    Err(err) => Err(SimpleError {
+                        message: format!("Operation `{}`: {}", op.id, err.message),
+                        ..err
+                    }),
                 }?;
             }
             for i in 0..rule.targets.len() {
@@ -751,10 +863,11 @@ impl Validator {
                 match self.find_value_type(tb, types, functions, variables, program) {
                     Ok(ref t) => {
                         if let Err(err) = Self::validate_type(&t, types) {
-                            Err(SimpleError::new(format!(
-                                "Operation `{}`: {}",
-                                op.id, err.message
-                            )))
+                            Err(
+                                SimpleError::new(format!("Operation `{}`: {}", op.id, err.message))
+                                    .with_code(codes::UNKNOWN_TYPE)
+                                    .with_span(tb.span()),
+                            )
                         } else if t == ta {
                             Ok(())
                         } else {
@@ -764,28 +877,57 @@ impl Validator {
                                 i,
                                 t.to_string(),
                                 ta.to_string(),
-                            )))
+                            ))
+                            .with_code(codes::TYPE_MISMATCH)
+                            .with_span(tb.span()))
                         }
                     }
-                    Err(err) => Err(SimpleError::new(format!(
-                        "Operation `{}`: {}",
-                        op.id, err.message
-                    ))),
+                    Err(err) => Err(SimpleError {
+                        message: format!("Operation `{}`: {}", op.id, err.message),
+                        ..err
+                    }),
                 }?;
             }
             if let Err(err) = V::validate_op(op, function, module, program, rule, self) {
-                Err(SimpleError::new(format!(
-                    "Operation `{}`: {}",
-                    op.id, err.message
-                )))
+                Err(SimpleError {
+                    message: format!("Operation `{}`: {}", op.id, err.message),
+                    ..err
+                })
             } else {
                 Ok(())
             }
+        } else {
+            Err(
+                SimpleError::new(format!("Operation is not supported: {}", op.id))
+                    .with_code(codes::UNKNOWN_OPERATION),
+            )
+        }
+    }
+
+    fn validate_label_param(
+        op: &Operation,
+        param: &OpParam,
+        value: &Value,
+        function: &Function,
+    ) -> SimpleResult<()> {
+        if let Value::String(ref id, _, _) = value {
+            if function.body.iter().any(|o| o.as_label() == Some(id)) {
+                Ok(())
+            } else {
+                Err(SimpleError::new(format!(
+                    "Operation `{}`: Could not find label `{}` in function `{}`",
+                    op.id, id, function.header.id
+                ))
+                .with_code(codes::UNKNOWN_LABEL)
+                .with_span(value.span()))
+            }
         } else {
             Err(SimpleError::new(format!(
-                "Operation is not supported: {}",
-                op.id
-            )))
+                "Operation `{}` parameter `{}` expects label name",
+                op.id, param.id
+            ))
+            .with_code(codes::TYPE_MISMATCH)
+            .with_span(value.span()))
         }
     }
 
@@ -796,9 +938,21 @@ impl Validator {
         functions: &ModuleFunctionIDs,
         variables: &FunctionVariablesIDs,
         program: &Program,
+    ) -> SimpleResult<Type> {
+        self.find_value_type_inner(value, types, functions, variables, program)
+            .map_err(|err| err.with_span(value.span()))
+    }
+
+    fn find_value_type_inner(
+        &self,
+        value: &Value,
+        types: &ModuleTypeIDs,
+        functions: &ModuleFunctionIDs,
+        variables: &FunctionVariablesIDs,
+        program: &Program,
     ) -> SimpleResult<Type> {
         match value {
-            Value::Ref(ref v, ref a) => {
+            Value::Ref(ref v, ref a, _) => {
                 let t = Type::Pointer(Box::new(
                     self.find_value_type(v, types, functions, variables, program)?,
                 ));
@@ -808,7 +962,7 @@ impl Validator {
                     Ok(t)
                 }
             }
-            Value::Deref(ref v, ref a) => {
+            Value::Deref(ref v, ref a, _) => {
                 let t = self.find_value_type(v, types, functions, variables, program)?;
                 if let Type::Pointer(t) = t {
                     if let Some(ref a) = a {
@@ -820,24 +974,25 @@ impl Validator {
                     Err(SimpleError::new(format!(
                         "Trying to dereference non-pointer type: {}",
                         t.to_string()
-                    )))
+                    ))
+                    .with_code(codes::ACCESS))
                 }
             }
-            Value::FunctionCall(ref fc, _, ref a) => {
-                self.find_function_call_value_type(fc, a, functions, types, program)
+            Value::FunctionCall(ref fc, ref v, ref a, _) => {
+                self.find_function_call_value_type(fc, v, a, types, functions, variables, program)
             }
-            Value::Tuple(ref t, ref a) => {
+            Value::Tuple(ref t, ref a, _) => {
                 self.find_tuple_value_type(t, a, types, functions, variables, program)
             }
-            Value::String(_, ref t) => Ok(t.clone()),
-            Value::Number(ref n) => Ok(match n {
+            Value::String(_, ref t, _) => Ok(t.clone()),
+            Value::Number(ref n, _) => Ok(match n {
                 Number::Integer(_, ref t) => t.clone(),
                 Number::Float(_, ref t) => t.clone(),
             }),
-            Value::OperationInline(ref n, _, ref a) => {
+            Value::OperationInline(ref n, _, ref a, _) => {
                 self.find_operation_inline_value_type(n, a, types, program)
             }
-            Value::Variable(ref id, ref a) => {
+            Value::Variable(ref id, ref a, _) => {
                 if let Some(t) = variables.iter().find(|v| v.0 == id) {
                     if let Some(ref a) = a {
                         self.find_access_value_type(&t.1, a, types, program)
@@ -845,10 +1000,10 @@ impl Validator {
                         Ok(t.1.clone())
                     }
                 } else {
-                    Err(SimpleError::new(format!(
-                        "Could not find variable `{}` in scope",
-                        id
-                    )))
+                    Err(
+                        SimpleError::new(format!("Could not find variable `{}` in scope", id))
+                            .with_code(codes::UNKNOWN_VARIABLE),
+                    )
                 }
             }
         }
@@ -857,13 +1012,50 @@ impl Validator {
     fn find_function_call_value_type(
         &self,
         id: &str,
+        args: &[Value],
         access: &Option<Box<Access>>,
-        functions: &ModuleFunctionIDs,
         types: &ModuleTypeIDs,
+        functions: &ModuleFunctionIDs,
+        variables: &FunctionVariablesIDs,
         program: &Program,
     ) -> SimpleResult<Type> {
-        if let Some(t) = functions.get(id) {
-            if let Some(t) = t {
+        if let Some(h) = functions.get(id) {
+            if h.params.len() != args.len() {
+                return Err(SimpleError::new(format!(
+                    "Function `{}` expects {} argument(s) but got {}",
+                    id,
+                    h.params.len(),
+                    args.len()
+                ))
+                .with_code(codes::ARITY));
+            }
+            for (i, (p, v)) in h.params.iter().zip(args.iter()).enumerate() {
+                let t = match self.find_value_type(v, types, functions, variables, program) {
+                    Ok(t) => t,
+                    Err(err) => {
+                        return Err(SimpleError {
+                            message: format!(
+                                "Function `{}` argument #{} `{}`: {}",
+                                id, i, p.id, err.message
+                            ),
+                            ..err
+                        });
+                    }
+                };
+                if t != p.typeid {
+                    return Err(SimpleError::new(format!(
+                        "Function `{}` argument #{} `{}` with type `{}` is not type of `{}`",
+                        id,
+                        i,
+                        p.id,
+                        t.to_string(),
+                        p.typeid.to_string(),
+                    ))
+                    .with_code(codes::TYPE_MISMATCH)
+                    .with_span(v.span()));
+                }
+            }
+            if let Some(ref t) = h.typeid {
                 if let Some(a) = access {
                     self.find_access_value_type(t, a, types, program)
                 } else {
@@ -872,13 +1064,14 @@ impl Validator {
             } else if access.is_none() {
                 Ok(Type::default())
             } else {
-                Err(SimpleError::new("Trying to access empty type".to_owned()))
+                Err(SimpleError::new("Trying to access empty type".to_owned())
+                    .with_code(codes::ACCESS))
             }
         } else {
-            Err(SimpleError::new(format!(
-                "Trying to call unknown function: {:?}",
-                id
-            )))
+            Err(
+                SimpleError::new(format!("Trying to call unknown function: {:?}", id))
+                    .with_code(codes::UNKNOWN_FUNCTION),
+            )
         }
     }
 
@@ -922,15 +1115,16 @@ impl Validator {
                             Ok(t[i].clone())
                         }
                     } else {
-                        Err(SimpleError::new(format!(
-                            "Tuple does not have field #{}",
-                            i
-                        )))
+                        Err(
+                            SimpleError::new(format!("Tuple does not have field #{}", i))
+                                .with_code(codes::ACCESS),
+                        )
                     }
                 }
-                _ => Err(SimpleError::new(
-                    "Only tuples can be accessed by index".to_owned(),
-                )),
+                _ => Err(
+                    SimpleError::new("Only tuples can be accessed by index".to_owned())
+                        .with_code(codes::ACCESS),
+                ),
             },
             Access::Variable(id, a) => match typeid {
                 Type::Identifier(ref i) => {
@@ -946,24 +1140,28 @@ impl Validator {
                                 Err(SimpleError::new(format!(
                                     "Could not find field `{}` in type: {:?}",
                                     id, s.id
-                                )))
+                                ))
+                                .with_code(codes::ACCESS))
                             }
                         } else {
                             Err(SimpleError::new(format!(
                                 "Atomic struct {} does not have any fields",
                                 i
-                            )))
+                            ))
+                            .with_code(codes::ACCESS))
                         }
                     } else {
                         Err(SimpleError::new(format!(
                             "Atomic struct {} does not have any fields",
                             i
-                        )))
+                        ))
+                        .with_code(codes::ACCESS))
                     }
                 }
-                _ => Err(SimpleError::new(
-                    "Only structs can be accessed by variable".to_owned(),
-                )),
+                _ => Err(
+                    SimpleError::new("Only structs can be accessed by variable".to_owned())
+                        .with_code(codes::ACCESS),
+                ),
             },
         }
     }
@@ -976,7 +1174,13 @@ impl Validator {
         program: &Program,
     ) -> SimpleResult<Type> {
         if let Some(rule) = self.rules.iter().find(|r| r.id == id) {
-            if rule.targets.len() == 1 {
+            if rule.params.iter().any(|p| p.is_label()) {
+                Err(SimpleError::new(format!(
+                    "Trying to inline operation with label parameters: {}",
+                    id
+                ))
+                .with_code(codes::TYPE_MISMATCH))
+            } else if rule.targets.len() == 1 {
                 if let Some(a) = access {
                     self.find_access_value_type(&rule.targets[0], a, types, program)
                 } else {
@@ -986,13 +1190,99 @@ impl Validator {
                 Err(SimpleError::new(format!(
                     "Trying to inline operation of not one target: {}",
                     id
-                )))
+                ))
+                .with_code(codes::ARITY))
             }
         } else {
-            Err(SimpleError::new(format!(
-                "Trying to inline unknown operation: {}",
-                id
-            )))
+            Err(
+                SimpleError::new(format!("Trying to inline unknown operation: {}", id))
+                    .with_code(codes::UNKNOWN_OPERATION),
+            )
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate_all(source: &str) -> DiagnosticResult<Vec<Diagnostic>> {
+        let ops = compile_ops_descriptor("pass v: $? {}").unwrap();
+        let module = transform_module_auto_types(compile_module(source).unwrap())?;
+        let program = Program::from_modules(None, vec![module])?;
+        Validator::new(&ops).validate_program::<EmptyDeepValidator>(&program)
+    }
+
+    fn validate(source: &str) -> DiagnosticResult<()> {
+        validate_all(source).map(|_| ())
+    }
+
+    #[test]
+    fn test_function_call_args() {
+        let header = "extern fn add(a: i32, b: i32): i32 from math:add;";
+        assert!(validate(&format!("{} fn main() {{ pass @<add(1, 2)>; }}", header)).is_ok());
+        assert_eq!(
+            validate(&format!("{} fn main() {{ pass @<add(1)>; }}", header)).unwrap_err()[0]
+                .message,
+            "Operation `pass`: Function `add` expects 2 argument(s) but got 1"
+        );
+        assert_eq!(
+            validate(&format!("{} fn main() {{ pass @<add(1, 2.0)>; }}", header)).unwrap_err()[0]
+                .message,
+            "Operation `pass`: Function `add` argument #1 `b` with type `f32` is not type of `i32`"
+        );
+    }
+
+    #[test]
+    fn test_error_span() {
+        let mut err = validate("fn main() <a: i32> {\n  pass a;\n  pass b;\n}")
+            .unwrap_err()
+            .remove(0);
+        assert_eq!(err.location().unwrap(), "3:8");
+        assert_eq!(err.code, codes::UNKNOWN_VARIABLE);
+        err.help = None;
+        assert_eq!(
+            err.pretty(|_| None),
+            "error[E0102]: Operation `pass`: Could not find variable `b` in scope\n --> 3:8"
+        );
+    }
+
+    #[test]
+    fn test_label_params() {
+        let ops = compile_ops_descriptor("goto to: @label {}").unwrap();
+        let validate = |source: &str| {
+            let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+            let program = Program::from_modules(None, vec![module]).unwrap();
+            Validator::new(&ops).validate_program::<EmptyDeepValidator>(&program)
+        };
+        assert!(validate("fn main() {\nloop:\n  goto 'loop';\n}").is_ok());
+        let err = validate("fn main() {\nloop:\n  goto 'lop';\n}").unwrap_err();
+        assert_eq!(err[0].code, codes::UNKNOWN_LABEL);
+        assert_eq!(err[0].location().unwrap(), "3:8");
+        let err = validate("fn main() {\nloop:\n  goto 42;\n}").unwrap_err();
+        assert_eq!(err[0].code, codes::TYPE_MISMATCH);
+
+        let ops = compile_ops_descriptor("put v: label {}").unwrap();
+        assert!(!ops.rules[0].params[0].is_label());
+        let source = "struct label {}\nfn main() <v: label> {\n  put v;\n}";
+        let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+        let program = Program::from_modules(None, vec![module]).unwrap();
+        assert!(Validator::new(&ops)
+            .validate_program::<EmptyDeepValidator>(&program)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_multiple_diagnostics() {
+        let errors = validate_all("fn foo() {}\nfn foo() {}\nfn foo() {}").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].code, codes::DUPLICATE);
+        assert_eq!(errors[0].location().unwrap(), "2:1");
+        assert_eq!(errors[0].labels[0].span.line, 1);
+        assert_eq!(errors[1].location().unwrap(), "3:1");
+        let errors = validate_all("fn main() {\n  pass a;\n  pass b;\n}").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].location().unwrap(), "2:8");
+        assert_eq!(errors[1].location().unwrap(), "3:8");
+    }
+}
@@ -0,0 +1,375 @@
+use crate::assembly::OpIndex;
+use crate::error::*;
+use crate::vm::*;
+use byteorder::{BigEndian, ReadBytesExt};
+use std::collections::HashSet;
+use std::io::Cursor;
+
+pub fn verify_assembly(assembly: &VmAssembly) -> SimpleResult<()> {
+    for (id, index) in assembly.export_structs() {
+        if *index >= assembly.structs().len() {
+            return Err(SimpleError::new(format!(
+                "Exported struct `{}` points to non-existing struct #{}",
+                id, index
+            )));
+        }
+    }
+    for (id, index) in assembly.export_functions() {
+        if *index >= assembly.functions().len() {
+            return Err(SimpleError::new(format!(
+                "Exported function `{}` points to non-existing function #{}",
+                id, index
+            )));
+        }
+    }
+    for (i, s) in assembly.structs().iter().enumerate() {
+        verify_struct(i, s, assembly)
+            .map_err(|err| SimpleError::new(format!("Struct #{}: {}", i, err.message)))?;
+    }
+    if assembly.functions_code().len() != assembly.functions().len() {
+        return Err(SimpleError::new(format!(
+            "Assembly has {} functions but {} function bodies",
+            assembly.functions().len(),
+            assembly.functions_code().len()
+        )));
+    }
+    if let Some(debug_info) = assembly.debug_info() {
+        verify_debug_info(debug_info, assembly)
+            .map_err(|err| SimpleError::new(format!("Debug info: {}", err.message)))?;
+    }
+    let data_offsets = assembly.data_offsets().into_iter().collect::<HashSet<_>>();
+    for (i, f) in assembly.functions().iter().enumerate() {
+        verify_function(i, f, &assembly.functions_code()[i], &data_offsets, assembly)
+            .map_err(|err| SimpleError::new(format!("Function #{}: {}", i, err.message)))?;
+    }
+    Ok(())
+}
+
+fn verify_debug_info(debug_info: &DebugInfo, assembly: &VmAssembly) -> SimpleResult<()> {
+    if debug_info.structs().len() != assembly.structs().len() {
+        return Err(SimpleError::new(format!(
+            "Expected {} structs, got {}",
+            assembly.structs().len(),
+            debug_info.structs().len()
+        )));
+    }
+    for (i, (d, s)) in debug_info
+        .structs()
+        .iter()
+        .zip(assembly.structs().iter())
+        .enumerate()
+    {
+        if d.fields().len() != s.fields().len() {
+            return Err(SimpleError::new(format!(
+                "Struct #{}: Expected {} fields, got {}",
+                i,
+                s.fields().len(),
+                d.fields().len()
+            )));
+        }
+    }
+    for g in debug_info.globals() {
+        if g.offset() >= assembly.globals_size() {
+            return Err(SimpleError::new(format!(
+                "Global `{}` offset {} exceeds globals size {}",
+                g.id(),
+                g.offset(),
+                assembly.globals_size()
+            )));
+        }
+        verify_type(g.typeid(), assembly)
+            .map_err(|err| SimpleError::new(format!("Global `{}`: {}", g.id(), err.message)))?;
+    }
+    if debug_info.functions().len() != assembly.functions().len() {
+        return Err(SimpleError::new(format!(
+            "Expected {} functions, got {}",
+            assembly.functions().len(),
+            debug_info.functions().len()
+        )));
+    }
+    for (i, (d, f)) in debug_info
+        .functions()
+        .iter()
+        .zip(assembly.functions().iter())
+        .enumerate()
+    {
+        if d.params().len() != f.params().len() || d.locals().len() != f.locals().len() {
+            return Err(SimpleError::new(format!(
+                "Function #{}: Params or locals count does not match",
+                i
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn verify_type(typeid: &Type, assembly: &VmAssembly) -> SimpleResult<()> {
+    match typeid {
+        Type::Identifier(i) => {
+            if *i < assembly.structs().len() {
+                Ok(())
+            } else {
+                Err(SimpleError::new(format!(
+                    "Type points to non-existing struct #{}",
+                    i
+                )))
+            }
+        }
+        Type::Pointer(t) => verify_type(t, assembly),
+        Type::Tuple(t) => t.iter().try_for_each(|t| verify_type(t, assembly)),
+    }
+}
+
+fn verify_struct(index: usize, struct_: &Struct, assembly: &VmAssembly) -> SimpleResult<()> {
+    if struct_.index() != index {
+        return Err(SimpleError::new(format!(
+            "Struct is stored with index #{}",
+            struct_.index()
+        )));
+    }
+    for (i, f) in struct_.fields().iter().enumerate() {
+        verify_type(f.typeid(), assembly)?;
+        if f.offset() + f.size() > struct_.size() {
+            return Err(SimpleError::new(format!(
+                "Field #{} exceeds struct size: {} + {} > {}",
+                i,
+                f.offset(),
+                f.size(),
+                struct_.size()
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn verify_function(
+    index: usize,
+    function: &Function,
+    body: &FunctionBody,
+    data_offsets: &HashSet<usize>,
+    assembly: &VmAssembly,
+) -> SimpleResult<()> {
+    if function.index() != index {
+        return Err(SimpleError::new(format!(
+            "Function is stored with index #{}",
+            function.index()
+        )));
+    }
+    for v in function.params().iter().chain(function.locals().iter()) {
+        verify_type(v.typeid(), assembly)?;
+    }
+    if let Some(t) = function.typeid() {
+        verify_type(t, assembly)?;
+    }
+    if function.external().is_some() && !body.code().is_empty() {
+        return Err(SimpleError::new(
+            "External function cannot have body".to_owned(),
+        ));
+    }
+    let params = function
+        .params()
+        .iter()
+        .filter_map(|v| *v.offset())
+        .collect::<HashSet<_>>();
+    let locals = function
+        .locals()
+        .iter()
+        .filter_map(|v| *v.offset())
+        .collect::<HashSet<_>>();
+    let locals_size = function.locals().iter().map(|v| v.size()).sum::<usize>();
+    let code = body.code();
+    let mut stream = Cursor::new(code);
+    // each entry holds the number of pointers pushed within current op or inline op.
+    let mut scopes: Vec<usize> = vec![];
+    let mut ops_starts = HashSet::new();
+    let mut labels_targets = vec![];
+    while (stream.position() as usize) < code.len() {
+        let address = stream.position() as usize;
+        let byte = stream.read_u8()?;
+        let op = if let Some(op) = OpIndex::from_byte(byte) {
+            op
+        } else {
+            return Err(SimpleError::new(format!(
+                "Unsupported op index at {:04}: {}",
+                address, byte
+            )));
+        };
+        let error = |message: String| {
+            SimpleError::new(format!("Op `{:?}` at {:04}: {}", op, address, message))
+        };
+        if scopes.is_empty() && op != OpIndex::ExecuteOpStart {
+            return Err(error("Expected start of operation".to_owned()));
+        }
+        let (pop, push) = match op {
+            OpIndex::NoOp => return Err(error("Unexpected empty op".to_owned())),
+            OpIndex::DataPointer => {
+                let offset = stream.read_u64::<BigEndian>()? as usize;
+                if !data_offsets.contains(&offset) {
+                    return Err(error(format!("No data constant at offset {}", offset)));
+                }
+                (0, 1)
+            }
+            OpIndex::ParamsPointer => {
+                let offset = stream.read_u64::<BigEndian>()? as usize;
+                if !params.contains(&offset) {
+                    return Err(error(format!("No param at offset {}", offset)));
+                }
+                (0, 1)
+            }
+            OpIndex::ResultPointer => {
+                if function.typeid().is_none() {
+                    return Err(error("Function does not return value".to_owned()));
+                }
+                (0, 1)
+            }
+            OpIndex::LocalsPointer => {
+                let offset = stream.read_u64::<BigEndian>()? as usize;
+                if locals_size == 0 || !locals.contains(&offset) {
+                    return Err(error(format!("No local variable at offset {}", offset)));
+                }
+                (0, 1)
+            }
+            OpIndex::GlobalsPointer => {
+                let offset = stream.read_u64::<BigEndian>()? as usize;
+                if offset >= assembly.globals_size() {
+                    return Err(error(format!(
+                        "Global offset {} exceeds globals size {}",
+                        offset,
+                        assembly.globals_size()
+                    )));
+                }
+                (0, 1)
+            }
+            OpIndex::OffsetPointer => {
+                stream.read_u64::<BigEndian>()?;
+                (1, 1)
+            }
+            OpIndex::ReferencePointer | OpIndex::DereferencePointer => (1, 1),
+            OpIndex::StoreTargetAddress | OpIndex::StoreParamAddress => (1, 0),
+            OpIndex::StoreTypedTargetAddress | OpIndex::StoreTypedParamAddress => {
+                verify_type(&read_type(&mut stream)?, assembly)
+                    .map_err(|err| error(err.message))?;
+                (1, 0)
+            }
+            OpIndex::LabelAddress => {
+                labels_targets.push((address, stream.read_u64::<BigEndian>()? as usize));
+                (0, 1)
+            }
+            OpIndex::ProduceTuple => {
+                let count = stream.read_u64::<BigEndian>()? as usize;
+                for _ in 0..count {
+                    stream.read_u64::<BigEndian>()?;
+                }
+                (count, 1)
+            }
+            OpIndex::CallFunction => {
+                let index = stream.read_u64::<BigEndian>()? as usize;
+                if let Some(f) = assembly.function_by_index(index) {
+                    (f.params().len(), 1)
+                } else {
+                    return Err(error(format!("Calling non-existing function #{}", index)));
+                }
+            }
+            OpIndex::ExecuteOpStart => {
+                if !scopes.is_empty() {
+                    return Err(error("Operation is already started".to_owned()));
+                }
+                ops_starts.insert(address);
+                scopes.push(0);
+                continue;
+            }
+            OpIndex::ExecuteOpInlineStart => {
+                scopes.push(0);
+                continue;
+            }
+            OpIndex::ExecuteOpStop
+            | OpIndex::ExecuteOpInlineStop
+            | OpIndex::ExecuteOpInlineTypedStop => {
+                let index = stream.read_u64::<BigEndian>()? as usize;
+                if index >= assembly.ops_map().len() {
+                    return Err(error(format!("Executing non-existing op #{}", index)));
+                }
+                let inline = op != OpIndex::ExecuteOpStop;
+                if inline {
+                    stream.read_u64::<BigEndian>()?;
+                }
+                if op == OpIndex::ExecuteOpInlineTypedStop {
+                    verify_type(&read_type(&mut stream)?, assembly)
+                        .map_err(|err| error(err.message))?;
+                }
+                if inline == (scopes.len() == 1) {
+                    return Err(error("Mismatched operation start".to_owned()));
+                }
+                let depth = scopes.pop().unwrap();
+                if depth != 0 {
+                    return Err(error(format!(
+                        "Pointer stack is not balanced: {} pointers left",
+                        depth
+                    )));
+                }
+                if inline {
+                    (0, 1)
+                } else {
+                    continue;
+                }
+            }
+        };
+        let depth = scopes.last_mut().unwrap();
+        if *depth < pop {
+            return Err(error(format!(
+                "Pointer stack underflow: expected {} pointers, got {}",
+                pop, depth
+            )));
+        }
+        *depth = *depth - pop + push;
+    }
+    if !scopes.is_empty() {
+        return Err(SimpleError::new(
+            "Function body ends inside operation".to_owned(),
+        ));
+    }
+    for (id, address) in body.labels() {
+        if *address != code.len() && !ops_starts.contains(address) {
+            return Err(SimpleError::new(format!(
+                "Label `{}` points to invalid address {:04}",
+                id, address
+            )));
+        }
+    }
+    for (address, target) in labels_targets {
+        if target != code.len() && !ops_starts.contains(&target) {
+            return Err(SimpleError::new(format!(
+                "Op `LabelAddress` at {:04}: Invalid target address {:04}",
+                address, target
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembly::encode_assembly;
+    use crate::program::*;
+    use crate::validator::*;
+
+    #[test]
+    fn test_verify() {
+        let ops = compile_ops_descriptor("goto to: @label {}\npass v: $? {}").unwrap();
+        let source = "fn foo(a: i32): i32 {\n  pass a;\n}\nexport fn main() <a: (i32, u8)> {\nloop:\n  pass a;\n  pass @<foo(42)>;\n  pass 'hi';\n  goto 'loop';\n}";
+        let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+        let program = Program::from_modules(None, vec![module]).unwrap();
+        let bytes = encode_assembly(&program, &ops, false).unwrap();
+        assert!(VmAssembly::new(bytes.clone()).is_ok());
+        for size in 0..bytes.len() {
+            assert!(VmAssembly::new(bytes[..size].to_vec()).is_err());
+        }
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() = 42;
+        assert_eq!(
+            VmAssembly::new(corrupted).unwrap_err().message,
+            "Function #1: Op `ExecuteOpStop` at 0080: Executing non-existing op #42"
+        );
+    }
+}
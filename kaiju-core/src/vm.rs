@@ -1,24 +1,39 @@
 #![allow(clippy::unused_io_amount)]
 
-use crate::assembly::DataType;
+use crate::assembly::{DataType, OpIndex};
 use crate::error::*;
-use byteorder::{BigEndian, ReadBytesExt};
+use crate::target::{Endianness, Target};
+use crate::verifier::verify_assembly;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::collections::HashMap;
 use std::fmt;
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::mem::size_of;
 
+fn read_bytes(stream: &mut dyn Read, size: usize) -> SimpleResult<Vec<u8>> {
+    let mut bytes = vec![];
+    stream.take(size as u64).read_to_end(&mut bytes)?;
+    if bytes.len() == size {
+        Ok(bytes)
+    } else {
+        Err(SimpleError::new(format!(
+            "Unexpected end of assembly: expected {} bytes, got {}",
+            size,
+            bytes.len()
+        )))
+    }
+}
+
 fn read_string(stream: &mut dyn Read) -> SimpleResult<String> {
     let size = stream.read_u64::<BigEndian>()? as usize;
-    let mut bytes = vec![0; size];
-    stream.read(&mut bytes)?;
+    let bytes = read_bytes(stream, size)?;
     match String::from_utf8(bytes) {
         Ok(s) => Ok(s),
         Err(err) => Err(SimpleError::new(format!("{}", err))),
     }
 }
 
-fn read_type(stream: &mut dyn Read) -> SimpleResult<Type> {
+pub fn read_type(stream: &mut dyn Read) -> SimpleResult<Type> {
     let mode = stream.read_u8()?;
     match mode {
         0 => {
@@ -34,10 +49,31 @@ fn read_type(stream: &mut dyn Read) -> SimpleResult<Type> {
             }
             Ok(Type::Tuple(types))
         }
-        _ => unreachable!(),
+        _ => Err(SimpleError::new(format!("Unsupported type mode: {}", mode))),
     }
 }
 
+pub fn write_type(typeid: &Type, stream: &mut dyn Write) -> SimpleResult<()> {
+    match typeid {
+        Type::Identifier(index) => {
+            stream.write_u8(0)?;
+            stream.write_u64::<BigEndian>(*index as u64)?;
+        }
+        Type::Pointer(typeid) => {
+            stream.write_u8(1)?;
+            write_type(typeid, stream)?;
+        }
+        Type::Tuple(types) => {
+            stream.write_u8(2)?;
+            stream.write_u64::<BigEndian>(types.len() as u64)?;
+            for t in types {
+                write_type(t, stream)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 fn read_variable(stream: &mut dyn Read) -> SimpleResult<Variable> {
     let index = stream.read_u64::<BigEndian>()? as usize;
     let typeid = read_type(stream)?;
@@ -75,13 +111,64 @@ pub enum Data {
     String(String),
 }
 
-#[derive(Debug, Clone)]
+impl Data {
+    pub fn data_type(&self) -> DataType {
+        match self {
+            Data::None => DataType::Unknown,
+            Data::I8(_) => DataType::I8,
+            Data::U8(_) => DataType::U8,
+            Data::I16(_) => DataType::I16,
+            Data::U16(_) => DataType::U16,
+            Data::I32(_) => DataType::I32,
+            Data::U32(_) => DataType::U32,
+            Data::I64(_) => DataType::I64,
+            Data::U64(_) => DataType::U64,
+            Data::F32(_) => DataType::F32,
+            Data::F64(_) => DataType::F64,
+            Data::Isize(_) => DataType::Isize,
+            Data::Usize(_) => DataType::Usize,
+            Data::String(_) => DataType::StringU8,
+        }
+    }
+
+    /// Size of data in memory of target with given pointer size.
+    pub fn size(&self, pointer_size: usize) -> usize {
+        match self {
+            Data::None => 0,
+            Data::I8(_) | Data::U8(_) => 1,
+            Data::I16(_) | Data::U16(_) => 2,
+            Data::I32(_) | Data::U32(_) | Data::F32(_) => 4,
+            Data::I64(_) | Data::U64(_) | Data::F64(_) => 8,
+            Data::Isize(_) | Data::Usize(_) => pointer_size,
+            Data::String(v) => v.len() + 1 + pointer_size,
+        }
+    }
+}
+
+/// Names of built-in atom structs, stored at the beginning of assembly structs.
+pub const ATOMS: [&str; 12] = [
+    "i8", "u8", "i16", "u16", "i32", "u32", "i64", "u64", "f32", "f64", "isize", "usize",
+];
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     Identifier(usize),
     Pointer(Box<Type>),
     Tuple(Vec<Type>),
 }
 
+impl Type {
+    /// Atom name if type is one of built-in atoms.
+    #[inline]
+    pub fn atom(&self) -> Option<&'static str> {
+        if let Type::Identifier(index) = self {
+            ATOMS.get(*index).cloned()
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Struct {
     index: usize,
@@ -178,10 +265,39 @@ impl Function {
     }
 }
 
+/// Decoded bytecode op with operands read into native values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    DataPointer(usize),
+    ParamsPointer(usize),
+    ResultPointer,
+    LocalsPointer(usize),
+    GlobalsPointer(usize),
+    OffsetPointer(usize),
+    ReferencePointer,
+    DereferencePointer,
+    StoreTargetAddress(Option<Box<Type>>),
+    StoreParamAddress(Option<Box<Type>>),
+    ExecuteOpStart,
+    /// Op index.
+    ExecuteOpStop(usize),
+    ExecuteOpInlineStart,
+    /// Op index, result size and optional result type.
+    ExecuteOpInlineStop(usize, usize, Option<Box<Type>>),
+    /// Sizes of tuple elements.
+    ProduceTuple(Box<[usize]>),
+    /// Function index.
+    CallFunction(usize),
+    /// Instruction index of label.
+    LabelAddress(usize),
+}
+
 #[derive(Clone)]
 pub struct FunctionBody {
     labels: HashMap<String, usize>,
     code: Vec<u8>,
+    instructions: Vec<Instr>,
+    offsets: Vec<usize>,
 }
 
 impl fmt::Debug for FunctionBody {
@@ -189,11 +305,16 @@ impl fmt::Debug for FunctionBody {
         f.debug_struct("FunctionBody")
             .field("labels", &self.labels)
             .field("code", &format!("[...; {}]", self.code.len()))
+            .field(
+                "instructions",
+                &format!("[...; {}]", self.instructions.len()),
+            )
             .finish()
     }
 }
 
 impl FunctionBody {
+    /// Labels bytecode addresses.
     #[inline]
     pub fn labels(&self) -> &HashMap<String, usize> {
         &self.labels
@@ -203,6 +324,125 @@ impl FunctionBody {
     pub fn code(&self) -> &[u8] {
         &self.code
     }
+
+    /// Code decoded once when assembly is loaded; VM executes these.
+    #[inline]
+    pub fn instructions(&self) -> &[Instr] {
+        &self.instructions
+    }
+
+    /// Bytecode address of instruction; index past the last instruction gives code size.
+    #[inline]
+    pub fn instruction_offset(&self, index: usize) -> usize {
+        self.offsets.get(index).cloned().unwrap_or(self.code.len())
+    }
+
+    /// Index of instruction starting at bytecode address; code size gives instructions count.
+    pub fn instruction_index(&self, address: usize) -> Option<usize> {
+        if address == self.code.len() {
+            Some(self.instructions.len())
+        } else {
+            self.offsets.binary_search(&address).ok()
+        }
+    }
+
+    fn decode(&mut self) -> SimpleResult<()> {
+        let mut stream = Cursor::new(&self.code);
+        let mut instructions = vec![];
+        let mut offsets = vec![];
+        while (stream.position() as usize) < self.code.len() {
+            let address = stream.position() as usize;
+            let byte = stream.read_u8()?;
+            let op = if let Some(op) = OpIndex::from_byte(byte) {
+                op
+            } else {
+                return Err(SimpleError::new(format!(
+                    "Unsupported op index at {:04}: {}",
+                    address, byte
+                )));
+            };
+            let instr = match op {
+                OpIndex::NoOp => {
+                    return Err(SimpleError::new(format!(
+                        "Unexpected empty op at {:04}",
+                        address
+                    )))
+                }
+                OpIndex::DataPointer => {
+                    Instr::DataPointer(stream.read_u64::<BigEndian>()? as usize)
+                }
+                OpIndex::ParamsPointer => {
+                    Instr::ParamsPointer(stream.read_u64::<BigEndian>()? as usize)
+                }
+                OpIndex::ResultPointer => Instr::ResultPointer,
+                OpIndex::LocalsPointer => {
+                    Instr::LocalsPointer(stream.read_u64::<BigEndian>()? as usize)
+                }
+                OpIndex::GlobalsPointer => {
+                    Instr::GlobalsPointer(stream.read_u64::<BigEndian>()? as usize)
+                }
+                OpIndex::OffsetPointer => {
+                    Instr::OffsetPointer(stream.read_u64::<BigEndian>()? as usize)
+                }
+                OpIndex::ReferencePointer => Instr::ReferencePointer,
+                OpIndex::DereferencePointer => Instr::DereferencePointer,
+                OpIndex::StoreTargetAddress => Instr::StoreTargetAddress(None),
+                OpIndex::StoreParamAddress => Instr::StoreParamAddress(None),
+                OpIndex::StoreTypedTargetAddress => {
+                    Instr::StoreTargetAddress(Some(Box::new(read_type(&mut stream)?)))
+                }
+                OpIndex::StoreTypedParamAddress => {
+                    Instr::StoreParamAddress(Some(Box::new(read_type(&mut stream)?)))
+                }
+                OpIndex::ExecuteOpStart => Instr::ExecuteOpStart,
+                OpIndex::ExecuteOpStop => {
+                    Instr::ExecuteOpStop(stream.read_u64::<BigEndian>()? as usize)
+                }
+                OpIndex::ExecuteOpInlineStart => Instr::ExecuteOpInlineStart,
+                OpIndex::ExecuteOpInlineStop | OpIndex::ExecuteOpInlineTypedStop => {
+                    let index = stream.read_u64::<BigEndian>()? as usize;
+                    let size = stream.read_u64::<BigEndian>()? as usize;
+                    let typeid = if op == OpIndex::ExecuteOpInlineTypedStop {
+                        Some(Box::new(read_type(&mut stream)?))
+                    } else {
+                        None
+                    };
+                    Instr::ExecuteOpInlineStop(index, size, typeid)
+                }
+                OpIndex::ProduceTuple => {
+                    let count = stream.read_u64::<BigEndian>()? as usize;
+                    let mut sizes = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        sizes.push(stream.read_u64::<BigEndian>()? as usize);
+                    }
+                    Instr::ProduceTuple(sizes.into_boxed_slice())
+                }
+                OpIndex::CallFunction => {
+                    Instr::CallFunction(stream.read_u64::<BigEndian>()? as usize)
+                }
+                OpIndex::LabelAddress => {
+                    Instr::LabelAddress(stream.read_u64::<BigEndian>()? as usize)
+                }
+            };
+            instructions.push(instr);
+            offsets.push(address);
+        }
+        self.instructions = instructions;
+        self.offsets = offsets;
+        for index in 0..self.instructions.len() {
+            if let Instr::LabelAddress(address) = self.instructions[index] {
+                if let Some(target) = self.instruction_index(address) {
+                    self.instructions[index] = Instr::LabelAddress(target);
+                } else {
+                    return Err(SimpleError::new(format!(
+                        "Label address {:04} does not point to instruction",
+                        address
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -235,8 +475,245 @@ impl Variable {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct SourceLocation {
+    address: usize,
+    line: usize,
+    column: usize,
+}
+
+impl SourceLocation {
+    #[inline]
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    #[inline]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    #[inline]
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StructDebugInfo {
+    id: String,
+    fields: Vec<String>,
+}
+
+impl StructDebugInfo {
+    #[inline]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[inline]
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionDebugInfo {
+    id: String,
+    path: String,
+    params: Vec<String>,
+    locals: Vec<String>,
+    locations: Vec<SourceLocation>,
+}
+
+impl FunctionDebugInfo {
+    #[inline]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[inline]
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    #[inline]
+    pub fn locals(&self) -> &[String] {
+        &self.locals
+    }
+
+    #[inline]
+    pub fn locations(&self) -> &[SourceLocation] {
+        &self.locations
+    }
+
+    /// Finds location of op that contains given bytecode address.
+    pub fn location(&self, address: usize) -> Option<&SourceLocation> {
+        self.locations
+            .iter()
+            .take_while(|l| l.address <= address)
+            .last()
+    }
+
+    pub fn describe_location(&self, address: usize) -> String {
+        match self.location(address) {
+            Some(l) if self.path.is_empty() => format!("{}:{}", l.line, l.column),
+            Some(l) => format!("{}:{}:{}", self.path, l.line, l.column),
+            None => self.path.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GlobalDebugInfo {
+    id: String,
+    offset: usize,
+    typeid: Type,
+}
+
+impl GlobalDebugInfo {
+    #[inline]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    pub fn typeid(&self) -> &Type {
+        &self.typeid
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DebugInfo {
+    structs: Vec<StructDebugInfo>,
+    globals: Vec<GlobalDebugInfo>,
+    functions: Vec<FunctionDebugInfo>,
+}
+
+impl DebugInfo {
+    #[inline]
+    pub fn structs(&self) -> &[StructDebugInfo] {
+        &self.structs
+    }
+
+    #[inline]
+    pub fn globals(&self) -> &[GlobalDebugInfo] {
+        &self.globals
+    }
+
+    #[inline]
+    pub fn functions(&self) -> &[FunctionDebugInfo] {
+        &self.functions
+    }
+
+    #[inline]
+    pub fn struct_by_index(&self, index: usize) -> Option<&StructDebugInfo> {
+        self.structs.get(index)
+    }
+
+    #[inline]
+    pub fn function_by_index(&self, index: usize) -> Option<&FunctionDebugInfo> {
+        self.functions.get(index)
+    }
+
+    pub fn global_by_offset(&self, offset: usize) -> Option<&str> {
+        self.globals
+            .iter()
+            .find(|g| g.offset == offset)
+            .map(|g| g.id.as_str())
+    }
+
+    #[inline]
+    pub fn global_by_id(&self, id: &str) -> Option<&GlobalDebugInfo> {
+        self.globals.iter().find(|g| g.id == id)
+    }
+
+    #[inline]
+    pub fn function_index_by_id(&self, id: &str) -> Option<usize> {
+        self.functions.iter().position(|f| f.id == id)
+    }
+
+    fn from_stream(stream: &mut dyn Read) -> SimpleResult<Self> {
+        let read_strings = |stream: &mut dyn Read| -> SimpleResult<Vec<String>> {
+            let count = stream.read_u64::<BigEndian>()? as usize;
+            (0..count).map(|_| read_string(stream)).collect()
+        };
+        let structs = {
+            let count = stream.read_u64::<BigEndian>()? as usize;
+            let mut result = vec![];
+            for _ in 0..count {
+                let id = read_string(stream)?;
+                let fields = read_strings(stream)?;
+                result.push(StructDebugInfo { id, fields });
+            }
+            result
+        };
+        let globals = {
+            let count = stream.read_u64::<BigEndian>()? as usize;
+            let mut result = vec![];
+            for _ in 0..count {
+                let id = read_string(stream)?;
+                let offset = stream.read_u64::<BigEndian>()? as usize;
+                let typeid = read_type(stream)?;
+                result.push(GlobalDebugInfo { id, offset, typeid });
+            }
+            result
+        };
+        let functions = {
+            let count = stream.read_u64::<BigEndian>()? as usize;
+            let mut result = vec![];
+            for _ in 0..count {
+                let id = read_string(stream)?;
+                let path = read_string(stream)?;
+                let params = read_strings(stream)?;
+                let locals = read_strings(stream)?;
+                let locations = {
+                    let count = stream.read_u64::<BigEndian>()? as usize;
+                    let mut result = vec![];
+                    for _ in 0..count {
+                        let address = stream.read_u64::<BigEndian>()? as usize;
+                        let line = stream.read_u64::<BigEndian>()? as usize;
+                        let column = stream.read_u64::<BigEndian>()? as usize;
+                        result.push(SourceLocation {
+                            address,
+                            line,
+                            column,
+                        });
+                    }
+                    result
+                };
+                result.push(FunctionDebugInfo {
+                    id,
+                    path,
+                    params,
+                    locals,
+                    locations,
+                });
+            }
+            result
+        };
+        Ok(Self {
+            structs,
+            globals,
+            functions,
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct VmAssembly {
+    version: u8,
+    target: Target,
     export_structs: HashMap<String, usize>,
     export_functions: HashMap<String, usize>,
     structs: Vec<Struct>,
@@ -245,11 +722,14 @@ pub struct VmAssembly {
     globals_size: usize,
     ops: Vec<String>,
     bodies: Vec<FunctionBody>,
+    debug_info: Option<DebugInfo>,
 }
 
 impl fmt::Debug for VmAssembly {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("VmAssembly")
+            .field("version", &self.version)
+            .field("target", &self.target)
             .field("export_structs", &self.export_structs)
             .field("export_functions", &self.export_functions)
             .field("structs", &self.structs)
@@ -258,6 +738,7 @@ impl fmt::Debug for VmAssembly {
             .field("globals_size", &self.globals_size)
             .field("ops", &self.ops)
             .field("bodies", &self.bodies)
+            .field("debug_info", &self.debug_info.is_some())
             .finish()
     }
 }
@@ -269,7 +750,28 @@ impl VmAssembly {
         let mut magic = [0; 4];
         stream.read(&mut magic)?;
         match magic {
-            [0x4b, 0x4a, 0x50, 1] => Self::new_v1(stream),
+            // first version did not declare target and used host one.
+            [0x4b, 0x4a, 0x50, 1] => Self::from_stream(
+                stream,
+                1,
+                Target::new(size_of::<usize>(), Endianness::native()),
+            ),
+            // second version declared only byte order.
+            [0x4b, 0x4a, 0x50, 2] => {
+                let endianness = stream.read_u8()?;
+                if let Some(endianness) = Endianness::from_byte(endianness) {
+                    Self::from_stream(stream, 2, Target::new(size_of::<usize>(), endianness))
+                } else {
+                    Err(SimpleError::new(format!(
+                        "Unsupported assembly endianness: {}",
+                        endianness
+                    )))
+                }
+            }
+            [0x4b, 0x4a, 0x50, 3] => {
+                let target = Target::read(&mut stream)?;
+                Self::from_stream(stream, 3, target)
+            }
             _ => Err(SimpleError::new(format!(
                 "Trying to run assembly with unsupported version: {}",
                 magic[3]
@@ -277,7 +779,14 @@ impl VmAssembly {
         }
     }
 
-    fn new_v1(mut stream: Cursor<Vec<u8>>) -> SimpleResult<Self> {
+    fn from_stream(mut stream: Cursor<Vec<u8>>, version: u8, target: Target) -> SimpleResult<Self> {
+        if target.pointer_size() > size_of::<usize>() {
+            return Err(SimpleError::new(format!(
+                "Assembly targets {}-byte pointers but VM supports at most {}-byte pointers",
+                target.pointer_size(),
+                size_of::<usize>()
+            )));
+        }
         let export_structs = {
             let _size = stream.read_u64::<BigEndian>()?;
             let count = stream.read_u64::<BigEndian>()?;
@@ -385,7 +894,12 @@ impl VmAssembly {
             let count = stream.read_u64::<BigEndian>()? as usize;
             let mut result = vec![];
             for _ in 0..count {
-                let t = DataType::from(stream.read_u8()?);
+                let t = stream.read_u8()?;
+                let t = if let Some(t) = DataType::from_byte(t) {
+                    t
+                } else {
+                    return Err(SimpleError::new(format!("Unsupported data type: {}", t)));
+                };
                 match t {
                     DataType::Unknown => (),
                     DataType::I8 => result.push(Data::I8(stream.read_i8()?)),
@@ -399,10 +913,10 @@ impl VmAssembly {
                     DataType::F32 => result.push(Data::F32(stream.read_f32::<BigEndian>()?)),
                     DataType::F64 => result.push(Data::F64(stream.read_f64::<BigEndian>()?)),
                     DataType::Isize => result.push(Data::Isize(
-                        stream.read_int::<BigEndian>(size_of::<isize>())? as isize,
+                        stream.read_int::<BigEndian>(target.pointer_size())? as isize,
                     )),
                     DataType::Usize => result.push(Data::Usize(
-                        stream.read_uint::<BigEndian>(size_of::<usize>())? as usize,
+                        stream.read_uint::<BigEndian>(target.pointer_size())? as usize,
                     )),
                     DataType::StringU8 => result.push(Data::String(read_string(&mut stream)?)),
                 }
@@ -440,15 +954,27 @@ impl VmAssembly {
                 let code = {
                     let size = stream.read_u64::<BigEndian>()? as usize;
                     let _count = stream.read_u64::<BigEndian>()? as usize;
-                    let mut result = vec![0; size];
-                    stream.read(&mut result)?;
-                    result
+                    read_bytes(&mut stream, size)?
                 };
-                result.push(FunctionBody { labels, code });
+                result.push(FunctionBody {
+                    labels,
+                    code,
+                    instructions: vec![],
+                    offsets: vec![],
+                });
             }
             result
         };
-        Ok(Self {
+        let debug_info = if (stream.position() as usize) < stream.get_ref().len() {
+            let size = stream.read_u64::<BigEndian>()? as usize;
+            let bytes = read_bytes(&mut stream, size)?;
+            Some(DebugInfo::from_stream(&mut Cursor::new(bytes))?)
+        } else {
+            None
+        };
+        let mut result = Self {
+            version,
+            target,
             export_structs,
             export_functions,
             structs,
@@ -457,7 +983,13 @@ impl VmAssembly {
             globals_size,
             ops,
             bodies,
-        })
+            debug_info,
+        };
+        verify_assembly(&result)?;
+        for body in &mut result.bodies {
+            body.decode()?;
+        }
+        Ok(result)
     }
 
     #[inline]
@@ -485,6 +1017,38 @@ impl VmAssembly {
         &self.data
     }
 
+    pub fn data_offsets(&self) -> Vec<usize> {
+        let mut offset = 0;
+        self.data
+            .iter()
+            .map(|d| {
+                let size = d.size(self.target.pointer_size());
+                offset += size;
+                match d {
+                    Data::String(_) => offset - self.target.pointer_size(),
+                    _ => offset - size,
+                }
+            })
+            .collect()
+    }
+
+    /// Version of assembly format that assembly was loaded from.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    #[inline]
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
+    /// Byte order of values in VM memory.
+    #[inline]
+    pub fn endianness(&self) -> Endianness {
+        self.target.endianness()
+    }
+
     #[inline]
     pub fn globals_size(&self) -> usize {
         self.globals_size
@@ -500,6 +1064,11 @@ impl VmAssembly {
         &self.bodies
     }
 
+    #[inline]
+    pub fn debug_info(&self) -> &Option<DebugInfo> {
+        &self.debug_info
+    }
+
     #[inline]
     pub fn struct_by_id(&self, id: &str) -> Option<&Struct> {
         if let Some(i) = self.export_structs.get(id) {
@@ -558,7 +1127,7 @@ impl VmAssembly {
     pub fn type_size(&self, typeid: &Type) -> usize {
         match typeid {
             Type::Identifier(i) => self.structs[*i].size(),
-            Type::Pointer(_) => size_of::<usize>(),
+            Type::Pointer(_) => self.target.pointer_size(),
             Type::Tuple(t) => t.iter().map(|t| self.type_size(t)).sum(),
         }
     }
@@ -9,23 +9,22 @@ extern crate lazy_static;
 use core::error::*;
 use std::collections::HashMap;
 use std::ffi::CString;
-use std::mem::{size_of, transmute};
 use std::ptr::{copy_nonoverlapping, null, null_mut};
+use std::slice::from_raw_parts;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
-use vm_core::processor::{OpAction, Processor};
-use vm_core::state::Value;
-use vm_core::vm::Vm;
+use vm_core::processor::{HandlerId, OpAction, Processor};
+use vm_core::vm::{NativesRegistry, ResumeStatus, Vm};
 
 lazy_static! {
     static ref HANDLE_GEN: AtomicUsize = AtomicUsize::new(0);
-    static ref VMS: Mutex<HashMap<Handle, Vm>> = Mutex::new(HashMap::new());
-    static ref PROCESS_OP: Mutex<Option<(usize, KaijuFuncProcessOp)>> = Mutex::new(None);
-    static ref VM: Mutex<Option<&'static mut Vm>> = Mutex::new(None);
-    static ref OP_ACTION: Mutex<OpAction> = Mutex::new(OpAction::None);
+    static ref VMS: Mutex<HashMap<Handle, (Vm, ExternalProcessor)>> = Mutex::new(HashMap::new());
 }
 
-type KaijuFuncProcessOp = fn(
+type KaijuFuncBindOp = extern "C" fn(context: *mut libc::c_void, op: *const libc::c_char) -> bool;
+
+type KaijuFuncProcessOp = extern "C" fn(
+    kaiju: *mut KaijuContext,
     context: *mut libc::c_void,
     op: *const libc::c_char,
     params: *const usize,
@@ -34,43 +33,109 @@ type KaijuFuncProcessOp = fn(
     targets_count: usize,
 );
 
+type KaijuFuncError = extern "C" fn(context: *mut libc::c_void, message: *const libc::c_char);
+
+type KaijuFuncPerform = extern "C" fn(kaiju: *mut KaijuContext, context: *mut libc::c_void);
+
+type KaijuFuncCallNative = extern "C" fn(
+    kaiju: *mut KaijuContext,
+    context: *mut libc::c_void,
+    params: *const usize,
+    params_count: usize,
+    result: usize,
+    has_result: bool,
+) -> bool;
+
 type Handle = usize;
 
-struct ExternalProcessor {}
+pub struct KaijuContext {
+    vm: *mut Vm,
+    action: OpAction,
+}
+
+#[derive(Default, Clone)]
+struct ExternalProcessor {
+    ops: Vec<CString>,
+    context: usize,
+    on_bind_op: Option<KaijuFuncBindOp>,
+    on_process_op: Option<KaijuFuncProcessOp>,
+}
+
+impl ExternalProcessor {
+    /// Creates processor that asks host with `on_bind_op` which ops it supports.
+    fn binding(context: *mut libc::c_void, on_bind_op: KaijuFuncBindOp) -> Self {
+        Self {
+            ops: vec![],
+            context: context as usize,
+            on_bind_op: Some(on_bind_op),
+            on_process_op: None,
+        }
+    }
+
+    fn attach(&mut self, context: *mut libc::c_void, on_process_op: KaijuFuncProcessOp) {
+        self.context = context as usize;
+        self.on_process_op = Some(on_process_op);
+    }
+
+    fn detach(&mut self) {
+        self.context = 0;
+        self.on_process_op = None;
+    }
+}
 
 impl Processor for ExternalProcessor {
+    fn bind_op(&mut self, op: &str) -> Option<HandlerId> {
+        if let Some(id) = self.ops.iter().position(|o| o.as_bytes() == op.as_bytes()) {
+            Some(id)
+        } else if let (Some(on_bind_op), Ok(op)) = (self.on_bind_op, CString::new(op)) {
+            if on_bind_op(self.context as *mut libc::c_void, op.as_ptr()) {
+                self.ops.push(op);
+                Some(self.ops.len() - 1)
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+
     fn process_op(
-        op: &String,
+        &mut self,
+        handler: HandlerId,
         params: &[usize],
         targets: &[usize],
         vm: &mut Vm,
     ) -> SimpleResult<OpAction> {
-        if let Some((context, on_process_op)) = *PROCESS_OP.lock().unwrap() {
-            let csop = CString::new(op.as_str()).unwrap();
-            {
-                *VM.lock().unwrap() = Some(unsafe { transmute::<&mut Vm, &'static mut Vm>(vm) });
-            }
+        if let Some(on_process_op) = self.on_process_op {
+            let mut kaiju = KaijuContext {
+                vm: vm as *mut Vm,
+                action: OpAction::None,
+            };
             on_process_op(
-                context as *mut libc::c_void,
-                csop.as_ptr(),
+                &mut kaiju as *mut KaijuContext,
+                self.context as *mut libc::c_void,
+                self.ops[handler].as_ptr(),
                 params.as_ptr(),
                 params.len(),
                 targets.as_ptr(),
                 targets.len(),
             );
-            {
-                *VM.lock().unwrap() = None;
-            }
-            let mut action = OP_ACTION.lock().unwrap();
-            let a = *action;
-            *action = OpAction::None;
-            Ok(a)
+            Ok(kaiju.action)
         } else {
             Err(SimpleError::new("There is no active processor".to_owned()))
         }
     }
 }
 
+/// Host function that `extern fn ... from module:function` calls are dispatched to.
+#[repr(C)]
+pub struct KaijuNative {
+    pub module: *const libc::c_char,
+    pub function: *const libc::c_char,
+    pub on_call_native: Option<KaijuFuncCallNative>,
+    pub context: *mut libc::c_void,
+}
+
 #[repr(C)]
 pub struct KaijuInfoState {
     pub stack_size: usize,
@@ -81,6 +146,14 @@ pub struct KaijuInfoState {
     pub all_free: usize,
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum KaijuResumeStatus {
+    Error = 0,
+    Yielded = 1,
+    Completed = 2,
+}
+
 #[no_mangle]
 pub extern "C" fn kaiju_start_program(
     bytes: *const libc::c_uchar,
@@ -88,25 +161,42 @@ pub extern "C" fn kaiju_start_program(
     entry: *const libc::c_char,
     memsize: usize,
     stacksize: usize,
-    error: fn(*mut libc::c_void, *const libc::c_char),
+    natives: *const KaijuNative,
+    natives_count: usize,
+    on_bind_op: Option<KaijuFuncBindOp>,
+    processor_context: *mut libc::c_void,
+    error: Option<KaijuFuncError>,
     error_context: *mut libc::c_void,
 ) -> Handle {
+    let error = match error {
+        Some(error) => error,
+        None => return 0,
+    };
+    let on_bind_op = match on_bind_op {
+        Some(on_bind_op) => on_bind_op,
+        None => {
+            let err = CString::new("Some of parameters are zeros or null pointers!").unwrap();
+            error(error_context, err.as_ptr());
+            return 0;
+        }
+    };
     if bytes.is_null()
         || size == 0
         || entry.is_null()
         || memsize == 0
         || stacksize == 0
-        || (error as *const libc::c_void).is_null()
+        || (natives.is_null() && natives_count > 0)
     {
-        if !(error as *const libc::c_void).is_null() {
-            let err = CString::new("Some of parameters are zeros or null pointers!").unwrap();
-            error(error_context, err.as_ptr());
-        }
+        let err = CString::new("Some of parameters are zeros or null pointers!").unwrap();
+        error(error_context, err.as_ptr());
         return 0;
     }
-    let bytes = bytes_from_raw(bytes, size as usize);
-    match Vm::from_bytes(bytes, stacksize as usize, memsize as usize) {
-        Ok(mut vm) => match vm.start(&string_from_raw_unsized(entry as *const libc::c_uchar)) {
+    let mut processor = ExternalProcessor::binding(processor_context, on_bind_op);
+    match vm_from_raw(bytes, size, memsize, stacksize, natives, natives_count) {
+        Ok(mut vm) => match vm
+            .bind(&mut processor)
+            .and_then(|_| vm.start(&string_from_raw_unsized(entry as *const libc::c_uchar)))
+        {
             Ok(_) => {
                 let handle = {
                     let gen = HANDLE_GEN.load(Ordering::Relaxed);
@@ -114,7 +204,7 @@ pub extern "C" fn kaiju_start_program(
                     HANDLE_GEN.store(handle, Ordering::Relaxed);
                     handle
                 };
-                VMS.lock().unwrap().insert(handle, vm);
+                VMS.lock().unwrap().insert(handle, (vm, processor));
                 handle
             }
             Err(err) => {
@@ -138,52 +228,60 @@ pub extern "C" fn kaiju_run_program(
     entry: *const libc::c_char,
     memsize: usize,
     stacksize: usize,
-    on_process_op: fn(
-        *mut libc::c_void,
-        *const libc::c_char,
-        *const usize,
-        usize,
-        *const usize,
-        usize,
-    ),
+    natives: *const KaijuNative,
+    natives_count: usize,
+    on_bind_op: Option<KaijuFuncBindOp>,
+    on_process_op: Option<KaijuFuncProcessOp>,
     processor_context: *mut libc::c_void,
-    error: fn(*mut libc::c_void, *const libc::c_char),
+    error: Option<KaijuFuncError>,
     error_context: *mut libc::c_void,
 ) -> bool {
+    let error = match error {
+        Some(error) => error,
+        None => return false,
+    };
+    let on_bind_op = match on_bind_op {
+        Some(on_bind_op) => on_bind_op,
+        None => {
+            let err = CString::new("Some of parameters are zeros or null pointers!").unwrap();
+            error(error_context, err.as_ptr());
+            return false;
+        }
+    };
+    let on_process_op = match on_process_op {
+        Some(on_process_op) => on_process_op,
+        None => {
+            let err = CString::new("Some of parameters are zeros or null pointers!").unwrap();
+            error(error_context, err.as_ptr());
+            return false;
+        }
+    };
     if bytes.is_null()
         || size == 0
         || entry.is_null()
         || memsize == 0
         || stacksize == 0
-        || (on_process_op as *const libc::c_void).is_null()
-        || (error as *const libc::c_void).is_null()
+        || (natives.is_null() && natives_count > 0)
     {
-        if !(error as *const libc::c_void).is_null() {
-            let err = CString::new("Some of parameters are zeros or null pointers!").unwrap();
-            error(error_context, err.as_ptr());
-        }
+        let err = CString::new("Some of parameters are zeros or null pointers!").unwrap();
+        error(error_context, err.as_ptr());
         return false;
     }
-    let bytes = bytes_from_raw(bytes, size as usize);
-    match Vm::from_bytes(bytes, stacksize as usize, memsize as usize) {
+    match vm_from_raw(bytes, size, memsize, stacksize, natives, natives_count) {
         Ok(mut vm) => {
-            {
-                *PROCESS_OP.lock().unwrap() = Some((processor_context as usize, on_process_op));
-            }
-            let result = match vm
-                .run::<ExternalProcessor>(&string_from_raw_unsized(entry as *const libc::c_uchar))
-            {
+            let mut processor = ExternalProcessor::binding(processor_context, on_bind_op);
+            processor.attach(processor_context, on_process_op);
+            match vm.run(
+                &mut processor,
+                &string_from_raw_unsized(entry as *const libc::c_uchar),
+            ) {
                 Ok(_) => true,
                 Err(err) => {
-                    let err = CString::new(err.message).unwrap();
+                    let err = CString::new(err.to_string()).unwrap();
                     error(error_context, err.as_ptr());
                     false
                 }
-            };
-            {
-                *PROCESS_OP.lock().unwrap() = None;
             }
-            result
         }
         Err(err) => {
             let err = CString::new(err.message).unwrap();
@@ -196,47 +294,40 @@ pub extern "C" fn kaiju_run_program(
 #[no_mangle]
 pub extern "C" fn kaiju_resume_program(
     handle: Handle,
-    on_process_op: fn(
-        *mut libc::c_void,
-        *const libc::c_char,
-        *const usize,
-        usize,
-        *const usize,
-        usize,
-    ),
+    on_process_op: Option<KaijuFuncProcessOp>,
     processor_context: *mut libc::c_void,
-    error: fn(*mut libc::c_void, *const libc::c_char),
+    error: Option<KaijuFuncError>,
     error_context: *mut libc::c_void,
 ) -> bool {
-    if (on_process_op as *const libc::c_void).is_null() || (error as *const libc::c_void).is_null()
-    {
-        if !(error as *const libc::c_void).is_null() {
+    let error = match error {
+        Some(error) => error,
+        None => return false,
+    };
+    let on_process_op = match on_process_op {
+        Some(on_process_op) => on_process_op,
+        None => {
             let err = CString::new("Some of parameters are null pointers!").unwrap();
             error(error_context, err.as_ptr());
+            return false;
         }
-        return false;
-    }
+    };
     let mut vms = VMS.lock().unwrap();
     match vms.get_mut(&handle) {
-        Some(vm) => {
+        Some((vm, processor)) => {
             if !vm.can_resume() {
                 vms.remove(&handle);
                 return false;
             }
-            {
-                *PROCESS_OP.lock().unwrap() = Some((processor_context as usize, on_process_op));
-            }
-            let result = match vm.resume::<ExternalProcessor>() {
+            processor.attach(processor_context, on_process_op);
+            let result = match vm.resume(processor) {
                 Ok(_) => true,
                 Err(err) => {
-                    let err = CString::new(err.message).unwrap();
+                    let err = CString::new(err.to_string()).unwrap();
                     error(error_context, err.as_ptr());
                     false
                 }
             };
-            {
-                *PROCESS_OP.lock().unwrap() = None;
-            }
+            processor.detach();
             result
         }
         None => {
@@ -247,51 +338,95 @@ pub extern "C" fn kaiju_resume_program(
     }
 }
 
+#[no_mangle]
+pub extern "C" fn kaiju_resume_program_budget(
+    handle: Handle,
+    budget: usize,
+    on_process_op: Option<KaijuFuncProcessOp>,
+    processor_context: *mut libc::c_void,
+    error: Option<KaijuFuncError>,
+    error_context: *mut libc::c_void,
+) -> KaijuResumeStatus {
+    let error = match error {
+        Some(error) => error,
+        None => return KaijuResumeStatus::Error,
+    };
+    let on_process_op = match on_process_op {
+        Some(on_process_op) => on_process_op,
+        None => {
+            let err = CString::new("Some of parameters are null pointers!").unwrap();
+            error(error_context, err.as_ptr());
+            return KaijuResumeStatus::Error;
+        }
+    };
+    let mut vms = VMS.lock().unwrap();
+    match vms.get_mut(&handle) {
+        Some((vm, processor)) => {
+            if !vm.can_resume() {
+                vms.remove(&handle);
+                return KaijuResumeStatus::Completed;
+            }
+            processor.attach(processor_context, on_process_op);
+            let result = match vm.resume_with_budget(processor, budget) {
+                ResumeStatus::Yielded => KaijuResumeStatus::Yielded,
+                ResumeStatus::Completed => KaijuResumeStatus::Completed,
+                ResumeStatus::Error(err) => {
+                    let err = CString::new(err.to_string()).unwrap();
+                    error(error_context, err.as_ptr());
+                    KaijuResumeStatus::Error
+                }
+            };
+            processor.detach();
+            if result != KaijuResumeStatus::Yielded {
+                vms.remove(&handle);
+            }
+            result
+        }
+        None => {
+            let err = CString::new(format!("There is no VM with handle: {}", handle)).unwrap();
+            error(error_context, err.as_ptr());
+            KaijuResumeStatus::Error
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn kaiju_consume_program(
     handle: Handle,
-    on_process_op: fn(
-        *mut libc::c_void,
-        *const libc::c_char,
-        *const usize,
-        usize,
-        *const usize,
-        usize,
-    ),
+    on_process_op: Option<KaijuFuncProcessOp>,
     processor_context: *mut libc::c_void,
-    error: fn(*mut libc::c_void, *const libc::c_char),
+    error: Option<KaijuFuncError>,
     error_context: *mut libc::c_void,
 ) -> bool {
-    if (on_process_op as *const libc::c_void).is_null() || (error as *const libc::c_void).is_null()
-    {
-        if !(error as *const libc::c_void).is_null() {
+    let error = match error {
+        Some(error) => error,
+        None => return false,
+    };
+    let on_process_op = match on_process_op {
+        Some(on_process_op) => on_process_op,
+        None => {
             let err = CString::new("Some of parameters are null pointers!").unwrap();
             error(error_context, err.as_ptr());
+            return false;
         }
-        return false;
-    }
+    };
     let mut vms = VMS.lock().unwrap();
     match vms.get_mut(&handle) {
-        Some(vm) => {
+        Some((vm, processor)) => {
             if !vm.can_resume() {
                 vms.remove(&handle);
                 return false;
             }
-            {
-                *PROCESS_OP.lock().unwrap() = Some((processor_context as usize, on_process_op));
-            }
-            let result = match vm.consume::<ExternalProcessor>() {
+            processor.attach(processor_context, on_process_op);
+            let result = match vm.consume(processor) {
                 Ok(_) => true,
                 Err(err) => {
-                    let err = CString::new(err.message).unwrap();
+                    let err = CString::new(err.to_string()).unwrap();
                     error(error_context, err.as_ptr());
                     false
                 }
             };
             vms.remove(&handle);
-            {
-                *PROCESS_OP.lock().unwrap() = None;
-            }
             result
         }
         None => {
@@ -302,6 +437,138 @@ pub extern "C" fn kaiju_consume_program(
     }
 }
 
+#[no_mangle]
+pub extern "C" fn kaiju_load_program(
+    bytes: *const libc::c_uchar,
+    size: usize,
+    memsize: usize,
+    stacksize: usize,
+    natives: *const KaijuNative,
+    natives_count: usize,
+    on_bind_op: Option<KaijuFuncBindOp>,
+    processor_context: *mut libc::c_void,
+    error: Option<KaijuFuncError>,
+    error_context: *mut libc::c_void,
+) -> Handle {
+    let error = match error {
+        Some(error) => error,
+        None => return 0,
+    };
+    let on_bind_op = match on_bind_op {
+        Some(on_bind_op) => on_bind_op,
+        None => {
+            let err = CString::new("Some of parameters are zeros or null pointers!").unwrap();
+            error(error_context, err.as_ptr());
+            return 0;
+        }
+    };
+    if bytes.is_null()
+        || size == 0
+        || memsize == 0
+        || stacksize == 0
+        || (natives.is_null() && natives_count > 0)
+    {
+        let err = CString::new("Some of parameters are zeros or null pointers!").unwrap();
+        error(error_context, err.as_ptr());
+        return 0;
+    }
+    let mut processor = ExternalProcessor::binding(processor_context, on_bind_op);
+    match vm_from_raw(bytes, size, memsize, stacksize, natives, natives_count)
+        .and_then(|mut vm| vm.bind(&mut processor).map(|_| vm))
+    {
+        Ok(vm) => {
+            let handle = {
+                let gen = HANDLE_GEN.load(Ordering::Relaxed);
+                let handle = gen + 1;
+                HANDLE_GEN.store(handle, Ordering::Relaxed);
+                handle
+            };
+            VMS.lock().unwrap().insert(handle, (vm, processor));
+            handle
+        }
+        Err(err) => {
+            let err = CString::new(err.message).unwrap();
+            error(error_context, err.as_ptr());
+            0
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn kaiju_call_function(
+    handle: Handle,
+    function: *const libc::c_char,
+    args: *const libc::c_uchar,
+    args_size: usize,
+    result: *mut libc::c_uchar,
+    result_size: usize,
+    on_process_op: Option<KaijuFuncProcessOp>,
+    processor_context: *mut libc::c_void,
+    error: Option<KaijuFuncError>,
+    error_context: *mut libc::c_void,
+) -> bool {
+    let error = match error {
+        Some(error) => error,
+        None => return false,
+    };
+    let on_process_op = match on_process_op {
+        Some(on_process_op) => on_process_op,
+        None => {
+            let err = CString::new("Some of parameters are null pointers!").unwrap();
+            error(error_context, err.as_ptr());
+            return false;
+        }
+    };
+    if function.is_null()
+        || (args.is_null() && args_size > 0)
+        || (result.is_null() && result_size > 0)
+    {
+        let err = CString::new("Some of parameters are null pointers!").unwrap();
+        error(error_context, err.as_ptr());
+        return false;
+    }
+    let mut vms = VMS.lock().unwrap();
+    match vms.get_mut(&handle) {
+        Some((vm, processor)) => {
+            let function = string_from_raw_unsized(function as *const libc::c_uchar);
+            let args = bytes_from_raw(args, args_size);
+            processor.attach(processor_context, on_process_op);
+            let status = match vm.call(processor, &function, &args) {
+                Ok(bytes) => {
+                    if bytes.len() == result_size {
+                        if result_size > 0 {
+                            unsafe { copy_nonoverlapping(bytes.as_ptr(), result, result_size) };
+                        }
+                        true
+                    } else {
+                        let err = CString::new(format!(
+                            "Function `{}` returns {} bytes but result buffer has {}",
+                            function,
+                            bytes.len(),
+                            result_size
+                        ))
+                        .unwrap();
+                        error(error_context, err.as_ptr());
+                        false
+                    }
+                }
+                Err(err) => {
+                    let err = CString::new(err.to_string()).unwrap();
+                    error(error_context, err.as_ptr());
+                    false
+                }
+            };
+            processor.detach();
+            status
+        }
+        None => {
+            let err = CString::new(format!("There is no VM with handle: {}", handle)).unwrap();
+            error(error_context, err.as_ptr());
+            false
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn kaiju_cancel_program(handle: Handle) {
     VMS.lock().unwrap().remove(&handle);
@@ -313,20 +580,21 @@ pub extern "C" fn kaiju_fork_program(
     entry: *const libc::c_char,
     memsize: usize,
     stacksize: usize,
-    error: fn(*mut libc::c_void, *const libc::c_char),
+    error: Option<KaijuFuncError>,
     error_context: *mut libc::c_void,
 ) -> Handle {
-    if entry.is_null() || memsize == 0 || stacksize == 0 || (error as *const libc::c_void).is_null()
-    {
-        if !(error as *const libc::c_void).is_null() {
-            let err = CString::new("Some of parameters are zeros or null pointers!").unwrap();
-            error(error_context, err.as_ptr());
-        }
+    let error = match error {
+        Some(error) => error,
+        None => return 0,
+    };
+    if entry.is_null() || memsize == 0 || stacksize == 0 {
+        let err = CString::new("Some of parameters are zeros or null pointers!").unwrap();
+        error(error_context, err.as_ptr());
         return 0;
     }
     let mut vms = VMS.lock().unwrap();
     match vms.get(&handle) {
-        Some(vm) => match vm.fork_advanced(stacksize as usize, memsize as usize) {
+        Some((vm, processor)) => match vm.fork_advanced(stacksize as usize, memsize as usize) {
             Ok(mut vm) => match vm.start(&string_from_raw_unsized(entry as *const libc::c_uchar)) {
                 Ok(_) => {
                     let handle = {
@@ -335,7 +603,8 @@ pub extern "C" fn kaiju_fork_program(
                         HANDLE_GEN.store(handle, Ordering::Relaxed);
                         handle
                     };
-                    vms.insert(handle, vm);
+                    let processor = processor.clone();
+                    vms.insert(handle, (vm, processor));
                     handle
                 }
                 Err(err) => {
@@ -361,31 +630,31 @@ pub extern "C" fn kaiju_fork_program(
 #[no_mangle]
 pub extern "C" fn kaiju_with_program(
     handle: Handle,
-    on_perform: fn(*mut libc::c_void),
+    on_perform: Option<KaijuFuncPerform>,
     perform_context: *mut libc::c_void,
-    error: fn(*mut libc::c_void, *const libc::c_char),
+    error: Option<KaijuFuncError>,
     error_context: *mut libc::c_void,
 ) -> bool {
-    if (on_perform as *const libc::c_void).is_null() || (error as *const libc::c_void).is_null() {
-        if !(error as *const libc::c_void).is_null() {
+    let error = match error {
+        Some(error) => error,
+        None => return false,
+    };
+    let on_perform = match on_perform {
+        Some(on_perform) => on_perform,
+        None => {
             let err = CString::new("Some of parameters are null pointers!").unwrap();
             error(error_context, err.as_ptr());
+            return false;
         }
-        return false;
-    }
-    if VM.lock().unwrap().is_some() {
-        return false;
-    }
+    };
     let mut vms = VMS.lock().unwrap();
     match vms.get_mut(&handle) {
-        Some(vm) => {
-            {
-                *VM.lock().unwrap() = Some(unsafe { transmute::<&mut Vm, &'static mut Vm>(vm) });
-            }
-            on_perform(perform_context);
-            {
-                *VM.lock().unwrap() = None;
-            }
+        Some((vm, _)) => {
+            let mut kaiju = KaijuContext {
+                vm: vm as *mut Vm,
+                action: OpAction::None,
+            };
+            on_perform(&mut kaiju as *mut KaijuContext, perform_context);
             true
         }
         None => {
@@ -397,16 +666,16 @@ pub extern "C" fn kaiju_with_program(
 }
 
 #[no_mangle]
-pub extern "C" fn kaiju_state_size() -> usize {
-    if let Some(ref vm) = *VM.lock().unwrap() {
+pub extern "C" fn kaiju_state_size(kaiju: *mut KaijuContext) -> usize {
+    if let Some(vm) = context_vm(kaiju) {
         return vm.state().all_size();
     }
     0
 }
 
 #[no_mangle]
-pub extern "C" fn kaiju_state_ptr(address: usize) -> *const libc::c_void {
-    if let Some(ref vm) = *VM.lock().unwrap() {
+pub extern "C" fn kaiju_state_ptr(kaiju: *mut KaijuContext, address: usize) -> *const libc::c_void {
+    if let Some(vm) = context_vm(kaiju) {
         let mem = vm.state().map_all();
         if address < mem.len() {
             return unsafe { mem.as_ptr().add(address) as *const libc::c_void };
@@ -416,8 +685,11 @@ pub extern "C" fn kaiju_state_ptr(address: usize) -> *const libc::c_void {
 }
 
 #[no_mangle]
-pub extern "C" fn kaiju_state_ptr_mut(address: usize) -> *mut libc::c_void {
-    if let Some(ref mut vm) = *VM.lock().unwrap() {
+pub extern "C" fn kaiju_state_ptr_mut(
+    kaiju: *mut KaijuContext,
+    address: usize,
+) -> *mut libc::c_void {
+    if let Some(vm) = context_vm(kaiju) {
         let mem = vm.state_mut().map_all_mut();
         if address < mem.len() {
             return unsafe { mem.as_mut_ptr().add(address) as *mut libc::c_void };
@@ -427,8 +699,11 @@ pub extern "C" fn kaiju_state_ptr_mut(address: usize) -> *mut libc::c_void {
 }
 
 #[no_mangle]
-pub extern "C" fn kaiju_state_info(out_info: *mut KaijuInfoState) -> bool {
-    if let Some(ref vm) = *VM.lock().unwrap() {
+pub extern "C" fn kaiju_state_info(
+    kaiju: *mut KaijuContext,
+    out_info: *mut KaijuInfoState,
+) -> bool {
+    if let Some(vm) = context_vm(kaiju) {
         unsafe {
             *out_info = KaijuInfoState {
                 stack_size: vm.state().stack_size(),
@@ -445,8 +720,12 @@ pub extern "C" fn kaiju_state_info(out_info: *mut KaijuInfoState) -> bool {
 }
 
 #[no_mangle]
-pub extern "C" fn kaiju_state_alloc_stack(size: usize, out_address: *mut usize) -> bool {
-    if let Some(ref mut vm) = *VM.lock().unwrap() {
+pub extern "C" fn kaiju_state_alloc_stack(
+    kaiju: *mut KaijuContext,
+    size: usize,
+    out_address: *mut usize,
+) -> bool {
+    if let Some(vm) = context_vm(kaiju) {
         if let Ok(val) = vm.state_mut().alloc_stack_value(size) {
             unsafe {
                 *out_address = val.address;
@@ -458,8 +737,8 @@ pub extern "C" fn kaiju_state_alloc_stack(size: usize, out_address: *mut usize)
 }
 
 #[no_mangle]
-pub extern "C" fn kaiju_state_pop_stack(size: usize) -> bool {
-    if let Some(ref mut vm) = *VM.lock().unwrap() {
+pub extern "C" fn kaiju_state_pop_stack(kaiju: *mut KaijuContext, size: usize) -> bool {
+    if let Some(vm) = context_vm(kaiju) {
         let pos = vm.state().stack_pos();
         if pos >= size && vm.state_mut().stack_reset(pos - size).is_ok() {
             return true;
@@ -469,8 +748,11 @@ pub extern "C" fn kaiju_state_pop_stack(size: usize) -> bool {
 }
 
 #[no_mangle]
-pub extern "C" fn kaiju_state_stack_address(out_address: *mut usize) -> bool {
-    if let Some(ref vm) = *VM.lock().unwrap() {
+pub extern "C" fn kaiju_state_stack_address(
+    kaiju: *mut KaijuContext,
+    out_address: *mut usize,
+) -> bool {
+    if let Some(vm) = context_vm(kaiju) {
         unsafe {
             *out_address = vm.state().stack_pos();
         }
@@ -480,40 +762,34 @@ pub extern "C" fn kaiju_state_stack_address(out_address: *mut usize) -> bool {
 }
 
 #[no_mangle]
-pub extern "C" fn kaiju_state_alloc_memory(size: usize, out_address: *mut usize) -> bool {
-    if let Some(ref mut vm) = *VM.lock().unwrap() {
-        let bs = size_of::<usize>();
-        let val = vm.state_mut().alloc_memory_value(size + bs);
-        if val.is_err() {
-            return false;
-        }
-        let val = val.unwrap();
-        if vm.state_mut().store_data(val.address, &size).is_err() {
-            vm.state_mut().dealloc_memory_value(&val).unwrap_or(());
-            return false;
-        }
-        unsafe {
-            *out_address = val.address + bs;
+pub extern "C" fn kaiju_state_alloc_memory(
+    kaiju: *mut KaijuContext,
+    size: usize,
+    out_address: *mut usize,
+) -> bool {
+    if let Some(vm) = context_vm(kaiju) {
+        if let Ok(val) = vm.state_mut().alloc_memory_value(size) {
+            unsafe {
+                *out_address = val.address;
+            }
+            return true;
         }
-        return true;
     }
     false
 }
 
 #[no_mangle]
-pub extern "C" fn kaiju_state_dealloc_memory(address: usize) -> bool {
-    if let Some(ref mut vm) = *VM.lock().unwrap() {
-        let bs = size_of::<usize>();
-        let size = vm.state().load_data::<usize>(address - bs);
-        if size.is_err() {
-            return false;
-        }
-        let size = size.unwrap() + bs;
-        if vm
-            .state_mut()
-            .dealloc_memory_value(&Value::new(address - bs, size))
-            .is_ok()
-        {
+pub extern "C" fn kaiju_state_realloc_memory(
+    kaiju: *mut KaijuContext,
+    address: usize,
+    size: usize,
+    out_address: *mut usize,
+) -> bool {
+    if let Some(vm) = context_vm(kaiju) {
+        if let Ok(val) = vm.state_mut().realloc_memory(address, size) {
+            unsafe {
+                *out_address = val.address;
+            }
             return true;
         }
     }
@@ -521,19 +797,91 @@ pub extern "C" fn kaiju_state_dealloc_memory(address: usize) -> bool {
 }
 
 #[no_mangle]
-pub extern "C" fn kaiju_context_go_to(label: *const libc::c_char) -> bool {
-    if let Some(ref vm) = *VM.lock().unwrap() {
-        if let Some(pos) = vm.find_label(&string_from_raw_unsized(label as *const libc::c_uchar)) {
-            *OP_ACTION.lock().unwrap() = OpAction::GoTo(pos);
-            return true;
+pub extern "C" fn kaiju_state_dealloc_memory(kaiju: *mut KaijuContext, address: usize) -> bool {
+    if let Some(vm) = context_vm(kaiju) {
+        return vm.state_mut().dealloc_memory(address).is_ok();
+    }
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn kaiju_context_go_to(
+    kaiju: *mut KaijuContext,
+    label: *const libc::c_char,
+) -> bool {
+    if let Some(kaiju) = unsafe { kaiju.as_mut() } {
+        if let Some(vm) = unsafe { kaiju.vm.as_ref() } {
+            if let Some(pos) =
+                vm.find_label(&string_from_raw_unsized(label as *const libc::c_uchar))
+            {
+                kaiju.action = OpAction::GoTo(pos);
+                return true;
+            }
         }
     }
     false
 }
 
 #[no_mangle]
-pub extern "C" fn kaiju_context_return() {
-    *OP_ACTION.lock().unwrap() = OpAction::Return;
+pub extern "C" fn kaiju_context_return(kaiju: *mut KaijuContext) {
+    if let Some(kaiju) = unsafe { kaiju.as_mut() } {
+        kaiju.action = OpAction::Return;
+    }
+}
+
+fn context_vm<'a>(kaiju: *mut KaijuContext) -> Option<&'a mut Vm> {
+    unsafe { kaiju.as_mut().and_then(|kaiju| kaiju.vm.as_mut()) }
+}
+
+/// Loads VM from assembly bytes and binds natives to it, so unbound extern functions are
+/// reported at load.
+fn vm_from_raw(
+    bytes: *const libc::c_uchar,
+    size: usize,
+    memsize: usize,
+    stacksize: usize,
+    natives: *const KaijuNative,
+    natives_count: usize,
+) -> SimpleResult<Vm> {
+    let mut registry = NativesRegistry::new();
+    if !natives.is_null() && natives_count > 0 {
+        for native in unsafe { from_raw_parts(natives, natives_count) } {
+            let module = string_from_raw_unsized(native.module as *const libc::c_uchar);
+            let function = string_from_raw_unsized(native.function as *const libc::c_uchar);
+            let on_call_native = match native.on_call_native {
+                Some(on_call_native) => on_call_native,
+                None => {
+                    return Err(SimpleError::new(format!(
+                        "Native function has no callback: {}:{}",
+                        module, function
+                    )))
+                }
+            };
+            let context = native.context as usize;
+            let id = format!("{}:{}", module, function);
+            registry.register(&module, &function, move |params, result, vm| {
+                let mut kaiju = KaijuContext {
+                    vm: vm as *mut Vm,
+                    action: OpAction::None,
+                };
+                if on_call_native(
+                    &mut kaiju as *mut KaijuContext,
+                    context as *mut libc::c_void,
+                    params.as_ptr(),
+                    params.len(),
+                    result.unwrap_or(0),
+                    result.is_some(),
+                ) {
+                    Ok(())
+                } else {
+                    Err(SimpleError::new(format!("Native function failed: {}", id)))
+                }
+            });
+        }
+    }
+    let mut vm = Vm::from_bytes(bytes_from_raw(bytes, size), stacksize, memsize)?;
+    vm.bind_natives(registry)?;
+    Ok(vm)
 }
 
 fn bytes_from_raw(source: *const libc::c_uchar, size: usize) -> Vec<u8> {
@@ -0,0 +1,142 @@
+use core::error::*;
+use std::io::{stdin, stdout, BufRead, Write};
+use vm_core::debugger::{Breakpoint, Debugger, StopReason};
+use vm_core::processor::Processor;
+use vm_core::vm::Vm;
+
+const HELP: &str = "Commands:
+  b, break fn NAME            break on function start
+  b, break label FUNC NAME    break on function label
+  b, break line [PATH:]LINE   break on source line
+  breakpoints                 list breakpoints
+  d, delete INDEX             remove breakpoint
+  c, continue                 run until breakpoint
+  s, step                     execute single op, entering function calls
+  n, next                     execute single op, stepping over function calls
+  o, out                      run until current function returns
+  bt, backtrace               print callstack
+  p, print NAME               print param, local or global value
+  h, help                     print this help
+  q, quit                     stop debugging";
+
+pub fn debug<P>(vm: &mut Vm, processor: &mut P, entry: &str) -> SimpleResult<()>
+where
+    P: Processor,
+{
+    vm.bind(processor)?;
+    vm.start(entry)?;
+    if vm.assembly().debug_info().is_none() {
+        println!("Assembly has no debug info - names and lines are not available");
+    }
+    let mut debugger = Debugger::new();
+    print_location(&debugger, vm);
+    let stdin = stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(kaiju) ");
+        stdout().flush()?;
+        let line = if let Some(line) = lines.next() {
+            line?
+        } else {
+            return Ok(());
+        };
+        let args = line.split_whitespace().collect::<Vec<_>>();
+        let result = match args.as_slice() {
+            [] => continue,
+            ["q"] | ["quit"] => return Ok(()),
+            ["h"] | ["help"] => {
+                println!("{}", HELP);
+                continue;
+            }
+            ["b", rest @ ..] | ["break", rest @ ..] => {
+                match parse_breakpoint(rest).and_then(|b| debugger.add_breakpoint(b, vm.assembly()))
+                {
+                    Ok(index) => println!("Breakpoint #{} added", index),
+                    Err(err) => println!("{}", err.message),
+                }
+                continue;
+            }
+            ["breakpoints"] => {
+                for (index, breakpoint) in debugger.breakpoints() {
+                    println!("  #{}: {}", index, breakpoint);
+                }
+                continue;
+            }
+            ["d", index] | ["delete", index] => {
+                if index
+                    .parse()
+                    .map(|i| debugger.remove_breakpoint(i))
+                    .unwrap_or(false)
+                {
+                    println!("Breakpoint #{} removed", index);
+                } else {
+                    println!("There is no breakpoint: {}", index);
+                }
+                continue;
+            }
+            ["bt"] | ["backtrace"] => {
+                for (i, frame) in debugger.backtrace(vm).iter().enumerate() {
+                    println!("  {}: {}", i, frame);
+                }
+                continue;
+            }
+            ["p", name] | ["print", name] => {
+                match debugger.read_variable(vm, name) {
+                    Ok(value) => println!("{} = {}", name, value),
+                    Err(err) => println!("{}", err.message),
+                }
+                continue;
+            }
+            ["c"] | ["continue"] => debugger.resume(vm, processor),
+            ["s"] | ["step"] => debugger.step(vm, processor),
+            ["n"] | ["next"] => debugger.step_over(vm, processor),
+            ["o"] | ["out"] => debugger.step_out(vm, processor),
+            _ => {
+                println!("Unknown command: {}\n{}", line.trim(), HELP);
+                continue;
+            }
+        };
+        match result {
+            Ok(StopReason::Complete) => {
+                println!("Program complete");
+                return Ok(());
+            }
+            Ok(StopReason::Breakpoint(index)) => {
+                println!("Breakpoint #{} hit", index);
+                print_location(&debugger, vm);
+            }
+            Ok(StopReason::Step) => print_location(&debugger, vm),
+            Err(err) => println!("Runtime error: {}", err),
+        }
+    }
+}
+
+fn print_location(debugger: &Debugger, vm: &Vm) {
+    if let Some(frame) = debugger.backtrace(vm).first() {
+        println!("Stopped in {}", frame);
+    }
+}
+
+fn parse_breakpoint(args: &[&str]) -> SimpleResult<Breakpoint> {
+    match args {
+        ["fn", id] => Ok(Breakpoint::Function((*id).to_owned())),
+        ["label", function, label] => Ok(Breakpoint::Label(
+            (*function).to_owned(),
+            (*label).to_owned(),
+        )),
+        ["line", location] => {
+            let (path, line) = if let Some(index) = location.rfind(':') {
+                (Some(location[..index].to_owned()), &location[index + 1..])
+            } else {
+                (None, *location)
+            };
+            match line.parse() {
+                Ok(line) => Ok(Breakpoint::Line(path, line)),
+                Err(_) => Err(SimpleError::new(format!("Invalid line number: {}", line))),
+            }
+        }
+        _ => Err(SimpleError::new(
+            "Expected: break fn NAME | break label FUNC NAME | break line [PATH:]LINE".to_owned(),
+        )),
+    }
+}
@@ -1,50 +1,105 @@
 use core::error::*;
-use libloading::{Library, Symbol};
-use std::sync::Mutex;
-use vm_core::processor::{OpAction, Processor};
-use vm_core::vm::Vm;
+use libloading::Library;
+use vm_core::processor::{HandlerId, OpAction, Processor};
+use vm_core::vm::{NativesRegistry, Vm};
 
-type FuncOnProcessOp = fn(&String, &[usize], &[usize], &mut Vm) -> SimpleResult<OpAction>;
+type FuncOnBindOp = fn(&str) -> bool;
+type FuncOnProcessOp = fn(&str, &[usize], &[usize], &mut Vm) -> SimpleResult<OpAction>;
+type FuncOnBindNative = fn(&str, &str) -> bool;
+type FuncOnCallNative = fn(&str, &str, &[usize], Option<usize>, &mut Vm) -> SimpleResult<()>;
 
-lazy_static! {
-    static ref LIB: Mutex<Option<Library>> = Mutex::new(None);
+pub fn load_processor(path: &str) -> SimpleResult<ExternalProcessor> {
+    let lib = match Library::new(path) {
+        Ok(lib) => lib,
+        Err(err) => return Err(SimpleError::new(format!("{}: {}", path, err))),
+    };
+    let on_bind_op = match unsafe { lib.get::<FuncOnBindOp>(b"on_bind_op") } {
+        Ok(f) => *f,
+        Err(_) => {
+            return Err(SimpleError::new(format!(
+                "{}: There is no external processor `on_bind_op` function",
+                path
+            )))
+        }
+    };
+    let on_process_op = match unsafe { lib.get::<FuncOnProcessOp>(b"on_process_op") } {
+        Ok(f) => *f,
+        Err(_) => {
+            return Err(SimpleError::new(format!(
+                "{}: There is no external processor `on_process_op` function",
+                path
+            )))
+        }
+    };
+    Ok(ExternalProcessor {
+        lib,
+        on_bind_op,
+        on_process_op,
+        ops: vec![],
+    })
 }
 
-pub fn load_processor(path: &str) -> SimpleResult<()> {
-    let mut lib = LIB.lock().unwrap();
-    *lib = Some(match Library::new(path) {
-        Ok(lib) => Ok(lib),
-        Err(err) => Err(SimpleError::new(format!("{}: {}", path, err))),
-    }?);
-    Ok(())
+/// Processor that forwards ops to dynamic library. Library functions are resolved once at load,
+/// so they stay valid for as long as processor keeps the library loaded.
+pub struct ExternalProcessor {
+    lib: Library,
+    on_bind_op: FuncOnBindOp,
+    on_process_op: FuncOnProcessOp,
+    ops: Vec<String>,
 }
 
-pub struct ExternalProcessor {}
+impl ExternalProcessor {
+    /// Binds extern functions of VM assembly to library `on_call_native` function, for every
+    /// one its `on_bind_native` function accepts. Library must outlive the VM.
+    pub fn bind_natives(&self, vm: &mut Vm) -> SimpleResult<()> {
+        let mut natives = NativesRegistry::new();
+        let symbols = unsafe {
+            self.lib
+                .get::<FuncOnBindNative>(b"on_bind_native")
+                .and_then(|bind| {
+                    self.lib
+                        .get::<FuncOnCallNative>(b"on_call_native")
+                        .map(|call| (*bind, *call))
+                })
+        };
+        if let Ok((on_bind_native, on_call_native)) = symbols {
+            for (module, function) in vm
+                .assembly()
+                .functions()
+                .iter()
+                .filter_map(|f| f.external().as_ref())
+            {
+                if on_bind_native(module, function) {
+                    let (m, f) = (module.clone(), function.clone());
+                    natives.register(module, function, move |params, result, vm| {
+                        on_call_native(&m, &f, params, result, vm)
+                    });
+                }
+            }
+        }
+        vm.bind_natives(natives)
+    }
+}
 
 impl Processor for ExternalProcessor {
+    fn bind_op(&mut self, op: &str) -> Option<HandlerId> {
+        if let Some(id) = self.ops.iter().position(|o| o == op) {
+            Some(id)
+        } else if (self.on_bind_op)(op) {
+            self.ops.push(op.to_owned());
+            Some(self.ops.len() - 1)
+        } else {
+            None
+        }
+    }
+
     fn process_op(
-        op: &String,
+        &mut self,
+        handler: HandlerId,
         params: &[usize],
         targets: &[usize],
         vm: &mut Vm,
     ) -> SimpleResult<OpAction> {
-        if let Some(ref lib) = *LIB.lock().unwrap() {
-            unsafe {
-                match lib.get(b"on_process_op") {
-                    Ok(f) => {
-                        let cb: Symbol<FuncOnProcessOp> = f;
-                        cb(op, params, targets, vm)
-                    }
-                    Err(_) => Err(SimpleError::new(
-                        "There is no external processor `on_process_op` function to call"
-                            .to_owned(),
-                    )),
-                }
-            }
-        } else {
-            Err(SimpleError::new(
-                "There is no external processor loaded".to_owned(),
-            ))
-        }
+        (self.on_process_op)(&self.ops[handler], params, targets, vm)
     }
 }
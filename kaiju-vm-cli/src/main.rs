@@ -1,15 +1,14 @@
 extern crate clap;
+extern crate kaiju_compiler_cli_core as compiler_cli_core;
 extern crate kaiju_core as core;
 extern crate kaiju_vm_core as vm_core;
-#[macro_use]
-extern crate lazy_static;
-extern crate kaiju_compiler_cli_core as compiler_cli_core;
 extern crate libloading;
 
+pub mod debugger;
 pub mod external_processor;
 
+use crate::debugger::debug;
 use crate::external_processor::load_processor;
-use crate::external_processor::ExternalProcessor;
 use clap::{App, Arg, ArgGroup};
 use compiler_cli_core::external_deep_validator::load_validator;
 use compiler_cli_core::external_deep_validator::ExternalDeepValidator;
@@ -18,7 +17,7 @@ use compiler_cli_core::load_opdescs;
 use core::program::OpsDescriptor;
 use std::fs::read;
 use std::path::Path;
-use vm_core::vm::Vm;
+use vm_core::vm::{ResumeStatus, Vm};
 
 fn main() {
     let matches = App::new("Kaiju Virtual Machine CLI")
@@ -104,6 +103,24 @@ fn main() {
                 .help("Kaiju language dialect name")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("maxops")
+                .long("max-ops")
+                .value_name("COUNT")
+                .help("Maximum number of ops executed before program is terminated")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("debug")
+                .long("debug")
+                .help("Run program in interactive debugger"),
+        )
+        .arg(
+            Arg::with_name("checked")
+                .long("checked")
+                .help("Trap on out-of-block memory access, use-after-free, double free and leaks"),
+        )
         .group(
             ArgGroup::with_name("inputs")
                 .args(&["input", "binary"])
@@ -168,10 +185,13 @@ fn main() {
     } else {
         matches.value_of("processor").unwrap().to_owned()
     };
-    if let Err(err) = load_processor(&processor) {
-        eprintln!("{}", err.message);
-        ::std::process::exit(1);
-    }
+    let mut processor = match load_processor(&processor) {
+        Ok(processor) => processor,
+        Err(err) => {
+            eprintln!("{}", err.message);
+            ::std::process::exit(1);
+        }
+    };
     let entry = if let Some(entry) = matches.value_of("entry") {
         entry
     } else {
@@ -218,8 +238,37 @@ fn main() {
     } else {
         unreachable!();
     };
-    if let Err(err) = vm.run::<ExternalProcessor>(entry) {
+    if let Err(err) = processor.bind_natives(&mut vm) {
         eprintln!("{}", err.message);
         ::std::process::exit(1);
     }
+    if matches.is_present("checked") {
+        vm.state_mut().set_checked(true);
+    }
+    if matches.is_present("debug") {
+        if let Err(err) = debug(&mut vm, &mut processor, entry) {
+            eprintln!("{}", err.message);
+            ::std::process::exit(1);
+        }
+    } else if let Some(maxops) = matches.value_of("maxops") {
+        let maxops = maxops.parse().unwrap();
+        if let Err(err) = vm.bind(&mut processor).and_then(|_| vm.start(entry)) {
+            eprintln!("{}", err.message);
+            ::std::process::exit(1);
+        }
+        match vm.resume_with_budget(&mut processor, maxops) {
+            ResumeStatus::Completed => {}
+            ResumeStatus::Yielded => {
+                eprintln!("Program exceeded ops limit: {}", maxops);
+                ::std::process::exit(1);
+            }
+            ResumeStatus::Error(err) => {
+                eprintln!("{}", err);
+                ::std::process::exit(1);
+            }
+        }
+    } else if let Err(err) = vm.run(&mut processor, entry) {
+        eprintln!("{}", err);
+        ::std::process::exit(1);
+    }
 }
@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Memory usage summary of allocator.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AllocatorStats {
+    pub used: usize,
+    pub free: usize,
+    pub used_blocks: usize,
+    pub free_blocks: usize,
+    pub largest_free_block: usize,
+}
+
+impl AllocatorStats {
+    /// Part of free memory that cannot be taken by single allocation: 0 when all free memory is
+    /// one block, close to 1 when it is scattered into many small blocks.
+    pub fn fragmentation(&self) -> f64 {
+        if self.free == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_block as f64 / self.free as f64
+        }
+    }
+}
+
+/// Manages blocks of VM memory. Addresses are offsets from the beginning of memory and blocks
+/// metadata is kept outside of VM memory, so allocated blocks have exactly requested size.
+pub trait Allocator: fmt::Debug + Send {
+    /// Forgets all blocks and starts managing `size` bytes of free memory.
+    fn reset(&mut self, size: usize);
+
+    /// Returns address of new block of given size.
+    fn alloc(&mut self, size: usize) -> Option<usize>;
+
+    /// Frees block starting at given address and returns its size.
+    fn dealloc(&mut self, address: usize) -> Option<usize>;
+
+    /// Resizes block in place; returns false if there is no room for it.
+    fn resize(&mut self, address: usize, size: usize) -> bool;
+
+    /// Size of taken block starting at given address.
+    fn block_size(&self, address: usize) -> Option<usize>;
+
+    /// Marks given free range as taken block (used when restoring snapshots).
+    fn reserve(&mut self, address: usize, size: usize) -> bool;
+
+    /// Addresses and sizes of taken blocks, sorted by address.
+    fn blocks(&self) -> Vec<(usize, usize)>;
+
+    fn stats(&self) -> AllocatorStats;
+
+    fn boxed_clone(&self) -> Box<dyn Allocator>;
+}
+
+impl Clone for Box<dyn Allocator> {
+    fn clone(&self) -> Self {
+        self.boxed_clone()
+    }
+}
+
+const SL_LOG: usize = 4;
+const SL_COUNT: usize = 1 << SL_LOG;
+const FL_COUNT: usize = usize::BITS as usize;
+
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    size: usize,
+    free: bool,
+    /// Physically previous block.
+    prev: Option<usize>,
+    prev_free: Option<usize>,
+    next_free: Option<usize>,
+}
+
+/// Two-level segregated fit allocator: free blocks are kept in lists of size classes (power of two
+/// ranges split into 16 linear subranges) with bitmaps of non-empty lists, so both allocation and
+/// freeing (with merging of neighbour free blocks) take constant time.
+#[derive(Debug, Clone)]
+pub struct TlsfAllocator {
+    size: usize,
+    blocks: HashMap<usize, Block>,
+    fl_bitmap: usize,
+    sl_bitmaps: Vec<u32>,
+    heads: Vec<Option<usize>>,
+    used: usize,
+    used_blocks: usize,
+}
+
+impl Default for TlsfAllocator {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl TlsfAllocator {
+    pub fn new(size: usize) -> Self {
+        let mut result = Self {
+            size: 0,
+            blocks: HashMap::new(),
+            fl_bitmap: 0,
+            sl_bitmaps: vec![0; FL_COUNT],
+            heads: vec![None; FL_COUNT * SL_COUNT],
+            used: 0,
+            used_blocks: 0,
+        };
+        result.reset(size);
+        result
+    }
+
+    fn mapping(size: usize) -> (usize, usize) {
+        if size < SL_COUNT {
+            (0, size)
+        } else {
+            let msb = FL_COUNT - 1 - size.leading_zeros() as usize;
+            (msb - SL_LOG + 1, (size >> (msb - SL_LOG)) - SL_COUNT)
+        }
+    }
+
+    fn mapping_search(size: usize) -> (usize, usize) {
+        if size < SL_COUNT {
+            Self::mapping(size)
+        } else {
+            let msb = FL_COUNT - 1 - size.leading_zeros() as usize;
+            Self::mapping(size.saturating_add((1 << (msb - SL_LOG)) - 1))
+        }
+    }
+
+    fn find_suitable(&self, fl: usize, sl: usize) -> Option<usize> {
+        let mut fl = fl;
+        let mut bitmap = self.sl_bitmaps[fl] & (!0u32 << sl);
+        if bitmap == 0 {
+            let bitmap_fl = if fl + 1 < FL_COUNT {
+                self.fl_bitmap & (!0usize << (fl + 1))
+            } else {
+                0
+            };
+            if bitmap_fl == 0 {
+                return None;
+            }
+            fl = bitmap_fl.trailing_zeros() as usize;
+            bitmap = self.sl_bitmaps[fl];
+        }
+        self.heads[fl * SL_COUNT + bitmap.trailing_zeros() as usize]
+    }
+
+    /// Searches list of given size class for block big enough, used when classes above are empty.
+    fn find_in_class(&self, size: usize) -> Option<usize> {
+        let (fl, sl) = Self::mapping(size);
+        let mut current = self.heads[fl * SL_COUNT + sl];
+        while let Some(address) = current {
+            let block = &self.blocks[&address];
+            if block.size >= size {
+                return Some(address);
+            }
+            current = block.next_free;
+        }
+        None
+    }
+
+    fn insert_free(&mut self, address: usize) {
+        let (fl, sl) = Self::mapping(self.blocks[&address].size);
+        let index = fl * SL_COUNT + sl;
+        let head = self.heads[index];
+        if let Some(head) = head {
+            self.blocks.get_mut(&head).unwrap().prev_free = Some(address);
+        }
+        let block = self.blocks.get_mut(&address).unwrap();
+        block.free = true;
+        block.prev_free = None;
+        block.next_free = head;
+        self.heads[index] = Some(address);
+        self.fl_bitmap |= 1 << fl;
+        self.sl_bitmaps[fl] |= 1 << sl;
+    }
+
+    fn remove_free(&mut self, address: usize) {
+        let block = self.blocks[&address];
+        let (fl, sl) = Self::mapping(block.size);
+        let index = fl * SL_COUNT + sl;
+        if let Some(prev) = block.prev_free {
+            self.blocks.get_mut(&prev).unwrap().next_free = block.next_free;
+        } else {
+            self.heads[index] = block.next_free;
+        }
+        if let Some(next) = block.next_free {
+            self.blocks.get_mut(&next).unwrap().prev_free = block.prev_free;
+        }
+        if self.heads[index].is_none() {
+            self.sl_bitmaps[fl] &= !(1 << sl);
+            if self.sl_bitmaps[fl] == 0 {
+                self.fl_bitmap &= !(1 << fl);
+            }
+        }
+        let block = self.blocks.get_mut(&address).unwrap();
+        block.free = false;
+        block.prev_free = None;
+        block.next_free = None;
+    }
+
+    fn set_prev(&mut self, address: usize, prev: Option<usize>) {
+        if let Some(block) = self.blocks.get_mut(&address) {
+            block.prev = prev;
+        }
+    }
+
+    /// Cuts block (not in free lists) to given size and makes rest of it new free block.
+    fn split(&mut self, address: usize, size: usize) {
+        let block = self.blocks.get_mut(&address).unwrap();
+        let rest = block.size - size;
+        if rest == 0 {
+            return;
+        }
+        block.size = size;
+        let next = address + size;
+        self.blocks.insert(
+            next,
+            Block {
+                size: rest,
+                free: false,
+                prev: Some(address),
+                prev_free: None,
+                next_free: None,
+            },
+        );
+        self.set_prev(next + rest, Some(next));
+        self.insert_free(next);
+    }
+
+    /// Makes block (not in free lists) free, merging it with free neighbours.
+    fn release(&mut self, address: usize) {
+        let mut address = address;
+        let mut block = self.blocks[&address];
+        let next = address + block.size;
+        if matches!(self.blocks.get(&next), Some(b) if b.free) {
+            self.remove_free(next);
+            block.size += self.blocks.remove(&next).unwrap().size;
+        }
+        if let Some(prev) = block.prev {
+            if self.blocks[&prev].free {
+                self.remove_free(prev);
+                self.blocks.remove(&address);
+                block.size += self.blocks[&prev].size;
+                block.prev = self.blocks[&prev].prev;
+                address = prev;
+            }
+        }
+        self.set_prev(address + block.size, Some(address));
+        self.blocks.insert(address, block);
+        self.insert_free(address);
+    }
+}
+
+impl Allocator for TlsfAllocator {
+    fn reset(&mut self, size: usize) {
+        self.size = size;
+        self.blocks.clear();
+        self.fl_bitmap = 0;
+        for bitmap in &mut self.sl_bitmaps {
+            *bitmap = 0;
+        }
+        for head in &mut self.heads {
+            *head = None;
+        }
+        self.used = 0;
+        self.used_blocks = 0;
+        if size > 0 {
+            self.blocks.insert(
+                0,
+                Block {
+                    size,
+                    free: false,
+                    prev: None,
+                    prev_free: None,
+                    next_free: None,
+                },
+            );
+            self.insert_free(0);
+        }
+    }
+
+    fn alloc(&mut self, size: usize) -> Option<usize> {
+        if size == 0 {
+            return None;
+        }
+        let (fl, sl) = Self::mapping_search(size);
+        let address = self
+            .find_suitable(fl, sl)
+            .or_else(|| self.find_in_class(size))?;
+        self.remove_free(address);
+        self.split(address, size);
+        self.used += size;
+        self.used_blocks += 1;
+        Some(address)
+    }
+
+    fn dealloc(&mut self, address: usize) -> Option<usize> {
+        let size = self.block_size(address)?;
+        self.used -= size;
+        self.used_blocks -= 1;
+        self.release(address);
+        Some(size)
+    }
+
+    fn resize(&mut self, address: usize, size: usize) -> bool {
+        let current = if let Some(current) = self.block_size(address) {
+            current
+        } else {
+            return false;
+        };
+        if size == 0 {
+            return false;
+        }
+        if size < current {
+            let next = address + size;
+            self.blocks.get_mut(&address).unwrap().size = size;
+            self.blocks.insert(
+                next,
+                Block {
+                    size: current - size,
+                    free: false,
+                    prev: Some(address),
+                    prev_free: None,
+                    next_free: None,
+                },
+            );
+            self.set_prev(address + current, Some(next));
+            self.release(next);
+            self.used -= current - size;
+        } else if size > current {
+            let next = address + current;
+            let available = match self.blocks.get(&next) {
+                Some(block) if block.free => current + block.size,
+                _ => current,
+            };
+            if available < size {
+                return false;
+            }
+            self.remove_free(next);
+            self.blocks.remove(&next);
+            self.blocks.get_mut(&address).unwrap().size = available;
+            self.set_prev(address + available, Some(address));
+            self.split(address, size);
+            self.used += size - current;
+        }
+        true
+    }
+
+    fn block_size(&self, address: usize) -> Option<usize> {
+        self.blocks
+            .get(&address)
+            .filter(|b| !b.free)
+            .map(|b| b.size)
+    }
+
+    fn reserve(&mut self, address: usize, size: usize) -> bool {
+        if size == 0 {
+            return false;
+        }
+        let start = if let Some((start, _)) = self
+            .blocks
+            .iter()
+            .find(|(a, b)| b.free && **a <= address && address + size <= **a + b.size)
+        {
+            *start
+        } else {
+            return false;
+        };
+        self.remove_free(start);
+        if address > start {
+            self.split(start, address - start);
+            self.remove_free(address);
+            self.insert_free(start);
+        }
+        self.split(address, size);
+        self.used += size;
+        self.used_blocks += 1;
+        true
+    }
+
+    fn blocks(&self) -> Vec<(usize, usize)> {
+        let mut result = self
+            .blocks
+            .iter()
+            .filter(|(_, b)| !b.free)
+            .map(|(a, b)| (*a, b.size))
+            .collect::<Vec<_>>();
+        result.sort();
+        result
+    }
+
+    fn stats(&self) -> AllocatorStats {
+        let (free_blocks, largest_free_block) = self
+            .blocks
+            .values()
+            .filter(|b| b.free)
+            .fold((0, 0), |(count, largest), b| {
+                (count + 1, largest.max(b.size))
+            });
+        AllocatorStats {
+            used: self.used,
+            free: self.size - self.used,
+            used_blocks: self.used_blocks,
+            free_blocks,
+            largest_free_block,
+        }
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Allocator> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tlsf() {
+        let mut allocator = TlsfAllocator::new(1024);
+        let a = allocator.alloc(10).unwrap();
+        let b = allocator.alloc(100).unwrap();
+        let c = allocator.alloc(20).unwrap();
+        assert_eq!((a, b, c), (0, 10, 110));
+        assert_eq!(allocator.block_size(b), Some(100));
+        assert_eq!(allocator.blocks(), vec![(0, 10), (10, 100), (110, 20)]);
+        assert_eq!(allocator.dealloc(b), Some(100));
+        assert_eq!(allocator.dealloc(b), None);
+        assert_eq!(allocator.block_size(b), None);
+        let stats = allocator.stats();
+        assert_eq!(stats.used, 30);
+        assert_eq!(stats.free, 994);
+        assert_eq!(stats.used_blocks, 2);
+        assert_eq!(stats.free_blocks, 2);
+        assert_eq!(stats.largest_free_block, 894);
+        assert!(stats.fragmentation() > 0.0);
+
+        assert_eq!(allocator.alloc(50), Some(10));
+        assert!(allocator.resize(10, 90));
+        assert!(!allocator.resize(10, 101));
+        assert!(allocator.resize(10, 40));
+        assert_eq!(allocator.block_size(10), Some(40));
+        assert!(allocator.resize(110, 500));
+        assert_eq!(allocator.dealloc(0), Some(10));
+        assert_eq!(allocator.dealloc(10), Some(40));
+        assert_eq!(allocator.dealloc(110), Some(500));
+        let stats = allocator.stats();
+        assert_eq!(stats.free, 1024);
+        assert_eq!(stats.free_blocks, 1);
+        assert_eq!(stats.fragmentation(), 0.0);
+
+        assert!(allocator.reserve(100, 24));
+        assert!(!allocator.reserve(110, 1));
+        assert!(allocator.reserve(0, 100));
+        assert_eq!(allocator.blocks(), vec![(0, 100), (100, 24)]);
+        assert_eq!(allocator.alloc(900), Some(124));
+        assert_eq!(allocator.alloc(1), None);
+    }
+}
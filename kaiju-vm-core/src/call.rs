@@ -0,0 +1,220 @@
+use core::error::*;
+use core::target::{Endianness, Target};
+use std::mem::size_of;
+
+/// Value that can be stored in VM memory or passed to or returned from function called with
+/// `Vm::call_typed`. Bytes are laid out the same way as VM lays out function params on given
+/// target: packed, in target byte order, with `isize` and `usize` taking target pointer size.
+/// Structs lay out their fields with alignment reported by field types.
+pub trait CallValue: Sized {
+    fn size(target: &Target) -> usize;
+
+    /// Alignment of value laid out as struct field on given target.
+    #[inline]
+    fn alignment(_target: &Target) -> usize {
+        1
+    }
+
+    /// `bytes` are guaranteed to be at least `size(target)` long.
+    fn write_bytes(&self, bytes: &mut [u8], target: &Target) -> SimpleResult<()>;
+
+    /// `bytes` are guaranteed to be at least `size(target)` long.
+    fn read_bytes(bytes: &[u8], target: &Target) -> Self;
+}
+
+macro_rules! impl_call_value_atom {
+    ($($type:ty),*) => {
+        $(
+            impl CallValue for $type {
+                #[inline]
+                fn size(_: &Target) -> usize {
+                    size_of::<$type>()
+                }
+
+                #[inline]
+                fn alignment(target: &Target) -> usize {
+                    target.alignment(stringify!($type)).unwrap_or(1)
+                }
+
+                #[inline]
+                fn write_bytes(&self, bytes: &mut [u8], target: &Target) -> SimpleResult<()> {
+                    let value = match target.endianness() {
+                        Endianness::Little => self.to_le_bytes(),
+                        Endianness::Big => self.to_be_bytes(),
+                    };
+                    bytes[..size_of::<$type>()].copy_from_slice(&value);
+                    Ok(())
+                }
+
+                #[inline]
+                fn read_bytes(bytes: &[u8], target: &Target) -> Self {
+                    let mut value = [0; size_of::<$type>()];
+                    value.copy_from_slice(&bytes[..size_of::<$type>()]);
+                    match target.endianness() {
+                        Endianness::Little => <$type>::from_le_bytes(value),
+                        Endianness::Big => <$type>::from_be_bytes(value),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_call_value_atom!(i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+
+impl CallValue for usize {
+    #[inline]
+    fn size(target: &Target) -> usize {
+        target.pointer_size()
+    }
+
+    #[inline]
+    fn alignment(target: &Target) -> usize {
+        target.alignment("usize").unwrap_or(1)
+    }
+
+    #[inline]
+    fn write_bytes(&self, bytes: &mut [u8], target: &Target) -> SimpleResult<()> {
+        write_pointer_sized(bytes, *self, false, target)
+    }
+
+    #[inline]
+    fn read_bytes(bytes: &[u8], target: &Target) -> Self {
+        read_pointer_sized(bytes, false, target)
+    }
+}
+
+impl CallValue for isize {
+    #[inline]
+    fn size(target: &Target) -> usize {
+        target.pointer_size()
+    }
+
+    #[inline]
+    fn alignment(target: &Target) -> usize {
+        target.alignment("isize").unwrap_or(1)
+    }
+
+    #[inline]
+    fn write_bytes(&self, bytes: &mut [u8], target: &Target) -> SimpleResult<()> {
+        write_pointer_sized(bytes, *self as usize, true, target)
+    }
+
+    #[inline]
+    fn read_bytes(bytes: &[u8], target: &Target) -> Self {
+        read_pointer_sized(bytes, true, target) as isize
+    }
+}
+
+/// Writes value in target pointer size, failing when it does not fit in it.
+pub(crate) fn write_pointer_sized(
+    bytes: &mut [u8],
+    value: usize,
+    signed: bool,
+    target: &Target,
+) -> SimpleResult<()> {
+    let size = target.pointer_size();
+    let shift = usize::BITS as usize - size * 8;
+    let fits = if signed {
+        (((value << shift) as isize) >> shift) as usize == value
+    } else {
+        (value << shift) >> shift == value
+    };
+    if !fits {
+        return Err(SimpleError::new(format!(
+            "Trying to store value {} that does not fit into {} bytes",
+            if signed {
+                (value as isize).to_string()
+            } else {
+                value.to_string()
+            },
+            size
+        )));
+    }
+    match target.endianness() {
+        Endianness::Little => bytes[..size].copy_from_slice(&value.to_le_bytes()[..size]),
+        Endianness::Big => {
+            bytes[..size].copy_from_slice(&value.to_be_bytes()[size_of::<usize>() - size..])
+        }
+    }
+    Ok(())
+}
+
+/// Reads value stored in target pointer size, extending sign of signed values.
+pub(crate) fn read_pointer_sized(bytes: &[u8], signed: bool, target: &Target) -> usize {
+    let size = target.pointer_size();
+    let mut buffer = [0; size_of::<usize>()];
+    let value = match target.endianness() {
+        Endianness::Little => {
+            buffer[..size].copy_from_slice(&bytes[..size]);
+            usize::from_le_bytes(buffer)
+        }
+        Endianness::Big => {
+            buffer[size_of::<usize>() - size..].copy_from_slice(&bytes[..size]);
+            usize::from_be_bytes(buffer)
+        }
+    };
+    let shift = usize::BITS as usize - size * 8;
+    if signed {
+        (((value << shift) as isize) >> shift) as usize
+    } else {
+        value
+    }
+}
+
+impl CallValue for () {
+    #[inline]
+    fn size(_: &Target) -> usize {
+        0
+    }
+
+    #[inline]
+    fn write_bytes(&self, _: &mut [u8], _: &Target) -> SimpleResult<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn read_bytes(_: &[u8], _: &Target) -> Self {}
+}
+
+macro_rules! impl_call_value_tuple {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($name: CallValue),*> CallValue for ($($name,)*) {
+            #[inline]
+            fn size(target: &Target) -> usize {
+                0 $(+ $name::size(target))*
+            }
+
+            #[inline]
+            fn write_bytes(&self, bytes: &mut [u8], target: &Target) -> SimpleResult<()> {
+                let ($($name,)*) = self;
+                let mut offset = 0;
+                $(
+                    $name.write_bytes(&mut bytes[offset..], target)?;
+                    offset += $name::size(target);
+                )*
+                let _ = offset;
+                Ok(())
+            }
+
+            #[inline]
+            fn read_bytes(bytes: &[u8], target: &Target) -> Self {
+                let mut offset = 0;
+                $(
+                    let $name = $name::read_bytes(&bytes[offset..], target);
+                    offset += $name::size(target);
+                )*
+                let _ = offset;
+                ($($name,)*)
+            }
+        }
+    };
+}
+
+impl_call_value_tuple!(A);
+impl_call_value_tuple!(A, B);
+impl_call_value_tuple!(A, B, C);
+impl_call_value_tuple!(A, B, C, D);
+impl_call_value_tuple!(A, B, C, D, E);
+impl_call_value_tuple!(A, B, C, D, E, F);
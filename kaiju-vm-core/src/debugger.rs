@@ -0,0 +1,554 @@
+use crate::processor::Processor;
+use crate::vm::{BacktraceFrame, Vm, VmResult};
+use core::error::*;
+use core::vm::{Instr, Type, VmAssembly};
+use std::cell::Cell;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    /// Function ID.
+    Function(String),
+    /// Function ID and label ID.
+    Label(String, String),
+    /// Optional source path and line number.
+    Line(Option<String>, usize),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Function(id) => write!(f, "fn {}", id),
+            Breakpoint::Label(function, label) => write!(f, "label {} {}", function, label),
+            Breakpoint::Line(Some(path), line) => write!(f, "line {}:{}", path, line),
+            Breakpoint::Line(None, line) => write!(f, "line {}", line),
+        }
+    }
+}
+
+/// Function index and instruction index pairs.
+pub type BreakpointAddresses = Vec<(usize, usize)>;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StopReason {
+    /// Index of breakpoint that was hit.
+    Breakpoint(usize),
+    Step,
+    Complete,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Debugger {
+    breakpoints: Vec<Option<(Breakpoint, BreakpointAddresses)>>,
+    /// Callstack depth, function index and instruction index of last breakpoint stop.
+    breakpoint_stop: Cell<Option<(usize, usize, usize)>>,
+}
+
+impl Debugger {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn breakpoints(&self) -> Vec<(usize, &Breakpoint)> {
+        self.breakpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| b.as_ref().map(|(b, _)| (i, b)))
+            .collect()
+    }
+
+    pub fn add_breakpoint(
+        &mut self,
+        breakpoint: Breakpoint,
+        assembly: &VmAssembly,
+    ) -> SimpleResult<usize> {
+        let addresses = resolve_breakpoint(&breakpoint, assembly)?;
+        self.breakpoints.push(Some((breakpoint, addresses)));
+        Ok(self.breakpoints.len() - 1)
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> bool {
+        if let Some(b) = self.breakpoints.get_mut(index) {
+            b.take().is_some()
+        } else {
+            false
+        }
+    }
+
+    /// Runs VM until breakpoint is hit or execution completes.
+    /// Breakpoint at current location is hit before stepping, unless VM is already stopped on it.
+    pub fn resume<P>(&self, vm: &mut Vm, processor: &mut P) -> VmResult<StopReason>
+    where
+        P: Processor + ?Sized,
+    {
+        self.run_until(vm, processor, |_| false)
+    }
+
+    /// Runs VM until next op start, entering called functions.
+    pub fn step<P>(&self, vm: &mut Vm, processor: &mut P) -> VmResult<StopReason>
+    where
+        P: Processor + ?Sized,
+    {
+        self.run_until(vm, processor, |_| true)
+    }
+
+    /// Runs VM until next op start of current or calling function.
+    pub fn step_over<P>(&self, vm: &mut Vm, processor: &mut P) -> VmResult<StopReason>
+    where
+        P: Processor + ?Sized,
+    {
+        let depth = depth(vm);
+        self.run_until(vm, processor, |vm| self::depth(vm) <= depth)
+    }
+
+    /// Runs VM until next op start of calling function.
+    pub fn step_out<P>(&self, vm: &mut Vm, processor: &mut P) -> VmResult<StopReason>
+    where
+        P: Processor + ?Sized,
+    {
+        let depth = depth(vm);
+        self.run_until(vm, processor, |vm| self::depth(vm) < depth)
+    }
+
+    /// Callstack of VM with location of the op that is about to execute at the top.
+    pub fn backtrace(&self, vm: &Vm) -> Vec<BacktraceFrame> {
+        let mut result = vm.backtrace();
+        if is_op_start(vm) {
+            let frame = &mut result[0];
+            if let Some(info) = vm
+                .assembly()
+                .debug_info()
+                .as_ref()
+                .and_then(|d| d.function_by_index(frame.function))
+            {
+                frame.location = Some(info.describe_location(frame.address));
+            }
+        }
+        result
+    }
+
+    /// Formats value of param or local of current function, or global, found by name.
+    pub fn read_variable(&self, vm: &Vm, name: &str) -> SimpleResult<String> {
+        let assembly = vm.assembly();
+        let debug_info = if let Some(debug_info) = assembly.debug_info() {
+            debug_info
+        } else {
+            return Err(SimpleError::new(
+                "Reading variables requires assembly with debug info".to_owned(),
+            ));
+        };
+        if let Some(frame) = vm.callstack().last() {
+            let function = assembly.function_by_index(frame.function()).unwrap();
+            let info = debug_info.function_by_index(frame.function()).unwrap();
+            if let Some(i) = info.params().iter().position(|p| p == name) {
+                let param = &function.params()[i];
+                let address = frame.params_stackpos() + param.offset().unwrap_or(0);
+                return format_value(vm, address, param.typeid());
+            }
+            if let Some(i) = info.locals().iter().position(|l| l == name) {
+                let local = &function.locals()[i];
+                if let Some(locals) = frame.locals() {
+                    let address = locals.address + local.offset().unwrap_or(0);
+                    return format_value(vm, address, local.typeid());
+                }
+            }
+        }
+        if let Some(global) = debug_info.global_by_id(name) {
+            if let Some(globals) = vm.globals() {
+                return format_value(vm, globals.address + global.offset(), global.typeid());
+            }
+        }
+        Err(SimpleError::new(format!(
+            "There is no variable in scope: {}",
+            name
+        )))
+    }
+
+    fn run_until<P, F>(&self, vm: &mut Vm, processor: &mut P, stop: F) -> VmResult<StopReason>
+    where
+        P: Processor + ?Sized,
+        F: Fn(&Vm) -> bool,
+    {
+        let resumed = self.breakpoint_stop.replace(None);
+        if is_op_start(vm) && location(vm) != resumed {
+            if let Some(index) = self.hit(vm) {
+                self.breakpoint_stop.set(location(vm));
+                return Ok(StopReason::Breakpoint(index));
+            }
+        }
+        loop {
+            vm.step(processor)?;
+            if !vm.can_resume() {
+                return Ok(StopReason::Complete);
+            }
+            if is_op_start(vm) {
+                if let Some(index) = self.hit(vm) {
+                    self.breakpoint_stop.set(location(vm));
+                    return Ok(StopReason::Breakpoint(index));
+                }
+                if stop(vm) {
+                    return Ok(StopReason::Step);
+                }
+            }
+        }
+    }
+
+    fn hit(&self, vm: &Vm) -> Option<usize> {
+        let frame = vm.callstack().last()?;
+        let location = (frame.function(), frame.address());
+        self.breakpoints.iter().position(|b| {
+            if let Some((_, addresses)) = b {
+                addresses.contains(&location)
+            } else {
+                false
+            }
+        })
+    }
+}
+
+fn depth(vm: &Vm) -> usize {
+    vm.callstack().iter().filter(|f| !f.is_inline()).count()
+}
+
+fn location(vm: &Vm) -> Option<(usize, usize, usize)> {
+    let frame = vm.callstack().last()?;
+    Some((vm.callstack().len(), frame.function(), frame.address()))
+}
+
+fn is_op_start(vm: &Vm) -> bool {
+    if let Some(frame) = vm.callstack().last() {
+        let body = vm
+            .assembly()
+            .function_body_by_index(frame.function())
+            .unwrap();
+        body.instructions().get(frame.address()) == Some(&Instr::ExecuteOpStart)
+    } else {
+        false
+    }
+}
+
+fn find_function(id: &str, assembly: &VmAssembly) -> SimpleResult<usize> {
+    let index = if let Some(debug_info) = assembly.debug_info() {
+        debug_info.function_index_by_id(id)
+    } else {
+        assembly.function_by_id(id).map(|f| f.index())
+    };
+    if let Some(index) = index {
+        if assembly.functions()[index].external().is_some() {
+            Err(SimpleError::new(format!(
+                "Cannot break on external function: {}",
+                id
+            )))
+        } else {
+            Ok(index)
+        }
+    } else {
+        Err(SimpleError::new(format!("Function not found: {}", id)))
+    }
+}
+
+fn resolve_breakpoint(
+    breakpoint: &Breakpoint,
+    assembly: &VmAssembly,
+) -> SimpleResult<BreakpointAddresses> {
+    match breakpoint {
+        Breakpoint::Function(id) => Ok(vec![(find_function(id, assembly)?, 0)]),
+        Breakpoint::Label(function, label) => {
+            let index = find_function(function, assembly)?;
+            let body = assembly.function_body_by_index(index).unwrap();
+            if let Some(address) = body
+                .labels()
+                .get(label)
+                .and_then(|address| body.instruction_index(*address))
+            {
+                Ok(vec![(index, address)])
+            } else {
+                Err(SimpleError::new(format!(
+                    "Label `{}` not found in function: {}",
+                    label, function
+                )))
+            }
+        }
+        Breakpoint::Line(path, line) => {
+            let debug_info = if let Some(debug_info) = assembly.debug_info() {
+                debug_info
+            } else {
+                return Err(SimpleError::new(
+                    "Line breakpoints require assembly with debug info".to_owned(),
+                ));
+            };
+            let result = debug_info
+                .functions()
+                .iter()
+                .enumerate()
+                .filter(|(_, f)| {
+                    if let Some(path) = path {
+                        f.path().ends_with(path.as_str())
+                    } else {
+                        true
+                    }
+                })
+                .filter_map(|(i, f)| {
+                    let address = f.locations().iter().find(|l| l.line() == *line)?.address();
+                    let body = assembly.function_body_by_index(i)?;
+                    Some((i, body.instruction_index(address)?))
+                })
+                .collect::<Vec<_>>();
+            if result.is_empty() {
+                Err(SimpleError::new(format!(
+                    "There is no op at line: {}",
+                    line
+                )))
+            } else {
+                Ok(result)
+            }
+        }
+    }
+}
+
+fn format_value(vm: &Vm, address: usize, typeid: &Type) -> SimpleResult<String> {
+    let state = vm.state();
+    let assembly = vm.assembly();
+    match typeid {
+        Type::Identifier(index) => match index {
+            0 => Ok(state.load_data::<i8>(address)?.to_string()),
+            1 => Ok(state.load_data::<u8>(address)?.to_string()),
+            2 => Ok(state.load_data::<i16>(address)?.to_string()),
+            3 => Ok(state.load_data::<u16>(address)?.to_string()),
+            4 => Ok(state.load_data::<i32>(address)?.to_string()),
+            5 => Ok(state.load_data::<u32>(address)?.to_string()),
+            6 => Ok(state.load_data::<i64>(address)?.to_string()),
+            7 => Ok(state.load_data::<u64>(address)?.to_string()),
+            8 => Ok(state.load_data::<f32>(address)?.to_string()),
+            9 => Ok(state.load_data::<f64>(address)?.to_string()),
+            10 => Ok(state.load_isize(address)?.to_string()),
+            11 => Ok(state.load_usize(address)?.to_string()),
+            _ => {
+                let struct_ = assembly.struct_by_index(*index).unwrap();
+                let info = assembly
+                    .debug_info()
+                    .as_ref()
+                    .and_then(|d| d.struct_by_index(*index));
+                let fields = struct_
+                    .fields()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, f)| {
+                        let value = format_value(vm, address + f.offset(), f.typeid())?;
+                        if let Some(info) = info {
+                            Ok(format!("{}: {}", info.fields()[i], value))
+                        } else {
+                            Ok(format!("#{}: {}", i, value))
+                        }
+                    })
+                    .collect::<SimpleResult<Vec<_>>>()?;
+                let name = info
+                    .map(|i| i.id().to_owned())
+                    .unwrap_or_else(|| format!("#{}", index));
+                if fields.is_empty() {
+                    Ok(format!("{} {{}}", name))
+                } else {
+                    Ok(format!("{} {{ {} }}", name, fields.join(", ")))
+                }
+            }
+        },
+        Type::Pointer(_) => Ok(format!("{:#x}", state.load_usize(address)?)),
+        Type::Tuple(types) => {
+            let mut offset = address;
+            let items = types
+                .iter()
+                .map(|t| {
+                    let value = format_value(vm, offset, t)?;
+                    offset += assembly.type_size(t);
+                    Ok(value)
+                })
+                .collect::<SimpleResult<Vec<_>>>()?;
+            Ok(format!("({})", items.join(", ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::{HandlerId, OpAction};
+    use compiler_core::module_reader::ModuleReader;
+    use core::program::compile_ops_descriptor;
+    use core::validator::EmptyDeepValidator;
+
+    const OPS: &str = "mov v: i32 => i32 {}\npass v: $? {}";
+
+    const MAIN: &str = r#"struct Point { x: i32, y: i32 }
+< counter: i32 >
+fn bump(p: Point) {
+  mov 1 => counter;
+}
+export fn main() <p: Point> {
+  mov 40 => p.x;
+  mov 2 => p.y;
+  pass @<bump(p)>;
+  mov 3 => counter;
+}
+"#;
+
+    struct TestModuleReader {}
+
+    impl ModuleReader for TestModuleReader {
+        fn load_module_source(&self, path: &str) -> Option<String> {
+            if path == "main.kj" {
+                Some(MAIN.to_owned())
+            } else {
+                None
+            }
+        }
+
+        fn push_module_path(&mut self, _: &str) {}
+
+        fn pop_module_path(&mut self) {}
+
+        fn compose_path(&self, path: &str) -> String {
+            path.to_owned()
+        }
+    }
+
+    struct TestProcessor {}
+
+    impl Processor for TestProcessor {
+        fn bind_op(&mut self, op: &str) -> Option<HandlerId> {
+            match op {
+                "mov" => Some(0),
+                "pass" => Some(1),
+                _ => None,
+            }
+        }
+
+        fn process_op(
+            &mut self,
+            handler: HandlerId,
+            params: &[usize],
+            targets: &[usize],
+            vm: &mut Vm,
+        ) -> SimpleResult<OpAction> {
+            if handler == 0 {
+                let v = vm.state().load_data::<i32>(params[0])?;
+                vm.state_mut().store_data(targets[0], &v)?;
+            }
+            Ok(OpAction::None)
+        }
+    }
+
+    fn start_vm() -> Vm {
+        let ops = compile_ops_descriptor(OPS).unwrap();
+        let mut vm = Vm::from_source::<EmptyDeepValidator, _>(
+            "main.kj",
+            TestModuleReader {},
+            &ops,
+            256,
+            256,
+        )
+        .unwrap();
+        vm.bind(&mut TestProcessor {}).unwrap();
+        vm.start("main").unwrap();
+        vm
+    }
+
+    #[test]
+    fn test_debugger() {
+        let mut processor = TestProcessor {};
+        let mut vm = start_vm();
+        let mut debugger = Debugger::new();
+        assert!(debugger
+            .add_breakpoint(Breakpoint::Function("foo".to_owned()), vm.assembly())
+            .is_err());
+        let b = debugger
+            .add_breakpoint(Breakpoint::Function("bump".to_owned()), vm.assembly())
+            .unwrap();
+        let l = debugger
+            .add_breakpoint(Breakpoint::Line(None, 10), vm.assembly())
+            .unwrap();
+        assert_eq!(
+            debugger.resume(&mut vm, &mut processor).unwrap(),
+            StopReason::Breakpoint(b)
+        );
+        let names = vm
+            .backtrace()
+            .into_iter()
+            .map(|f| f.name.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["bump".to_owned(), "main".to_owned()]);
+        assert_eq!(
+            debugger.read_variable(&vm, "p").unwrap(),
+            "Point { x: 40, y: 2 }"
+        );
+        assert_eq!(debugger.read_variable(&vm, "counter").unwrap(), "0");
+        assert!(debugger.read_variable(&vm, "q").is_err());
+        assert_eq!(
+            debugger.step_out(&mut vm, &mut processor).unwrap(),
+            StopReason::Breakpoint(l)
+        );
+        assert_eq!(
+            debugger.backtrace(&vm)[0].location.as_ref().unwrap(),
+            "main.kj:10:3"
+        );
+        assert_eq!(debugger.read_variable(&vm, "counter").unwrap(), "1");
+        assert!(debugger.remove_breakpoint(l));
+        assert!(!debugger.remove_breakpoint(l));
+        assert_eq!(
+            debugger.step(&mut vm, &mut processor).unwrap(),
+            StopReason::Complete
+        );
+
+        let mut vm = start_vm();
+        assert_eq!(
+            debugger.step(&mut vm, &mut processor).unwrap(),
+            StopReason::Step
+        );
+        assert_eq!(
+            debugger.backtrace(&vm)[0].location.as_ref().unwrap(),
+            "main.kj:8:3"
+        );
+        assert_eq!(
+            debugger.step_over(&mut vm, &mut processor).unwrap(),
+            StopReason::Step
+        );
+        assert_eq!(debugger.backtrace(&vm).len(), 1);
+        assert_eq!(
+            debugger.backtrace(&vm)[0].location.as_ref().unwrap(),
+            "main.kj:9:3"
+        );
+    }
+
+    #[test]
+    fn test_debugger_entry_breakpoint() {
+        let mut processor = TestProcessor {};
+        let mut vm = start_vm();
+        let mut debugger = Debugger::new();
+        let m = debugger
+            .add_breakpoint(Breakpoint::Function("main".to_owned()), vm.assembly())
+            .unwrap();
+        assert_eq!(
+            debugger.resume(&mut vm, &mut processor).unwrap(),
+            StopReason::Breakpoint(m)
+        );
+        assert_eq!(
+            debugger.backtrace(&vm)[0].location.as_ref().unwrap(),
+            "main.kj:7:3"
+        );
+        let l = debugger
+            .add_breakpoint(Breakpoint::Line(None, 8), vm.assembly())
+            .unwrap();
+        assert_eq!(
+            debugger.step(&mut vm, &mut processor).unwrap(),
+            StopReason::Breakpoint(l)
+        );
+        assert_eq!(
+            debugger.backtrace(&vm)[0].location.as_ref().unwrap(),
+            "main.kj:8:3"
+        );
+        assert_eq!(
+            debugger.resume(&mut vm, &mut processor).unwrap(),
+            StopReason::Complete
+        );
+    }
+}
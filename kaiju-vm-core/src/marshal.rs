@@ -0,0 +1,122 @@
+use crate::call::CallValue;
+use crate::state::State;
+use core::error::*;
+use core::target::Target;
+use core::vm::VmAssembly;
+
+/// Rust type that has matching Kaiju type.
+pub trait KaijuType: CallValue {
+    fn type_id() -> String;
+}
+
+macro_rules! impl_kaiju_type_atom {
+    ($($type:ty),*) => {
+        $(
+            impl KaijuType for $type {
+                #[inline]
+                fn type_id() -> String {
+                    stringify!($type).to_owned()
+                }
+            }
+        )*
+    };
+}
+
+impl_kaiju_type_atom!(i8, u8, i16, u16, i32, u32, i64, u64, f32, f64, isize, usize);
+
+#[inline]
+pub fn load_value<T: CallValue>(state: &State, address: usize) -> SimpleResult<T> {
+    state.load_data(address)
+}
+
+#[inline]
+pub fn store_value<T: CallValue>(value: &T, state: &mut State, address: usize) -> SimpleResult<()> {
+    state.store_data(address, value)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KaijuStructField {
+    pub id: &'static str,
+    pub type_id: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Rust struct laid out the same way as Kaiju struct of the same name.
+/// Usually implemented with `#[derive(KaijuStruct)]` from `kaiju-vm-macros` crate.
+pub trait KaijuStruct: KaijuType {
+    /// Fields laid out for given target.
+    fn fields(target: &Target) -> Vec<KaijuStructField>;
+
+    /// Kaiju struct declaration source.
+    fn declaration() -> String {
+        let mut result = format!("export struct {} {{\n", Self::type_id());
+        for field in Self::fields(&Target::default()) {
+            result.push_str(&format!("  {}: {},\n", field.id, field.type_id));
+        }
+        result.push_str("}\n");
+        result
+    }
+
+    #[inline]
+    fn load(state: &State, address: usize) -> SimpleResult<Self> {
+        load_value(state, address)
+    }
+
+    #[inline]
+    fn store(&self, state: &mut State, address: usize) -> SimpleResult<()> {
+        store_value(self, state, address)
+    }
+
+    /// Checks if exported struct of the same name in assembly has the same layout.
+    fn check_layout(assembly: &VmAssembly) -> SimpleResult<()> {
+        let id = Self::type_id();
+        let struct_ = if let Some(struct_) = assembly.struct_by_id(&id) {
+            struct_
+        } else {
+            return Err(SimpleError::new(format!(
+                "There is no exported struct: {}",
+                id
+            )));
+        };
+        let target = assembly.target();
+        let fields = Self::fields(target);
+        if struct_.size() != Self::size(target) || struct_.fields().len() != fields.len() {
+            return Err(SimpleError::new(format!(
+                "Struct `{}` has size {} with {} fields, expected size {} with {} fields",
+                id,
+                struct_.size(),
+                struct_.fields().len(),
+                Self::size(target),
+                fields.len()
+            )));
+        }
+        let names = assembly
+            .debug_info()
+            .as_ref()
+            .and_then(|d| d.struct_by_index(struct_.index()))
+            .map(|d| d.fields());
+        for (i, (a, b)) in struct_.fields().iter().zip(fields.iter()).enumerate() {
+            if let Some(names) = names {
+                if names[i] != b.id {
+                    return Err(SimpleError::new(format!(
+                        "Struct `{}` field #{} is named `{}`, expected `{}`",
+                        id, i, names[i], b.id
+                    )));
+                }
+            }
+            if a.offset() != b.offset || a.size() != b.size {
+                return Err(SimpleError::new(format!(
+                    "Struct `{}` field `{}` has offset {} and size {}, expected offset {} and size {}",
+                    id,
+                    b.id,
+                    a.offset(),
+                    a.size(),
+                    b.offset,
+                    b.size
+                )));
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,23 +1,256 @@
 use crate::vm::Vm;
 use core::error::*;
+use core::program::{compile_ops_descriptor, OpsDescriptor};
+use core::vm::Type;
+use std::collections::HashMap;
+
+pub type HandlerId = usize;
+
+pub type OpHandler<P> = fn(&mut P, &[usize], &[usize], &mut Vm) -> SimpleResult<OpAction>;
 
 #[derive(Debug, Copy, Clone)]
 pub enum OpAction {
     None,
+    /// Instruction index to continue from (label address).
     GoTo(usize),
     Return,
 }
 
+/// Op params or targets addresses, together with their types if assembly stores operand types.
+#[derive(Debug, Copy, Clone)]
+pub struct Operands<'a> {
+    addresses: &'a [usize],
+    types: &'a [Option<Type>],
+}
+
+impl<'a> Operands<'a> {
+    #[inline]
+    pub fn new(addresses: &'a [usize], types: &'a [Option<Type>]) -> Self {
+        Self { addresses, types }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    #[inline]
+    pub fn addresses(&self) -> &'a [usize] {
+        self.addresses
+    }
+
+    #[inline]
+    pub fn address(&self, index: usize) -> Option<usize> {
+        self.addresses.get(index).cloned()
+    }
+
+    #[inline]
+    pub fn typeid(&self, index: usize) -> Option<&'a Type> {
+        self.types.get(index).and_then(|t| t.as_ref())
+    }
+}
+
 pub trait Processor {
+    fn bind_op(&mut self, _op: &str) -> Option<HandlerId> {
+        None
+    }
+
+    /// Called by `process_op_typed` by default. Processors that bind ops must implement either
+    /// of them, otherwise every op fails.
     fn process_op(
-        _op: &String,
+        &mut self,
+        handler: HandlerId,
         _params: &[usize],
         _targets: &[usize],
         _vm: &mut Vm,
     ) -> SimpleResult<OpAction> {
-        unimplemented!()
+        Err(SimpleError::new(format!(
+            "Processor does not implement processing of op handler: {}",
+            handler
+        )))
+    }
+
+    /// Called by VM for every op. By default passes only addresses to `process_op`.
+    fn process_op_typed(
+        &mut self,
+        handler: HandlerId,
+        params: Operands,
+        targets: Operands,
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        self.process_op(handler, params.addresses(), targets.addresses(), vm)
+    }
+
+    /// Budget units charged for executing op with given handler.
+    fn op_cost(&self, _handler: HandlerId) -> usize {
+        1
     }
 }
 
 pub struct EmptyProcessor {}
 impl Processor for EmptyProcessor {}
+
+/// Op handler together with its ops descriptor rule source.
+/// Usually generated with `#[kaiju_op]` from `kaiju-vm-macros` crate.
+pub struct OpDefinition<P> {
+    pub id: &'static str,
+    pub descriptor: String,
+    pub handler: OpHandler<P>,
+}
+
+pub struct OpsRegistry<P> {
+    ids: HashMap<String, HandlerId>,
+    handlers: Vec<OpHandler<P>>,
+    descriptors: Vec<Option<String>>,
+}
+
+impl<P> Default for OpsRegistry<P> {
+    fn default() -> Self {
+        Self {
+            ids: HashMap::new(),
+            handlers: vec![],
+            descriptors: vec![],
+        }
+    }
+}
+
+impl<P> Clone for OpsRegistry<P> {
+    fn clone(&self) -> Self {
+        Self {
+            ids: self.ids.clone(),
+            handlers: self.handlers.clone(),
+            descriptors: self.descriptors.clone(),
+        }
+    }
+}
+
+impl<P> OpsRegistry<P> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn with(mut self, op: &str, handler: OpHandler<P>) -> Self {
+        self.register(op, handler);
+        self
+    }
+
+    #[inline]
+    pub fn with_definition(mut self, definition: OpDefinition<P>) -> Self {
+        self.register_definition(definition);
+        self
+    }
+
+    #[inline]
+    pub fn register(&mut self, op: &str, handler: OpHandler<P>) -> HandlerId {
+        self.register_inner(op, handler, None)
+    }
+
+    #[inline]
+    pub fn register_definition(&mut self, definition: OpDefinition<P>) -> HandlerId {
+        self.register_inner(
+            definition.id,
+            definition.handler,
+            Some(definition.descriptor),
+        )
+    }
+
+    fn register_inner(
+        &mut self,
+        op: &str,
+        handler: OpHandler<P>,
+        descriptor: Option<String>,
+    ) -> HandlerId {
+        if let Some(id) = self.ids.get(op) {
+            self.handlers[*id] = handler;
+            self.descriptors[*id] = descriptor;
+            *id
+        } else {
+            let id = self.handlers.len();
+            self.handlers.push(handler);
+            self.descriptors.push(descriptor);
+            self.ids.insert(op.to_owned(), id);
+            id
+        }
+    }
+
+    #[inline]
+    pub fn find(&self, op: &str) -> Option<HandlerId> {
+        self.ids.get(op).cloned()
+    }
+
+    #[inline]
+    pub fn handler(&self, id: HandlerId) -> Option<OpHandler<P>> {
+        self.handlers.get(id).cloned()
+    }
+
+    #[inline]
+    pub fn ops(&self) -> impl Iterator<Item = &str> {
+        self.ids.keys().map(|k| k.as_str())
+    }
+
+    /// Ops descriptor source of ops registered with definitions, in registration order.
+    pub fn descriptor(&self) -> String {
+        self.descriptors
+            .iter()
+            .filter_map(|d| d.as_ref())
+            .fold(String::new(), |mut result, d| {
+                result.push_str(d);
+                result.push('\n');
+                result
+            })
+    }
+
+    pub fn ops_descriptor(&self) -> SimpleResult<OpsDescriptor> {
+        match compile_ops_descriptor(&self.descriptor()) {
+            Ok(descriptor) => Ok(descriptor),
+            Err(err) => Err(SimpleError::new(err.pretty)),
+        }
+    }
+}
+
+/// Processor that dispatches ops to handlers from registry.
+pub struct OpsProcessor {
+    ops: OpsRegistry<OpsProcessor>,
+}
+
+impl OpsProcessor {
+    #[inline]
+    pub fn new(ops: OpsRegistry<OpsProcessor>) -> Self {
+        Self { ops }
+    }
+
+    #[inline]
+    pub fn ops(&self) -> &OpsRegistry<OpsProcessor> {
+        &self.ops
+    }
+}
+
+impl Processor for OpsProcessor {
+    fn bind_op(&mut self, op: &str) -> Option<HandlerId> {
+        self.ops.find(op)
+    }
+
+    fn process_op(
+        &mut self,
+        handler: HandlerId,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        if let Some(handler) = self.ops.handler(handler) {
+            handler(self, params, targets, vm)
+        } else {
+            Err(SimpleError::new(format!(
+                "There is no op handler: {}",
+                handler
+            )))
+        }
+    }
+}
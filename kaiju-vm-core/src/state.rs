@@ -1,6 +1,11 @@
+use crate::allocator::{Allocator, AllocatorStats, TlsfAllocator};
+use crate::call::{read_pointer_sized, write_pointer_sized, CallValue};
+use byteorder::{BigEndian, ReadBytesExt};
 use core::error::*;
-use itertools::Itertools;
+use core::target::{Endianness, Target};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::io::Read;
 use std::mem::size_of;
 use std::ptr::copy_nonoverlapping;
 
@@ -16,13 +21,60 @@ impl Value {
     }
 }
 
+pub(crate) fn write_usize(stream: &mut Vec<u8>, value: usize) {
+    stream.extend_from_slice(&(value as u64).to_be_bytes());
+}
+
+pub(crate) fn read_usize(stream: &mut dyn Read) -> SimpleResult<usize> {
+    Ok(stream.read_u64::<BigEndian>()? as usize)
+}
+
+/// Live and freed memory blocks (absolute addresses and sizes) tracked in checked mode.
+#[derive(Debug, Clone, Default)]
+struct HeapChecker {
+    live: BTreeMap<usize, usize>,
+    freed: BTreeMap<usize, usize>,
+}
+
+impl HeapChecker {
+    fn alloc(&mut self, address: usize, size: usize) {
+        let reused = self
+            .freed
+            .range(..address + size)
+            .rev()
+            .take_while(|(a, s)| *a + *s > address)
+            .map(|(a, _)| *a)
+            .collect::<Vec<_>>();
+        for a in reused {
+            self.freed.remove(&a);
+        }
+        self.live.insert(address, size);
+    }
+
+    fn dealloc(&mut self, address: usize) {
+        if let Some(size) = self.live.remove(&address) {
+            self.freed.insert(address, size);
+        }
+    }
+
+    fn find(blocks: &BTreeMap<usize, usize>, address: usize) -> Option<(usize, usize)> {
+        blocks
+            .range(..=address)
+            .next_back()
+            .filter(|(a, s)| address < *a + *s)
+            .map(|(a, s)| (*a, *s))
+    }
+}
+
 #[derive(Clone)]
 pub struct State {
     bytes: Vec<u8>,
     memory_size: usize,
     stack_size: usize,
-    memory_free: Vec<(usize, usize)>,
+    allocator: Box<dyn Allocator>,
     stack_pos: usize,
+    target: Target,
+    checker: Option<HeapChecker>,
 }
 
 impl fmt::Debug for State {
@@ -31,23 +83,59 @@ impl fmt::Debug for State {
             .field("bytes", &format!("[...; {}]", self.bytes.len()))
             .field("memory_size", &self.memory_size)
             .field("stack_size", &self.stack_size)
-            .field("memory_free", &self.memory_free)
+            .field("allocator", &self.allocator)
             .field("stack_pos", &self.stack_pos)
+            .field("target", &self.target)
+            .field("checked", &self.checker.is_some())
             .finish()
     }
 }
 
 impl State {
+    #[inline]
     pub fn new(stack_size: usize, memory_size: usize) -> Self {
+        Self::with_target(stack_size, memory_size, Target::default())
+    }
+
+    /// Creates state which typed accessors store and load values in given byte order.
+    #[inline]
+    pub fn with_endianness(stack_size: usize, memory_size: usize, endianness: Endianness) -> Self {
+        Self::with_target(
+            stack_size,
+            memory_size,
+            Target::new(size_of::<usize>(), endianness),
+        )
+    }
+
+    /// Creates state which stores values in target byte order and `usize`/`isize` values in
+    /// target pointer size.
+    pub fn with_target(stack_size: usize, memory_size: usize, target: Target) -> Self {
         Self {
             bytes: vec![0; stack_size + memory_size],
             stack_size,
             memory_size,
             stack_pos: 0,
-            memory_free: vec![(0, memory_size)],
+            allocator: Box::new(TlsfAllocator::new(memory_size)),
+            target,
+            checker: None,
         }
     }
 
+    #[inline]
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
+    #[inline]
+    pub fn endianness(&self) -> Endianness {
+        self.target.endianness()
+    }
+
+    #[inline]
+    pub fn pointer_size(&self) -> usize {
+        self.target.pointer_size()
+    }
+
     #[inline]
     pub fn stack_size(&self) -> usize {
         self.stack_size
@@ -75,7 +163,53 @@ impl State {
 
     #[inline]
     pub fn memory_free(&self) -> usize {
-        self.memory_free.iter().map(|(_, c)| c).sum()
+        self.allocator.stats().free
+    }
+
+    #[inline]
+    pub fn memory_stats(&self) -> AllocatorStats {
+        self.allocator.stats()
+    }
+
+    #[inline]
+    pub fn allocator(&self) -> &dyn Allocator {
+        self.allocator.as_ref()
+    }
+
+    /// Replaces memory allocator; can be done only when there is no memory allocated.
+    pub fn set_allocator(&mut self, mut allocator: Box<dyn Allocator>) -> SimpleResult<()> {
+        if self.allocator.stats().used_blocks > 0 {
+            return Err(SimpleError::new(
+                "Trying to replace allocator of memory that is in use".to_owned(),
+            ));
+        }
+        allocator.reset(self.memory_size);
+        self.allocator = allocator;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn is_checked(&self) -> bool {
+        self.checker.is_some()
+    }
+
+    /// Enables or disables checked mode, in which memory blocks are tracked and accesses crossing
+    /// block boundary, accesses to freed blocks and double frees are reported as errors.
+    pub fn set_checked(&mut self, checked: bool) {
+        self.checker = if checked {
+            let stack_size = self.stack_size;
+            Some(HeapChecker {
+                live: self
+                    .allocator
+                    .blocks()
+                    .into_iter()
+                    .map(|(a, s)| (a + stack_size, s))
+                    .collect(),
+                freed: BTreeMap::new(),
+            })
+        } else {
+            None
+        };
     }
 
     #[inline]
@@ -83,19 +217,15 @@ impl State {
         self.stack_free() + self.memory_free()
     }
 
-    pub fn stack_push_data<T>(&mut self, value: &T) -> SimpleResult<Value> {
-        let size = size_of::<T>();
+    pub fn stack_push_data<T: CallValue>(&mut self, value: &T) -> SimpleResult<Value> {
+        let size = T::size(&self.target);
         if self.stack_pos + size > self.stack_size {
             Err(SimpleError::new(format!(
                 "Stack overflow while trying to push {} bytes",
                 size
             )))
         } else {
-            unsafe {
-                let dp = self.bytes.as_mut_ptr().add(self.stack_pos);
-                let sp = value as *const T as *const u8;
-                copy_nonoverlapping(sp, dp, size);
-            }
+            value.write_bytes(&mut self.bytes[self.stack_pos..], &self.target)?;
             self.stack_pos += size;
             Ok(Value::new(self.stack_pos - size, size))
         }
@@ -119,6 +249,7 @@ impl State {
     }
 
     pub fn stack_push_move(&mut self, source: usize, size: usize) -> SimpleResult<Value> {
+        self.check_access("load", source, size)?;
         if source + size > self.stack_size + self.memory_size {
             Err(SimpleError::new(format!(
                 "Trying to push move {} bytes from outside of memory",
@@ -157,26 +288,21 @@ impl State {
         }
     }
 
-    pub fn stack_pop_data<T: Default>(&mut self) -> SimpleResult<T> {
-        let size = size_of::<T>();
+    pub fn stack_pop_data<T: CallValue>(&mut self) -> SimpleResult<T> {
+        let size = T::size(&self.target);
         if size > self.stack_pos {
             Err(SimpleError::new(format!(
                 "Stack underflow while trying to pop {} bytes",
                 size
             )))
         } else {
-            unsafe {
-                self.stack_pos -= size;
-                let sp = self.bytes.as_ptr().add(self.stack_pos);
-                let mut value = T::default();
-                let dp = &mut value as *mut T as *mut u8;
-                copy_nonoverlapping(sp, dp, size);
-                Ok(value)
-            }
+            self.stack_pos -= size;
+            Ok(T::read_bytes(&self.bytes[self.stack_pos..], &self.target))
         }
     }
 
     pub fn stack_pop_move(&mut self, destination: usize, size: usize) -> SimpleResult<()> {
+        self.check_access("store", destination, size)?;
         if destination + size > self.stack_size + self.memory_size {
             Err(SimpleError::new(format!(
                 "Trying to pop move {} bytes to outside of memory",
@@ -221,6 +347,8 @@ impl State {
         size: usize,
         destination: usize,
     ) -> SimpleResult<()> {
+        self.check_access("load", source, size)?;
+        self.check_access("store", destination, size)?;
         if source + size > self.stack_size + self.memory_size {
             Err(SimpleError::new(format!(
                 "Trying to move {} bytes from outside of memory",
@@ -241,25 +369,41 @@ impl State {
         }
     }
 
-    pub fn store_data<T>(&mut self, destination: usize, value: &T) -> SimpleResult<()> {
-        let size = size_of::<T>();
+    /// Stores value at given address in state byte order.
+    pub fn store_data<T: CallValue>(&mut self, destination: usize, value: &T) -> SimpleResult<()> {
+        let size = T::size(&self.target);
+        self.check_access("store", destination, size)?;
         if destination + size > self.stack_size + self.memory_size {
             Err(SimpleError::new(format!(
                 "Trying to store {} bytes to outside of memory",
                 size
             )))
         } else {
-            unsafe {
-                let dp = self.bytes.as_mut_ptr().add(destination);
-                let sp = value as *const T as *const u8;
-                copy_nonoverlapping(sp, dp, size);
-            }
-            Ok(())
+            value.write_bytes(&mut self.bytes[destination..], &self.target)
         }
     }
 
+    /// Stores `usize` value (usually address) in target pointer size.
+    #[inline]
+    pub fn store_usize(&mut self, destination: usize, value: usize) -> SimpleResult<()> {
+        self.store_pointer_sized(destination, value, false)
+    }
+
+    /// Stores `isize` value in target pointer size.
+    #[inline]
+    pub fn store_isize(&mut self, destination: usize, value: isize) -> SimpleResult<()> {
+        self.store_pointer_sized(destination, value as usize, true)
+    }
+
+    pub fn stack_push_usize(&mut self, value: usize) -> SimpleResult<Value> {
+        let v = self.alloc_stack_value(self.pointer_size())?;
+        self.store_usize(v.address, value)?;
+        Ok(v)
+    }
+
     pub fn store_bytes(&mut self, destination: usize, value: &[u8]) -> SimpleResult<()> {
         let size = value.len();
+        self.check_access("store", destination, size)?;
         if destination + size > self.stack_size + self.memory_size {
             Err(SimpleError::new(format!(
                 "Trying to store {} bytes to outside of memory",
@@ -275,25 +419,34 @@ impl State {
         }
     }
 
-    pub fn load_data<T: Default>(&self, source: usize) -> SimpleResult<T> {
-        let size = size_of::<T>();
+    /// Loads value from given address in state byte order.
+    pub fn load_data<T: CallValue>(&self, source: usize) -> SimpleResult<T> {
+        let size = T::size(&self.target);
+        self.check_access("load", source, size)?;
         if source + size > self.stack_size + self.memory_size {
             Err(SimpleError::new(format!(
                 "Trying to load {} bytes from outside of memory",
                 size
             )))
         } else {
-            unsafe {
-                let sp = self.bytes.as_ptr().add(source);
-                let mut value = T::default();
-                let dp = &mut value as *mut T as *mut u8;
-                copy_nonoverlapping(sp, dp, size);
-                Ok(value)
-            }
+            Ok(T::read_bytes(&self.bytes[source..], &self.target))
         }
     }
 
+    /// Loads `usize` value (usually address) stored in target pointer size.
+    #[inline]
+    pub fn load_usize(&self, source: usize) -> SimpleResult<usize> {
+        self.load_pointer_sized(source, false)
+    }
+
+    /// Loads `isize` value stored in target pointer size.
+    #[inline]
+    pub fn load_isize(&self, source: usize) -> SimpleResult<isize> {
+        self.load_pointer_sized(source, true).map(|v| v as isize)
+    }
+
     pub fn load_bytes(&self, source: usize, size: usize) -> SimpleResult<Vec<u8>> {
+        self.check_access("load", source, size)?;
         if source + size > self.stack_size + self.memory_size {
             Err(SimpleError::new(format!(
                 "Trying to load {} bytes from outside of memory",
@@ -321,6 +474,7 @@ impl State {
     }
 
     pub fn map(&self, value: Value) -> SimpleResult<&[u8]> {
+        self.check_access("map", value.address, value.size)?;
         if value.address + value.size > self.stack_size + self.memory_size {
             Err(SimpleError::new(format!(
                 "Trying to map {} bytes from outside of memory",
@@ -332,6 +486,7 @@ impl State {
     }
 
     pub fn map_mut(&mut self, value: Value) -> SimpleResult<&mut [u8]> {
+        self.check_access("map", value.address, value.size)?;
         if value.address + value.size > self.stack_size + self.memory_size {
             Err(SimpleError::new(format!(
                 "Trying to map {} bytes from outside of memory",
@@ -372,74 +527,226 @@ impl State {
         Ok(Value { address, size })
     }
 
+    /// Allocates memory block; zero sized values are not backed by any block.
     pub fn alloc_memory_value(&mut self, size: usize) -> SimpleResult<Value> {
-        let (index, address, s) = self.find_free_memory(size)?;
-        if self.memory_free[index].1 == size {
-            self.memory_free.remove(index);
+        if size == 0 {
+            return Ok(Value::new(self.stack_size, 0));
+        }
+        if let Some(address) = self.allocator.alloc(size) {
+            let address = address + self.stack_size;
+            if let Some(checker) = &mut self.checker {
+                checker.alloc(address, size);
+            }
+            Ok(Value::new(address, size))
         } else {
-            self.memory_free[index] = (address + size, s - size);
+            Err(SimpleError::new(format!(
+                "Could not find free {} bytes in memory",
+                size
+            )))
         }
-        Ok(Value {
-            address: address + self.stack_size,
-            size,
-        })
     }
 
+    /// Frees memory block starting at value address; value size is not needed.
     pub fn dealloc_memory_value(&mut self, value: &Value) -> SimpleResult<()> {
-        self.ensure_taken_memory(value)?;
-        self.memory_free
-            .push((value.address - self.stack_size, value.size));
-        self.defragment_free_memory();
-        Ok(())
+        if value.size == 0 {
+            Ok(())
+        } else {
+            self.dealloc_memory(value.address).map(|_| ())
+        }
     }
 
-    fn find_free_memory(&self, size: usize) -> SimpleResult<(usize, usize, usize)> {
-        if let Some((i, (a, s))) = self
-            .memory_free
-            .iter()
-            .enumerate()
-            .find(|(_, (_, s))| size <= *s)
+    /// Frees memory block starting at given address and returns its size.
+    pub fn dealloc_memory(&mut self, address: usize) -> SimpleResult<usize> {
+        if let Some(checker) = &self.checker {
+            if !checker.live.contains_key(&address) {
+                if let Some((a, s)) = HeapChecker::find(&checker.freed, address) {
+                    return Err(SimpleError::new(format!(
+                        "Double free of memory block {}..{} at {}",
+                        a,
+                        a + s,
+                        address
+                    )));
+                }
+            }
+        }
+        if let Some(size) = address
+            .checked_sub(self.stack_size)
+            .and_then(|a| self.allocator.dealloc(a))
         {
-            Ok((i, *a, *s))
+            if let Some(checker) = &mut self.checker {
+                checker.dealloc(address);
+            }
+            Ok(size)
         } else {
             Err(SimpleError::new(format!(
-                "Could not find free {} bytes in memory",
-                size
+                "There is no memory block at {}",
+                address
             )))
         }
     }
 
-    fn ensure_taken_memory(&self, value: &Value) -> SimpleResult<()> {
-        let address = value.address - self.stack_size;
-        if !self
-            .memory_free
-            .iter()
-            .any(|(a, s)| address >= *a && address + value.size <= *a + *s)
-        {
-            Ok(())
+    /// Resizes memory block starting at given address, moving its content to new block if it
+    /// cannot grow in place.
+    pub fn realloc_memory(&mut self, address: usize, size: usize) -> SimpleResult<Value> {
+        let current = self
+            .memory_block_size(address)
+            .ok_or_else(|| SimpleError::new(format!("There is no memory block at {}", address)))?;
+        if size == 0 {
+            return Err(SimpleError::new(format!(
+                "Trying to reallocate memory block at {} to 0 bytes",
+                address
+            )));
+        }
+        if self.allocator.resize(address - self.stack_size, size) {
+            if let Some(checker) = &mut self.checker {
+                checker.alloc(address, size);
+            }
+            return Ok(Value::new(address, size));
+        }
+        let value = self.alloc_memory_value(size)?;
+        self.memory_move(address, current.min(size), value.address)?;
+        self.dealloc_memory(address)?;
+        Ok(value)
+    }
+
+    /// Size of memory block starting at given address.
+    pub fn memory_block_size(&self, address: usize) -> Option<usize> {
+        address
+            .checked_sub(self.stack_size)
+            .and_then(|a| self.allocator.block_size(a))
+    }
+
+    pub(crate) fn write_snapshot(&self, stream: &mut Vec<u8>) {
+        self.target.write(stream).unwrap();
+        write_usize(stream, self.stack_size);
+        write_usize(stream, self.memory_size);
+        write_usize(stream, self.stack_pos);
+        let blocks = self.allocator.blocks();
+        write_usize(stream, blocks.len());
+        for (address, size) in blocks {
+            write_usize(stream, address);
+            write_usize(stream, size);
+        }
+        stream.extend_from_slice(&self.bytes);
+    }
+
+    /// Restored state uses default allocator and is not checked.
+    pub(crate) fn read_snapshot(stream: &mut dyn Read) -> SimpleResult<Self> {
+        let target = Target::read(stream)?;
+        let stack_size = read_usize(stream)?;
+        let memory_size = read_usize(stream)?;
+        let stack_pos = read_usize(stream)?;
+        if stack_pos > stack_size {
+            return Err(SimpleError::new(format!(
+                "Stack position {} exceeds stack size {}",
+                stack_pos, stack_size
+            )));
+        }
+        let count = read_usize(stream)?;
+        let mut allocator = TlsfAllocator::new(memory_size);
+        for _ in 0..count {
+            let address = read_usize(stream)?;
+            let size = read_usize(stream)?;
+            if !allocator.reserve(address, size) {
+                return Err(SimpleError::new(format!(
+                    "Memory block {}..{} overlaps other block or exceeds memory size {}",
+                    address,
+                    address + size,
+                    memory_size
+                )));
+            }
+        }
+        let mut bytes = vec![0; stack_size + memory_size];
+        stream.read_exact(&mut bytes)?;
+        Ok(Self {
+            bytes,
+            memory_size,
+            stack_size,
+            allocator: Box::new(allocator),
+            stack_pos,
+            target,
+            checker: None,
+        })
+    }
+
+    fn store_pointer_sized(
+        &mut self,
+        destination: usize,
+        value: usize,
+        signed: bool,
+    ) -> SimpleResult<()> {
+        let size = self.pointer_size();
+        self.check_access("store", destination, size)?;
+        if destination + size > self.stack_size + self.memory_size {
+            return Err(SimpleError::new(format!(
+                "Trying to store {} bytes to outside of memory",
+                size
+            )));
+        }
+        write_pointer_sized(&mut self.bytes[destination..], value, signed, &self.target)
+    }
+
+    fn load_pointer_sized(&self, source: usize, signed: bool) -> SimpleResult<usize> {
+        let size = self.pointer_size();
+        self.check_access("load", source, size)?;
+        if source + size > self.stack_size + self.memory_size {
+            return Err(SimpleError::new(format!(
+                "Trying to load {} bytes from outside of memory",
+                size
+            )));
+        }
+        Ok(read_pointer_sized(
+            &self.bytes[source..],
+            signed,
+            &self.target,
+        ))
+    }
+
+    fn check_access(&self, access: &str, address: usize, size: usize) -> SimpleResult<()> {
+        let checker = match &self.checker {
+            Some(checker) if size > 0 => checker,
+            _ => return Ok(()),
+        };
+        let end = address.saturating_add(size);
+        if address < self.stack_size {
+            return if end <= self.stack_size {
+                Ok(())
+            } else {
+                Err(SimpleError::new(format!(
+                    "Trying to {} {} bytes at {} crossing end of stack at {}",
+                    access, size, address, self.stack_size
+                )))
+            };
+        }
+        if let Some((a, s)) = HeapChecker::find(&checker.live, address) {
+            if end <= a + s {
+                Ok(())
+            } else {
+                Err(SimpleError::new(format!(
+                    "Trying to {} {} bytes at {} crossing boundary of memory block {}..{}",
+                    access,
+                    size,
+                    address,
+                    a,
+                    a + s
+                )))
+            }
+        } else if let Some((a, s)) = HeapChecker::find(&checker.freed, address) {
+            Err(SimpleError::new(format!(
+                "Trying to {} {} bytes at {} in freed memory block {}..{}",
+                access,
+                size,
+                address,
+                a,
+                a + s
+            )))
         } else {
             Err(SimpleError::new(format!(
-                "Memory block at {} is free",
-                value.address
+                "Trying to {} {} bytes at {} outside of allocated memory blocks",
+                access, size, address
             )))
         }
     }
-
-    fn defragment_free_memory(&mut self) {
-        self.memory_free.sort_by(|a, b| a.0.cmp(&b.0));
-        self.memory_free = self
-            .memory_free
-            .iter()
-            .cloned()
-            .coalesce(|a, b| {
-                if a.0 + a.1 == b.0 {
-                    Ok((a.0, a.1 + b.1))
-                } else {
-                    Err((a, b))
-                }
-            })
-            .collect();
-    }
 }
 
 #[cfg(test)]
@@ -479,7 +786,7 @@ mod tests {
     #[test]
     fn test_memory() {
         let mut state = State::new(8, 8);
-        assert_eq!(state.memory_free, vec![(0, 8)]);
+        assert_eq!(state.memory_free(), 8);
 
         assert_eq!(
             state.alloc_memory_value(4).unwrap(),
@@ -488,7 +795,7 @@ mod tests {
                 size: 4
             }
         );
-        assert_eq!(state.memory_free, vec![(4, 4)]);
+        assert_eq!(state.memory_free(), 4);
         assert_eq!(
             state.alloc_memory_value(4).unwrap(),
             Value {
@@ -496,16 +803,124 @@ mod tests {
                 size: 4
             }
         );
-        assert_eq!(state.memory_free, vec![]);
+        assert_eq!(state.memory_free(), 0);
         assert!(state.alloc_memory_value(4).is_err());
 
         let mut state = State::new(8, 8);
         let a = state.alloc_memory_value(4).unwrap();
         let b = state.alloc_memory_value(4).unwrap();
-        assert_eq!(state.memory_free, vec![]);
+        assert_eq!(state.memory_free(), 0);
         state.dealloc_memory_value(&b).unwrap();
-        assert_eq!(state.memory_free, vec![(4, 4)]);
-        state.dealloc_memory_value(&a).unwrap();
-        assert_eq!(state.memory_free, vec![(0, 8)]);
+        assert_eq!(state.memory_free(), 4);
+        assert!(state.dealloc_memory_value(&b).is_err());
+        state.dealloc_memory(a.address).unwrap();
+        assert_eq!(state.memory_free(), 8);
+        assert_eq!(state.memory_stats().free_blocks, 1);
+        assert_eq!(state.alloc_memory_value(8).unwrap().address, 8);
+    }
+
+    #[test]
+    fn test_realloc() {
+        let mut state = State::new(0, 24);
+        let a = state.alloc_memory_value(4).unwrap();
+        let b = state.alloc_memory_value(4).unwrap();
+        state.store_data(a.address, &42u32).unwrap();
+        assert_eq!(
+            state.realloc_memory(b.address, 8).unwrap(),
+            Value::new(4, 8)
+        );
+        let a = state.realloc_memory(a.address, 8).unwrap();
+        assert_eq!(a, Value::new(12, 8));
+        assert_eq!(state.load_data::<u32>(a.address).unwrap(), 42);
+        assert_eq!(state.memory_block_size(0), None);
+        assert!(state.realloc_memory(0, 4).is_err());
+        assert!(state
+            .set_allocator(Box::new(TlsfAllocator::default()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_endianness() {
+        let mut state = State::new(8, 0);
+        state.store_data(0, &0x0102_0304u32).unwrap();
+        state.store_data(4, &(0x0506u16, -1i16)).unwrap();
+        assert_eq!(state.map_stack(), &[4, 3, 2, 1, 6, 5, 0xff, 0xff]);
+        assert_eq!(state.load_data::<u32>(0).unwrap(), 0x0102_0304);
+        let mut state = State::with_endianness(8, 0, Endianness::Big);
+        state.store_data(0, &0x0102_0304u32).unwrap();
+        state.stack_reset(4).unwrap();
+        state.stack_push_data(&0x0506u16).unwrap();
+        assert_eq!(state.map_stack()[..6], [1, 2, 3, 4, 5, 6]);
+        assert_eq!(state.load_data::<u16>(0).unwrap(), 0x0102);
+        assert_eq!(state.stack_pop_data::<u16>().unwrap(), 0x0506);
+    }
+
+    #[test]
+    fn test_pointer_size() {
+        let target = Target::from_name("be16").unwrap();
+        let mut state = State::with_target(8, 0, target);
+        state.store_usize(0, 0x0102).unwrap();
+        state.store_isize(2, -2).unwrap();
+        assert_eq!(state.map_stack()[..4], [1, 2, 0xff, 0xfe]);
+        assert_eq!(state.load_usize(0).unwrap(), 0x0102);
+        assert_eq!(state.load_isize(2).unwrap(), -2);
+        assert_eq!(state.load_usize(2).unwrap(), 0xfffe);
+        assert_eq!(
+            state.store_usize(0, 0x10000).unwrap_err().message,
+            "Trying to store value 65536 that does not fit into 2 bytes"
+        );
+        assert_eq!(
+            state.store_isize(0, -0x8001).unwrap_err().message,
+            "Trying to store value -32769 that does not fit into 2 bytes"
+        );
+        state.stack_reset(4).unwrap();
+        let v = state.stack_push_usize(7).unwrap();
+        assert_eq!(v, Value::new(4, 2));
+        assert_eq!(state.load_usize(4).unwrap(), 7);
+        state.store_data(0, &0x0304usize).unwrap();
+        state.store_data(2, &-3isize).unwrap();
+        assert_eq!(state.map_stack()[..4], [3, 4, 0xff, 0xfd]);
+        assert_eq!(state.load_data::<usize>(0).unwrap(), 0x0304);
+        assert_eq!(state.load_data::<isize>(2).unwrap(), -3);
+        assert!(state.store_data(0, &0x10000usize).is_err());
+    }
+
+    #[test]
+    fn test_checked() {
+        let mut state = State::new(8, 32);
+        let a = state.alloc_memory_value(8).unwrap();
+        state.set_checked(true);
+        let b = state.alloc_memory_value(8).unwrap();
+        state.store_data(a.address + 4, &1u32).unwrap();
+        state.store_data(b.address, &2u64).unwrap();
+        state.store_data(4, &3u32).unwrap();
+        assert_eq!(
+            state.store_data(6, &3u32).unwrap_err().message,
+            "Trying to store 4 bytes at 6 crossing end of stack at 8"
+        );
+        assert_eq!(
+            state.load_data::<u64>(a.address + 4).unwrap_err().message,
+            "Trying to load 8 bytes at 12 crossing boundary of memory block 8..16"
+        );
+        assert_eq!(
+            state.load_data::<u8>(b.address + 8).unwrap_err().message,
+            "Trying to load 1 bytes at 24 outside of allocated memory blocks"
+        );
+        state.dealloc_memory(b.address).unwrap();
+        assert_eq!(
+            state.load_data::<u8>(b.address + 1).unwrap_err().message,
+            "Trying to load 1 bytes at 17 in freed memory block 16..24"
+        );
+        assert_eq!(
+            state.dealloc_memory(b.address).unwrap_err().message,
+            "Double free of memory block 16..24 at 16"
+        );
+        let c = state.alloc_memory_value(4).unwrap();
+        assert_eq!(c.address, b.address);
+        state.load_data::<u32>(c.address).unwrap();
+        let c = state.realloc_memory(c.address, 12).unwrap();
+        state.load_data::<u64>(c.address + 4).unwrap();
+        state.set_checked(false);
+        state.load_data::<u64>(a.address + 4).unwrap();
     }
 }
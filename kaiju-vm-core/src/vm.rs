@@ -1,16 +1,93 @@
-use crate::processor::{OpAction, Processor};
-use crate::state::{State, Value};
+use crate::call::CallValue;
+use crate::processor::{HandlerId, OpAction, Operands, Processor};
+use crate::state::{read_usize, write_usize, State, Value};
 use byteorder::{BigEndian, ReadBytesExt};
 use compiler_core::compile_program;
 use compiler_core::module_reader::ModuleReader;
-use core::assembly::{encode_assembly, OpIndex};
+use core::assembly::encode_assembly;
 use core::error::*;
 use core::program::OpsDescriptor;
 use core::validator::DeepValidator;
-use core::vm::{Data, VmAssembly};
+use core::vm::{read_type, write_type, Data, FunctionBody, Instr, Type, VmAssembly};
+use std::collections::HashMap;
 use std::ffi::CString;
-use std::io::{Cursor, Read, Seek, SeekFrom};
-use std::mem::size_of;
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::mem::{size_of, take};
+use std::sync::Arc;
+
+/// Host function that extern function is dispatched to: receives params addresses, result
+/// address (if function returns value) and VM.
+pub type NativeFunction =
+    Arc<dyn Fn(&[usize], Option<usize>, &mut Vm) -> SimpleResult<()> + Send + Sync>;
+
+/// Host functions that extern functions are bound to by module and function names.
+#[derive(Default, Clone)]
+pub struct NativesRegistry {
+    natives: HashMap<(String, String), NativeFunction>,
+}
+
+impl fmt::Debug for NativesRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.natives.keys()).finish()
+    }
+}
+
+impl NativesRegistry {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn with<F>(mut self, module: &str, function: &str, handler: F) -> Self
+    where
+        F: Fn(&[usize], Option<usize>, &mut Vm) -> SimpleResult<()> + Send + Sync + 'static,
+    {
+        self.register(module, function, handler);
+        self
+    }
+
+    #[inline]
+    pub fn register<F>(&mut self, module: &str, function: &str, handler: F)
+    where
+        F: Fn(&[usize], Option<usize>, &mut Vm) -> SimpleResult<()> + Send + Sync + 'static,
+    {
+        self.natives
+            .insert((module.to_owned(), function.to_owned()), Arc::new(handler));
+    }
+
+    #[inline]
+    pub fn unregister(&mut self, module: &str, function: &str) {
+        self.natives
+            .remove(&(module.to_owned(), function.to_owned()));
+    }
+
+    #[inline]
+    pub fn find(&self, module: &str, function: &str) -> Option<NativeFunction> {
+        self.natives
+            .get(&(module.to_owned(), function.to_owned()))
+            .cloned()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.natives.is_empty()
+    }
+}
+
+pub type VmResult<T> = Result<T, VmError>;
+
+#[derive(Debug, Clone)]
+pub enum ResumeStatus {
+    /// Budget was spent before program completion.
+    Yielded,
+    Completed,
+    Error(VmError),
+}
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"KJVS";
+const SNAPSHOT_VERSION: usize = 5;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ExecutionStage {
@@ -28,9 +105,14 @@ pub struct CallStackFrame {
     locals: Option<Value>,
     op_target_addresses: Vec<usize>,
     op_param_addresses: Vec<usize>,
+    op_target_types: Vec<Option<Type>>,
+    op_param_types: Vec<Option<Type>>,
     op_stackpos: usize,
+    inline: bool,
 }
 
+type OperandsBuffer = (Vec<usize>, Vec<Option<Type>>);
+
 impl CallStackFrame {
     #[inline]
     pub(crate) fn new(
@@ -48,7 +130,10 @@ impl CallStackFrame {
             locals,
             op_target_addresses: vec![],
             op_param_addresses: vec![],
+            op_target_types: vec![],
+            op_param_types: vec![],
             op_stackpos: 0,
+            inline: false,
         }
     }
 
@@ -57,6 +142,7 @@ impl CallStackFrame {
         self.function
     }
 
+    /// Index of next instruction in function body.
     #[inline]
     pub fn address(&self) -> usize {
         self.address
@@ -77,6 +163,11 @@ impl CallStackFrame {
         &self.locals
     }
 
+    #[inline]
+    pub fn is_inline(&self) -> bool {
+        self.inline
+    }
+
     #[inline]
     pub fn op_target_addresses(&self) -> &[usize] {
         &self.op_target_addresses
@@ -87,14 +178,43 @@ impl CallStackFrame {
         &self.op_param_addresses
     }
 
-    pub(crate) fn collect_params_targets(&mut self) -> (Vec<usize>, Vec<usize>) {
-        let params = self.op_param_addresses.clone();
-        let targets = self.op_target_addresses.clone();
-        self.op_param_addresses.clear();
-        self.op_target_addresses.clear();
+    #[inline]
+    pub fn op_target_types(&self) -> &[Option<Type>] {
+        &self.op_target_types
+    }
+
+    #[inline]
+    pub fn op_param_types(&self) -> &[Option<Type>] {
+        &self.op_param_types
+    }
+
+    pub(crate) fn take_params_targets(&mut self) -> (OperandsBuffer, OperandsBuffer) {
+        let params = (
+            take(&mut self.op_param_addresses),
+            take(&mut self.op_param_types),
+        );
+        let targets = (
+            take(&mut self.op_target_addresses),
+            take(&mut self.op_target_types),
+        );
         (params, targets)
     }
 
+    pub(crate) fn restore_params_targets(
+        &mut self,
+        mut params: OperandsBuffer,
+        mut targets: OperandsBuffer,
+    ) {
+        params.0.clear();
+        params.1.clear();
+        targets.0.clear();
+        targets.1.clear();
+        self.op_param_addresses = params.0;
+        self.op_param_types = params.1;
+        self.op_target_addresses = targets.0;
+        self.op_target_types = targets.1;
+    }
+
     pub(crate) fn duplicate(&self) -> Self {
         Self {
             function: self.function,
@@ -104,20 +224,90 @@ impl CallStackFrame {
             locals: self.locals,
             op_target_addresses: vec![],
             op_param_addresses: vec![],
+            op_target_types: vec![],
+            op_param_types: vec![],
             op_stackpos: 0,
+            inline: true,
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct BacktraceFrame {
+    pub function: usize,
+    /// Bytecode address of next instruction.
+    pub address: usize,
+    pub name: Option<String>,
+    pub location: Option<String>,
+}
+
+impl fmt::Display for BacktraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref name) = self.name {
+            write!(f, "{}", name)?;
+        } else {
+            write!(f, "#{}", self.function)?;
+        }
+        if let Some(ref location) = self.location {
+            write!(f, " at {}", location)
+        } else {
+            write!(f, " at {:04}", self.address)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VmError {
+    pub message: String,
+    pub callstack: Vec<CallStackFrame>,
+    pub backtrace: Vec<BacktraceFrame>,
+}
+
+impl VmError {
+    pub fn new(message: String) -> Self {
+        Self {
+            message,
+            callstack: vec![],
+            backtrace: vec![],
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for (i, frame) in self.backtrace.iter().enumerate() {
+            write!(f, "\n  {}: {}", i, frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<SimpleError> for VmError {
+    fn from(error: SimpleError) -> Self {
+        Self::new(error.message)
+    }
+}
+
+impl From<VmError> for SimpleError {
+    fn from(error: VmError) -> Self {
+        Self::new(error.to_string())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Vm {
-    assembly: VmAssembly,
+    assembly: Arc<VmAssembly>,
     state: State,
     stage: ExecutionStage,
     callstack: Vec<CallStackFrame>,
     data: Option<Value>,
     globals: Option<Value>,
     pointers: Vec<usize>,
+    natives: NativesRegistry,
+    handlers: Vec<HandlerId>,
+    costs: Vec<usize>,
+    spent: usize,
 }
 
 impl Vm {
@@ -142,14 +332,21 @@ impl Vm {
                 usize::max_value()
             )));
         }
+        let target = *assembly.target();
+        check_pointer_range(target.pointer_size(), stack_size + memory_size)?;
+        let state = State::with_target(stack_size, memory_size, target);
         Ok(Self {
-            assembly,
-            state: State::new(stack_size, memory_size),
+            assembly: Arc::new(assembly),
+            state,
             stage: ExecutionStage::Uninitialized,
             callstack: vec![],
             data: None,
             globals: None,
             pointers: vec![],
+            natives: NativesRegistry::default(),
+            handlers: vec![],
+            costs: vec![],
+            spent: 0,
         })
     }
 
@@ -181,8 +378,8 @@ impl Vm {
         V: DeepValidator,
         R: ModuleReader,
     {
-        let program = compile_program::<V, R>(entry_path, module_reader, ops_descriptor)?;
-        let bytes = encode_assembly(&program, ops_descriptor)?;
+        let (program, _) = compile_program::<V, R>(entry_path, module_reader, ops_descriptor)?;
+        let bytes = encode_assembly(&program, ops_descriptor, true)?;
         Self::from_bytes(bytes, stack_size, memory_size)
     }
 
@@ -211,14 +408,157 @@ impl Vm {
                 usize::max_value()
             )));
         }
+        let target = *self.state.target();
+        check_pointer_range(target.pointer_size(), stack_size + memory_size)?;
+        let mut state = State::with_target(stack_size, memory_size, target);
+        state.set_allocator(self.state.allocator().boxed_clone())?;
         Ok(Self {
             assembly: self.assembly.clone(),
-            state: State::new(stack_size, memory_size),
+            state,
             stage: ExecutionStage::Uninitialized,
             callstack: vec![],
             data: None,
             globals: None,
             pointers: vec![],
+            natives: self.natives.clone(),
+            handlers: self.handlers.clone(),
+            costs: self.costs.clone(),
+            spent: 0,
+        })
+    }
+
+    /// Serializes complete execution state. Natives and bound processor are not stored, so
+    /// they have to be registered and bound again after restore.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut stream = SNAPSHOT_MAGIC.to_vec();
+        write_usize(&mut stream, SNAPSHOT_VERSION);
+        stream.extend_from_slice(&assembly_fingerprint(&self.assembly).to_be_bytes());
+        stream.push(match self.stage {
+            ExecutionStage::Uninitialized => 0,
+            ExecutionStage::Running => 1,
+            ExecutionStage::Complete => 2,
+        });
+        self.state.write_snapshot(&mut stream);
+        write_value(&mut stream, &self.data);
+        write_value(&mut stream, &self.globals);
+        write_usizes(&mut stream, &self.pointers);
+        write_usize(&mut stream, self.callstack.len());
+        for frame in &self.callstack {
+            write_usize(&mut stream, frame.function);
+            write_usize(&mut stream, frame.address);
+            write_usize(&mut stream, frame.params_stackpos);
+            write_value(&mut stream, &frame.result);
+            write_value(&mut stream, &frame.locals);
+            write_usizes(&mut stream, &frame.op_target_addresses);
+            write_usizes(&mut stream, &frame.op_param_addresses);
+            write_types(&mut stream, &frame.op_target_types);
+            write_types(&mut stream, &frame.op_param_types);
+            write_usize(&mut stream, frame.op_stackpos);
+            stream.push(frame.inline as u8);
+        }
+        stream
+    }
+
+    pub fn restore(assembly: VmAssembly, bytes: &[u8]) -> SimpleResult<Self> {
+        let mut stream = Cursor::new(bytes);
+        let mut magic = [0; 4];
+        stream.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(SimpleError::new("Data is not a VM snapshot".to_owned()));
+        }
+        let version = read_usize(&mut stream)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SimpleError::new(format!(
+                "Unsupported VM snapshot version: {}",
+                version
+            )));
+        }
+        if stream.read_u64::<BigEndian>()? != assembly_fingerprint(&assembly) {
+            return Err(SimpleError::new(
+                "VM snapshot was made for different assembly".to_owned(),
+            ));
+        }
+        let stage = match stream.read_u8()? {
+            0 => ExecutionStage::Uninitialized,
+            1 => ExecutionStage::Running,
+            2 => ExecutionStage::Complete,
+            stage => {
+                return Err(SimpleError::new(format!(
+                    "Unsupported VM execution stage: {}",
+                    stage
+                )))
+            }
+        };
+        let state = State::read_snapshot(&mut stream)?;
+        if state.target() != assembly.target() {
+            return Err(SimpleError::new(format!(
+                "VM snapshot memory targets {} but assembly targets {}",
+                state.target(),
+                assembly.target()
+            )));
+        }
+        let size = state.all_size();
+        let data = read_value(&mut stream, size)?;
+        let globals = read_value(&mut stream, size)?;
+        if stage != ExecutionStage::Uninitialized
+            && (globals.is_none() || data.is_none() != assembly.data().is_empty())
+        {
+            return Err(SimpleError::new(
+                "VM snapshot of initialized VM does not have data or globals".to_owned(),
+            ));
+        }
+        let pointers = read_usizes(&mut stream)?;
+        let count = read_usize(&mut stream)?;
+        let mut callstack = vec![];
+        for _ in 0..count {
+            let function = read_usize(&mut stream)?;
+            let address = read_usize(&mut stream)?;
+            if let Some(body) = assembly.function_body_by_index(function) {
+                if address > body.instructions().len() {
+                    return Err(SimpleError::new(format!(
+                        "Call stack frame address {} exceeds body of function #{}",
+                        address, function
+                    )));
+                }
+            } else {
+                return Err(SimpleError::new(format!(
+                    "Call stack frame points to non-existing function #{}",
+                    function
+                )));
+            }
+            let frame = CallStackFrame {
+                function,
+                address,
+                params_stackpos: read_usize(&mut stream)?,
+                result: read_value(&mut stream, size)?,
+                locals: read_value(&mut stream, size)?,
+                op_target_addresses: read_usizes(&mut stream)?,
+                op_param_addresses: read_usizes(&mut stream)?,
+                op_target_types: read_types(&mut stream)?,
+                op_param_types: read_types(&mut stream)?,
+                op_stackpos: read_usize(&mut stream)?,
+                inline: stream.read_u8()? != 0,
+            };
+            check_frame(&assembly, &frame, callstack.last(), state.stack_pos())?;
+            callstack.push(frame);
+        }
+        if (stream.position() as usize) < bytes.len() {
+            return Err(SimpleError::new(
+                "Unexpected bytes at the end of VM snapshot".to_owned(),
+            ));
+        }
+        Ok(Self {
+            assembly: Arc::new(assembly),
+            state,
+            stage,
+            callstack,
+            data,
+            globals,
+            pointers,
+            natives: NativesRegistry::default(),
+            handlers: vec![],
+            costs: vec![],
+            spent: 0,
         })
     }
 
@@ -242,8 +582,109 @@ impl Vm {
         self.stage
     }
 
+    #[inline]
+    pub fn callstack(&self) -> &[CallStackFrame] {
+        &self.callstack
+    }
+
+    #[inline]
+    pub fn globals(&self) -> &Option<Value> {
+        &self.globals
+    }
+
+    #[inline]
+    pub fn natives(&self) -> &NativesRegistry {
+        &self.natives
+    }
+
+    #[inline]
+    pub fn register_native<F>(&mut self, module: &str, function: &str, handler: F)
+    where
+        F: Fn(&[usize], Option<usize>, &mut Vm) -> SimpleResult<()> + Send + Sync + 'static,
+    {
+        self.natives.register(module, function, handler);
+    }
+
+    #[inline]
+    pub fn unregister_native(&mut self, module: &str, function: &str) {
+        self.natives.unregister(module, function);
+    }
+
+    #[inline]
+    pub fn native(&self, module: &str, function: &str) -> Option<NativeFunction> {
+        self.natives.find(module, function)
+    }
+
+    /// Replaces registered natives and reports extern functions of assembly left unbound, so
+    /// missing bindings are found right after loading instead of at start or call.
+    pub fn bind_natives(&mut self, natives: NativesRegistry) -> SimpleResult<()> {
+        self.natives = natives;
+        self.ensure_natives()
+    }
+
+    pub fn ensure_natives(&self) -> SimpleResult<()> {
+        let mut missing = self
+            .assembly
+            .functions()
+            .iter()
+            .filter_map(|f| f.external().as_ref())
+            .filter(|e| !self.natives.natives.contains_key(e))
+            .map(|(m, f)| format!("{}:{}", m, f))
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            missing.sort();
+            Err(SimpleError::new(format!(
+                "There are unbound external functions: {}",
+                missing.join(", ")
+            )))
+        }
+    }
+
+    #[inline]
+    pub fn is_bound(&self) -> bool {
+        self.handlers.len() == self.assembly.ops_map().len()
+    }
+
+    pub fn bind<P>(&mut self, processor: &mut P) -> SimpleResult<()>
+    where
+        P: Processor + ?Sized,
+    {
+        let mut missing = vec![];
+        let handlers = self
+            .assembly
+            .ops_map()
+            .iter()
+            .map(|op| {
+                if let Some(id) = processor.bind_op(op) {
+                    id
+                } else {
+                    missing.push(op.as_str());
+                    0
+                }
+            })
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            self.costs = handlers.iter().map(|h| processor.op_cost(*h)).collect();
+            self.handlers = handlers;
+            Ok(())
+        } else {
+            Err(SimpleError::new(format!(
+                "Processor does not support ops: {}",
+                missing.join(", ")
+            )))
+        }
+    }
+
     pub fn start(&mut self, entry: &str) -> SimpleResult<()> {
         if self.stage == ExecutionStage::Uninitialized {
+            if !self.is_bound() {
+                return Err(SimpleError::new(
+                    "Trying to start VM with no processor bound".to_owned(),
+                ));
+            }
+            self.ensure_natives()?;
             let f = {
                 if let Some(f) = self.assembly.function_by_id(entry) {
                     if !f.params().is_empty() {
@@ -265,13 +706,7 @@ impl Vm {
                     )));
                 }
             };
-            let vd = self.alloc_data()?;
-            let vg = self
-                .state
-                .alloc_memory_value(self.assembly.globals_size())?;
-            self.data = vd;
-            self.globals = Some(vg);
-            self.stage = ExecutionStage::Running;
+            self.initialize()?;
             self.call_function(f)?;
             Ok(())
         } else {
@@ -281,55 +716,286 @@ impl Vm {
         }
     }
 
+    fn initialize(&mut self) -> SimpleResult<()> {
+        let vd = self.alloc_data()?;
+        let vg = self
+            .state
+            .alloc_memory_value(self.assembly.globals_size())?;
+        self.data = vd;
+        self.globals = Some(vg);
+        self.stage = ExecutionStage::Running;
+        Ok(())
+    }
+
+    /// Calls exported function with arguments bytes laid out as its params and runs it to
+    /// completion, returning bytes of its result. VM gets initialized on first call and keeps
+    /// its globals between calls; calling while program is suspended runs the function on top
+    /// of current callstack.
+    pub fn call<P>(
+        &mut self,
+        processor: &mut P,
+        function_id: &str,
+        args: &[u8],
+    ) -> VmResult<Vec<u8>>
+    where
+        P: Processor + ?Sized,
+    {
+        if !self.is_bound() {
+            return Err(VmError::new(
+                "Trying to call function on VM with no processor bound".to_owned(),
+            ));
+        }
+        let (index, result_size) = if let Some(f) = self.assembly.function_by_id(function_id) {
+            let params_size = f.params().iter().map(|p| p.size()).sum::<usize>();
+            if args.len() != params_size {
+                return Err(VmError::new(format!(
+                    "Function `{}` expects {} bytes of arguments, got: {}",
+                    function_id,
+                    params_size,
+                    args.len()
+                )));
+            }
+            if f.external().is_some() {
+                return Err(VmError::new(format!(
+                    "Trying to call external function: {}",
+                    function_id
+                )));
+            }
+            let result_size = f
+                .typeid()
+                .as_ref()
+                .map_or(0, |t| self.assembly.type_size(t));
+            (f.index(), result_size)
+        } else {
+            return Err(VmError::new(format!(
+                "Trying to call non-existing function: {}",
+                function_id
+            )));
+        };
+        match self.stage {
+            ExecutionStage::Uninitialized => {
+                self.ensure_natives()?;
+                self.initialize()?;
+            }
+            ExecutionStage::Complete => self.stage = ExecutionStage::Running,
+            ExecutionStage::Running => {}
+        }
+        let depth = self.callstack.len();
+        let stack_pos = self.state.stack_pos();
+        let pointers = self.pointers.len();
+        let result = self
+            .call_inner(processor, index, args, depth, result_size)
+            .map_err(|err| self.make_error(err));
+        self.callstack.truncate(depth);
+        self.pointers.truncate(pointers);
+        self.state.stack_reset(stack_pos)?;
+        self.stage = if depth == 0 {
+            ExecutionStage::Complete
+        } else {
+            ExecutionStage::Running
+        };
+        result
+    }
+
+    fn call_inner<P>(
+        &mut self,
+        processor: &mut P,
+        index: usize,
+        args: &[u8],
+        depth: usize,
+        result_size: usize,
+    ) -> SimpleResult<Vec<u8>>
+    where
+        P: Processor + ?Sized,
+    {
+        self.state.stack_push_bytes(args)?;
+        self.call_function(index)?;
+        while self.callstack.len() > depth {
+            self.resume_op(processor)?;
+        }
+        let address = self.pop_pointer()?;
+        if result_size > 0 {
+            self.state.load_bytes(address, result_size)
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    /// Typed version of `call`.
+    pub fn call_typed<P, A, R>(
+        &mut self,
+        processor: &mut P,
+        function_id: &str,
+        args: A,
+    ) -> VmResult<R>
+    where
+        P: Processor + ?Sized,
+        A: CallValue,
+        R: CallValue,
+    {
+        let target = *self.state.target();
+        let mut bytes = vec![0; A::size(&target)];
+        args.write_bytes(&mut bytes, &target)?;
+        let result = self.call(processor, function_id, &bytes)?;
+        if result.len() == R::size(&target) {
+            Ok(R::read_bytes(&result, &target))
+        } else {
+            Err(VmError::new(format!(
+                "Function `{}` returns {} bytes but expected type has {}",
+                function_id,
+                result.len(),
+                R::size(&target)
+            )))
+        }
+    }
+
     #[inline]
     pub fn can_resume(&self) -> bool {
         self.stage == ExecutionStage::Running && !self.callstack.is_empty()
     }
 
-    pub fn resume<P>(&mut self) -> SimpleResult<()>
+    pub fn resume<P>(&mut self, processor: &mut P) -> VmResult<()>
     where
-        P: Processor,
+        P: Processor + ?Sized,
     {
+        if !self.is_bound() {
+            return Err(VmError::new(
+                "Trying to resume VM with no processor bound".to_owned(),
+            ));
+        }
         if self.stage == ExecutionStage::Running {
-            while self.resume_op::<P>()? {}
+            while self
+                .resume_op(processor)
+                .map_err(|err| self.make_error(err))?
+            {}
             Ok(())
         } else {
-            Err(SimpleError::new(
+            Err(VmError::new(
+                "Trying to resume uninitialized or complete VM".to_owned(),
+            ))
+        }
+    }
+
+    /// Runs program until it completes or spends given budget. Each op is charged with cost
+    /// reported by processor and each function call with single unit, so budget can be exceeded
+    /// by cost of the last executed op.
+    pub fn resume_with_budget<P>(&mut self, processor: &mut P, budget: usize) -> ResumeStatus
+    where
+        P: Processor + ?Sized,
+    {
+        if !self.is_bound() {
+            return ResumeStatus::Error(VmError::new(
+                "Trying to resume VM with no processor bound".to_owned(),
+            ));
+        }
+        if self.stage != ExecutionStage::Running {
+            return ResumeStatus::Error(VmError::new(
                 "Trying to resume uninitialized or complete VM".to_owned(),
+            ));
+        }
+        self.spent = 0;
+        while self.can_resume() {
+            if self.spent >= budget {
+                return ResumeStatus::Yielded;
+            }
+            if let Err(err) = self.resume_op(processor) {
+                return ResumeStatus::Error(self.make_error(err));
+            }
+        }
+        ResumeStatus::Completed
+    }
+
+    /// Budget spent by last `resume_with_budget` call.
+    #[inline]
+    pub fn spent_budget(&self) -> usize {
+        self.spent
+    }
+
+    /// Executes single op of current function, or enters/leaves function call.
+    pub fn step<P>(&mut self, processor: &mut P) -> VmResult<()>
+    where
+        P: Processor + ?Sized,
+    {
+        if !self.is_bound() {
+            return Err(VmError::new(
+                "Trying to step VM with no processor bound".to_owned(),
+            ));
+        }
+        if self.stage == ExecutionStage::Running {
+            self.resume_op(processor)
+                .map(|_| ())
+                .map_err(|err| self.make_error(err))
+        } else {
+            Err(VmError::new(
+                "Trying to step uninitialized or complete VM".to_owned(),
             ))
         }
     }
 
     #[inline]
-    pub fn consume<P>(&mut self) -> SimpleResult<()>
+    pub fn consume<P>(&mut self, processor: &mut P) -> VmResult<()>
     where
-        P: Processor,
+        P: Processor + ?Sized,
     {
         if self.stage == ExecutionStage::Running {
             while self.can_resume() {
-                self.resume::<P>()?;
+                self.resume(processor)?;
             }
             Ok(())
         } else {
-            Err(SimpleError::new(
+            Err(VmError::new(
                 "Trying to consume uninitialized or complete VM".to_owned(),
             ))
         }
     }
 
     #[inline]
-    pub fn run<P>(&mut self, entry: &str) -> SimpleResult<()>
+    pub fn run<P>(&mut self, processor: &mut P, entry: &str) -> VmResult<()>
     where
-        P: Processor,
+        P: Processor + ?Sized,
     {
+        self.bind(processor)?;
         self.start(entry)?;
-        self.consume::<P>()
+        self.consume(processor)
+    }
+
+    pub fn backtrace(&self) -> Vec<BacktraceFrame> {
+        let debug_info = self.assembly.debug_info().as_ref();
+        self.callstack
+            .iter()
+            .rev()
+            .filter(|frame| !frame.is_inline())
+            .map(|frame| {
+                let info = debug_info.and_then(|d| d.function_by_index(frame.function()));
+                let address = self
+                    .assembly
+                    .function_body_by_index(frame.function())
+                    .map_or(0, |b| b.instruction_offset(frame.address()));
+                BacktraceFrame {
+                    function: frame.function(),
+                    address,
+                    name: info.map(|i| i.id().to_owned()),
+                    location: info.map(|i| i.describe_location(address.saturating_sub(1))),
+                }
+            })
+            .collect()
+    }
+
+    fn make_error(&self, error: SimpleError) -> VmError {
+        VmError {
+            message: error.message,
+            callstack: self.callstack.clone(),
+            backtrace: self.backtrace(),
+        }
     }
 
+    /// Instruction index of label in current function, to be used with `OpAction::GoTo`.
     pub fn find_label(&self, id: &str) -> Option<usize> {
         if let Some((_, func, _)) = self.location() {
             if let Some(f) = self.assembly.function_body_by_index(func) {
-                f.labels().get(id).cloned()
+                f.labels()
+                    .get(id)
+                    .and_then(|address| f.instruction_index(*address))
             } else {
                 None
             }
@@ -397,13 +1063,13 @@ impl Vm {
                     r
                 }
                 Data::Isize(v) => {
-                    let r = self.state.alloc_memory_value(size_of::<isize>())?;
-                    self.state.store_data(r.address, v)?;
+                    let r = self.state.alloc_memory_value(self.state.pointer_size())?;
+                    self.state.store_isize(r.address, *v)?;
                     r
                 }
                 Data::Usize(v) => {
-                    let r = self.state.alloc_memory_value(size_of::<usize>())?;
-                    self.state.store_data(r.address, v)?;
+                    let r = self.state.alloc_memory_value(self.state.pointer_size())?;
+                    self.state.store_usize(r.address, *v)?;
                     r
                 }
                 Data::String(s) => {
@@ -411,8 +1077,8 @@ impl Vm {
                         let bytes = s.as_bytes_with_nul();
                         let sv = self.state.alloc_memory_value(bytes.len())?;
                         self.state.store_bytes(sv.address, bytes)?;
-                        let v = self.state.alloc_memory_value(size_of::<usize>())?;
-                        self.state.store_data(v.address, &sv.address)?;
+                        let v = self.state.alloc_memory_value(self.state.pointer_size())?;
+                        self.state.store_usize(v.address, sv.address)?;
                         Value::new(sv.address, sv.size + v.size)
                     } else {
                         return Err(SimpleError::new(format!(
@@ -478,6 +1144,9 @@ impl Vm {
                 }
                 if !self.can_resume() {
                     self.stage = ExecutionStage::Complete;
+                    if self.state.is_checked() {
+                        self.check_leaks()?;
+                    }
                 }
                 Ok(())
             } else {
@@ -492,6 +1161,89 @@ impl Vm {
         }
     }
 
+    /// Reports memory blocks still allocated on program completion, except data and globals blocks.
+    fn check_leaks(&self) -> SimpleResult<()> {
+        let stack_size = self.state.stack_size();
+        let data = self.data.map_or(0..0, |v| v.address..v.address + v.size);
+        let globals = self.globals.map(|v| v.address);
+        let leaks = self
+            .state
+            .allocator()
+            .blocks()
+            .into_iter()
+            .map(|(a, s)| (a + stack_size, s))
+            .filter(|(a, _)| !data.contains(a) && globals != Some(*a))
+            .collect::<Vec<_>>();
+        if leaks.is_empty() {
+            Ok(())
+        } else {
+            Err(SimpleError::new(format!(
+                "Memory leak of {} blocks: {}",
+                leaks.len(),
+                leaks
+                    .iter()
+                    .map(|(a, s)| format!("{}..{} ({} bytes)", a, a + s, s))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )))
+        }
+    }
+
+    fn call_native(
+        &mut self,
+        function: usize,
+        external: &(String, String),
+        params_stackpos: usize,
+    ) -> SimpleResult<()> {
+        let handler = if let Some(handler) = self.natives.natives.get(external) {
+            handler.clone()
+        } else {
+            return Err(SimpleError::new(format!(
+                "Trying to call unbound external function: {}:{}",
+                external.0, external.1
+            )));
+        };
+        let (params, result) = {
+            let f = self.assembly.function_by_index(function).unwrap();
+            let mut offset = params_stackpos;
+            let params = f
+                .params()
+                .iter()
+                .map(|p| {
+                    let a = offset;
+                    offset += p.size();
+                    a
+                })
+                .collect::<Vec<_>>();
+            let result = if let Some(t) = f.typeid() {
+                Some(self.state.alloc_stack_value(self.assembly.type_size(t))?)
+            } else {
+                None
+            };
+            (params, result)
+        };
+        handler(&params, result.map(|v| v.address), self)?;
+        if let Some(v) = result {
+            let bytes = self.state.load_bytes(v.address, v.size)?;
+            self.state.stack_reset(params_stackpos)?;
+            let v = self.state.stack_push_bytes(&bytes)?;
+            self.pointers.push(v.address);
+        } else {
+            self.state.stack_reset(params_stackpos)?;
+            self.pointers.push(0usize);
+        }
+        Ok(())
+    }
+
+    fn pop_pointer(&mut self) -> SimpleResult<usize> {
+        match self.pointers.pop() {
+            Some(address) => Ok(address),
+            None => Err(SimpleError::new(
+                "Trying to pop pointer from empty pointers stack".to_owned(),
+            )),
+        }
+    }
+
     fn location(&self) -> Option<(usize, usize, usize)> {
         if let Some(f) = self.callstack.last() {
             Some((self.callstack.len() - 1, f.function(), f.address()))
@@ -500,87 +1252,91 @@ impl Vm {
         }
     }
 
-    fn resume_op<P>(&mut self) -> SimpleResult<bool>
+    fn resume_op<P>(&mut self, processor: &mut P) -> SimpleResult<bool>
     where
-        P: Processor,
+        P: Processor + ?Sized,
     {
-        if let Some((i, func, addr)) = self.location() {
-            let b = self.assembly.function_body_by_index(func).unwrap();
-            let bodysize = b.code().len();
-            if addr >= bodysize {
+        if let Some((i, func, mut addr)) = self.location() {
+            let assembly = Arc::clone(&self.assembly);
+            let body = assembly.function_body_by_index(func).unwrap();
+            let instructions = body.instructions();
+            let count = instructions.len();
+            if addr >= count {
                 self.return_function()?;
                 return Ok(false);
             }
-            let mut stream = Cursor::new(b.code());
-            stream.seek(SeekFrom::Start(addr as u64))?;
             loop {
-                let op = OpIndex::from(stream.read_u8()?);
-                match op {
-                    OpIndex::NoOp => unreachable!(),
-                    OpIndex::DataPointer => {
-                        let offset = stream.read_u64::<BigEndian>()? as usize;
+                let instr = &instructions[addr];
+                addr += 1;
+                match instr {
+                    Instr::DataPointer(offset) => {
                         let address = self.data.unwrap().address + offset;
-                        self.callstack[i].address = stream.position() as usize;
+                        self.callstack[i].address = addr;
                         self.pointers.push(address);
                     }
-                    OpIndex::ParamsPointer => {
-                        let offset = stream.read_u64::<BigEndian>()? as usize;
+                    Instr::ParamsPointer(offset) => {
                         let address = self.callstack[i].params_stackpos + offset;
-                        self.callstack[i].address = stream.position() as usize;
+                        self.callstack[i].address = addr;
                         self.pointers.push(address);
                     }
-                    OpIndex::ResultPointer => {
+                    Instr::ResultPointer => {
                         let address = self.callstack[i].result.unwrap().address;
-                        self.callstack[i].address = stream.position() as usize;
+                        self.callstack[i].address = addr;
                         self.pointers.push(address);
                     }
-                    OpIndex::LocalsPointer => {
-                        let offset = stream.read_u64::<BigEndian>()? as usize;
+                    Instr::LocalsPointer(offset) => {
                         let address = self.callstack[i].locals.unwrap().address + offset;
-                        self.callstack[i].address = stream.position() as usize;
+                        self.callstack[i].address = addr;
                         self.pointers.push(address);
                     }
-                    OpIndex::GlobalsPointer => {
-                        let offset = stream.read_u64::<BigEndian>()? as usize;
+                    Instr::GlobalsPointer(offset) => {
                         let address = self.globals.unwrap().address + offset;
-                        self.callstack[i].address = stream.position() as usize;
+                        self.callstack[i].address = addr;
                         self.pointers.push(address);
                     }
-                    OpIndex::OffsetPointer => {
-                        let offset = stream.read_u64::<BigEndian>()? as usize;
-                        let address = self.pointers.pop().unwrap() + offset;
-                        self.callstack[i].address = stream.position() as usize;
+                    Instr::OffsetPointer(offset) => {
+                        let address = self.pop_pointer()? + offset;
+                        self.callstack[i].address = addr;
                         self.pointers.push(address);
                     }
-                    OpIndex::ReferencePointer => {
-                        let v = self.state.stack_push_data(&self.pointers.pop().unwrap())?;
-                        self.callstack[i].address = stream.position() as usize;
+                    Instr::ReferencePointer => {
+                        let address = self.pop_pointer()?;
+                        let v = self.state.stack_push_usize(address)?;
+                        self.callstack[i].address = addr;
                         self.pointers.push(v.address);
                     }
-                    OpIndex::DereferencePointer => {
-                        let address = self.pointers.pop().unwrap();
-                        let address = self.state.load_data::<usize>(address)?;
-                        self.callstack[i].address = stream.position() as usize;
+                    Instr::DereferencePointer => {
+                        let address = self.pop_pointer()?;
+                        let address = self.state.load_usize(address)?;
+                        self.callstack[i].address = addr;
                         self.pointers.push(address);
                     }
-                    OpIndex::StoreTargetAddress => {
-                        let address = self.pointers.pop().unwrap();
-                        self.callstack[i].address = stream.position() as usize;
+                    Instr::LabelAddress(index) => {
+                        self.callstack[i].address = addr;
+                        self.pointers.push(*index);
+                    }
+                    Instr::StoreTargetAddress(typeid) => {
+                        let address = self.pop_pointer()?;
+                        self.callstack[i].address = addr;
                         self.callstack[i].op_target_addresses.push(address);
+                        self.callstack[i]
+                            .op_target_types
+                            .push(typeid.as_deref().cloned());
                     }
-                    OpIndex::StoreParamAddress => {
-                        let address = self.pointers.pop().unwrap();
-                        self.callstack[i].address = stream.position() as usize;
+                    Instr::StoreParamAddress(typeid) => {
+                        let address = self.pop_pointer()?;
+                        self.callstack[i].address = addr;
                         self.callstack[i].op_param_addresses.push(address);
+                        self.callstack[i]
+                            .op_param_types
+                            .push(typeid.as_deref().cloned());
                     }
-                    OpIndex::ProduceTuple => {
-                        let count = stream.read_u64::<BigEndian>()? as usize;
+                    Instr::ProduceTuple(sizes) => {
                         let mut addresses_sizes = vec![];
                         let mut size = 0;
-                        for _ in 0..count {
-                            let s = stream.read_u64::<BigEndian>()? as usize;
-                            let a = self.pointers.pop().unwrap();
-                            addresses_sizes.push((a, s));
+                        for s in sizes.iter() {
+                            let a = self.pop_pointer()?;
+                            addresses_sizes.push((a, *s));
                             size += s;
                         }
                         let v = self.state.alloc_stack_value(size)?;
@@ -589,61 +1345,71 @@ impl Vm {
                             self.state.memory_move(a, s, v.address + offset)?;
                             offset += s;
                         }
-                        self.callstack[i].address = stream.position() as usize;
+                        self.callstack[i].address = addr;
                         self.pointers.push(v.address);
                     }
-                    OpIndex::ExecuteOpStart => {
+                    Instr::ExecuteOpStart => {
                         self.callstack[i].op_stackpos = self.state.stack_pos();
                     }
-                    OpIndex::ExecuteOpStop => {
-                        let op = stream.read_u64::<BigEndian>()? as usize;
-                        let op = self.assembly.ops_map().get(op).unwrap().to_owned();
-                        let (params, targets) = self.callstack[i].collect_params_targets();
-                        let addr = stream.position() as usize;
+                    Instr::ExecuteOpStop(op) => {
+                        let handler = self.handlers[*op];
+                        self.spent += self.costs[*op];
+                        let (params, targets) = self.callstack[i].take_params_targets();
                         self.callstack[i].address = addr;
-                        let action = { P::process_op(&op, &params, &targets, self)? };
+                        let action = processor.process_op_typed(
+                            handler,
+                            Operands::new(&params.0, &params.1),
+                            Operands::new(&targets.0, &targets.1),
+                            self,
+                        );
+                        self.callstack[i].restore_params_targets(params, targets);
+                        let action = action?;
                         self.state.stack_reset(self.callstack[i].op_stackpos)?;
                         self.callstack[i].op_stackpos = 0;
                         match action {
                             OpAction::None => {
-                                if addr >= bodysize || !self.can_resume() {
+                                if addr >= count || !self.can_resume() {
                                     self.return_function()?;
                                 }
                             }
                             OpAction::GoTo(a) => {
+                                check_goto(body, func, a)?;
                                 self.callstack[i].address = a;
                             }
                             OpAction::Return => self.return_function()?,
                         }
                         break;
                     }
-                    OpIndex::ExecuteOpInlineStart => {
-                        self.callstack[i].address = stream.position() as usize;
+                    Instr::ExecuteOpInlineStart => {
+                        self.callstack[i].address = addr;
                         self.callstack.push(self.callstack[i].duplicate());
                         return Ok(true);
                     }
-                    OpIndex::ExecuteOpInlineStop => {
-                        let op = stream.read_u64::<BigEndian>()? as usize;
-                        let size = stream.read_u64::<BigEndian>()? as usize;
-                        let op = self.assembly.ops_map().get(op).unwrap().to_owned();
-                        let (params, mut targets) = self.callstack[i].collect_params_targets();
-                        let v = self.state.alloc_stack_value(size)?;
-                        targets.push(v.address);
-                        self.callstack[i - 1].address = stream.position() as usize;
-                        {
-                            P::process_op(&op, &params, &targets, self)?;
-                        }
+                    Instr::ExecuteOpInlineStop(op, size, typeid) => {
+                        let handler = self.handlers[*op];
+                        self.spent += self.costs[*op];
+                        let (params, mut targets) = self.callstack[i].take_params_targets();
+                        let v = self.state.alloc_stack_value(*size)?;
+                        targets.0.push(v.address);
+                        targets.1.push(typeid.as_deref().cloned());
+                        self.callstack[i - 1].address = addr;
+                        processor.process_op_typed(
+                            handler,
+                            Operands::new(&params.0, &params.1),
+                            Operands::new(&targets.0, &targets.1),
+                            self,
+                        )?;
                         self.callstack.pop();
                         self.pointers.push(v.address);
                         break;
                     }
-                    OpIndex::CallFunction => {
-                        let f = stream.read_u64::<BigEndian>()? as usize;
-                        if let Some(func) = self.assembly.function_by_index(f) {
+                    Instr::CallFunction(f) => {
+                        self.spent += 1;
+                        if let Some(func) = assembly.function_by_index(*f) {
                             let mut addresses_sizes = vec![];
                             let mut size = 0;
                             for p in func.params() {
-                                let a = self.pointers.pop().unwrap();
+                                let a = self.pop_pointer()?;
                                 addresses_sizes.push((a, p.size()));
                                 size += p.size();
                             }
@@ -653,8 +1419,12 @@ impl Vm {
                                 self.state.memory_move(a, s, v.address + offset)?;
                                 offset += s;
                             }
-                            self.callstack[i].address = stream.position() as usize;
-                            self.call_function(f)?;
+                            self.callstack[i].address = addr;
+                            if let Some(external) = func.external() {
+                                self.call_native(*f, external, v.address)?;
+                            } else {
+                                self.call_function(*f)?;
+                            }
                             return Ok(true);
                         } else {
                             unreachable!();
@@ -668,3 +1438,789 @@ impl Vm {
         Ok(false)
     }
 }
+
+fn write_value(stream: &mut Vec<u8>, value: &Option<Value>) {
+    if let Some(value) = value {
+        stream.push(1);
+        write_usize(stream, value.address);
+        write_usize(stream, value.size);
+    } else {
+        stream.push(0);
+    }
+}
+
+fn read_value(stream: &mut dyn Read, size: usize) -> SimpleResult<Option<Value>> {
+    if stream.read_u8()? == 0 {
+        return Ok(None);
+    }
+    let address = read_usize(stream)?;
+    let value_size = read_usize(stream)?;
+    if address + value_size > size {
+        Err(SimpleError::new(format!(
+            "Value {}..{} exceeds VM state size {}",
+            address,
+            address + value_size,
+            size
+        )))
+    } else {
+        Ok(Some(Value::new(address, value_size)))
+    }
+}
+
+fn write_usizes(stream: &mut Vec<u8>, values: &[usize]) {
+    write_usize(stream, values.len());
+    for value in values {
+        write_usize(stream, *value);
+    }
+}
+
+fn read_usizes(stream: &mut dyn Read) -> SimpleResult<Vec<usize>> {
+    let count = read_usize(stream)?;
+    (0..count).map(|_| read_usize(stream)).collect()
+}
+
+fn write_types(stream: &mut Vec<u8>, types: &[Option<Type>]) {
+    write_usize(stream, types.len());
+    for typeid in types {
+        if let Some(typeid) = typeid {
+            stream.push(1);
+            write_type(typeid, stream).unwrap();
+        } else {
+            stream.push(0);
+        }
+    }
+}
+
+fn read_types(stream: &mut dyn Read) -> SimpleResult<Vec<Option<Type>>> {
+    let count = read_usize(stream)?;
+    (0..count)
+        .map(|_| {
+            if stream.read_u8()? == 0 {
+                Ok(None)
+            } else {
+                Ok(Some(read_type(stream)?))
+            }
+        })
+        .collect()
+}
+
+/// Ensures that processor jumps only to label or op start in body of current function.
+fn check_goto(body: &FunctionBody, function: usize, index: usize) -> SimpleResult<()> {
+    let valid = match body.instructions().get(index) {
+        Some(Instr::ExecuteOpStart) => true,
+        _ => body
+            .labels()
+            .values()
+            .any(|address| body.instruction_index(*address) == Some(index)),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(SimpleError::new(format!(
+            "Trying to go to instruction {} that is neither label nor op start of function #{}",
+            index, function
+        )))
+    }
+}
+
+/// Ensures that restored call stack frame matches its function, so execution never hits
+/// missing result or locals.
+fn check_frame(
+    assembly: &VmAssembly,
+    frame: &CallStackFrame,
+    parent: Option<&CallStackFrame>,
+    stack_pos: usize,
+) -> SimpleResult<()> {
+    let f = match assembly.function_by_index(frame.function) {
+        Some(f) => f,
+        None => {
+            return Err(SimpleError::new(format!(
+                "Call stack frame points to non-existing function #{}",
+                frame.function
+            )))
+        }
+    };
+    let result_size = f.typeid().as_ref().map(|t| assembly.type_size(t));
+    if frame.result.map(|v| v.size) != result_size {
+        return Err(SimpleError::new(format!(
+            "Call stack frame result does not match return type of function #{}",
+            frame.function
+        )));
+    }
+    let locals_size = f.locals().iter().map(|l| l.size()).sum::<usize>();
+    if frame.locals.map(|v| v.size) != Some(locals_size).filter(|s| *s != 0) {
+        return Err(SimpleError::new(format!(
+            "Call stack frame locals do not match locals of function #{}",
+            frame.function
+        )));
+    }
+    if frame.inline && parent.map(|p| p.function) != Some(frame.function) {
+        return Err(SimpleError::new(format!(
+            "Inline call stack frame of function #{} has no parent frame",
+            frame.function
+        )));
+    }
+    if frame.op_stackpos > stack_pos {
+        return Err(SimpleError::new(format!(
+            "Call stack frame op stack position {} exceeds stack position {}",
+            frame.op_stackpos, stack_pos
+        )));
+    }
+    Ok(())
+}
+
+/// Ensures that addresses of memory of given size fit in target pointer size.
+fn check_pointer_range(pointer_size: usize, size: usize) -> SimpleResult<()> {
+    if pointer_size < size_of::<usize>() && size > 1 << (pointer_size * 8) {
+        Err(SimpleError::new(format!(
+            "Memory and stack sizes combined cannot exceed target pointer range: {}",
+            1usize << (pointer_size * 8)
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// FNV-1a hash of assembly parts that snapshot state depends on.
+fn assembly_fingerprint(assembly: &VmAssembly) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    feed(&(assembly.globals_size() as u64).to_be_bytes());
+    for s in assembly.structs() {
+        feed(&(s.size() as u64).to_be_bytes());
+    }
+    for f in assembly.functions() {
+        let size = f
+            .params()
+            .iter()
+            .chain(f.locals().iter())
+            .map(|v| v.size())
+            .sum::<usize>();
+        feed(&(size as u64).to_be_bytes());
+    }
+    for d in assembly.data() {
+        feed(&(d.size(assembly.target().pointer_size()) as u64).to_be_bytes());
+    }
+    for op in assembly.ops_map() {
+        feed(op.as_bytes());
+        feed(&[0]);
+    }
+    for b in assembly.functions_code() {
+        feed(&(b.code().len() as u64).to_be_bytes());
+        feed(b.code());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::EmptyProcessor;
+    use core::assembly::{encode_assembly_with_options, AssemblyOptions};
+    use core::program::{compile_module, compile_ops_descriptor, Program};
+    use core::target::{Endianness, Target};
+    use core::validator::{transform_module_auto_types, EmptyDeepValidator};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const OPS: &str = "pass v: $? {}";
+
+    const MAIN: &str = r#"
+        extern fn add(a: i32, b: i32): i32 from math:add;
+        extern fn expect(v: i32) from test:expect;
+
+        export fn main() {
+          pass @<expect(@<add(40, 2)>)>;
+        }
+    "#;
+
+    struct TestModuleReader {}
+
+    impl ModuleReader for TestModuleReader {
+        fn load_module_source(&self, path: &str) -> Option<String> {
+            if path == "main.kj" {
+                Some(MAIN.to_owned())
+            } else {
+                None
+            }
+        }
+
+        fn push_module_path(&mut self, _: &str) {}
+
+        fn pop_module_path(&mut self) {}
+
+        fn compose_path(&self, path: &str) -> String {
+            path.to_owned()
+        }
+    }
+
+    struct TestProcessor {}
+
+    struct BindOnlyProcessor {}
+
+    impl Processor for BindOnlyProcessor {
+        fn bind_op(&mut self, _op: &str) -> Option<HandlerId> {
+            Some(0)
+        }
+    }
+
+    impl Processor for TestProcessor {
+        fn bind_op(&mut self, op: &str) -> Option<HandlerId> {
+            if op == "pass" {
+                Some(0)
+            } else {
+                None
+            }
+        }
+
+        fn process_op(
+            &mut self,
+            _handler: HandlerId,
+            _params: &[usize],
+            _targets: &[usize],
+            _vm: &mut Vm,
+        ) -> SimpleResult<OpAction> {
+            Ok(OpAction::None)
+        }
+    }
+
+    struct AddProcessor {}
+
+    impl Processor for AddProcessor {
+        fn bind_op(&mut self, op: &str) -> Option<HandlerId> {
+            if op == "add" {
+                Some(0)
+            } else {
+                None
+            }
+        }
+
+        fn process_op(
+            &mut self,
+            _handler: HandlerId,
+            params: &[usize],
+            targets: &[usize],
+            vm: &mut Vm,
+        ) -> SimpleResult<OpAction> {
+            let a = vm.state().load_data::<i32>(params[0])?;
+            let b = vm.state().load_data::<i32>(params[1])?;
+            vm.state_mut().store_data(targets[0], &(a + b))?;
+            Ok(OpAction::None)
+        }
+    }
+
+    struct TypedAddProcessor {}
+
+    impl Processor for TypedAddProcessor {
+        fn bind_op(&mut self, op: &str) -> Option<HandlerId> {
+            if op == "add" {
+                Some(0)
+            } else {
+                None
+            }
+        }
+
+        fn process_op_typed(
+            &mut self,
+            _handler: HandlerId,
+            params: Operands,
+            targets: Operands,
+            vm: &mut Vm,
+        ) -> SimpleResult<OpAction> {
+            let (a, b, t) = (
+                params.address(0).unwrap(),
+                params.address(1).unwrap(),
+                targets.address(0).unwrap(),
+            );
+            match targets.typeid(0).and_then(|t| t.atom()) {
+                Some("i8") => {
+                    let v = vm.state().load_data::<i8>(a)? + vm.state().load_data::<i8>(b)?;
+                    vm.state_mut().store_data(t, &v)?;
+                }
+                Some("f64") => {
+                    let v = vm.state().load_data::<f64>(a)? + vm.state().load_data::<f64>(b)?;
+                    vm.state_mut().store_data(t, &v)?;
+                }
+                typeid => {
+                    return Err(SimpleError::new(format!(
+                        "Unsupported add type: {:?}",
+                        typeid
+                    )))
+                }
+            }
+            Ok(OpAction::None)
+        }
+    }
+
+    struct GoToProcessor {
+        target: Option<usize>,
+    }
+
+    impl Processor for GoToProcessor {
+        fn bind_op(&mut self, op: &str) -> Option<HandlerId> {
+            if op == "goto" {
+                Some(0)
+            } else {
+                None
+            }
+        }
+
+        fn process_op(
+            &mut self,
+            _handler: HandlerId,
+            params: &[usize],
+            _targets: &[usize],
+            _vm: &mut Vm,
+        ) -> SimpleResult<OpAction> {
+            Ok(OpAction::GoTo(self.target.unwrap_or(params[0])))
+        }
+    }
+
+    fn native_add(params: &[usize], result: Option<usize>, vm: &mut Vm) -> SimpleResult<()> {
+        let a = vm.state().load_data::<i32>(params[0])?;
+        let b = vm.state().load_data::<i32>(params[1])?;
+        vm.state_mut().store_data(result.unwrap(), &(a + b))
+    }
+
+    fn native_sub(params: &[usize], result: Option<usize>, vm: &mut Vm) -> SimpleResult<()> {
+        let a = vm.state().load_data::<i32>(params[0])?;
+        let b = vm.state().load_data::<i32>(params[1])?;
+        vm.state_mut().store_data(result.unwrap(), &(a - b))
+    }
+
+    fn native_expect(params: &[usize], result: Option<usize>, vm: &mut Vm) -> SimpleResult<()> {
+        assert!(result.is_none());
+        let v = vm.state().load_data::<i32>(params[0])?;
+        if v == 42 {
+            Ok(())
+        } else {
+            Err(SimpleError::new(format!("Expected 42, got: {}", v)))
+        }
+    }
+
+    fn create_vm() -> Vm {
+        let ops = compile_ops_descriptor(OPS).unwrap();
+        Vm::from_source::<EmptyDeepValidator, _>("main.kj", TestModuleReader {}, &ops, 256, 256)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut vm = create_vm();
+        assert!(vm.start("main").is_err());
+        assert!(vm.bind(&mut EmptyProcessor {}).is_err());
+        assert!(!vm.is_bound());
+        vm.bind(&mut TestProcessor {}).unwrap();
+        assert!(vm.is_bound());
+
+        let mut vm = create_vm();
+        vm.register_native("math", "add", native_add);
+        vm.register_native("test", "expect", native_expect);
+        assert_eq!(
+            vm.run(&mut BindOnlyProcessor {}, "main")
+                .unwrap_err()
+                .message,
+            "Processor does not implement processing of op handler: 0"
+        );
+    }
+
+    #[test]
+    fn test_natives() {
+        let mut vm = create_vm();
+        vm.bind(&mut TestProcessor {}).unwrap();
+        assert!(vm.start("main").is_err());
+
+        let mut vm = create_vm();
+        vm.bind(&mut TestProcessor {}).unwrap();
+        vm.register_native("math", "add", native_add);
+        assert!(vm.start("main").is_err());
+
+        let mut vm = create_vm();
+        vm.register_native("math", "add", native_sub);
+        vm.register_native("test", "expect", native_expect);
+        let err = vm.run(&mut TestProcessor {}, "main").unwrap_err();
+        assert_eq!(err.message, "Expected 42, got: 38");
+        assert_eq!(err.callstack.len(), 1);
+        assert_eq!(
+            err.to_string(),
+            "Expected 42, got: 38\n  0: main at main.kj:6:11"
+        );
+
+        let mut vm = create_vm();
+        vm.register_native("math", "add", native_add);
+        vm.register_native("test", "expect", native_expect);
+        vm.run(&mut TestProcessor {}, "main").unwrap();
+        assert_eq!(vm.stage(), ExecutionStage::Complete);
+        assert_eq!(vm.state().stack_pos(), 0);
+
+        let mut vm = create_vm();
+        assert_eq!(
+            vm.bind_natives(NativesRegistry::new().with("math", "add", native_add))
+                .unwrap_err()
+                .message,
+            "There are unbound external functions: test:expect"
+        );
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let natives = NativesRegistry::new()
+            .with("math", "add", move |params, result, vm| {
+                counter.fetch_add(1, Ordering::Relaxed);
+                native_add(params, result, vm)
+            })
+            .with("test", "expect", native_expect);
+        vm.bind_natives(natives).unwrap();
+        vm.run(&mut TestProcessor {}, "main").unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_checked() {
+        let mut vm = create_vm();
+        vm.register_native("math", "add", native_add);
+        vm.register_native("test", "expect", native_expect);
+        vm.bind(&mut TestProcessor {}).unwrap();
+        vm.start("main").unwrap();
+        vm.state_mut().set_checked(true);
+        let v = vm.state_mut().alloc_memory_value(4).unwrap();
+        vm.state_mut().dealloc_memory_value(&v).unwrap();
+        assert!(vm.state().load_data::<u32>(v.address).is_err());
+        let v = vm.state_mut().alloc_memory_value(4).unwrap();
+        let err = vm.consume(&mut TestProcessor {}).unwrap_err();
+        assert_eq!(
+            err.message,
+            format!(
+                "Memory leak of 1 blocks: {}..{} (4 bytes)",
+                v.address,
+                v.address + 4
+            )
+        );
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut vm = create_vm();
+        vm.register_native("math", "add", native_add);
+        vm.register_native("test", "expect", native_expect);
+        match vm.resume_with_budget(&mut TestProcessor {}, 1) {
+            ResumeStatus::Error(err) => {
+                assert_eq!(err.message, "Trying to resume VM with no processor bound")
+            }
+            status => panic!("Unexpected status: {:?}", status),
+        }
+        vm.bind(&mut TestProcessor {}).unwrap();
+        vm.start("main").unwrap();
+        match vm.resume_with_budget(&mut TestProcessor {}, 1) {
+            ResumeStatus::Yielded => assert_eq!(vm.spent_budget(), 1),
+            status => panic!("Unexpected status: {:?}", status),
+        }
+        match vm.resume_with_budget(&mut TestProcessor {}, 1) {
+            ResumeStatus::Yielded => assert_eq!(vm.spent_budget(), 1),
+            status => panic!("Unexpected status: {:?}", status),
+        }
+        match vm.resume_with_budget(&mut TestProcessor {}, 10) {
+            ResumeStatus::Completed => assert_eq!(vm.spent_budget(), 1),
+            status => panic!("Unexpected status: {:?}", status),
+        }
+        assert_eq!(vm.stage(), ExecutionStage::Complete);
+    }
+
+    #[test]
+    fn test_call() {
+        let ops = compile_ops_descriptor("add a: i32 b: i32 => i32 {}").unwrap();
+        let source = "< total: i32 >\nexport fn add(a: i32, b: i32): i32 {\n  add a b => _;\n  add total _ => total;\n}\nexport fn total(): i32 {\n  add total 0 => _;\n}";
+        let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+        let program = Program::from_modules(None, vec![module]).unwrap();
+        let bytes = encode_assembly(&program, &ops, false).unwrap();
+        let mut vm = Vm::from_bytes(bytes, 256, 256).unwrap();
+        let mut processor = AddProcessor {};
+        let mut args = vec![0; 8];
+        (40i32, 2i32)
+            .write_bytes(&mut args, vm.state().target())
+            .unwrap();
+        assert!(vm.call(&mut processor, "add", &args).is_err());
+        vm.bind(&mut processor).unwrap();
+        assert_eq!(
+            vm.call(&mut processor, "add", &args[..4])
+                .unwrap_err()
+                .message,
+            "Function `add` expects 8 bytes of arguments, got: 4"
+        );
+        assert_eq!(
+            vm.call(&mut processor, "add", &args).unwrap(),
+            42i32.to_le_bytes().to_vec()
+        );
+        assert_eq!(vm.stage(), ExecutionStage::Complete);
+        assert_eq!(
+            vm.call_typed::<_, _, i32>(&mut processor, "add", (1i32, 2i32))
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            vm.call_typed::<_, _, i32>(&mut processor, "total", ())
+                .unwrap(),
+            45
+        );
+        assert!(vm
+            .call_typed::<_, _, i64>(&mut processor, "total", ())
+            .is_err());
+        assert_eq!(vm.state().stack_pos(), 0);
+    }
+
+    #[test]
+    fn test_instructions() {
+        let ops = compile_ops_descriptor("add a: i32 b: i32 => i32 {}").unwrap();
+        let source = "export fn main() <v: i32> {\n  add 1 2 => v;\nnext:\n  add v 3 => v;\n}";
+        let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+        let program = Program::from_modules(None, vec![module]).unwrap();
+        let bytes = encode_assembly(&program, &ops, false).unwrap();
+        let mut vm = Vm::from_bytes(bytes, 256, 256).unwrap();
+        {
+            let body = vm.assembly().function_body_by_id("main").unwrap();
+            let address = body.labels()["next"];
+            let index = body.instruction_index(address).unwrap();
+            assert_eq!(body.instructions()[0], Instr::ExecuteOpStart);
+            assert_eq!(body.instructions()[index], Instr::ExecuteOpStart);
+            assert_eq!(body.instruction_offset(index), address);
+            assert_eq!(body.instruction_index(body.code().len() + 1), None);
+            assert_eq!(
+                body.instruction_index(body.code().len()),
+                Some(body.instructions().len())
+            );
+        }
+        let mut processor = AddProcessor {};
+        vm.bind(&mut processor).unwrap();
+        vm.start("main").unwrap();
+        let index = vm.find_label("next").unwrap();
+        vm.step(&mut processor).unwrap();
+        assert_eq!(vm.callstack()[0].address(), index);
+        assert_eq!(
+            vm.backtrace()[0].address,
+            vm.assembly().functions_code()[0].labels()["next"]
+        );
+        vm.consume(&mut processor).unwrap();
+        assert_eq!(vm.stage(), ExecutionStage::Complete);
+    }
+
+    #[test]
+    fn test_goto() {
+        let ops = compile_ops_descriptor("goto to: @label {}").unwrap();
+        let source = "export fn main() {\nloop:\n  goto 'loop';\n}";
+        let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+        let program = Program::from_modules(None, vec![module]).unwrap();
+        let bytes = encode_assembly(&program, &ops, false).unwrap();
+        let vm = Vm::from_bytes(bytes, 256, 256).unwrap();
+        let count = vm.assembly().functions_code()[0].instructions().len();
+
+        let mut processor = GoToProcessor { target: None };
+        let mut valid = vm.fork().unwrap();
+        valid.bind(&mut processor).unwrap();
+        valid.start("main").unwrap();
+        let index = valid.find_label("loop").unwrap();
+        for _ in 0..3 {
+            valid.step(&mut processor).unwrap();
+            assert_eq!(valid.callstack()[0].address(), index);
+        }
+
+        for target in &[1, count, count + 10] {
+            let mut processor = GoToProcessor {
+                target: Some(*target),
+            };
+            let mut vm = vm.fork().unwrap();
+            vm.bind(&mut processor).unwrap();
+            vm.start("main").unwrap();
+            assert_eq!(
+                vm.step(&mut processor).unwrap_err().message,
+                format!(
+                    "Trying to go to instruction {} that is neither label nor op start of function #0",
+                    target
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_operand_types() {
+        let ops = compile_ops_descriptor("add a: $? b: $? => $? {}").unwrap();
+        let source = "export fn add_i8(a: i8, b: i8): i8 {\n  add a b => _;\n}\nexport fn add_f64(a: f64, b: f64): f64 {\n  add a b => _;\n}";
+        let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+        let program = Program::from_modules(None, vec![module]).unwrap();
+        let options = AssemblyOptions {
+            operand_types: true,
+            ..Default::default()
+        };
+        let bytes = encode_assembly_with_options(&program, &ops, &options).unwrap();
+        let mut vm = Vm::from_bytes(bytes, 256, 256).unwrap();
+        let mut processor = TypedAddProcessor {};
+        vm.bind(&mut processor).unwrap();
+        assert_eq!(
+            vm.call_typed::<_, _, i8>(&mut processor, "add_i8", (40i8, 2i8))
+                .unwrap(),
+            42
+        );
+        assert_eq!(
+            vm.call_typed::<_, _, f64>(&mut processor, "add_f64", (40.5f64, 1.5f64))
+                .unwrap(),
+            42.0
+        );
+
+        let bytes = encode_assembly(&program, &ops, false).unwrap();
+        let mut vm = Vm::from_bytes(bytes, 256, 256).unwrap();
+        vm.bind(&mut processor).unwrap();
+        assert_eq!(
+            vm.call_typed::<_, _, i8>(&mut processor, "add_i8", (40i8, 2i8))
+                .unwrap_err()
+                .message,
+            "Unsupported add type: None"
+        );
+    }
+
+    #[test]
+    fn test_endianness() {
+        let ops = compile_ops_descriptor("add a: i32 b: i32 => i32 {}").unwrap();
+        let source = "export fn add(a: i32, b: i32): i32 {\n  add a b => _;\n}";
+        let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+        let program = Program::from_modules(None, vec![module]).unwrap();
+        let little = encode_assembly(&program, &ops, false).unwrap();
+        let options = AssemblyOptions {
+            target: Target::new(size_of::<usize>(), Endianness::Big),
+            ..Default::default()
+        };
+        let big = encode_assembly_with_options(&program, &ops, &options).unwrap();
+        let mut vm = Vm::from_bytes(big, 256, 256).unwrap();
+        assert_eq!(vm.assembly().endianness(), Endianness::Big);
+        assert_eq!(vm.state().endianness(), Endianness::Big);
+        let mut processor = AddProcessor {};
+        vm.bind(&mut processor).unwrap();
+        let mut args = vec![0; 8];
+        (40i32, 2i32)
+            .write_bytes(&mut args, vm.state().target())
+            .unwrap();
+        assert_eq!(
+            vm.call(&mut processor, "add", &args).unwrap(),
+            42i32.to_be_bytes().to_vec()
+        );
+        assert_eq!(
+            vm.call_typed::<_, _, i32>(&mut processor, "add", (1i32, 2i32))
+                .unwrap(),
+            3
+        );
+        let little = VmAssembly::new(little).unwrap();
+        assert_eq!(little.endianness(), Endianness::Little);
+        assert_eq!(
+            Vm::restore(little, &vm.snapshot()).unwrap_err().message,
+            format!(
+                "VM snapshot memory targets be{0}-packed but assembly targets le{0}-packed",
+                usize::BITS
+            )
+        );
+    }
+
+    #[test]
+    fn test_target() {
+        let ops = compile_ops_descriptor("add a: i32 b: i32 => i32 {}").unwrap();
+        let source = "export fn add(a: i32, b: i32): i32 {\n  add a b => _;\n}";
+        let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+        let program = Program::from_modules(None, vec![module]).unwrap();
+        let options = AssemblyOptions {
+            target: Target::from_name("le16").unwrap(),
+            ..Default::default()
+        };
+        let bytes = encode_assembly_with_options(&program, &ops, &options).unwrap();
+        assert_eq!(
+            Vm::from_bytes(bytes.clone(), 1024, 1024 * 64)
+                .unwrap_err()
+                .message,
+            "Memory and stack sizes combined cannot exceed target pointer range: 65536"
+        );
+        let mut vm = Vm::from_bytes(bytes, 256, 256).unwrap();
+        assert_eq!(vm.state().pointer_size(), 2);
+        let mut processor = AddProcessor {};
+        vm.bind(&mut processor).unwrap();
+        assert_eq!(
+            vm.call_typed::<_, _, i32>(&mut processor, "add", (40i32, 2i32))
+                .unwrap(),
+            42
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut vm = create_vm();
+        vm.register_native("math", "add", native_add);
+        vm.register_native("test", "expect", native_expect);
+        vm.bind(&mut TestProcessor {}).unwrap();
+        vm.start("main").unwrap();
+        let snapshot = vm.snapshot();
+        vm.consume(&mut TestProcessor {}).unwrap();
+        assert_eq!(vm.stage(), ExecutionStage::Complete);
+
+        let mut restored = Vm::restore(vm.assembly().clone(), &snapshot).unwrap();
+        assert_eq!(restored.stage(), ExecutionStage::Running);
+        assert_eq!(restored.callstack().len(), 1);
+        assert_eq!(restored.snapshot(), snapshot);
+        assert!(restored.consume(&mut TestProcessor {}).is_err());
+        restored.register_native("math", "add", native_add);
+        restored.register_native("test", "expect", native_expect);
+        restored.bind(&mut TestProcessor {}).unwrap();
+        restored.consume(&mut TestProcessor {}).unwrap();
+        assert_eq!(restored.stage(), ExecutionStage::Complete);
+        assert_eq!(restored.state().map_all(), vm.state().map_all());
+
+        for size in 0..snapshot.len() {
+            assert!(Vm::restore(vm.assembly().clone(), &snapshot[..size]).is_err());
+        }
+        let ops = compile_ops_descriptor(OPS).unwrap();
+        let module = compile_module("export fn main() {\n  pass 42;\n}").unwrap();
+        let module = transform_module_auto_types(module).unwrap();
+        let program = Program::from_modules(None, vec![module]).unwrap();
+        let other =
+            Vm::from_bytes(encode_assembly(&program, &ops, false).unwrap(), 256, 256).unwrap();
+        assert_eq!(
+            Vm::restore(other.assembly().clone(), &snapshot)
+                .unwrap_err()
+                .message,
+            "VM snapshot was made for different assembly"
+        );
+
+        let mut vm = create_vm();
+        vm.register_native("math", "add", native_add);
+        vm.register_native("test", "expect", native_expect);
+        vm.bind(&mut TestProcessor {}).unwrap();
+        vm.start("main").unwrap();
+        let restore_error = |vm: &Vm| {
+            Vm::restore(vm.assembly().clone(), &vm.snapshot())
+                .unwrap_err()
+                .message
+        };
+        vm.callstack[0].result = Some(Value::new(0, 4));
+        assert_eq!(
+            restore_error(&vm),
+            "Call stack frame result does not match return type of function #0"
+        );
+        vm.callstack[0].result = None;
+        vm.callstack[0].locals = Some(Value::new(0, 4));
+        assert_eq!(
+            restore_error(&vm),
+            "Call stack frame locals do not match locals of function #0"
+        );
+        vm.callstack[0].locals = None;
+        vm.callstack[0].inline = true;
+        assert_eq!(
+            restore_error(&vm),
+            "Inline call stack frame of function #0 has no parent frame"
+        );
+        vm.callstack[0].inline = false;
+        vm.callstack[0].op_stackpos = 1000;
+        assert!(restore_error(&vm).starts_with("Call stack frame op stack position 1000"));
+        vm.callstack[0].op_stackpos = 0;
+        vm.globals = None;
+        assert_eq!(
+            restore_error(&vm),
+            "VM snapshot of initialized VM does not have data or globals"
+        );
+    }
+}
@@ -0,0 +1,361 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Expr, Fields, FnArg, GenericArgument, ItemFn, Lit,
+    Meta, Pat, PathArguments, ReturnType, Type,
+};
+
+/// Implements `CallValue`, `KaijuType` and `KaijuStruct` for struct with named fields, laying
+/// fields out the same way as Kaiju struct with the same name and fields on VM target.
+#[proc_macro_derive(KaijuStruct)]
+pub fn derive_kaiju_struct(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match kaiju_struct(&input) {
+        Ok(result) => result.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn kaiju_struct(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "KaijuStruct cannot be derived for generic types",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "KaijuStruct can be derived only for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "KaijuStruct can be derived only for structs",
+            ))
+        }
+    };
+    let name = &input.ident;
+    let id = name.to_string();
+    let idents = fields
+        .iter()
+        .map(|f| f.ident.as_ref().unwrap())
+        .collect::<Vec<_>>();
+    let ids = idents
+        .iter()
+        .map(|i| i.to_string().trim_start_matches("r#").to_owned())
+        .collect::<Vec<_>>();
+    let types = fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+    Ok(quote! {
+        impl ::kaiju_vm_core::call::CallValue for #name {
+            fn size(target: &::kaiju_core::target::Target) -> usize {
+                let mut offset = 0;
+                #(
+                    offset = ::kaiju_core::target::align_offset(
+                        offset,
+                        <#types as ::kaiju_vm_core::call::CallValue>::alignment(target),
+                    ) + <#types as ::kaiju_vm_core::call::CallValue>::size(target);
+                )*
+                ::kaiju_core::target::align_offset(
+                    offset,
+                    <Self as ::kaiju_vm_core::call::CallValue>::alignment(target),
+                )
+            }
+
+            fn alignment(target: &::kaiju_core::target::Target) -> usize {
+                1 #(.max(<#types as ::kaiju_vm_core::call::CallValue>::alignment(target)))*
+            }
+
+            fn write_bytes(
+                &self,
+                bytes: &mut [u8],
+                target: &::kaiju_core::target::Target,
+            ) -> ::kaiju_core::error::SimpleResult<()> {
+                let mut offset = 0;
+                #(
+                    offset = ::kaiju_core::target::align_offset(
+                        offset,
+                        <#types as ::kaiju_vm_core::call::CallValue>::alignment(target),
+                    );
+                    ::kaiju_vm_core::call::CallValue::write_bytes(
+                        &self.#idents,
+                        &mut bytes[offset..],
+                        target,
+                    )?;
+                    offset += <#types as ::kaiju_vm_core::call::CallValue>::size(target);
+                )*
+                let _ = offset;
+                Ok(())
+            }
+
+            fn read_bytes(bytes: &[u8], target: &::kaiju_core::target::Target) -> Self {
+                let mut offset = 0;
+                #(
+                    offset = ::kaiju_core::target::align_offset(
+                        offset,
+                        <#types as ::kaiju_vm_core::call::CallValue>::alignment(target),
+                    );
+                    let #idents = <#types as ::kaiju_vm_core::call::CallValue>::read_bytes(
+                        &bytes[offset..],
+                        target,
+                    );
+                    offset += <#types as ::kaiju_vm_core::call::CallValue>::size(target);
+                )*
+                let _ = offset;
+                Self { #(#idents),* }
+            }
+        }
+
+        impl ::kaiju_vm_core::marshal::KaijuType for #name {
+            fn type_id() -> String {
+                #id.to_owned()
+            }
+        }
+
+        impl ::kaiju_vm_core::marshal::KaijuStruct for #name {
+            fn fields(
+                target: &::kaiju_core::target::Target,
+            ) -> Vec<::kaiju_vm_core::marshal::KaijuStructField> {
+                let mut offset = 0;
+                let mut result = vec![];
+                #(
+                    offset = ::kaiju_core::target::align_offset(
+                        offset,
+                        <#types as ::kaiju_vm_core::call::CallValue>::alignment(target),
+                    );
+                    let size = <#types as ::kaiju_vm_core::call::CallValue>::size(target);
+                    result.push(::kaiju_vm_core::marshal::KaijuStructField {
+                        id: #ids,
+                        type_id: <#types as ::kaiju_vm_core::marshal::KaijuType>::type_id(),
+                        offset,
+                        size,
+                    });
+                    offset += size;
+                )*
+                let _ = offset;
+                result
+            }
+        }
+    })
+}
+
+/// Turns function into VM op: keeps function as it is and generates module of the same name with
+/// `ID`, `descriptor()` (ops descriptor rule source), `handler()` (op handler that loads params
+/// from and stores results into VM state) and `definition()` (`OpDefinition` for `OpsRegistry`).
+///
+/// Params of `&mut Vm` or `&Vm` type get VM; first param of other reference type gets processor.
+/// Rest of params and returned value (or tuple of values) must implement `KaijuType`.
+/// Function can return `SimpleResult` to report errors.
+#[proc_macro_attribute]
+pub fn kaiju_op(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return Error::new(
+            proc_macro2::Span::call_site(),
+            "kaiju_op does not take arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let item = parse_macro_input!(item as ItemFn);
+    match kaiju_op_impl(&item) {
+        Ok(result) => result.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn kaiju_op_impl(item: &ItemFn) -> Result<proc_macro2::TokenStream, Error> {
+    let sig = &item.sig;
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            "kaiju_op cannot be used on generic functions",
+        ));
+    }
+    let name = &sig.ident;
+    let id = name.to_string().trim_start_matches("r#").to_owned();
+    let mut comments = vec![];
+    for attr in &item.attrs {
+        if let Meta::NameValue(meta) = &attr.meta {
+            if meta.path.is_ident("doc") {
+                if let Expr::Lit(lit) = &meta.value {
+                    if let Lit::Str(lit) = &lit.lit {
+                        comments.push(format!("//{}\n", lit.value()));
+                    }
+                }
+            }
+        }
+    }
+    let mut processor = None;
+    let mut args = vec![];
+    let mut loads = vec![];
+    let mut params = vec![];
+    for (i, input) in sig.inputs.iter().enumerate() {
+        let input = match input {
+            FnArg::Typed(input) => input,
+            FnArg::Receiver(receiver) => {
+                return Err(Error::new_spanned(
+                    receiver,
+                    "kaiju_op cannot be used on methods",
+                ))
+            }
+        };
+        if let Type::Reference(reference) = &*input.ty {
+            if is_vm(&reference.elem) {
+                args.push(quote! { vm });
+            } else if i == 0 {
+                processor = Some(&reference.elem);
+                args.push(quote! { processor });
+            } else {
+                return Err(Error::new_spanned(
+                    &input.ty,
+                    "Only VM and processor (first param) can be passed by reference",
+                ));
+            }
+            continue;
+        }
+        let param = if let Pat::Ident(pat) = &*input.pat {
+            pat.ident.to_string().trim_start_matches("r#").to_owned()
+        } else {
+            return Err(Error::new_spanned(
+                &input.pat,
+                "kaiju_op params must be named",
+            ));
+        };
+        let ty = &input.ty;
+        let index = params.len();
+        let arg = format_ident!("__param_{}", index);
+        loads.push(quote! {
+            let #arg = ::kaiju_vm_core::marshal::load_value::<#ty>(vm.state(), params[#index])?;
+        });
+        args.push(quote! { #arg });
+        params.push(quote! {
+            result.push_str(&format!(
+                " {}: {}",
+                #param,
+                <#ty as ::kaiju_vm_core::marshal::KaijuType>::type_id()
+            ));
+        });
+    }
+    let (output, fallible) = match &sig.output {
+        ReturnType::Default => (None, false),
+        ReturnType::Type(_, ty) => match result_value(ty) {
+            Some(ty) => (Some(ty), true),
+            None => (Some(&**ty), false),
+        },
+    };
+    let outputs = match output {
+        Some(Type::Tuple(tuple)) => tuple.elems.iter().collect::<Vec<_>>(),
+        Some(ty) => vec![ty],
+        None => vec![],
+    };
+    let call = if fallible {
+        quote! { super::#name(#(#args),*)? }
+    } else {
+        quote! { super::#name(#(#args),*) }
+    };
+    let results = (0..outputs.len())
+        .map(|i| format_ident!("__result_{}", i))
+        .collect::<Vec<_>>();
+    let indices = 0..outputs.len();
+    let store = match output {
+        Some(Type::Tuple(_)) => quote! {
+            let (#(#results,)*) = #call;
+            #(::kaiju_vm_core::marshal::store_value(&#results, vm.state_mut(), targets[#indices])?;)*
+        },
+        Some(_) => quote! {
+            let __result_0 = #call;
+            ::kaiju_vm_core::marshal::store_value(&__result_0, vm.state_mut(), targets[0])?;
+        },
+        None => quote! { #call; },
+    };
+    let targets = if outputs.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            result.push_str(" =>");
+            #(
+                result.push_str(" ");
+                result.push_str(&<#outputs as ::kaiju_vm_core::marshal::KaijuType>::type_id());
+            )*
+        }
+    };
+    let (generics, processor_type) = match processor {
+        Some(ty) => (quote! {}, quote! { #ty }),
+        None => (quote! { <P> }, quote! { P }),
+    };
+    let comments = comments.concat();
+    let vis = &item.vis;
+    Ok(quote! {
+        #item
+
+        #[allow(non_snake_case, unused_imports, clippy::unused_unit)]
+        #vis mod #name {
+            use super::*;
+
+            pub const ID: &str = #id;
+
+            pub fn descriptor() -> String {
+                let mut result = String::from(#comments);
+                result.push_str(ID);
+                #(#params)*
+                #targets
+                result.push_str(" {}");
+                result
+            }
+
+            pub fn handler #generics (
+                processor: &mut #processor_type,
+                params: &[usize],
+                targets: &[usize],
+                vm: &mut ::kaiju_vm_core::vm::Vm,
+            ) -> ::kaiju_core::error::SimpleResult<::kaiju_vm_core::processor::OpAction> {
+                let _ = (&processor, params, targets);
+                #(#loads)*
+                #store
+                Ok(::kaiju_vm_core::processor::OpAction::None)
+            }
+
+            pub fn definition #generics () -> ::kaiju_vm_core::processor::OpDefinition<#processor_type> {
+                ::kaiju_vm_core::processor::OpDefinition {
+                    id: ID,
+                    descriptor: descriptor(),
+                    handler,
+                }
+            }
+        }
+    })
+}
+
+fn is_vm(ty: &Type) -> bool {
+    if let Type::Path(path) = ty {
+        path.path
+            .segments
+            .last()
+            .map(|s| s.ident == "Vm")
+            .unwrap_or(false)
+    } else {
+        false
+    }
+}
+
+fn result_value(ty: &Type) -> Option<&Type> {
+    if let Type::Path(path) = ty {
+        let segment = path.path.segments.last()?;
+        if segment.ident != "Result" && segment.ident != "SimpleResult" {
+            return None;
+        }
+        if let PathArguments::AngleBracketed(args) = &segment.arguments {
+            if let Some(GenericArgument::Type(ty)) = args.args.first() {
+                return Some(ty);
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,106 @@
+extern crate kaiju_core as core;
+
+use core::assembly::{encode_assembly_with_options, AssemblyOptions};
+use core::program::{compile_module, compile_ops_descriptor, Program};
+use core::target::Target;
+use core::validator::transform_module_auto_types;
+use kaiju_vm_core::call::CallValue;
+use kaiju_vm_core::marshal::KaijuStruct;
+use kaiju_vm_core::state::State;
+use kaiju_vm_core::vm::Vm;
+use kaiju_vm_macros::KaijuStruct;
+
+#[derive(Debug, PartialEq, KaijuStruct)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Debug, PartialEq, KaijuStruct)]
+struct Tagged {
+    r#type: u8,
+    value: f64,
+}
+
+fn create_vm(declarations: &str) -> Vm {
+    create_vm_with_target(declarations, Target::default())
+}
+
+fn create_vm_with_target(declarations: &str, target: Target) -> Vm {
+    let ops = compile_ops_descriptor("pass v: $? {}").unwrap();
+    let source = format!("{}export fn main() {{}}", declarations);
+    let module = transform_module_auto_types(compile_module(&source).unwrap()).unwrap();
+    let program = Program::from_modules(None, vec![module]).unwrap();
+    let options = AssemblyOptions {
+        debug_info: true,
+        target,
+        ..Default::default()
+    };
+    let bytes = encode_assembly_with_options(&program, &ops, &options).unwrap();
+    Vm::from_bytes(bytes, 256, 256).unwrap()
+}
+
+#[test]
+fn test_kaiju_struct() {
+    assert_eq!(
+        Point::declaration(),
+        "export struct Point {\n  x: i32,\n  y: i32,\n}\n"
+    );
+    assert_eq!(
+        Tagged::declaration(),
+        "export struct Tagged {\n  type: u8,\n  value: f64,\n}\n"
+    );
+    let fields = Tagged::fields(&Target::default());
+    assert_eq!(fields[1].offset, 1);
+    assert_eq!(fields[1].size, 8);
+
+    let vm = create_vm(&(Point::declaration() + &Tagged::declaration()));
+    Point::check_layout(vm.assembly()).unwrap();
+    Tagged::check_layout(vm.assembly()).unwrap();
+    let vm = create_vm("export struct Point {\n  y: i32,\n  x: i32,\n}\n");
+    assert_eq!(
+        Point::check_layout(vm.assembly()).unwrap_err().message,
+        "Struct `Point` field #0 is named `y`, expected `x`"
+    );
+    let vm = create_vm("export struct Point {\n  x: i32,\n  y: i64,\n}\n");
+    assert!(Point::check_layout(vm.assembly()).is_err());
+    assert!(Tagged::check_layout(vm.assembly()).is_err());
+
+    let mut state = State::new(64, 64);
+    let point = Point { x: 40, y: 2 };
+    point.store(&mut state, 8).unwrap();
+    assert_eq!(Point::load(&state, 8).unwrap(), point);
+    let tagged = Tagged {
+        r#type: 3,
+        value: 4.5,
+    };
+    tagged.store(&mut state, 16).unwrap();
+    assert_eq!(Tagged::load(&state, 16).unwrap(), tagged);
+}
+
+#[test]
+fn test_kaiju_struct_target() {
+    for (name, offset, size) in &[("le16", 2, 10), ("be32", 4, 12), ("le64", 8, 16)] {
+        let target = Target::from_name(name).unwrap();
+        let fields = Tagged::fields(&target);
+        assert_eq!(fields[1].offset, *offset);
+        assert_eq!(Tagged::size(&target), *size);
+        let vm = create_vm_with_target(&(Point::declaration() + &Tagged::declaration()), target);
+        Point::check_layout(vm.assembly()).unwrap();
+        Tagged::check_layout(vm.assembly()).unwrap();
+
+        let mut state = State::with_target(64, 64, target);
+        let tagged = Tagged {
+            r#type: 3,
+            value: 4.5,
+        };
+        tagged.store(&mut state, 16).unwrap();
+        assert_eq!(state.load_data::<f64>(16 + offset).unwrap(), 4.5);
+        assert_eq!(Tagged::load(&state, 16).unwrap(), tagged);
+    }
+    let vm = create_vm_with_target(
+        &Tagged::declaration(),
+        Target::from_name("le16-packed").unwrap(),
+    );
+    Tagged::check_layout(vm.assembly()).unwrap();
+}
@@ -0,0 +1,166 @@
+extern crate kaiju_core as core;
+
+use core::assembly::{encode_assembly_with_options, AssemblyOptions};
+use core::error::*;
+use core::program::{compile_module, Program};
+use core::target::Target;
+use core::validator::transform_module_auto_types;
+use kaiju_vm_core::processor::{HandlerId, OpAction, OpsProcessor, OpsRegistry, Processor};
+use kaiju_vm_core::vm::Vm;
+use kaiju_vm_macros::kaiju_op;
+
+/// adds two integers.
+#[kaiju_op]
+fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+#[kaiju_op]
+fn swap(a: i32, b: i32) -> (i32, i32) {
+    (b, a)
+}
+
+#[kaiju_op]
+fn div(a: i32, b: i32) -> SimpleResult<i32> {
+    if b == 0 {
+        Err(SimpleError::new("Division by zero".to_owned()))
+    } else {
+        Ok(a / b)
+    }
+}
+
+#[kaiju_op]
+fn offset(a: usize, b: isize) -> usize {
+    (a as isize + b) as usize
+}
+
+#[kaiju_op]
+fn acc(processor: &mut TestProcessor, v: i32, vm: &Vm) {
+    processor.total += v;
+    processor.stack_pos = vm.state().stack_pos();
+}
+
+struct TestProcessor {
+    ops: OpsRegistry<TestProcessor>,
+    total: i32,
+    stack_pos: usize,
+}
+
+impl Processor for TestProcessor {
+    fn bind_op(&mut self, op: &str) -> Option<HandlerId> {
+        self.ops.find(op)
+    }
+
+    fn process_op(
+        &mut self,
+        handler: HandlerId,
+        params: &[usize],
+        targets: &[usize],
+        vm: &mut Vm,
+    ) -> SimpleResult<OpAction> {
+        let handler = self.ops.handler(handler).unwrap();
+        handler(self, params, targets, vm)
+    }
+}
+
+fn create_vm<P>(ops: &OpsRegistry<P>, source: &str) -> Vm {
+    create_vm_with_target(ops, source, Target::default())
+}
+
+fn create_vm_with_target<P>(ops: &OpsRegistry<P>, source: &str, target: Target) -> Vm {
+    let ops = ops.ops_descriptor().unwrap();
+    let module = transform_module_auto_types(compile_module(source).unwrap()).unwrap();
+    let program = Program::from_modules(None, vec![module]).unwrap();
+    let options = AssemblyOptions {
+        target,
+        ..Default::default()
+    };
+    let bytes = encode_assembly_with_options(&program, &ops, &options).unwrap();
+    Vm::from_bytes(bytes, 256, 256).unwrap()
+}
+
+#[test]
+fn test_kaiju_op() {
+    assert_eq!(add::ID, "add");
+    assert_eq!(
+        add::descriptor(),
+        "// adds two integers.\nadd a: i32 b: i32 => i32 {}"
+    );
+    assert_eq!(swap::descriptor(), "swap a: i32 b: i32 => i32 i32 {}");
+    assert_eq!(div::descriptor(), "div a: i32 b: i32 => i32 {}");
+    assert_eq!(acc::descriptor(), "acc v: i32 {}");
+    assert_eq!(add(40, 2), 42);
+
+    let ops = OpsRegistry::new()
+        .with_definition(add::definition())
+        .with_definition(swap::definition())
+        .with_definition(div::definition())
+        .with_definition(acc::definition());
+    assert_eq!(
+        ops.descriptor(),
+        "// adds two integers.\nadd a: i32 b: i32 => i32 {}\n\
+         swap a: i32 b: i32 => i32 i32 {}\n\
+         div a: i32 b: i32 => i32 {}\n\
+         acc v: i32 {}\n"
+    );
+    let source = "export fn run(a: i32, b: i32): i32 {\n  swap a b => a b;\n  acc a;\n  div a b => a;\n  add a b => _;\n}";
+    let mut vm = create_vm(&ops, source);
+    let mut processor = TestProcessor {
+        ops,
+        total: 0,
+        stack_pos: 0,
+    };
+    vm.bind(&mut processor).unwrap();
+    assert_eq!(
+        vm.call_typed::<_, _, i32>(&mut processor, "run", (2i32, 40i32))
+            .unwrap(),
+        22
+    );
+    assert_eq!(processor.total, 40);
+    assert!(processor.stack_pos > 0);
+    assert_eq!(
+        vm.call_typed::<_, _, i32>(&mut processor, "run", (0i32, 1i32))
+            .unwrap_err()
+            .message,
+        "Division by zero"
+    );
+}
+
+#[test]
+fn test_ops_processor() {
+    let ops = OpsRegistry::new().with_definition(add::definition());
+    let mut vm = create_vm(&ops, "export fn run(a: i32): i32 {\n  add a a => _;\n}");
+    let mut processor = OpsProcessor::new(ops);
+    vm.bind(&mut processor).unwrap();
+    assert_eq!(
+        vm.call_typed::<_, _, i32>(&mut processor, "run", 21i32)
+            .unwrap(),
+        42
+    );
+}
+
+#[test]
+fn test_pointer_sized_ops() {
+    assert_eq!(offset::descriptor(), "offset a: usize b: isize => usize {}");
+    let ops = OpsRegistry::new().with_definition(offset::definition());
+    let source = "export fn run(a: usize, b: isize): usize {\n  offset a b => _;\n}";
+    let mut vm = create_vm_with_target(&ops, source, Target::from_name("le16").unwrap());
+    let mut processor = OpsProcessor::new(ops);
+    vm.bind(&mut processor).unwrap();
+    assert_eq!(
+        vm.call(&mut processor, "run", &[40, 0, 0xfe, 0xff])
+            .unwrap(),
+        vec![38, 0]
+    );
+    assert_eq!(
+        vm.call_typed::<_, _, usize>(&mut processor, "run", (40usize, 2isize))
+            .unwrap(),
+        42
+    );
+    assert_eq!(
+        vm.call_typed::<_, _, usize>(&mut processor, "run", (0x10000usize, 2isize))
+            .unwrap_err()
+            .message,
+        "Trying to store value 65536 that does not fit into 2 bytes"
+    );
+}
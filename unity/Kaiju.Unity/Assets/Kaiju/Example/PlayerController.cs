@@ -58,7 +58,17 @@ public class PlayerController : MonoBehaviour
         }
         m_files["program.kj"] = Encoding.UTF8.GetBytes(m_code.text);
 
-        var assembly = Compiler.CompileBin("program.kj", "descriptor.kjo", m_files, error => Debug.LogError(error));
+        var assembly = Compiler.CompileBin("program.kj", "descriptor.kjo", m_files, diagnostic =>
+        {
+            if (diagnostic.Severity == Kaiju.Compiler.Severity.Error)
+            {
+                Debug.LogError(diagnostic);
+            }
+            else
+            {
+                Debug.LogWarning(diagnostic);
+            }
+        });
         if (assembly == null)
         {
             return;
@@ -126,18 +136,18 @@ public class PlayerController : MonoBehaviour
         }
     }
 
-    private void OnProcessOp(string op, UIntPtr[] paramsPtrs, UIntPtr[] targetsPtrs)
+    private void OnProcessOp(IntPtr kaiju, string op, UIntPtr[] paramsPtrs, UIntPtr[] targetsPtrs)
     {
         if (op == "movx")
         {
             m_action = Action.MovX;
-            var value = VM.StateLoad<int>(paramsPtrs[0]);
+            var value = VM.StateLoad<int>(kaiju, paramsPtrs[0]);
             m_coroutine = StartCoroutine(Move(Mathf.Sign((float)value), 0, Mathf.Abs((float)value)));
         }
         else if (op == "movy")
         {
             m_action = Action.MovY;
-            var value = VM.StateLoad<int>(paramsPtrs[0]);
+            var value = VM.StateLoad<int>(kaiju, paramsPtrs[0]);
             m_coroutine = StartCoroutine(Move(0, Mathf.Sign((float)-value), Mathf.Abs((float)value)));
         }
     }